@@ -0,0 +1,107 @@
+//! Time reading every entry of a dat file, as whole-index extractions do, with a new buffer for
+//! each entry against reusing a [ReadScratch]:
+//!
+//! ```sh
+//! cargo bench -p last-legend-dob --bench dat_read
+//! ```
+use std::hint::black_box;
+use std::io::{Cursor, Read};
+use std::time::{Duration, Instant};
+
+use binrw::BinReaderExt;
+
+use last_legend_dob::data::dat::{DatEntryHeader, ReadScratch};
+use last_legend_dob::data::dat_writer::DatEntryWriter;
+use last_legend_dob::limits::Limits;
+
+/// Most entries are small, with the odd larger one, like the files of a typical index.
+const ENTRY_SIZES: [usize; 4] = [2_000, 9_000, 40_000, 300_000];
+const ENTRIES: usize = 2_000;
+
+fn main() {
+    let writer = DatEntryWriter::new();
+    let mut dat = Vec::new();
+    let mut offsets = Vec::with_capacity(ENTRIES);
+    let mut total_bytes = 0;
+    let mut seed = 0x2545_F491_u32;
+    for i in 0..ENTRIES {
+        let size = ENTRY_SIZES[i % ENTRY_SIZES.len()];
+        // Somewhat compressible content, like most game files.
+        let content = (0..size)
+            .map(|j| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                if j % 4 == 0 {
+                    seed as u8
+                } else {
+                    (j / 64) as u8
+                }
+            })
+            .collect::<Vec<_>>();
+        offsets.push(dat.len() as u64);
+        dat.extend(writer.encode(&content).unwrap());
+        total_bytes += size;
+    }
+
+    let read_all = |read: &mut dyn FnMut(&DatEntryHeader, Cursor<&[u8]>) -> usize| {
+        let start = Instant::now();
+        let mut read_bytes = 0;
+        for &offset in &offsets {
+            let mut reader = Cursor::new(dat.as_slice());
+            reader.set_position(offset);
+            let header: DatEntryHeader = reader.read_le().unwrap();
+            reader.set_position(offset);
+            read_bytes += read(&header, reader);
+        }
+        assert_eq!(read_bytes, total_bytes);
+        start.elapsed()
+    };
+
+    report(
+        "DatEntryContent::read_to_end",
+        total_bytes,
+        read_all(&mut |header, reader| {
+            let mut content = Vec::new();
+            header
+                .read_content(reader)
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            black_box(content).len()
+        }),
+    );
+    report(
+        "read_content_to_vec",
+        total_bytes,
+        read_all(&mut |header, reader| {
+            black_box(header.read_content_to_vec(reader).unwrap()).len()
+        }),
+    );
+    let mut scratch = ReadScratch::default();
+    let limits = Limits::default();
+    report(
+        "read_content_with_scratch",
+        total_bytes,
+        read_all(&mut |header, reader| {
+            black_box(
+                header
+                    .read_content_with_scratch(reader, &mut scratch, None, &limits)
+                    .unwrap(),
+            )
+            .len()
+        }),
+    );
+}
+
+fn report(name: &str, bytes: usize, elapsed: Duration) {
+    let megabytes = bytes as f64 / 1_000_000.0;
+    println!(
+        "{}: {} entries ({:.1} MB) in {:.2?}, {:.0} MB/s",
+        name,
+        ENTRIES,
+        megabytes,
+        elapsed,
+        megabytes / elapsed.as_secs_f64()
+    );
+}
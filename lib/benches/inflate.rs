@@ -0,0 +1,60 @@
+//! Time inflating dat-sized blocks with the decompressor in use, to compare deflate backends:
+//!
+//! ```sh
+//! cargo bench -p last-legend-dob --bench inflate
+//! cargo bench -p last-legend-dob --bench inflate --features zlib-rs
+//! ```
+use std::hint::black_box;
+use std::io::Write;
+use std::time::Instant;
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use last_legend_dob::data::inflate::decompressor;
+
+/// Blocks in dat files hold at most this much, before compression.
+const BLOCK_SIZE: usize = 16_000;
+const BLOCKS: usize = 4_000;
+
+fn main() {
+    // Somewhat compressible content, like most game files.
+    let mut seed = 0x2545_F491_u32;
+    let content = (0..BLOCK_SIZE)
+        .map(|i| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            if i % 4 == 0 {
+                seed as u8
+            } else {
+                (i / 64) as u8
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&content).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let decompressor = decompressor();
+    let mut output = vec![0; BLOCK_SIZE];
+    let start = Instant::now();
+    for _ in 0..BLOCKS {
+        decompressor
+            .decompress(black_box(&compressed), &mut output)
+            .unwrap();
+        black_box(&output);
+    }
+    let elapsed = start.elapsed();
+    assert_eq!(output, content);
+
+    let megabytes = (BLOCK_SIZE * BLOCKS) as f64 / 1_000_000.0;
+    println!(
+        "{}: {} blocks ({:.1} MB) in {:.2?}, {:.0} MB/s",
+        decompressor.name(),
+        BLOCKS,
+        megabytes,
+        elapsed,
+        megabytes / elapsed.as_secs_f64()
+    );
+}
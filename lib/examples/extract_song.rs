@@ -0,0 +1,22 @@
+//! Extract a single song as FLAC.
+//!
+//! ```sh
+//! cargo run -p last-legend-dob --example extract_song -- ~/ffxiv/game/sqpack music/ffxiv/BGM_System_Title.scd title
+//! ```
+use std::path::PathBuf;
+
+use last_legend_dob::prelude::*;
+
+fn main() -> Result<(), LastLegendError> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let [sqpack, song, output] = &args[..] else {
+        eprintln!("Usage: extract_song <sqpack> <song> <output>");
+        std::process::exit(2);
+    };
+
+    let repo = Repository::new(PathBuf::from(sqpack));
+    let transformers = [TransformerImpl::ScdToFlac(ScdOptions::default())];
+    let written = extract_to_file(&repo, SqPathBuf::new(song), &transformers, output)?;
+    println!("Wrote {}", written.display());
+    Ok(())
+}
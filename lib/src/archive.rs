@@ -0,0 +1,116 @@
+//! A facade over [Repository] for the common case of reading files out of the game data.
+//!
+//! ```no_run
+//! use last_legend_dob::archive::Archive;
+//!
+//! let archive = Archive::open("/path/to/game/sqpack").unwrap();
+//! if archive.exists("exd/root.exl").unwrap() {
+//!     let root = archive.read("exd/root.exl").unwrap();
+//!     println!("{}", String::from_utf8_lossy(&root));
+//! }
+//! ```
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::data::repo::Repository;
+use crate::error::{ErrorCategory, LastLegendError};
+use crate::simple_task::{create_transformed_reader, TransformedReader};
+use crate::sqpath::SqPath;
+use crate::transformers::{TransformStream, TransformerImpl};
+
+#[derive(Debug, Clone)]
+pub struct Archive {
+    repo: Repository,
+    transformers: Vec<TransformerImpl>,
+}
+
+impl Archive {
+    /// Open the SqPack at [path]. Indexes are loaded lazily, so this only checks the path exists.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, LastLegendError> {
+        let path = path.into();
+        if !path.is_dir() {
+            return Err(LastLegendError::Io(
+                format!("{} is not a directory", path.display()),
+                std::io::ErrorKind::NotFound.into(),
+            ));
+        }
+        Ok(Self::from_repository(Repository::new(path)))
+    }
+
+    pub fn from_repository(repo: Repository) -> Self {
+        Self {
+            repo,
+            transformers: Vec::new(),
+        }
+    }
+
+    /// Run [transformers] on everything read from this archive.
+    pub fn with_transformers(mut self, transformers: Vec<TransformerImpl>) -> Self {
+        self.transformers = transformers;
+        self
+    }
+
+    pub fn repository(&self) -> &Repository {
+        &self.repo
+    }
+
+    /// Check if [file] is in the archive. Paths that don't map to an index are reported as missing.
+    pub fn exists<F: AsRef<SqPath>>(&self, file: F) -> Result<bool, LastLegendError> {
+        let file = file.as_ref();
+        match self.repo.get_index_for(file) {
+            Ok(index) => Ok(index.get_entry(file).is_ok()),
+            Err(e) if e.category() == ErrorCategory::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Open [file], after running the transformers. The transformed file name is included.
+    pub fn transformed_reader<F: AsRef<SqPath>>(
+        &self,
+        file: F,
+    ) -> Result<TransformedReader, LastLegendError> {
+        let file = file.as_ref();
+        let index = self.repo.get_index_for(file)?;
+        let entry = index.get_entry(file)?;
+        create_transformed_reader(&index, entry, file.to_owned(), &self.transformers)
+    }
+
+    pub fn reader<F: AsRef<SqPath>>(
+        &self,
+        file: F,
+    ) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        self.transformed_reader(file).map(|t| t.reader)
+    }
+
+    pub fn read<F: AsRef<SqPath>>(&self, file: F) -> Result<Vec<u8>, LastLegendError> {
+        let mut content = Vec::new();
+        self.reader(file)?
+            .read_to_end(&mut content)
+            .map_err(|e| LastLegendError::Io("Couldn't read content".into(), e))?;
+        Ok(content)
+    }
+
+    /// Write [file] to [path], creating any missing parent directories.
+    pub fn extract_to<F: AsRef<SqPath>, P: AsRef<Path>>(
+        &self,
+        file: F,
+        path: P,
+    ) -> Result<(), LastLegendError> {
+        let path = path.as_ref();
+        let mut reader = self.reader(file)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| LastLegendError::Io("Couldn't create output dirs".into(), e))?;
+        }
+        let mut output = BufWriter::new(
+            File::create(path)
+                .map_err(|e| LastLegendError::Io("Couldn't create output file".into(), e))?,
+        );
+        std::io::copy(&mut reader, &mut output)
+            .map_err(|e| LastLegendError::Io("Couldn't copy to output file".into(), e))?;
+        output
+            .flush()
+            .map_err(|e| LastLegendError::Io("Couldn't flush output file".into(), e))
+    }
+}
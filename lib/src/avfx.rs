@@ -0,0 +1,216 @@
+//! Reader for the `.avfx` VFX files, which are a tree of tagged blocks.
+//!
+//! Each block is a 4 byte tag, a 4 byte size, then the data, padded to 4 bytes. Blocks such as
+//! timelines (`TmLn`) and emitters (`Emit`) hold more blocks, others hold values. The format of
+//! each value isn't known, so they're described by their size, except for paths.
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::error::LastLegendError;
+use crate::limits::Limits;
+
+const BLOCK_HEADER_SIZE: usize = 8;
+
+#[derive(Debug, Serialize)]
+pub struct AvfxBlock {
+    pub tag: String,
+    #[serde(flatten)]
+    pub value: AvfxValue,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AvfxValue {
+    Children(Vec<AvfxBlock>),
+    /// A NUL-terminated string, such as a texture path.
+    String(String),
+    /// A 1 byte value.
+    Byte(u8),
+    /// A 4 byte value, which may be either an int or a float.
+    Word {
+        int: i32,
+        float: f32,
+    },
+    /// Anything else, by size.
+    Bytes(usize),
+}
+
+/// A parsed `.avfx` file.
+#[derive(Debug)]
+pub struct Avfx {
+    pub root: AvfxBlock,
+}
+
+impl Avfx {
+    pub fn parse(data: &[u8]) -> Result<Self, LastLegendError> {
+        Self::parse_with_limits(data, &Limits::default())
+    }
+
+    /// Like [parse](Self::parse), only parsing blocks up to [Limits::max_avfx_depth] deep.
+    pub fn parse_with_limits(data: &[u8], limits: &Limits) -> Result<Self, LastLegendError> {
+        let mut blocks = parse_blocks(data, limits.max_avfx_depth)
+            .ok_or_else(|| LastLegendError::Custom("Not an AVFX file".into()))?;
+        match blocks.pop() {
+            Some(root) if root.tag == "AVFX" && blocks.is_empty() => Ok(Self { root }),
+            _ => Err(LastLegendError::Custom(
+                "AVFX file should have a single AVFX block".into(),
+            )),
+        }
+    }
+
+    /// Get all blocks with [tag], anywhere in the tree.
+    pub fn find_all<'a>(&'a self, tag: &str) -> Vec<&'a AvfxBlock> {
+        let mut found = Vec::new();
+        let mut pending = vec![&self.root];
+        while let Some(block) = pending.pop() {
+            if block.tag == tag {
+                found.push(block);
+            }
+            if let AvfxValue::Children(children) = &block.value {
+                pending.extend(children.iter().rev());
+            }
+        }
+        found
+    }
+
+    fn paths(&self, tag: &str) -> Vec<&str> {
+        self.find_all(tag)
+            .into_iter()
+            .filter_map(|b| match &b.value {
+                AvfxValue::String(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The textures this effect uses, as game paths.
+    pub fn textures(&self) -> Vec<&str> {
+        self.paths("Tex")
+    }
+
+    /// A summary of the timelines, emitters, particles and textures, and the full block tree.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "timelines": self.find_all("TmLn").len(),
+            "emitters": self.find_all("Emit").len(),
+            "particles": self.find_all("Ptcl").len(),
+            "effectors": self.find_all("Efct").len(),
+            "textures": self.textures(),
+            "blocks": self.root,
+        })
+    }
+}
+
+/// Parse [data] as a sequence of blocks, or None if it isn't one. Blocks within them are parsed
+/// [depth] more levels down.
+fn parse_blocks(data: &[u8], depth: u32) -> Option<Vec<AvfxBlock>> {
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data.get(pos..pos + BLOCK_HEADER_SIZE)?;
+        // Tags are stored as little-endian ints, so the text is reversed.
+        let tag = header[..4]
+            .iter()
+            .rev()
+            .copied()
+            .filter(|&b| b != 0)
+            .collect::<Vec<_>>();
+        if tag.is_empty() || !tag.iter().all(u8::is_ascii_alphanumeric) {
+            return None;
+        }
+        let size = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        let start = pos + BLOCK_HEADER_SIZE;
+        let content = data.get(start..start.checked_add(size)?)?;
+        blocks.push(AvfxBlock {
+            tag: String::from_utf8(tag).unwrap(),
+            value: parse_value(content, depth),
+        });
+        pos = (start + size).next_multiple_of(4);
+    }
+    (!blocks.is_empty()).then_some(blocks)
+}
+
+fn parse_value(content: &[u8], depth: u32) -> AvfxValue {
+    if depth > 0 && content.len() >= BLOCK_HEADER_SIZE {
+        if let Some(children) = parse_blocks(content, depth - 1) {
+            return AvfxValue::Children(children);
+        }
+    }
+    match content {
+        [value] => AvfxValue::Byte(*value),
+        [a, b, c, d] => AvfxValue::Word {
+            int: i32::from_le_bytes([*a, *b, *c, *d]),
+            float: f32::from_le_bytes([*a, *b, *c, *d]),
+        },
+        [text @ .., 0] if text.len() > 1 && text.iter().all(|b| b.is_ascii_graphic()) => {
+            AvfxValue::String(String::from_utf8(text.to_vec()).unwrap())
+        }
+        _ => AvfxValue::Bytes(content.len()),
+    }
+}
+
+#[cfg(test)]
+mod avfx_tests {
+    use super::*;
+
+    fn block(tag: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut block = tag.iter().rev().copied().collect::<Vec<_>>();
+        block.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        block.extend_from_slice(content);
+        block.resize(block.len().next_multiple_of(4), 0);
+        block
+    }
+
+    #[test]
+    fn parses_tree_and_textures() {
+        let timeline = [
+            block(b"\0\0TC", &[2]),
+            block(b"\0Ver", &1.5f32.to_le_bytes()),
+        ]
+        .concat();
+        let root = [
+            block(b"\0Ver", &[1, 0, 0, 0]),
+            block(b"TmLn", &timeline),
+            block(b"\0Tex", b"vfx/common/texture/dust.atex\0"),
+            block(b"Modl", &[1, 2, 3, 4, 5, 6]),
+        ]
+        .concat();
+        let avfx = Avfx::parse(&block(b"AVFX", &root)).unwrap();
+
+        assert_eq!(avfx.textures(), vec!["vfx/common/texture/dust.atex"]);
+        assert_eq!(avfx.find_all("TmLn").len(), 1);
+        assert!(matches!(avfx.find_all("TC")[0].value, AvfxValue::Byte(2)));
+        assert!(matches!(
+            avfx.find_all("Modl")[0].value,
+            AvfxValue::Bytes(6)
+        ));
+        let json = avfx.to_json();
+        assert_eq!(json["timelines"], 1);
+        assert_eq!(json["blocks"]["tag"], "AVFX");
+
+        assert!(Avfx::parse(b"not an avfx file").is_err());
+    }
+
+    #[test]
+    fn deep_nesting_stops_at_the_limit() {
+        // Enough levels to overflow the stack if they were all parsed, each just a header.
+        const LEVELS: usize = 100_000;
+        let texture = block(b"\0Tex", b"vfx/common/texture/dust.atex\0");
+        let mut nested = Vec::with_capacity(LEVELS * BLOCK_HEADER_SIZE + texture.len());
+        for level in (0..LEVELS).rev() {
+            nested.extend(b"Emit".iter().rev());
+            let size = texture.len() + level * BLOCK_HEADER_SIZE;
+            nested.extend_from_slice(&(size as u32).to_le_bytes());
+        }
+        nested.extend(texture);
+        let limits = Limits {
+            max_avfx_depth: 4,
+            ..Limits::default()
+        };
+        let avfx = Avfx::parse_with_limits(&block(b"AVFX", &nested), &limits).unwrap();
+        let emitters = avfx.find_all("Emit");
+        assert_eq!(emitters.len(), 4);
+        assert!(matches!(emitters[3].value, AvfxValue::Bytes(_)));
+        assert!(avfx.textures().is_empty());
+    }
+}
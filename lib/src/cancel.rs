@@ -0,0 +1,63 @@
+//! Stopping long-running work from another thread.
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::LastLegendError;
+
+/// A flag shared between the code doing work and the code that wants it stopped. Clones share the
+/// same flag.
+///
+/// Give it to a [Repository](crate::data::repo::Repository) with
+/// [with_cancellation](crate::data::repo::Repository::with_cancellation), and reading entries
+/// checks it before each file and between the blocks of each file. Bulk functions like
+/// [verify_index](crate::data::verify::verify_index) also check it between entries. Once
+/// cancelled, those fail with [LastLegendError::Cancelled], or an error whose
+/// [category](LastLegendError::category) is [Cancelled](crate::error::ErrorCategory::Cancelled).
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask work using this token to stop. This can't be undone.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fail with [LastLegendError::Cancelled] if this has been cancelled.
+    pub fn check(&self) -> Result<(), LastLegendError> {
+        if self.is_cancelled() {
+            return Err(LastLegendError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Like [check](Self::check), for use inside readers.
+    pub(crate) fn check_io(&self) -> std::io::Result<()> {
+        if self.is_cancelled() {
+            return Err(std::io::Error::other(Cancelled));
+        }
+        Ok(())
+    }
+}
+
+/// Marks an I/O error as caused by cancellation, so it can be told apart from real failures.
+#[derive(Debug)]
+pub(crate) struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
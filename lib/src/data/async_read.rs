@@ -0,0 +1,178 @@
+//! Reading entries from async code, without blocking its threads.
+//!
+//! Decoding dat blocks is blocking work, so it runs on tokio's blocking thread pool, and the
+//! content is passed back in chunks as it's decoded. Must be used inside a tokio runtime.
+use std::io::Read;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::sync::mpsc;
+
+use crate::data::index2::{Index2, Index2Entry};
+use crate::data::repo::Repository;
+use crate::error::LastLegendError;
+use crate::simple_task::{read_entry_content, read_entry_header};
+use crate::sqpath::SqPathBuf;
+
+/// How much content is decoded at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// How many decoded chunks can wait to be read, before decoding pauses.
+const CHUNKS_AHEAD: usize = 4;
+
+/// Read the whole decompressed content of the entry with [key] in [index], through the content
+/// cache if the index has one. See [read_entry_content].
+pub async fn read_entry_content_async(
+    index: Arc<Index2>,
+    key: u64,
+) -> Result<Arc<[u8]>, LastLegendError> {
+    tokio::task::spawn_blocking(move || {
+        let entry = entry_for_key(&index, key)?;
+        read_entry_content(&index, entry)
+    })
+    .await
+    .map_err(|e| LastLegendError::Io("Reading the entry panicked".into(), e.into()))?
+}
+
+/// Streams the decompressed content of an entry, decoding it on the blocking thread pool a chunk
+/// at a time, so large files can be sent on without holding them in memory.
+///
+/// Decoding stops early if this is dropped, or if the index's cancellation token is cancelled.
+pub struct AsyncEntryReader {
+    size: u64,
+    chunks: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl AsyncEntryReader {
+    /// Start reading [file] from [repo], loading its index on the blocking thread pool if needed.
+    pub async fn open_file(repo: Repository, file: SqPathBuf) -> Result<Self, LastLegendError> {
+        let (index, key) = tokio::task::spawn_blocking(move || {
+            let index = repo.get_index_for(&file)?;
+            let key = index.get_entry(&file)?.key();
+            Ok::<_, LastLegendError>((index, key))
+        })
+        .await
+        .map_err(|e| LastLegendError::Io("Loading the index panicked".into(), e.into()))??;
+        Self::open(index, key).await
+    }
+
+    /// Start reading the entry with [key] in [index], see [Index2Entry::key].
+    pub async fn open(index: Arc<Index2>, key: u64) -> Result<Self, LastLegendError> {
+        tokio::task::spawn_blocking(move || {
+            let entry = entry_for_key(&index, key)?;
+            let (header, dat_reader) = read_entry_header(&index, entry)?;
+            let content = header
+                .read_content(dat_reader)
+                .map_err(|e| LastLegendError::Io("Couldn't read dat content".into(), e))?
+                .with_cancellation(index.cancellation.clone())
+                .with_max_block_size(index.limits.max_block_size);
+            Ok(Self::from_blocking(
+                content,
+                u64::from(header.uncompressed_size),
+            ))
+        })
+        .await
+        .map_err(|e| LastLegendError::Io("Opening the entry panicked".into(), e.into()))?
+    }
+
+    /// Stream [size] bytes from [reader], read on the blocking thread pool.
+    fn from_blocking<R: Read + Send + 'static>(mut reader: R, size: u64) -> Self {
+        let (sender, chunks) = mpsc::channel(CHUNKS_AHEAD);
+        tokio::task::spawn_blocking(move || loop {
+            let mut chunk = vec![0u8; CHUNK_SIZE];
+            let chunk = match reader.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(length) => {
+                    chunk.truncate(length);
+                    Ok(chunk)
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            // Stop once the reader is dropped, or after passing on an error.
+            if sender.blocking_send(chunk).is_err() || failed {
+                return;
+            }
+        });
+        Self {
+            size,
+            chunks,
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// The size of the decompressed content, e.g. for a `Content-Length` header.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl AsyncRead for AsyncEntryReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        loop {
+            if self.position < self.chunk.len() {
+                let length = buf.remaining().min(self.chunk.len() - self.position);
+                let start = self.position;
+                buf.put_slice(&self.chunk[start..start + length]);
+                self.position += length;
+                return Poll::Ready(Ok(()));
+            }
+            match ready!(self.chunks.poll_recv(cx)) {
+                Some(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                // Everything has been read.
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+fn entry_for_key(index: &Index2, key: u64) -> Result<&Index2Entry, LastLegendError> {
+    index.entries.get(&key).ok_or_else(|| {
+        LastLegendError::Custom(format!(
+            "No entry with key {:X} in {}",
+            key,
+            index.index_path.display()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod async_read_tests {
+    use std::io::Cursor;
+
+    use tokio::io::AsyncReadExt;
+
+    use crate::data::async_read::{AsyncEntryReader, CHUNK_SIZE};
+
+    #[test]
+    fn streams_everything_in_order() {
+        let content = (0..CHUNK_SIZE * 5 + 17)
+            .map(|i| (i % 253) as u8)
+            .collect::<Vec<_>>();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let read = runtime.block_on(async {
+            let mut reader =
+                AsyncEntryReader::from_blocking(Cursor::new(content.clone()), content.len() as u64);
+            assert_eq!(reader.size(), content.len() as u64);
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).await.unwrap();
+            read
+        });
+        assert_eq!(read, content);
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::error::LastLegendError;
+use crate::metrics::{self, Metric};
+
+/// An LRU cache of decompressed entry content, bounded by the total size of the content.
+///
+/// Entries are keyed by the index path and the entry key, other content can be cached under
+/// other keys with [get_or_load_key](Self::get_or_load_key).
+#[derive(Debug)]
+pub struct ContentCache<K = CacheKey> {
+    budget_bytes: u64,
+    /// What's cached, for the summary logged when the cache is dropped.
+    name: &'static str,
+    state: Mutex<CacheState<K>>,
+}
+
+/// Entries are keyed by the index path and the entry key.
+type CacheKey = (PathBuf, u64);
+
+#[derive(Debug)]
+struct CacheState<K> {
+    entries: HashMap<K, (Arc<[u8]>, u64)>,
+    /// Entries by when they were last used.
+    by_use: BTreeMap<u64, K>,
+    next_use: u64,
+    bytes: u64,
+    stats: CacheStats,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+impl<K> Default for CacheState<K> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+            next_use: 0,
+            bytes: 0,
+            stats: CacheStats::default(),
+        }
+    }
+}
+
+impl ContentCache {
+    pub fn new(budget_bytes: u64) -> Self {
+        Self::named("Content cache", budget_bytes)
+    }
+
+    /// Get the content for the key, or [load] it and keep it if it fits in the budget.
+    pub fn get_or_load(
+        &self,
+        index_path: &std::path::Path,
+        key: u64,
+        load: impl FnOnce() -> Result<Vec<u8>, LastLegendError>,
+    ) -> Result<Arc<[u8]>, LastLegendError> {
+        let (content, hit) = self.lookup((index_path.to_path_buf(), key), load)?;
+        metrics::global().increment(if hit {
+            Metric::ContentCacheHits
+        } else {
+            Metric::ContentCacheMisses
+        });
+        Ok(content)
+    }
+
+    /// Forget the content for the key, e.g. because the entry was replaced.
+    pub fn remove(&self, index_path: &std::path::Path, key: u64) {
+        let mut state = self.state.lock();
+        let key = (index_path.to_path_buf(), key);
+        if let Some((content, use_id)) = state.entries.remove(&key) {
+            state.by_use.remove(&use_id);
+            state.bytes -= content.len() as u64;
+        }
+    }
+
+    /// Forget the content of every entry of the index at [index_path], e.g. because the index
+    /// changed on disk.
+    pub fn remove_index(&self, index_path: &std::path::Path) {
+        let mut state = self.state.lock();
+        let keys = state
+            .entries
+            .keys()
+            .filter(|(path, _)| path == index_path)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys {
+            if let Some((content, use_id)) = state.entries.remove(&key) {
+                state.by_use.remove(&use_id);
+                state.bytes -= content.len() as u64;
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone> ContentCache<K> {
+    /// A cache of up to [budget_bytes] of content, summarized as [name] when it's dropped.
+    pub fn named(name: &'static str, budget_bytes: u64) -> Self {
+        Self {
+            budget_bytes,
+            name,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().stats
+    }
+
+    /// Like [get_or_load](ContentCache::get_or_load), for content under any [key].
+    pub fn get_or_load_key(
+        &self,
+        key: K,
+        load: impl FnOnce() -> Result<Vec<u8>, LastLegendError>,
+    ) -> Result<Arc<[u8]>, LastLegendError> {
+        self.lookup(key, load).map(|(content, _)| content)
+    }
+
+    /// Get the content for [key], or [load] it, with whether it was cached.
+    fn lookup(
+        &self,
+        key: K,
+        load: impl FnOnce() -> Result<Vec<u8>, LastLegendError>,
+    ) -> Result<(Arc<[u8]>, bool), LastLegendError> {
+        if let Some(content) = self.get(&key) {
+            return Ok((content, true));
+        }
+
+        // Load outside the lock, so other entries can be read meanwhile.
+        let content: Arc<[u8]> = load()?.into();
+        self.insert(key, Arc::clone(&content));
+        Ok((content, false))
+    }
+
+    /// Get the content for [key], if it's cached.
+    pub(crate) fn get(&self, key: &K) -> Option<Arc<[u8]>> {
+        let mut state = self.state.lock();
+        let content = state.touch(key);
+        match content {
+            Some(_) => state.stats.hits += 1,
+            None => state.stats.misses += 1,
+        }
+        content
+    }
+
+    /// Whether [key] is cached, without counting as a use of it.
+    #[cfg(feature = "http")]
+    pub(crate) fn contains(&self, key: &K) -> bool {
+        self.state.lock().entries.contains_key(key)
+    }
+
+    /// Keep [content] under [key] if it fits in the budget, dropping the least recently used
+    /// content to make room.
+    pub(crate) fn insert(&self, key: K, content: Arc<[u8]>) {
+        if content.len() as u64 > self.budget_bytes {
+            return;
+        }
+        let mut state = self.state.lock();
+        state.insert(key, content);
+        while state.bytes > self.budget_bytes {
+            state.evict_oldest();
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone> CacheState<K> {
+    fn touch(&mut self, key: &K) -> Option<Arc<[u8]>> {
+        let use_id = self.next_use;
+        let (content, last_use) = self.entries.get_mut(key)?;
+        let content = Arc::clone(content);
+        let old_use = std::mem::replace(last_use, use_id);
+        self.next_use += 1;
+        let key = self
+            .by_use
+            .remove(&old_use)
+            .expect("entries and by_use must agree");
+        self.by_use.insert(use_id, key);
+        Some(content)
+    }
+
+    fn insert(&mut self, key: K, content: Arc<[u8]>) {
+        // Another thread may have loaded the same entry.
+        if self.entries.contains_key(&key) {
+            return;
+        }
+        let use_id = self.next_use;
+        self.next_use += 1;
+        self.bytes += content.len() as u64;
+        self.by_use.insert(use_id, key.clone());
+        self.entries.insert(key, (content, use_id));
+    }
+
+    fn evict_oldest(&mut self) {
+        let Some((_, key)) = self.by_use.pop_first() else {
+            return;
+        };
+        let (content, _) = self
+            .entries
+            .remove(&key)
+            .expect("entries and by_use must agree");
+        self.bytes -= content.len() as u64;
+        self.stats.evictions += 1;
+    }
+}
+
+impl<K> Drop for ContentCache<K> {
+    fn drop(&mut self) {
+        let stats = self.state.get_mut().stats;
+        log::debug!(
+            "{}: {} hits, {} misses ({:.1}% hit rate), {} evictions",
+            self.name,
+            stats.hits,
+            stats.misses,
+            stats.hit_rate() * 100.0,
+            stats.evictions
+        );
+    }
+}
+
+#[cfg(test)]
+mod content_cache_tests {
+    use std::path::Path;
+
+    use crate::data::content_cache::ContentCache;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = ContentCache::new(8);
+        let path = Path::new("index");
+        let load = |n: u8| move || Ok(vec![n; 4]);
+        cache.get_or_load(path, 1, load(1)).unwrap();
+        cache.get_or_load(path, 2, load(2)).unwrap();
+        // Use 1 again, so 2 is the oldest.
+        cache.get_or_load(path, 1, load(1)).unwrap();
+        cache.get_or_load(path, 3, load(3)).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 3, 1));
+        assert_eq!(*cache.get_or_load(path, 1, load(9)).unwrap(), [1; 4]);
+        assert_eq!(*cache.get_or_load(path, 2, load(9)).unwrap(), [9; 4]);
+    }
+
+    #[test]
+    fn removes_every_entry_of_an_index() {
+        let cache = ContentCache::new(64);
+        let (a, b) = (Path::new("a"), Path::new("b"));
+        let load = |n: u8| move || Ok(vec![n; 4]);
+        cache.get_or_load(a, 1, load(1)).unwrap();
+        cache.get_or_load(a, 2, load(2)).unwrap();
+        cache.get_or_load(b, 1, load(3)).unwrap();
+        cache.remove_index(a);
+
+        assert_eq!(*cache.get_or_load(a, 1, load(9)).unwrap(), [9; 4]);
+        assert_eq!(*cache.get_or_load(b, 1, load(9)).unwrap(), [3; 4]);
+    }
+
+    #[test]
+    fn caches_under_other_keys() {
+        let cache = ContentCache::named("Sheet page cache", 8);
+        let load = |n: u8| move || Ok(vec![n; 4]);
+        cache
+            .get_or_load_key("exd/bgm_0.exd".to_string(), load(1))
+            .unwrap();
+        let page = cache
+            .get_or_load_key("exd/bgm_0.exd".to_string(), load(9))
+            .unwrap();
+
+        assert_eq!(*page, [1; 4]);
+        assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+    }
+}
@@ -1,9 +1,11 @@
 use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
 
-use binrw::{binread, binrw, BinReaderExt};
-use flate2::read::DeflateDecoder;
-
-use crate::io_tricks::ReadMixer;
+use crate::cancel::CancellationToken;
+use crate::data::inflate::{decompressor, Decompressor};
+use crate::error::LastLegendError;
+use crate::limits::Limits;
+use binrw::{binread, binrw, BinRead, BinReaderExt};
 
 // I didn't write a Dat reader, since that's not really needed.
 /// Dat Entry Header reader, find entries using the [Index2].
@@ -18,80 +20,278 @@ pub struct DatEntryHeader {
     #[br(temp)]
     _unknown: u32,
     pub block_size: u32,
+    /// The number of blocks for [ContentType::Binary], or LOD blocks for [ContentType::Texture].
+    /// For [ContentType::Model] this is instead the model version.
     pub num_blocks: u32,
     #[br(args { content_type, num_blocks })]
     blocks: DatEntryHeaderBlocks,
 }
 
 impl DatEntryHeader {
+    pub fn content_type(&self) -> ContentType {
+        self.blocks.content_type()
+    }
+
+    /// The size of the header, which the content follows.
+    pub fn header_size(&self) -> u32 {
+        self.header_size
+    }
+
+    /// Where the content's blocks are, which depends on the content type.
+    pub fn blocks(&self) -> &DatEntryHeaderBlocks {
+        &self.blocks
+    }
+
     /// Given a [reader], positioned at the start of the header, get a new reader for the content.
     pub fn read_content<R: Read + Seek>(
         &self,
         mut reader: R,
     ) -> std::io::Result<DatEntryContent<R>> {
-        let DatEntryHeaderBlocks::Binary(blocks) = &self.blocks;
         let stream_pos = reader.stream_position()?;
         Ok(DatEntryContent {
             inner: reader,
             base_pos: stream_pos + u64::from(self.header_size),
-            block_iter: blocks.iter(),
+            parts: self
+                .blocks
+                .content_parts()
+                .map_err(invalid_data)?
+                .into_iter(),
             buf: None,
+            compressed: Vec::new(),
+            decompressor: decompressor(),
+            cancellation: None,
+            max_block_size: Limits::default().max_block_size,
         })
     }
 
     /// Given a [reader], positioned at the start of the header, read the content to a [Vec].
     pub fn read_content_to_vec<R: Read + Seek>(&self, reader: R) -> std::io::Result<Vec<u8>> {
-        let mut content = Vec::with_capacity(self.uncompressed_size.try_into().unwrap());
-        self.read_content(reader)?.read_to_end(&mut content)?;
-        assert_eq!(
-            usize::try_from(self.uncompressed_size).unwrap(),
-            content.len()
-        );
+        self.read_content_to_vec_with(reader, None, &Limits::default())
+    }
 
+    /// Like [read_content_to_vec](Self::read_content_to_vec), stopping early if [cancellation]
+    /// is cancelled, and failing on blocks larger than [limits] allow.
+    pub fn read_content_to_vec_with<R: Read + Seek>(
+        &self,
+        reader: R,
+        cancellation: Option<CancellationToken>,
+        limits: &Limits,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.read_parts_into(
+            reader,
+            &mut content,
+            &mut Vec::new(),
+            cancellation.as_ref(),
+            limits,
+        )?;
         Ok(content)
     }
+
+    /// Like [read_content_to_vec_with](Self::read_content_to_vec_with), reading into the
+    /// buffers of [scratch] instead of allocating new ones. The content is only valid until
+    /// [scratch] is used again.
+    pub fn read_content_with_scratch<'s, R: Read + Seek>(
+        &self,
+        reader: R,
+        scratch: &'s mut ReadScratch,
+        cancellation: Option<&CancellationToken>,
+        limits: &Limits,
+    ) -> std::io::Result<&'s [u8]> {
+        scratch.trim();
+        self.read_parts_into(
+            reader,
+            &mut scratch.content,
+            &mut scratch.compressed,
+            cancellation,
+            limits,
+        )?;
+        Ok(&scratch.content)
+    }
+
+    /// Read every part of the content into [content], replacing what it held. Unlike going
+    /// through [DatEntryContent], blocks are decompressed straight into [content].
+    fn read_parts_into<R: Read + Seek>(
+        &self,
+        mut reader: R,
+        content: &mut Vec<u8>,
+        compressed: &mut Vec<u8>,
+        cancellation: Option<&CancellationToken>,
+        limits: &Limits,
+    ) -> std::io::Result<()> {
+        let base_pos = reader.stream_position()? + u64::from(self.header_size);
+        let decompressor = decompressor();
+        content.clear();
+        content.reserve(self.uncompressed_size.try_into().unwrap());
+        for part in self.blocks.content_parts().map_err(invalid_data)? {
+            if let Some(cancellation) = cancellation {
+                cancellation.check_io()?;
+            }
+            let start = content.len();
+            match part {
+                ContentPart::Inline(inline) => content.extend_from_slice(&inline),
+                ContentPart::Raw { offset, size } => {
+                    reader.seek(SeekFrom::Start(base_pos + u64::from(offset)))?;
+                    content.resize(start + size as usize, 0);
+                    reader.read_exact(&mut content[start..])?;
+                }
+                ContentPart::Block {
+                    offset,
+                    decompressed_size,
+                } => {
+                    let header = read_block_header(
+                        &mut reader,
+                        base_pos + u64::from(offset),
+                        decompressed_size,
+                        limits.max_block_size,
+                    )?;
+                    content.resize(start + header.decompressed_size() as usize, 0);
+                    read_block_data(
+                        &mut reader,
+                        &header,
+                        compressed,
+                        &*decompressor,
+                        &mut content[start..],
+                    )?;
+                }
+            }
+        }
+        if self.content_type() != ContentType::Empty
+            && usize::try_from(self.uncompressed_size) != Ok(content.len())
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Entry header says it has {} bytes, but its blocks have {}",
+                    self.uncompressed_size,
+                    content.len()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Buffers kept between reads of entries with
+/// [read_content_with_scratch](DatEntryHeader::read_content_with_scratch), so reading many
+/// entries doesn't allocate for each. See the `dat_read` bench.
+#[derive(Debug, Default)]
+pub struct ReadScratch {
+    /// The content of the last entry read.
+    content: Vec<u8>,
+    /// The compressed content of the last block.
+    compressed: Vec<u8>,
+}
+
+impl ReadScratch {
+    /// Buffers larger than this are dropped instead of reused, so one large entry doesn't keep
+    /// its memory for the rest of the run.
+    const MAX_KEPT_BYTES: usize = 16 * 1024 * 1024;
+
+    fn trim(&mut self) {
+        if self.content.capacity() > Self::MAX_KEPT_BYTES {
+            self.content = Vec::new();
+        }
+        if self.compressed.capacity() > Self::MAX_KEPT_BYTES {
+            self.compressed = Vec::new();
+        }
+    }
 }
 
-pub struct DatEntryContent<'a, R> {
+pub struct DatEntryContent<R> {
     inner: R,
     /// Starting position for computing relative offsets.
     base_pos: u64,
-    /// The iterator over the blocks.
-    block_iter: std::slice::Iter<'a, BinaryDatEntryHeaderBlock>,
-    /// The buffer for the last read content block.
+    /// The iterator over the parts of the content.
+    parts: std::vec::IntoIter<ContentPart>,
+    /// The buffer for the last read content part.
     buf: Option<Buffer>,
+    /// The compressed content of the last block, kept to reuse the allocation.
+    compressed: Vec<u8>,
+    decompressor: Arc<dyn Decompressor>,
+    /// Checked before reading each part, to stop reading large entries early.
+    cancellation: Option<CancellationToken>,
+    /// Blocks that decompress to more than this are rejected before allocating for them.
+    max_block_size: u32,
 }
 
-impl<R: Read + Seek> DatEntryContent<'_, R> {
+impl<R: Read + Seek> DatEntryContent<R> {
+    /// Fail reads once [cancellation] is cancelled, checked between blocks.
+    pub fn with_cancellation(mut self, cancellation: Option<CancellationToken>) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Reject blocks that decompress to more than [max_block_size] bytes, see
+    /// [Limits::max_block_size].
+    pub fn with_max_block_size(mut self, max_block_size: u32) -> Self {
+        self.max_block_size = max_block_size;
+        self
+    }
+
     /// Finish using the content reader, and get back the original reader.
     /// The position will not be adjusted.
     pub fn into_inner(self) -> R {
         self.inner
     }
 
-    fn read_block(&mut self, block: &BinaryDatEntryHeaderBlock) -> std::io::Result<()> {
-        self.inner
-            .seek(SeekFrom::Start(self.base_pos + u64::from(block.offset)))?;
-        let header: DataBlockHeader = self
-            .inner
-            .read_le()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-
-        assert_eq!(
-            header.decompressed_size(),
-            block.decompressed_size.into(),
-            "Block headers disagree on decompressed size!"
-        );
-        let base_reader = (&mut self.inner).take(header.source_size().into());
-        let mut reader = if header.is_compressed() {
-            ReadMixer::Wrapped(DeflateDecoder::new(base_reader))
-        } else {
-            ReadMixer::Plain(base_reader)
-        };
+    /// Make sure there's a buffer that can hold at least [capacity] bytes.
+    fn buffer_with_capacity(&mut self, capacity: u32) -> &mut Buffer {
+        // Check if we need a buffer, which includes if the current buffer is too small.
+        if matches!(&self.buf, Some(b) if b.content.len() >= capacity.try_into().unwrap()) {
+            return self.buf.as_mut().unwrap();
+        }
+        self.buf.insert(Buffer::with_capacity(capacity))
+    }
+
+    fn read_part(&mut self, part: ContentPart) -> std::io::Result<()> {
+        match part {
+            ContentPart::Inline(content) => {
+                let limit = content.len();
+                self.buf = Some(Buffer {
+                    content: content.into_boxed_slice(),
+                    pos: 0,
+                    limit,
+                });
+                Ok(())
+            }
+            ContentPart::Raw { offset, size } => {
+                self.inner
+                    .seek(SeekFrom::Start(self.base_pos + u64::from(offset)))?;
+                self.buffer_with_capacity(size);
+
+                let buffer = self.buf.as_mut().unwrap();
+                let limit = size as usize;
+                self.inner.read_exact(&mut buffer.content[0..limit])?;
+                buffer.pos = 0;
+                buffer.limit = limit;
+                Ok(())
+            }
+            ContentPart::Block {
+                offset,
+                decompressed_size,
+            } => self.read_block(offset, decompressed_size),
+        }
+    }
 
+    fn read_block(&mut self, offset: u32, decompressed_size: Option<u32>) -> std::io::Result<()> {
+        let header = read_block_header(
+            &mut self.inner,
+            self.base_pos + u64::from(offset),
+            decompressed_size,
+            self.max_block_size,
+        )?;
+        self.buffer_with_capacity(header.decompressed_size());
         let buffer = self.buf.as_mut().unwrap();
         let limit = header.decompressed_size() as usize;
-        reader.read_exact(&mut buffer.content[0..limit])?;
+        read_block_data(
+            &mut self.inner,
+            &header,
+            &mut self.compressed,
+            &*self.decompressor,
+            &mut buffer.content[0..limit],
+        )?;
         buffer.pos = 0;
         buffer.limit = limit;
 
@@ -99,29 +299,87 @@ impl<R: Read + Seek> DatEntryContent<'_, R> {
     }
 }
 
-impl<R: Read + Seek> Read for DatEntryContent<'_, R> {
+/// Read the header of the block at [pos], checking it against the [decompressed_size] the entry
+/// header gives and [max_block_size].
+fn read_block_header<R: Read + Seek>(
+    reader: &mut R,
+    pos: u64,
+    decompressed_size: Option<u32>,
+    max_block_size: u32,
+) -> std::io::Result<DataBlockHeader> {
+    reader.seek(SeekFrom::Start(pos))?;
+    let header: DataBlockHeader = reader.read_le().map_err(std::io::Error::other)?;
+
+    if let Some(decompressed_size) = decompressed_size {
+        if header.decompressed_size() != decompressed_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Block header says it has {} bytes, but the entry header says {}",
+                    header.decompressed_size(),
+                    decompressed_size
+                ),
+            ));
+        }
+    }
+    if header.decompressed_size() > max_block_size {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Block decompresses to {} bytes, more than max_block_size of {}",
+                header.decompressed_size(),
+                max_block_size
+            ),
+        ));
+    }
+    Ok(header)
+}
+
+/// Report a malformed entry header from a reader of its content, as [Read] only returns I/O
+/// errors.
+fn invalid_data(e: LastLegendError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+/// Read the data of the block with [header], which [reader] is positioned after, into [output],
+/// which is its decompressed size. Compressed data is read into [compressed] first.
+fn read_block_data<R: Read>(
+    reader: &mut R,
+    header: &DataBlockHeader,
+    compressed: &mut Vec<u8>,
+    decompressor: &dyn Decompressor,
+    output: &mut [u8],
+) -> std::io::Result<()> {
+    if header.is_compressed() {
+        compressed.resize(header.source_size() as usize, 0);
+        reader.read_exact(compressed)?;
+        decompressor.decompress(compressed, output)
+    } else {
+        reader.read_exact(output)
+    }
+}
+
+impl<R: Read + Seek> Read for DatEntryContent<R> {
     fn read(&mut self, output_buf: &mut [u8]) -> std::io::Result<usize> {
-        let buf = match &mut self.buf {
-            Some(buf) if buf.can_read() => buf,
-            _ => {
-                let next_block = match self.block_iter.next() {
-                    Some(b) => b,
-                    None => {
-                        // free the buf in advance
-                        self.buf = None;
-                        return Ok(0);
+        // Loop to skip over any parts that turn out to be empty.
+        let buf = loop {
+            match &mut self.buf {
+                Some(buf) if buf.can_read() => break buf,
+                _ => {
+                    if let Some(cancellation) = &self.cancellation {
+                        cancellation.check_io()?;
                     }
-                };
-                // Check if we need a buffer, which includes if the current buffer is too small.
-                if self.buf.is_none()
-                    || matches!(&self.buf, Some(b) if b.content.len() < next_block.decompressed_size.into())
-                {
-                    self.buf = Some(Buffer::with_capacity(next_block.decompressed_size.into()));
+                    let next_part = match self.parts.next() {
+                        Some(p) => p,
+                        None => {
+                            // free the buf in advance
+                            self.buf = None;
+                            return Ok(0);
+                        }
+                    };
+                    // Fill the buffer with the next part
+                    self.read_part(next_part)?;
                 }
-                // Fill the buffer with the next block
-                self.read_block(next_block)?;
-
-                self.buf.as_mut().unwrap()
             }
         };
 
@@ -134,6 +392,22 @@ impl<R: Read + Seek> Read for DatEntryContent<'_, R> {
 
 // TODO: Implement Seek?
 
+/// A piece of the content of an entry, in the order it should be output.
+#[derive(Debug)]
+enum ContentPart {
+    /// Content that is not stored in the dat file, e.g. a reconstructed file header.
+    Inline(Vec<u8>),
+    /// Content that is stored as-is, relative to the end of the entry header.
+    Raw { offset: u32, size: u32 },
+    /// A [DataBlockHeader] and its (possibly compressed) data, relative to the end of the entry
+    /// header.
+    Block {
+        offset: u32,
+        /// The expected decompressed size, if the entry header says what it is.
+        decompressed_size: Option<u32>,
+    },
+}
+
 struct Buffer {
     pub content: Box<[u8]>,
     pub pos: usize,
@@ -162,16 +436,40 @@ impl Buffer {
 #[derive(Debug)]
 #[br(import { content_type: ContentType, num_blocks: u32 })]
 pub enum DatEntryHeaderBlocks {
+    #[br(pre_assert(content_type == ContentType::Empty))]
+    Empty,
     #[br(pre_assert(content_type == ContentType::Binary))]
     Binary(#[br(args { count: num_blocks.try_into().unwrap() })] Vec<BinaryDatEntryHeaderBlock>),
+    #[br(pre_assert(content_type == ContentType::Model))]
+    Model(#[br(args { version: num_blocks })] ModelDatEntryHeaderBlocks),
+    #[br(pre_assert(content_type == ContentType::Texture))]
+    Texture(#[br(args { lod_count: num_blocks })] TextureDatEntryHeaderBlocks),
 }
 
 impl DatEntryHeaderBlocks {
     pub fn content_type(&self) -> ContentType {
         match self {
+            Self::Empty => ContentType::Empty,
             Self::Binary(..) => ContentType::Binary,
+            Self::Model(..) => ContentType::Model,
+            Self::Texture(..) => ContentType::Texture,
         }
     }
+
+    fn content_parts(&self) -> Result<Vec<ContentPart>, LastLegendError> {
+        Ok(match self {
+            Self::Empty => Vec::new(),
+            Self::Binary(blocks) => blocks
+                .iter()
+                .map(|b| ContentPart::Block {
+                    offset: b.offset,
+                    decompressed_size: Some(b.decompressed_size.into()),
+                })
+                .collect(),
+            Self::Model(model) => model.content_parts()?,
+            Self::Texture(texture) => texture.content_parts()?,
+        })
+    }
 }
 
 #[binread]
@@ -182,21 +480,214 @@ pub struct BinaryDatEntryHeaderBlock {
     pub decompressed_size: u16,
 }
 
-const KNOWN_HEADER_SIZE: u32 = 0x10;
+/// The chunks of a model file, each of which is stored as a run of blocks.
+#[binread]
+#[derive(Debug)]
+pub struct ModelChunks<T: for<'a> BinRead<Args<'a> = ()> + Copy + 'static> {
+    pub stack: T,
+    pub runtime: T,
+    pub vertex: [T; 3],
+    pub edge_geometry_vertex: [T; 3],
+    pub index: [T; 3],
+}
+
+impl<T: for<'a> BinRead<Args<'a> = ()> + Copy + 'static> ModelChunks<T> {
+    /// Iterate the chunks in the order they appear in the file.
+    fn in_file_order(&self) -> impl Iterator<Item = T> + '_ {
+        [self.stack, self.runtime]
+            .into_iter()
+            .chain((0..3).flat_map(|lod| {
+                [
+                    self.vertex[lod],
+                    self.edge_geometry_vertex[lod],
+                    self.index[lod],
+                ]
+            }))
+    }
+}
+
+/// Refer to https://github.com/NotAdam/Lumina/blob/40dab50183eb7ddc28344378baccc2d63ae71d35/src/Lumina/Data/SqPackStream.cs#L137
+#[binread]
+#[derive(Debug)]
+#[br(import { version: u32 })]
+pub struct ModelDatEntryHeaderBlocks {
+    #[br(calc = version)]
+    pub version: u32,
+    pub uncompressed_size: ModelChunks<u32>,
+    pub compressed_size: ModelChunks<u32>,
+    pub offset: ModelChunks<u32>,
+    pub block_index: ModelChunks<u16>,
+    pub block_count: ModelChunks<u16>,
+    pub vertex_declaration_count: u16,
+    pub material_count: u16,
+    pub lod_count: u8,
+    pub index_buffer_streaming_enabled: u8,
+    pub edge_geometry_enabled: u8,
+    #[br(temp)]
+    _padding: u8,
+    #[br(args {
+        count: block_count.in_file_order().map(usize::from).sum()
+    })]
+    pub block_sizes: Vec<u16>,
+}
+
+/// Size of the header that is rebuilt at the start of a model file.
+const MODEL_HEADER_SIZE: u32 = 0x44;
+
+impl ModelDatEntryHeaderBlocks {
+    fn content_parts(&self) -> Result<Vec<ContentPart>, LastLegendError> {
+        let mut parts = vec![ContentPart::Inline(self.rebuild_header()?)];
+        for ((offset, index), count) in self
+            .offset
+            .in_file_order()
+            .zip(self.block_index.in_file_order())
+            .zip(self.block_count.in_file_order())
+        {
+            let mut offset = offset;
+            for block in usize::from(index)..usize::from(index) + usize::from(count) {
+                parts.push(ContentPart::Block {
+                    offset,
+                    decompressed_size: None,
+                });
+                let size = self.block_sizes.get(block).ok_or_else(|| {
+                    LastLegendError::Custom(format!(
+                        "Model block {} is past the {} block sizes in the entry header",
+                        block,
+                        self.block_sizes.len()
+                    ))
+                })?;
+                offset = offset.checked_add(u32::from(*size)).ok_or_else(|| {
+                    LastLegendError::Custom("Model block offsets overflow".into())
+                })?;
+            }
+        }
+        Ok(parts)
+    }
+
+    /// The model file starts with a header that isn't stored in the dat, but is derived from the
+    /// sizes of the chunks.
+    fn rebuild_header(&self) -> Result<Vec<u8>, LastLegendError> {
+        let mut vertex_offsets = [0u32; 3];
+        let mut index_offsets = [0u32; 3];
+        let mut vertex_sizes = [0u32; 3];
+        let mut index_sizes = [0u32; 3];
+
+        let advance = |pos: u32, size: u32| {
+            pos.checked_add(size)
+                .ok_or_else(|| LastLegendError::Custom("Model chunk sizes overflow".into()))
+        };
+        let mut pos = advance(MODEL_HEADER_SIZE, self.uncompressed_size.stack)?;
+        pos = advance(pos, self.uncompressed_size.runtime)?;
+        for lod in 0..3 {
+            if self.block_count.vertex[lod] != 0 {
+                // LODs that share data with the previous one have an offset of 0.
+                if lod == 0 || pos != vertex_offsets[lod - 1] {
+                    vertex_offsets[lod] = pos;
+                }
+                vertex_sizes[lod] = self.uncompressed_size.vertex[lod];
+                pos = advance(pos, vertex_sizes[lod])?;
+            }
+            if self.block_count.edge_geometry_vertex[lod] != 0 {
+                pos = advance(pos, self.uncompressed_size.edge_geometry_vertex[lod])?;
+            }
+            if self.block_count.index[lod] != 0 {
+                if lod == 0 || pos != index_offsets[lod - 1] {
+                    index_offsets[lod] = pos;
+                }
+                index_sizes[lod] = self.uncompressed_size.index[lod];
+                pos = advance(pos, index_sizes[lod])?;
+            }
+        }
+
+        let mut header = Vec::with_capacity(MODEL_HEADER_SIZE as usize);
+        header.extend_from_slice(&self.version.to_le_bytes());
+        header.extend_from_slice(&self.uncompressed_size.stack.to_le_bytes());
+        header.extend_from_slice(&self.uncompressed_size.runtime.to_le_bytes());
+        header.extend_from_slice(&self.vertex_declaration_count.to_le_bytes());
+        header.extend_from_slice(&self.material_count.to_le_bytes());
+        for values in [vertex_offsets, index_offsets, vertex_sizes, index_sizes] {
+            for v in values {
+                header.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        header.extend_from_slice(&[
+            self.lod_count,
+            self.index_buffer_streaming_enabled,
+            self.edge_geometry_enabled,
+            0,
+        ]);
+        assert_eq!(header.len(), MODEL_HEADER_SIZE as usize);
+        Ok(header)
+    }
+}
+
+#[binread]
+#[derive(Debug)]
+#[br(import { lod_count: u32 })]
+pub struct TextureDatEntryHeaderBlocks {
+    #[br(args { count: lod_count.try_into().unwrap() })]
+    pub lod_blocks: Vec<TextureLodBlock>,
+    #[br(args {
+        count: lod_blocks.iter().map(|l| usize::try_from(l.block_count).unwrap()).sum()
+    })]
+    pub block_sizes: Vec<u16>,
+}
+
+impl TextureDatEntryHeaderBlocks {
+    fn content_parts(&self) -> Result<Vec<ContentPart>, LastLegendError> {
+        let mut parts = Vec::new();
+        // The texture header is stored uncompressed before the first LOD.
+        if let Some(first) = self.lod_blocks.first().filter(|l| l.compressed_offset != 0) {
+            parts.push(ContentPart::Raw {
+                offset: 0,
+                size: first.compressed_offset,
+            });
+        }
+        let mut sizes = self.block_sizes.iter();
+        for lod in &self.lod_blocks {
+            let mut offset = lod.compressed_offset;
+            for _ in 0..lod.block_count {
+                parts.push(ContentPart::Block {
+                    offset,
+                    decompressed_size: None,
+                });
+                offset = offset
+                    .checked_add(u32::from(*sizes.next().expect("counted above")))
+                    .ok_or_else(|| {
+                        LastLegendError::Custom("Texture block offsets overflow".into())
+                    })?;
+            }
+        }
+        Ok(parts)
+    }
+}
+
+#[binread]
+#[derive(Debug)]
+pub struct TextureLodBlock {
+    pub compressed_offset: u32,
+    pub compressed_size: u32,
+    pub decompressed_size: u32,
+    pub block_offset: u32,
+    pub block_count: u32,
+}
+
+pub(crate) const KNOWN_HEADER_SIZE: u32 = 0x10;
+/// The compressed length of blocks that aren't compressed.
+pub(crate) const NOT_COMPRESSED: u32 = 32_000;
 
 #[binread]
 #[derive(Debug)]
 struct DataBlockHeader {
     #[br(temp, assert(header_size == KNOWN_HEADER_SIZE))]
     header_size: u32,
-    #[br(pad_before = 0x4)]
+    #[br(pad_before = 0x4, assert(compressed_length <= NOT_COMPRESSED))]
     compressed_length: u32,
     decompressed_length: u32,
 }
 
 impl DataBlockHeader {
     pub fn is_compressed(&self) -> bool {
-        const NOT_COMPRESSED: u32 = 32_000;
         if self.compressed_length < NOT_COMPRESSED {
             return true;
         }
@@ -233,3 +724,164 @@ pub enum ContentType {
     Model,
     Texture,
 }
+
+#[cfg(test)]
+mod dat_tests {
+    use std::io::{Cursor, Read};
+
+    use binrw::BinReaderExt;
+
+    use crate::data::dat::{ContentType, DatEntryHeader, ReadScratch};
+    use crate::data::dat_writer::DatEntryWriter;
+    use crate::limits::Limits;
+
+    fn le(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// An uncompressed data block.
+    fn raw_block(content: &[u8]) -> Vec<u8> {
+        let mut block = le(&[0x10, 0, 32_000, content.len() as u32]);
+        block.extend_from_slice(content);
+        block
+    }
+
+    fn texture_entry() -> Vec<u8> {
+        const HEADER_SIZE: usize = 0x80;
+        let mut entry = le(&[HEADER_SIZE as u32, 4, 12, 0, 0, 1]);
+        // One LOD, one block, starting after the 4 byte texture header.
+        entry.extend(le(&[4, 24, 8, 0, 1]));
+        entry.extend_from_slice(&24u16.to_le_bytes());
+        entry.resize(HEADER_SIZE, 0);
+        entry.extend_from_slice(b"TEXH");
+        entry.extend(raw_block(b"ABCDEFGH"));
+        entry
+    }
+
+    /// A model with two chunks, the stack and the runtime, of one block each. [runtime_block] is
+    /// the index of the runtime's block in the block sizes.
+    fn model_entry(runtime_block: u16) -> Vec<u8> {
+        const HEADER_SIZE: usize = 0x100;
+        const VERSION: u32 = 5;
+        let mut entry = le(&[HEADER_SIZE as u32, 3, 0x44 + 16, 0, 0, VERSION]);
+        // Uncompressed sizes, compressed sizes and offsets of the chunks, in file order.
+        entry.extend(le(&[8, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        entry.extend(le(&[24, 24, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        entry.extend(le(&[0, 24, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        // Block indexes and counts.
+        for values in [[0, runtime_block], [1, 1]] {
+            for v in values.into_iter().chain([0; 9]) {
+                entry.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        // Vertex declaration and material counts, then the LOD count and flags.
+        entry.extend_from_slice(&[1, 0, 2, 0, 1, 0, 0, 0]);
+        for size in [24u16, 24] {
+            entry.extend_from_slice(&size.to_le_bytes());
+        }
+        entry.resize(HEADER_SIZE, 0);
+        entry.extend(raw_block(b"STACKDAT"));
+        entry.extend(raw_block(b"RUNTIME!"));
+        entry
+    }
+
+    #[test]
+    fn read_model_entry() {
+        let mut reader = Cursor::new(model_entry(1));
+        let header: DatEntryHeader = reader.read_le().unwrap();
+        assert_eq!(header.content_type(), ContentType::Model);
+        reader.set_position(0);
+        let content = header.read_content_to_vec(reader).unwrap();
+
+        assert_eq!(content.len(), 0x44 + 16);
+        // The rebuilt header starts with the version, then the stack and runtime sizes.
+        assert_eq!(content[..12], le(&[5, 8, 8]));
+        assert_eq!(&content[0x44..], b"STACKDATRUNTIME!");
+    }
+
+    #[test]
+    fn model_blocks_past_the_block_sizes_are_rejected() {
+        let mut reader = Cursor::new(model_entry(7));
+        let header: DatEntryHeader = reader.read_le().unwrap();
+        reader.set_position(0);
+        let error = header
+            .read_content_to_vec(reader)
+            .expect_err("there are only 2 block sizes");
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn overflowing_model_chunk_sizes_are_rejected() {
+        let mut entry = model_entry(1);
+        // The uncompressed size of the stack.
+        entry[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = Cursor::new(entry);
+        let header: DatEntryHeader = reader.read_le().unwrap();
+        reader.set_position(0);
+        let error = header
+            .read_content_to_vec(reader)
+            .expect_err("the rebuilt header's offsets overflow");
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn overflowing_texture_offsets_are_rejected() {
+        let mut entry = texture_entry();
+        // The compressed offset of the only LOD.
+        entry[24..28].copy_from_slice(&(u32::MAX - 4).to_le_bytes());
+        let mut reader = Cursor::new(entry);
+        let header: DatEntryHeader = reader.read_le().unwrap();
+        reader.set_position(0);
+        let error = header
+            .read_content_to_vec(reader)
+            .expect_err("the block offsets overflow");
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_texture_entry() {
+        let mut reader = Cursor::new(texture_entry());
+        let header: DatEntryHeader = reader.read_le().unwrap();
+        assert_eq!(header.content_type(), ContentType::Texture);
+        reader.set_position(0);
+        let content = header.read_content_to_vec(reader).unwrap();
+        assert_eq!(content, b"TEXHABCDEFGH");
+    }
+
+    #[test]
+    fn scratch_is_reused_between_entries() {
+        let binary = b"binary ".repeat(5_000);
+        let entries = [
+            texture_entry(),
+            DatEntryWriter::new().encode(&binary).unwrap(),
+            texture_entry(),
+        ];
+        let mut scratch = ReadScratch::default();
+        let mut contents = Vec::new();
+        for entry in entries {
+            let mut reader = Cursor::new(entry);
+            let header: DatEntryHeader = reader.read_le().unwrap();
+            reader.set_position(0);
+            let content = header
+                .read_content_with_scratch(reader, &mut scratch, None, &Limits::default())
+                .unwrap();
+            contents.push(content.to_vec());
+        }
+        let expected: [&[u8]; 3] = [b"TEXHABCDEFGH", &binary, b"TEXHABCDEFGH"];
+        assert_eq!(contents, expected);
+    }
+
+    #[test]
+    fn blocks_over_the_limit_are_rejected() {
+        let mut reader = Cursor::new(texture_entry());
+        let header: DatEntryHeader = reader.read_le().unwrap();
+        reader.set_position(0);
+        let error = header
+            .read_content(reader)
+            .unwrap()
+            .with_max_block_size(4)
+            .read_to_end(&mut Vec::new())
+            .expect_err("the 8 byte block is over the limit");
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
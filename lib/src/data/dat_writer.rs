@@ -0,0 +1,151 @@
+//! Writing entries into dat files, for replacing files in a repository.
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::data::dat::{ContentType, KNOWN_HEADER_SIZE, NOT_COMPRESSED};
+use crate::error::LastLegendError;
+
+/// Entries, entry headers and blocks all start on multiples of this.
+pub const ALIGNMENT: u64 = 0x80;
+/// The most content a single block holds before compression.
+const MAX_BLOCK_CONTENT: usize = 16_000;
+/// The size of the fixed part of the entry header, before the block table.
+const ENTRY_HEADER_FIXED_SIZE: usize = 6 * 4;
+/// The size of each block's entry in the block table.
+const BLOCK_TABLE_ENTRY_SIZE: usize = 4 + 2 + 2;
+
+/// Encodes content as a [ContentType::Binary] dat entry, the same way the game stores most files.
+///
+/// Models and textures are stored with their own layouts, which this doesn't write.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DatEntryWriter {
+    compression: Compression,
+}
+
+impl DatEntryWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encode [content] as a complete entry: the entry header followed by its blocks. Blocks that
+    /// don't get smaller when compressed are stored as-is. The result is padded to [ALIGNMENT].
+    pub fn encode(&self, content: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut blocks = Vec::new();
+        let mut table = Vec::new();
+        for chunk in content.chunks(MAX_BLOCK_CONTENT) {
+            let offset = blocks.len();
+            self.write_block(chunk, &mut blocks)?;
+            table.push((offset, blocks.len() - offset, chunk.len()));
+        }
+
+        let header_size = align(ENTRY_HEADER_FIXED_SIZE + table.len() * BLOCK_TABLE_ENTRY_SIZE);
+        let mut entry = Vec::with_capacity(header_size + blocks.len());
+        // The block data size, in units of the alignment, is given twice.
+        let block_units = (blocks.len() as u64 / ALIGNMENT) as u32;
+        for value in [
+            header_size as u32,
+            ContentType::Binary as u32,
+            u32::try_from(content.len()).map_err(std::io::Error::other)?,
+            block_units,
+            block_units,
+            table.len() as u32,
+        ] {
+            entry.extend_from_slice(&value.to_le_bytes());
+        }
+        for (offset, size, decompressed_size) in table {
+            entry.extend_from_slice(&(offset as u32).to_le_bytes());
+            entry.extend_from_slice(&(size as u16).to_le_bytes());
+            entry.extend_from_slice(&(decompressed_size as u16).to_le_bytes());
+        }
+        entry.resize(header_size, 0);
+        entry.extend_from_slice(&blocks);
+        Ok(entry)
+    }
+
+    /// Write a block header and [chunk] to [output], padded to [ALIGNMENT].
+    fn write_block(&self, chunk: &[u8], output: &mut Vec<u8>) -> std::io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), self.compression);
+        encoder.write_all(chunk)?;
+        let compressed = encoder.finish()?;
+        let (compressed_length, data) = if compressed.len() < chunk.len() {
+            (compressed.len() as u32, compressed.as_slice())
+        } else {
+            (NOT_COMPRESSED, chunk)
+        };
+
+        let start = output.len();
+        for value in [KNOWN_HEADER_SIZE, 0, compressed_length, chunk.len() as u32] {
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+        output.extend_from_slice(data);
+        output.resize(start + align(output.len() - start), 0);
+        Ok(())
+    }
+}
+
+fn align(size: usize) -> usize {
+    size.next_multiple_of(ALIGNMENT as usize)
+}
+
+/// Append an [entry] from [DatEntryWriter::encode] to the end of the dat file at [dat_path],
+/// returning the offset it was written at.
+///
+/// The dat file's own header isn't updated, so its recorded size and hashes no longer match.
+pub fn append_entry(dat_path: &Path, entry: &[u8]) -> Result<u64, LastLegendError> {
+    let mut dat = OpenOptions::new()
+        .write(true)
+        .open(dat_path)
+        .map_err(|e| LastLegendError::Io(format!("Couldn't open {}", dat_path.display()), e))?;
+    let end = dat
+        .seek(SeekFrom::End(0))
+        .map_err(|e| LastLegendError::Io("Couldn't seek to the end of the dat".into(), e))?;
+    let offset = end.next_multiple_of(ALIGNMENT);
+    dat.write_all(&vec![0u8; (offset - end) as usize])
+        .and_then(|_| dat.write_all(entry))
+        .and_then(|_| dat.flush())
+        .map_err(|e| LastLegendError::Io(format!("Couldn't write to {}", dat_path.display()), e))?;
+    Ok(offset)
+}
+
+#[cfg(test)]
+mod dat_writer_tests {
+    use std::io::Cursor;
+
+    use binrw::BinReaderExt;
+
+    use crate::data::dat::{ContentType, DatEntryHeader};
+    use crate::data::dat_writer::DatEntryWriter;
+
+    fn round_trip(content: &[u8]) {
+        let entry = DatEntryWriter::new().encode(content).unwrap();
+        assert_eq!(entry.len() % 0x80, 0);
+        let mut reader = Cursor::new(entry);
+        let header: DatEntryHeader = reader.read_le().unwrap();
+        assert_eq!(header.content_type(), ContentType::Binary);
+        reader.set_position(0);
+        assert_eq!(header.read_content_to_vec(reader).unwrap(), content);
+    }
+
+    #[test]
+    fn encoded_entries_read_back() {
+        round_trip(b"");
+        round_trip(b"short");
+        // Compresses well, across several blocks.
+        round_trip(&b"repeat ".repeat(10_000));
+        // Doesn't compress, so blocks are stored as-is.
+        let mut state = 0x1234_5678u32;
+        let noise = (0..40_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+        round_trip(&noise);
+    }
+}
@@ -0,0 +1,120 @@
+//! Reading the version files patches leave next to the repository.
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::data::source::DatSource;
+use crate::sqpath::ALL_EXPANSIONS;
+
+/// The versions of the base game and each installed expansion, see
+/// [Repository::game_version](crate::data::repo::Repository::game_version).
+///
+/// Displays as the base version followed by each expansion's, e.g.
+/// `2024.07.10.0000.0000 ex1:2024.07.06.0000.0000`.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct GameVersion {
+    /// The version from `ffxivgame.ver`, next to the `sqpack` folder.
+    pub base: Option<String>,
+    /// The versions from each `sqpack/exN/exN.ver`, by expansion folder name, e.g. `ex1`.
+    pub expansions: BTreeMap<String, String>,
+}
+
+impl GameVersion {
+    /// Read the versions of the repository at [repo_path], the `sqpack` folder, from [source].
+    /// Missing or unreadable version files are left out.
+    pub fn read(source: &dyn DatSource, repo_path: &Path) -> Self {
+        let base = repo_path
+            .parent()
+            .and_then(|game| read_version_file(source, &game.join("ffxivgame.ver")));
+        // Sources that can't list directories are checked for the expansions this crate knows.
+        let folders = match source.list_dir(repo_path) {
+            Ok(entries) => entries
+                .into_iter()
+                .filter(|(_, is_dir)| *is_dir)
+                .filter_map(|(path, _)| path.file_name()?.to_str().map(str::to_string))
+                .collect(),
+            Err(_) => ALL_EXPANSIONS
+                .iter()
+                .map(|e| e.as_str().to_string())
+                .collect::<Vec<_>>(),
+        };
+        let expansions = folders
+            .into_iter()
+            .filter(|name| name.starts_with("ex"))
+            .filter_map(|name| {
+                let ver_file = repo_path.join(&name).join(format!("{}.ver", name));
+                Some((name, read_version_file(source, &ver_file)?))
+            })
+            .collect();
+        Self { base, expansions }
+    }
+
+    /// The version of [expansion], e.g. `ex1`, or the base version for `ffxiv`.
+    pub fn for_expansion(&self, expansion: &str) -> Option<&str> {
+        match expansion {
+            "ffxiv" => self.base.as_deref(),
+            _ => self.expansions.get(expansion).map(String::as_str),
+        }
+    }
+
+    /// Whether no version file could be read.
+    pub fn is_unknown(&self) -> bool {
+        self.base.is_none() && self.expansions.is_empty()
+    }
+}
+
+impl Display for GameVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.base.as_deref().unwrap_or("unknown"))?;
+        for (expansion, version) in &self.expansions {
+            write!(f, " {}:{}", expansion, version)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_version_file(source: &dyn DatSource, path: &Path) -> Option<String> {
+    if !source.exists(path) {
+        return None;
+    }
+    let mut version = String::new();
+    if let Err(e) = source
+        .open(path)
+        .and_then(|mut reader| reader.read_to_string(&mut version))
+    {
+        log::warn!("Couldn't read {}: {}", path.display(), e);
+        return None;
+    }
+    Some(version.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod game_version_tests {
+    use crate::data::source::LocalSource;
+
+    use super::*;
+
+    #[test]
+    fn reads_base_and_expansion_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let sqpack = dir.path().join("sqpack");
+        std::fs::create_dir_all(sqpack.join("ex1")).unwrap();
+        std::fs::create_dir_all(sqpack.join("ex2")).unwrap();
+        std::fs::create_dir_all(sqpack.join("ffxiv")).unwrap();
+        std::fs::write(dir.path().join("ffxivgame.ver"), "2024.07.10.0000.0000\r\n").unwrap();
+        std::fs::write(sqpack.join("ex1/ex1.ver"), "2024.07.06.0000.0000").unwrap();
+
+        let version = GameVersion::read(&LocalSource, &sqpack);
+        assert_eq!(version.for_expansion("ffxiv"), Some("2024.07.10.0000.0000"));
+        assert_eq!(version.for_expansion("ex1"), Some("2024.07.06.0000.0000"));
+        assert_eq!(version.for_expansion("ex2"), None);
+        assert_eq!(
+            version.to_string(),
+            "2024.07.10.0000.0000 ex1:2024.07.06.0000.0000"
+        );
+        assert!(GameVersion::read(&LocalSource, &sqpack.join("ffxiv")).is_unknown());
+    }
+}
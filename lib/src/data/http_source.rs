@@ -0,0 +1,322 @@
+//! Reading repositories from a web server, with HTTP range requests.
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::data::content_cache::ContentCache;
+use crate::data::source::{DatReader, DatSource};
+
+/// Files are fetched and cached in blocks of this size.
+const BLOCK_SIZE: u64 = 64 * 1024;
+/// How many blocks to fetch at once. Entries are read from start to end, so the blocks after the
+/// one needed are likely to be needed next, and one request is much faster than several.
+const READ_AHEAD_BLOCKS: u64 = 8;
+/// How much fetched data to keep in memory by default.
+pub const DEFAULT_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Reads a repository from a copy of its `sqpack` directory on a web server, such as a mirror,
+/// fetching only the parts of files that are read. The server must support range requests.
+///
+/// Paths under [root] are fetched from the same path under [base_url], so make the
+/// [Repository](crate::data::repo::Repository) with [root] as its path. Fetched blocks are kept in
+/// memory, shared between readers, so reading the same index or nearby entries again is cheap.
+#[derive(Debug, Clone)]
+pub struct HttpSource {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    agent: ureq::Agent,
+    base_url: String,
+    root: PathBuf,
+    /// File sizes by URL, or `None` for files that don't exist.
+    sizes: Mutex<HashMap<String, Option<u64>>>,
+    /// Fetched blocks by URL and block number.
+    blocks: ContentCache<(Arc<str>, u64)>,
+}
+
+impl HttpSource {
+    /// Read files under [root] from [base_url], keeping up to [cache_bytes] of them in memory.
+    pub fn new(base_url: &str, root: PathBuf, cache_bytes: u64) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                agent: ureq::AgentBuilder::new()
+                    .timeout(Duration::from_secs(60))
+                    .build(),
+                base_url: base_url.trim_end_matches('/').to_string(),
+                root,
+                sizes: Mutex::new(HashMap::new()),
+                blocks: ContentCache::named("HTTP block cache", cache_bytes),
+            }),
+        }
+    }
+}
+
+impl DatSource for HttpSource {
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn DatReader>> {
+        let url: Arc<str> = self.inner.url_for(path)?.into();
+        let size = self.inner.size_of(&url)?.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found", url))
+        })?;
+        Ok(Box::new(HttpReader {
+            inner: Arc::clone(&self.inner),
+            url,
+            size,
+            position: 0,
+        }))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner
+            .url_for(path)
+            .and_then(|url| self.inner.size_of(&url))
+            .is_ok_and(|size| size.is_some())
+    }
+}
+
+impl Inner {
+    fn url_for(&self, path: &Path) -> std::io::Result<String> {
+        let relative = path.strip_prefix(&self.root).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} isn't in the repository", path.display()),
+            )
+        })?;
+        let mut url = self.base_url.clone();
+        for component in relative.components() {
+            let Component::Normal(name) = component else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} isn't a plain path", path.display()),
+                ));
+            };
+            url.push('/');
+            url.push_str(&name.to_string_lossy());
+        }
+        Ok(url)
+    }
+
+    /// The size of the file at [url], or `None` if it doesn't exist. Asked for once per file.
+    fn size_of(&self, url: &str) -> std::io::Result<Option<u64>> {
+        if let Some(&size) = self.sizes.lock().get(url) {
+            return Ok(size);
+        }
+        let size = match self.agent.head(url).call() {
+            Ok(response) => Some(
+                response
+                    .header("Content-Length")
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .ok_or_else(|| {
+                        std::io::Error::other(format!("{} has no Content-Length", url))
+                    })?,
+            ),
+            Err(ureq::Error::Status(404 | 410, _)) => None,
+            Err(e) => return Err(std::io::Error::other(e)),
+        };
+        self.sizes.lock().insert(url.to_string(), size);
+        Ok(size)
+    }
+
+    /// Get [block] of the [size] byte file at [url], fetching it and the blocks after it that
+    /// aren't cached yet if needed.
+    fn block(&self, url: &Arc<str>, block: u64, size: u64) -> std::io::Result<Arc<[u8]>> {
+        if let Some(data) = self.blocks.get(&(Arc::clone(url), block)) {
+            return Ok(data);
+        }
+        let block_count = size.div_ceil(BLOCK_SIZE);
+        let mut end_block = block + 1;
+        while end_block < block_count
+            && end_block - block < READ_AHEAD_BLOCKS
+            && !self.blocks.contains(&(Arc::clone(url), end_block))
+        {
+            end_block += 1;
+        }
+
+        let start = block * BLOCK_SIZE;
+        let end = (end_block * BLOCK_SIZE).min(size);
+        log::debug!("Fetching bytes {}-{} of {}", start, end - 1, url);
+        let response = self
+            .agent
+            .get(url.as_ref())
+            .set("Range", &format!("bytes={}-{}", start, end - 1))
+            .call()
+            .map_err(std::io::Error::other)?;
+        if response.status() != 206 {
+            return Err(std::io::Error::other(format!(
+                "{} didn't answer a range request, got status {}",
+                url,
+                response.status()
+            )));
+        }
+        let mut data = Vec::with_capacity((end - start) as usize);
+        response
+            .into_reader()
+            .take(end - start)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 != end - start {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{} ended early", url),
+            ));
+        }
+
+        let mut first = None;
+        for (i, chunk) in data.chunks(BLOCK_SIZE as usize).enumerate() {
+            let chunk: Arc<[u8]> = Arc::from(chunk);
+            first.get_or_insert_with(|| Arc::clone(&chunk));
+            self.blocks
+                .insert((Arc::clone(url), block + i as u64), chunk);
+        }
+        Ok(first.expect("At least one block is fetched"))
+    }
+}
+
+/// Reads a single file, a block at a time.
+struct HttpReader {
+    inner: Arc<Inner>,
+    url: Arc<str>,
+    size: u64,
+    position: u64,
+}
+
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() || self.position >= self.size {
+            return Ok(0);
+        }
+        let block = self
+            .inner
+            .block(&self.url, self.position / BLOCK_SIZE, self.size)?;
+        let start = (self.position % BLOCK_SIZE) as usize;
+        let length = buf.len().min(block.len() - start);
+        buf[..length].copy_from_slice(&block[start..start + length]);
+        self.position += length as u64;
+        Ok(length)
+    }
+}
+
+impl Seek for HttpReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "can't seek before the start of the file",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod http_source_tests {
+    use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use crate::data::http_source::{HttpSource, BLOCK_SIZE};
+    use crate::data::source::DatSource;
+
+    /// Serve [content] at `/sqpack/ffxiv/file.dat0`, answering HEAD and range requests.
+    fn serve(content: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut range = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = value.trim().split_once('-').unwrap();
+                        range = Some((
+                            start.parse::<usize>().unwrap(),
+                            end.parse::<usize>().unwrap(),
+                        ));
+                    }
+                }
+                let response = if !request.contains(" /sqpack/ffxiv/file.dat0 ") {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+                        .as_bytes()
+                        .to_vec()
+                } else if request.starts_with("HEAD") {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                        content.len()
+                    )
+                    .into_bytes()
+                } else {
+                    let (start, end) = range.unwrap();
+                    let mut response = format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\r\n",
+                        end + 1 - start
+                    )
+                    .into_bytes();
+                    response.extend_from_slice(&content[start..=end]);
+                    response
+                };
+                stream.write_all(&response).unwrap();
+            }
+        });
+        format!("http://{}/sqpack", address)
+    }
+
+    #[test]
+    fn reads_ranges() {
+        let content = (0..BLOCK_SIZE * 3 + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let source = HttpSource::new(&serve(content.clone()), PathBuf::from("repo"), 1 << 20);
+        let path = Path::new("repo/ffxiv/file.dat0");
+        assert!(source.exists(path));
+        assert!(!source.exists(Path::new("repo/ffxiv/missing.dat0")));
+
+        let mut reader = source.open(path).unwrap();
+        let offset = BLOCK_SIZE * 2 - 10;
+        reader.seek(SeekFrom::Start(offset)).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, content[offset as usize..]);
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), content.len() as u64);
+    }
+
+    #[test]
+    fn keeps_recently_read_blocks() {
+        let content = (0..BLOCK_SIZE * 20)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let base_url = serve(content);
+        // Room for one read ahead and one more block.
+        let source = HttpSource::new(&base_url, PathBuf::from("repo"), BLOCK_SIZE * 9);
+        let mut reader = source.open(Path::new("repo/ffxiv/file.dat0")).unwrap();
+        let mut byte = [0];
+        for block in [0, 0, 8] {
+            reader.seek(SeekFrom::Start(block * BLOCK_SIZE)).unwrap();
+            reader.read_exact(&mut byte).unwrap();
+        }
+
+        let url: Arc<str> = format!("{}/ffxiv/file.dat0", base_url).into();
+        let cached = |block| source.inner.blocks.contains(&(Arc::clone(&url), block));
+        // Block 0 was read again after blocks 1 to 7, so they're dropped first.
+        assert!(cached(0));
+        assert!((1..8).all(|block| !cached(block)));
+        assert!((8..16).all(cached));
+    }
+}
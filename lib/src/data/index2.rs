@@ -1,36 +1,108 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufReader, Seek};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use binrw::{binread, helpers::count_with, io::SeekFrom, BinReaderExt};
-use bitvec::prelude::*;
+use serde::Serialize;
 
+use crate::cancel::CancellationToken;
+use crate::data::content_cache::ContentCache;
 use crate::data::index_header::IndexHeader;
 use crate::data::pack_header::PackHeader;
+use crate::data::source::{DatReader, DatSource, FileStamp, LocalSource};
 use crate::error::LastLegendError;
-use crate::sqpath::SqPath;
+use crate::limits::Limits;
+use crate::sqpath::{PathHasher, SqPath};
+use crate::warning::WarningSink;
+
+/// The format of an index file. Installs normally have both, but some partial installs only have
+/// one of them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IndexFormat {
+    /// `.index2` files, where entries are found by the hash of the full path.
+    Index2,
+    /// `.index` files, where entries are found by the hashes of the folder and file name.
+    Index,
+}
+
+impl IndexFormat {
+    /// Get the format of the index file at [index_path], by its extension.
+    pub fn of_path(index_path: &Path) -> Self {
+        match index_path.extension().and_then(|e| e.to_str()) {
+            Some("index") => Self::Index,
+            _ => Self::Index2,
+        }
+    }
+
+    pub(crate) fn entry_size(self) -> usize {
+        match self {
+            // Hash + info
+            Self::Index2 => 4 + 4,
+            // File hash + folder hash + info + padding
+            Self::Index => 4 + 4 + 4 + 4,
+        }
+    }
+}
 
 #[binread]
 #[derive(Debug)]
-#[br(import { index_path: PathBuf })]
+#[br(import {
+    index_path: PathBuf,
+    hasher: PathHasher,
+    content_cache: Option<Arc<ContentCache>>,
+    source: Arc<dyn DatSource>,
+    limits: Limits,
+    stamp: Option<FileStamp>,
+})]
 #[brw(little)]
 pub struct Index2 {
+    #[br(calc = IndexFormat::of_path(&index_path))]
+    pub format: IndexFormat,
     #[br(calc = index_path)]
     pub index_path: PathBuf,
+    /// The hash used to look up paths in this index.
+    #[br(calc = hasher)]
+    pub hasher: PathHasher,
+    /// Cache for decompressed content, shared with the repository this was loaded from.
+    #[br(calc = content_cache)]
+    pub content_cache: Option<Arc<ContentCache>>,
+    /// Where the index and its dat files are read from.
+    #[br(calc = source)]
+    pub source: Arc<dyn DatSource>,
+    /// Applied when reading entries, from the repository this was loaded from.
+    #[br(calc = limits)]
+    pub limits: Limits,
+    /// The size and modification time of the index file when this was loaded, if the source can
+    /// tell.
+    #[br(calc = stamp)]
+    pub stamp: Option<FileStamp>,
+    /// Checked when reading entries, shared with the repository this was loaded from.
+    #[br(default)]
+    pub cancellation: Option<CancellationToken>,
+    /// Where to report non-fatal problems, shared with the repository this was loaded from.
+    #[br(default)]
+    pub warnings: WarningSink,
     pub pack_header: PackHeader,
+    #[br(assert(
+        index_header.index_data_size.0 / format.entry_size() <= limits.max_index_entries as usize,
+        "index has {} entries, more than max_index_entries of {}",
+        index_header.index_data_size.0 / format.entry_size(),
+        limits.max_index_entries
+    ))]
     pub index_header: IndexHeader,
     #[br(
         seek_before = SeekFrom::Start(index_header.index_data_offset.into()),
         parse_with = count_with(
-            index_header.index_data_size.0 / ENTRY_SIZE,
-            |reader, ro, args| {
-                let entry = Index2Entry::read_options(reader, ro, args)?;
-                Ok((entry.hash, entry))
+            index_header.index_data_size.0 / format.entry_size(),
+            |reader, ro, _: ()| {
+                let entry = Index2Entry::read_options(reader, ro, (format,))?;
+                Ok((entry.key(), entry))
             },
         ),
     )]
-    pub entries: HashMap<u32, Index2Entry>,
+    pub entries: HashMap<u64, Index2Entry>,
 }
 
 impl Index2 {
@@ -48,9 +120,38 @@ impl Index2 {
     }
 
     pub fn load_from_path<P: AsRef<Path>>(index_path: P) -> Result<Self, LastLegendError> {
+        Self::load_from_path_with(index_path, PathHasher::default(), None)
+    }
+
+    pub fn load_from_path_with<P: AsRef<Path>>(
+        index_path: P,
+        hasher: PathHasher,
+        content_cache: Option<Arc<ContentCache>>,
+    ) -> Result<Self, LastLegendError> {
+        Self::load_from_source(
+            Arc::new(LocalSource),
+            index_path,
+            hasher,
+            content_cache,
+            Limits::default(),
+        )
+    }
+
+    /// Load the index at [index_path] from [source], which its dat files are read from too.
+    /// Fails if it has more entries than [limits] allow.
+    pub fn load_from_source<P: AsRef<Path>>(
+        source: Arc<dyn DatSource>,
+        index_path: P,
+        hasher: PathHasher,
+        content_cache: Option<Arc<ContentCache>>,
+        limits: Limits,
+    ) -> Result<Self, LastLegendError> {
         let index_path = index_path.as_ref();
+        // Taken first, so a change while reading counts as a change since loading.
+        let stamp = source.stamp(index_path);
         let mut reader = BufReader::new(
-            File::open(index_path)
+            source
+                .open(index_path)
                 .map_err(|e| LastLegendError::Io("Couldn't open reader".into(), e))?,
         );
 
@@ -58,6 +159,11 @@ impl Index2 {
             .read_le_args::<Index2>(
                 Index2BinReadArgs::builder()
                     .index_path(index_path.to_path_buf())
+                    .hasher(hasher)
+                    .content_cache(content_cache)
+                    .source(source)
+                    .limits(limits)
+                    .stamp(stamp)
                     .finalize(),
             )
             .map_err(|e| LastLegendError::BinRW("Couldn't read Index2".into(), e))
@@ -70,50 +176,347 @@ impl Index2 {
     /// Get an entry for a [file].
     pub fn get_entry<F: AsRef<SqPath>>(&self, file: F) -> Result<&Index2Entry, LastLegendError> {
         let file = file.as_ref();
-        self.entries.get(&file.sq_index_hash()).ok_or_else(|| {
+        let key = match self.format {
+            IndexFormat::Index2 => u64::from(file.sq_index_hash_with(&self.hasher)),
+            IndexFormat::Index => {
+                let (folder_hash, file_hash) = file.sq_folder_file_hash_with(&self.hasher);
+                Index2Entry::split_key(folder_hash, file_hash)
+            }
+        };
+        self.entries.get(&key).ok_or_else(|| {
             LastLegendError::MissingEntryFromIndex(file.to_owned(), self.index_path.clone())
         })
     }
 
     /// Given the [file] you want, open a reader and position it so it's ready to read a
     /// [DatEntryHeader] for the file.
-    pub fn open_reader<F: AsRef<SqPath>>(&self, file: F) -> Result<File, LastLegendError> {
+    pub fn open_reader<F: AsRef<SqPath>>(
+        &self,
+        file: F,
+    ) -> Result<Box<dyn DatReader>, LastLegendError> {
         self.open_reader_for_entry(self.get_entry(file)?)
     }
 
-    pub fn open_reader_for_entry(&self, entry: &Index2Entry) -> Result<File, LastLegendError> {
-        let path = self
-            .index_path
-            .parent()
-            .expect("index path must have a parent")
-            .join(
-                self.index_path
-                    .file_name()
-                    .expect("index path must have a file name")
-                    .to_string_lossy()
-                    .replace(".index2", &format!(".dat{}", entry.data_file_id)),
-            );
-        let mut reader =
-            File::open(path).map_err(|e| LastLegendError::Io("Couldn't open reader".into(), e))?;
+    pub fn open_reader_for_entry(
+        &self,
+        entry: &Index2Entry,
+    ) -> Result<Box<dyn DatReader>, LastLegendError> {
+        let mut reader = self
+            .source
+            .open(&self.dat_path(entry.data_file_id))
+            .map_err(|e| LastLegendError::Io("Couldn't open reader".into(), e))?;
         reader
             .seek(SeekFrom::Start(entry.offset_bytes))
             .map_err(|e| LastLegendError::Io("Couldn't seek into reader".into(), e))?;
         Ok(reader)
     }
+
+    /// Whether the index file's size or modification time changed since this was loaded from it.
+    /// False if the source can't tell, see [DatSource::stamp].
+    pub fn changed_since_loaded(&self) -> bool {
+        self.stamp
+            .is_some_and(|stamp| self.source.stamp(&self.index_path) != Some(stamp))
+    }
+
+    /// Get the path of the dat file for [data_file_id], next to this index.
+    pub fn dat_path(&self, data_file_id: u32) -> PathBuf {
+        self.index_path
+            .with_extension(format!("dat{}", data_file_id))
+    }
+
+    /// Point the entry for [file] at [offset] in the dat file [data_file_id], by rewriting it in
+    /// the index file on disk. This index isn't changed, so reload it to see the new location.
+    pub fn set_entry_location<F: AsRef<SqPath>>(
+        &self,
+        file: F,
+        data_file_id: u32,
+        offset: u64,
+    ) -> Result<(), LastLegendError> {
+        self.locate_entry(file)?.set_location(data_file_id, offset)
+    }
+
+    /// Find the entry for [file] in the index file on disk, opened for writing, so its location
+    /// can be changed with [IndexEntrySlot::set_location]. Fails if the file can't be opened for
+    /// writing, or no longer has the entry.
+    pub fn locate_entry<F: AsRef<SqPath>>(
+        &self,
+        file: F,
+    ) -> Result<IndexEntrySlot, LastLegendError> {
+        let entry = self.get_entry(&file)?;
+        let key = entry.key();
+        let io_error =
+            |e| LastLegendError::Io(format!("Couldn't update {}", self.index_path.display()), e);
+
+        let mut index_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.index_path)
+            .map_err(io_error)?;
+        let data_offset = u64::from(self.index_header.index_data_offset);
+        let mut data = vec![0u8; self.index_header.index_data_size.0];
+        index_file
+            .seek(SeekFrom::Start(data_offset))
+            .and_then(|_| index_file.read_exact(&mut data))
+            .map_err(io_error)?;
+
+        let entry_size = self.format.entry_size();
+        let read_u32 =
+            |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let position = data
+            .chunks_exact(entry_size)
+            .enumerate()
+            .find_map(|(i, raw)| {
+                let raw_key = match self.format {
+                    IndexFormat::Index2 => u64::from(read_u32(raw, 0)),
+                    IndexFormat::Index => {
+                        Index2Entry::split_key(read_u32(raw, 4), read_u32(raw, 0))
+                    }
+                };
+                (raw_key == key).then_some(i * entry_size)
+            })
+            .ok_or_else(|| {
+                LastLegendError::Custom(format!(
+                    "{} no longer has an entry for '{}', it was changed since it was loaded",
+                    self.index_path.display(),
+                    file.as_ref().as_str()
+                ))
+            })?;
+        let info_position = match self.format {
+            IndexFormat::Index2 => position + 4,
+            IndexFormat::Index => position + 8,
+        };
+        Ok(IndexEntrySlot {
+            index_path: self.index_path.clone(),
+            index_file,
+            position: data_offset + info_position as u64,
+            reserved: entry.reserved_bit_set(),
+        })
+    }
+
+    /// Hashes of entries with the reserved bit of their [PackedInfo] set, which means the index
+    /// uses a layout this doesn't know about, and their locations may be wrong.
+    pub fn reserved_bit_entries(&self) -> Vec<u32> {
+        let mut hashes = self
+            .entries()
+            .filter(|e| e.reserved_bit_set())
+            .map(|e| e.hash)
+            .collect::<Vec<_>>();
+        hashes.sort_unstable();
+        hashes
+    }
+
+    /// Group the entries by the dat file they're in, each sorted by offset.
+    pub fn entries_by_dat(&self) -> BTreeMap<u32, Vec<&Index2Entry>> {
+        let mut by_dat = BTreeMap::<u32, Vec<&Index2Entry>>::new();
+        for entry in self.entries() {
+            by_dat.entry(entry.data_file_id).or_default().push(entry);
+        }
+        for entries in by_dat.values_mut() {
+            entries.sort_by_key(|e| e.offset_bytes);
+        }
+        by_dat
+    }
+
+    /// Summarize each dat file referenced by this index, checking entries against the dat size.
+    /// A dat file that doesn't exist has no size, and all of its entries are out of bounds.
+    pub fn dat_summaries(&self) -> Result<Vec<DatSummary>, LastLegendError> {
+        self.entries_by_dat()
+            .into_iter()
+            .map(|(data_file_id, entries)| {
+                let dat_size = match self
+                    .source
+                    .open(&self.dat_path(data_file_id))
+                    .and_then(|mut dat| dat.seek(SeekFrom::End(0)))
+                {
+                    Ok(size) => Some(size),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => {
+                        return Err(LastLegendError::Io("Couldn't find the dat size".into(), e))
+                    }
+                };
+                Ok(DatSummary {
+                    data_file_id,
+                    dat_size,
+                    entry_count: entries.len(),
+                    max_offset: entries.last().map_or(0, |e| e.offset_bytes),
+                    out_of_bounds: entries
+                        .iter()
+                        .filter(|e| dat_size.is_none_or(|size| e.offset_bytes >= size))
+                        .map(|e| e.hash)
+                        .collect(),
+                })
+            })
+            .collect()
+    }
 }
 
-// Hash + info
-const ENTRY_SIZE: usize = 4 + 4;
+/// Information about one of the dat files an index spills over into.
+#[derive(Debug, Serialize)]
+pub struct DatSummary {
+    pub data_file_id: u32,
+    /// The size of the dat file, if it exists.
+    pub dat_size: Option<u64>,
+    pub entry_count: usize,
+    pub max_offset: u64,
+    /// Hashes of entries that start past the end of the dat file.
+    pub out_of_bounds: Vec<u32>,
+}
 
 #[binread]
 #[derive(Debug)]
 #[brw(little)]
+#[br(import(format: IndexFormat))]
 pub struct Index2Entry {
+    /// The hash of the full path, or for [IndexFormat::Index], of the file name.
     pub hash: u32,
-    #[br(temp, map = BitArray::new)]
-    packed_info: BitArray<u32, Lsb0>,
-    #[br(calc = packed_info[1..4].load_le::<u32>())]
+    /// The hash of the folder, only present for [IndexFormat::Index].
+    #[br(if(format == IndexFormat::Index))]
+    pub folder_hash: Option<u32>,
+    /// The location of the entry as stored in the index, see [PackedInfo].
+    #[br(pad_after = if format == IndexFormat::Index { 4 } else { 0 })]
+    pub packed_info: u32,
+    #[br(calc = PackedInfo::decode(packed_info).data_file_id)]
     pub data_file_id: u32,
-    #[br(calc = (u64::from(packed_info[4..].load_le::<u32>())) << 7)]
+    #[br(calc = PackedInfo::decode(packed_info).offset_bytes)]
     pub offset_bytes: u64,
 }
+
+/// The location of an entry, as packed into 32 bits in index files:
+///
+/// | Bits    | Meaning                                         |
+/// |---------|-------------------------------------------------|
+/// | 0       | Reserved, 0 in every known version of the game  |
+/// | 1 to 3  | The dat file the entry is in                    |
+/// | 4 to 31 | The offset in the dat file, in 128 byte units   |
+///
+/// If a patch starts using the reserved bit, or more dat files than fit in 3 bits, entries will
+/// have the reserved bit set, which [Index2::reserved_bit_entries] and `verify` report.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+pub struct PackedInfo {
+    pub reserved: bool,
+    pub data_file_id: u32,
+    pub offset_bytes: u64,
+}
+
+impl PackedInfo {
+    /// How many dat files an index can point into.
+    pub const MAX_DATA_FILES: u32 = 1 << 3;
+    /// Offsets are stored in units of this many bytes.
+    pub const OFFSET_ALIGNMENT: u64 = 0x80;
+
+    pub fn decode(raw: u32) -> Self {
+        Self {
+            reserved: raw & 1 != 0,
+            data_file_id: (raw >> 1) & 0b111,
+            offset_bytes: u64::from(raw >> 4) * Self::OFFSET_ALIGNMENT,
+        }
+    }
+
+    /// Pack this location, failing if it can't be represented.
+    pub fn encode(&self) -> Result<u32, LastLegendError> {
+        let offset_units = self.offset_bytes / Self::OFFSET_ALIGNMENT;
+        if self.data_file_id >= Self::MAX_DATA_FILES
+            || !self.offset_bytes.is_multiple_of(Self::OFFSET_ALIGNMENT)
+            || offset_units >= 1 << 28
+        {
+            return Err(LastLegendError::Custom(format!(
+                "Can't point an index entry at 0x{:X} in dat{}",
+                self.offset_bytes, self.data_file_id
+            )));
+        }
+        Ok(u32::from(self.reserved) | (self.data_file_id << 1) | ((offset_units as u32) << 4))
+    }
+}
+
+impl Index2Entry {
+    pub(crate) fn split_key(folder_hash: u32, file_hash: u32) -> u64 {
+        (u64::from(folder_hash) << 32) | u64::from(file_hash)
+    }
+
+    /// Whether the reserved bit of [Self::packed_info] is set, which no known game version does.
+    pub fn reserved_bit_set(&self) -> bool {
+        PackedInfo::decode(self.packed_info).reserved
+    }
+
+    /// A key that is unique within the index, combining the hashes.
+    pub fn key(&self) -> u64 {
+        match self.folder_hash {
+            Some(folder_hash) => Self::split_key(folder_hash, self.hash),
+            None => u64::from(self.hash),
+        }
+    }
+}
+
+/// Where an entry's location is stored in an index file on disk, found with
+/// [Index2::locate_entry].
+#[derive(Debug)]
+pub struct IndexEntrySlot {
+    index_path: PathBuf,
+    index_file: File,
+    /// Where the entry's [PackedInfo] is in the file.
+    position: u64,
+    reserved: bool,
+}
+
+impl IndexEntrySlot {
+    pub fn index_path(&self) -> &Path {
+        &self.index_path
+    }
+
+    /// Point the entry at [offset] in the dat file [data_file_id].
+    pub fn set_location(mut self, data_file_id: u32, offset: u64) -> Result<(), LastLegendError> {
+        // Keep the reserved bit as it was.
+        let packed_info = PackedInfo {
+            reserved: self.reserved,
+            data_file_id,
+            offset_bytes: offset,
+        }
+        .encode()?;
+        self.index_file
+            .seek(SeekFrom::Start(self.position))
+            .and_then(|_| self.index_file.write_all(&packed_info.to_le_bytes()))
+            .and_then(|_| self.index_file.flush())
+            .map_err(|e| {
+                LastLegendError::Io(format!("Couldn't update {}", self.index_path.display()), e)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_packed_info() {
+        let info = PackedInfo::decode(0x1234_5676);
+        assert_eq!(
+            info,
+            PackedInfo {
+                reserved: false,
+                data_file_id: 3,
+                offset_bytes: 0x123_4567 * 0x80,
+            }
+        );
+        assert!(PackedInfo::decode(0x1234_5677).reserved);
+    }
+
+    #[test]
+    fn packed_info_round_trips() {
+        for raw in [0, 1, 0b1110, 0xFFFF_FFFF, 0x1234_5677] {
+            assert_eq!(PackedInfo::decode(raw).encode().unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn rejects_unpackable_locations() {
+        let info = |data_file_id, offset_bytes| PackedInfo {
+            reserved: false,
+            data_file_id,
+            offset_bytes,
+        };
+        assert!(info(8, 0).encode().is_err());
+        assert!(info(0, 0x81).encode().is_err());
+        assert!(info(0, 1 << 35).encode().is_err());
+        assert!(info(7, (1 << 35) - 0x80).encode().is_ok());
+    }
+}
@@ -0,0 +1,108 @@
+//! Decompressing dat blocks, which is most of the CPU time spent dumping raw files.
+//!
+//! The decompressor in use is picked with [set_decompressor], and defaults to
+//! [Flate2Decompressor]. Which deflate implementation that uses is picked at build time, with the
+//! `zlib-rs` feature.
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use flate2::{Decompress, FlushDecompress};
+use parking_lot::RwLock;
+
+/// Inflates the raw deflate streams in compressed dat blocks.
+pub trait Decompressor: Debug + Send + Sync {
+    /// A name for reports, e.g. the library doing the work.
+    fn name(&self) -> &str;
+
+    /// Inflate [input], a raw deflate stream without a zlib header, filling all of [output].
+    /// Any content past the end of [output] is ignored.
+    fn decompress(&self, input: &[u8], output: &mut [u8]) -> std::io::Result<()>;
+}
+
+static DECOMPRESSOR: RwLock<Option<Arc<dyn Decompressor>>> = RwLock::new(None);
+
+/// The decompressor dat blocks are read with.
+pub fn decompressor() -> Arc<dyn Decompressor> {
+    DECOMPRESSOR
+        .read()
+        .clone()
+        .unwrap_or_else(|| Arc::new(Flate2Decompressor))
+}
+
+/// Read dat blocks with [decompressor] from now on, for the whole process. Readers that are
+/// already open keep the one they started with.
+pub fn set_decompressor(decompressor: Arc<dyn Decompressor>) {
+    *DECOMPRESSOR.write() = Some(decompressor);
+}
+
+/// Uses flate2, with miniz_oxide, or zlib-rs when built with the `zlib-rs` feature.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Flate2Decompressor;
+
+thread_local! {
+    /// Reused between blocks, setting up the inflate state shows up in profiles otherwise.
+    static INFLATE_STATE: RefCell<Decompress> = RefCell::new(Decompress::new(false));
+}
+
+impl Decompressor for Flate2Decompressor {
+    fn name(&self) -> &str {
+        if cfg!(feature = "zlib-rs") {
+            "flate2 (zlib-rs)"
+        } else {
+            "flate2 (miniz_oxide)"
+        }
+    }
+
+    fn decompress(&self, input: &[u8], output: &mut [u8]) -> std::io::Result<()> {
+        INFLATE_STATE.with_borrow_mut(|state| {
+            state.reset(false);
+            let status = state
+                .decompress(input, output, FlushDecompress::Finish)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            let written = state.total_out() as usize;
+            if written < output.len() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "Block inflated to {} bytes, expected {} ({:?})",
+                        written,
+                        output.len(),
+                        status
+                    ),
+                ));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod inflate_tests {
+    use std::io::Write;
+
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    #[test]
+    fn inflates_whole_and_truncated_blocks() {
+        let content = (0..20_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&content).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut output = vec![0; content.len()];
+        Flate2Decompressor
+            .decompress(&compressed, &mut output)
+            .unwrap();
+        assert_eq!(output, content);
+
+        let mut too_large = vec![0; content.len() + 1];
+        let error = Flate2Decompressor
+            .decompress(&compressed, &mut too_large)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,113 @@
+//! Reading local repositories through memory maps.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use parking_lot::Mutex;
+
+use crate::data::source::{DatReader, DatSource, FileStamp, LocalSource};
+
+/// Reads files from the local filesystem by mapping them into memory, so reading an entry
+/// doesn't need a system call per block. Each file is mapped once and shared by every reader,
+/// which helps most when reading many small entries, like sheet pages.
+///
+/// Files are re-mapped when their size or modification time changes, e.g. after
+/// [Repository::replace_file](crate::data::repo::Repository::replace_file) appends to a dat file.
+/// Other programs must not truncate the files while they're mapped.
+#[derive(Debug, Default)]
+pub struct MmapSource {
+    maps: Mutex<HashMap<PathBuf, MappedFile>>,
+}
+
+#[derive(Debug)]
+struct MappedFile {
+    stamp: FileStamp,
+    map: Arc<Mmap>,
+}
+
+/// A shared map, readable through a [Cursor].
+struct SharedMap(Arc<Mmap>);
+
+impl AsRef<[u8]> for SharedMap {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl MmapSource {
+    fn map(&self, path: &Path) -> std::io::Result<Arc<Mmap>> {
+        let file = File::open(path)?;
+        let stamp = FileStamp::from(&file.metadata()?);
+        let mut maps = self.maps.lock();
+        if let Some(mapped) = maps.get(path) {
+            if mapped.stamp == stamp {
+                return Ok(Arc::clone(&mapped.map));
+            }
+        }
+        // SAFETY: the map is only read, and re-made when the file changes size. Files being
+        // truncated by other programs while mapped is documented as unsupported.
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        maps.insert(
+            path.to_path_buf(),
+            MappedFile {
+                stamp,
+                map: Arc::clone(&map),
+            },
+        );
+        Ok(map)
+    }
+}
+
+impl DatSource for MmapSource {
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn DatReader>> {
+        Ok(Box::new(Cursor::new(SharedMap(self.map(path)?))))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        LocalSource.exists(path)
+    }
+
+    fn list_dir(&self, directory: &Path) -> std::io::Result<Vec<(PathBuf, bool)>> {
+        LocalSource.list_dir(directory)
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn stamp(&self, path: &Path) -> Option<FileStamp> {
+        LocalSource.stamp(path)
+    }
+}
+
+#[cfg(test)]
+mod mmap_source_tests {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    use super::*;
+
+    #[test]
+    fn remaps_changed_files() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"0123").unwrap();
+        let source = MmapSource::default();
+        let mut reader = source.open(file.path()).unwrap();
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "23");
+
+        file.write_all(b"4567").unwrap();
+        file.flush().unwrap();
+        let mut content = String::new();
+        source
+            .open(file.path())
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "01234567");
+    }
+}
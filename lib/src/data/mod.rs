@@ -1,5 +1,17 @@
+#[cfg(feature = "async")]
+pub mod async_read;
+pub mod content_cache;
 pub mod dat;
+pub mod dat_writer;
+pub mod game_version;
+#[cfg(feature = "http")]
+pub mod http_source;
 pub mod index2;
 pub mod index_header;
+pub mod inflate;
+#[cfg(feature = "mmap")]
+pub mod mmap_source;
 pub mod pack_header;
 pub mod repo;
+pub mod source;
+pub mod verify;
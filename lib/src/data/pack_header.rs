@@ -2,7 +2,8 @@ use std::fmt::{Debug, Formatter};
 use std::io::{Read, Seek, Write};
 
 use binrw::{binrw, BinRead, BinResult, BinWrite, Endian};
-use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+use chrono::{DateTime, Datelike, SecondsFormat, TimeZone, Timelike, Utc};
+use serde::Serialize;
 
 use crate::tricks::U32Size;
 
@@ -36,6 +37,31 @@ pub struct PackHeader {
     pub timestamp: SqPackTimestamp,
 }
 
+impl PackHeader {
+    /// The fields worth reporting, e.g. to spot index files left behind by a partial patch.
+    pub fn info(&self) -> PackInfo {
+        PackInfo {
+            platform: format!("{:?}", self.platform_id),
+            content_type: format!("{:?}", self.content_type),
+            version: self.version,
+            timestamp: self
+                .timestamp
+                .as_datetime()
+                .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        }
+    }
+}
+
+/// A summary of a [PackHeader], for reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackInfo {
+    pub platform: String,
+    pub content_type: String,
+    pub version: u32,
+    /// When the file was built, in RFC 3339 format, if the header says.
+    pub timestamp: Option<String>,
+}
+
 #[binrw]
 #[derive(Debug)]
 #[brw(repr(u32))]
@@ -63,6 +89,13 @@ pub enum SqPackTimestamp {
 }
 
 impl SqPackTimestamp {
+    pub fn as_datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Present(d) => Some(*d),
+            Self::Missing => None,
+        }
+    }
+
     fn from_raw(date: u32, time: u32) -> Self {
         if date == 0 || time == 0 {
             return Self::Missing;
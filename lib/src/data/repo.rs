@@ -4,16 +4,39 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use parking_lot::{RwLock, RwLockUpgradableReadGuard};
+use serde::Serialize;
 
-use crate::data::index2::Index2;
+use crate::cancel::CancellationToken;
+use crate::data::content_cache::ContentCache;
+use crate::data::dat::ContentType;
+use crate::data::dat_writer::{append_entry, DatEntryWriter};
+use crate::data::game_version::GameVersion;
+use crate::data::index2::{Index2, Index2Entry, IndexFormat};
+use crate::data::pack_header::PackInfo;
+use crate::data::source::{DatSource, LocalSource};
 use crate::error::LastLegendError;
-use crate::sqpath::SqPath;
+use crate::limits::Limits;
+use crate::simple_task::read_file_entry_header;
+use crate::sqpath::{Expansion, FileType, PathHasher, SqPath};
+use crate::warning::{Warning, WarningSink};
 
 /// Entry point for loading FFXIV data.
 /// This is best to use at a high level, as it caches the data from disk.
+///
+/// A repository can fall back to others for files it doesn't have, see
+/// [with_fallback](Self::with_fallback), so an override, like a benchmark client or a mod
+/// preview, can be read on top of the main game data.
 #[derive(Debug, Clone)]
 pub struct Repository {
     repo_path: PathBuf,
+    /// Repositories to look in, in order, for files this one doesn't have.
+    fallbacks: Vec<Repository>,
+    hasher: PathHasher,
+    content_cache: Option<Arc<ContentCache>>,
+    cancellation: Option<CancellationToken>,
+    warnings: WarningSink,
+    limits: Limits,
+    source: Arc<dyn DatSource>,
     state: Arc<RwLock<RepoState>>,
 }
 
@@ -21,29 +44,364 @@ impl Repository {
     pub fn new(repo_path: PathBuf) -> Self {
         Self {
             repo_path,
-            state: Arc::new(RwLock::new(RepoState {
-                indexes: HashMap::new(),
-            })),
+            fallbacks: Vec::new(),
+            hasher: PathHasher::default(),
+            content_cache: None,
+            cancellation: None,
+            warnings: WarningSink::default(),
+            limits: Limits::default(),
+            source: Arc::new(LocalSource),
+            state: Arc::default(),
         }
     }
 
+    /// Open the repositories at [top], then each of [fallbacks] in order, as one, see
+    /// [with_fallback](Self::with_fallback).
+    pub fn layered(top: PathBuf, fallbacks: impl IntoIterator<Item = PathBuf>) -> Self {
+        fallbacks.into_iter().fold(Self::new(top), |repo, path| {
+            repo.with_fallback(Self::new(path))
+        })
+    }
+
+    /// Look in [fallback] for files this repository, and its fallbacks so far, don't have. A file
+    /// is read from the first repository whose index has an entry for it.
+    ///
+    /// The hasher, content cache, cancellation and limits set on this repository from now on apply
+    /// to fallbacks too, but the source doesn't, so fallbacks can be read from elsewhere.
+    pub fn with_fallback(mut self, mut fallback: Repository) -> Self {
+        let deeper = std::mem::take(&mut fallback.fallbacks);
+        self.fallbacks.push(fallback);
+        self.fallbacks.extend(deeper);
+        self
+    }
+
+    /// The repositories this one falls back to, in the order they're searched.
+    pub fn fallbacks(&self) -> &[Repository] {
+        &self.fallbacks
+    }
+
+    /// Read index and dat files from [source] instead of the local filesystem, e.g. an
+    /// [HttpSource](crate::data::http_source::HttpSource). Any indexes loaded so far are dropped.
+    pub fn with_source(mut self, source: Arc<dyn DatSource>) -> Self {
+        self.source = source;
+        self.state = Arc::default();
+        self
+    }
+
+    /// Read the local index and dat files through memory maps, see
+    /// [MmapSource](crate::data::mmap_source::MmapSource). Any indexes loaded so far are dropped.
+    #[cfg(feature = "mmap")]
+    pub fn with_mmap(self) -> Self {
+        self.with_source(Arc::new(crate::data::mmap_source::MmapSource::default()))
+    }
+
+    /// Use a non-standard [hasher] to look up paths. Any indexes loaded so far are dropped.
+    pub fn with_hasher(mut self, hasher: PathHasher) -> Self {
+        self.hasher = hasher;
+        self.state = Arc::default();
+        self.fallbacks = self
+            .fallbacks
+            .into_iter()
+            .map(|f| f.with_hasher(hasher))
+            .collect();
+        self
+    }
+
+    /// Keep up to [budget_bytes] of decompressed content in memory, for workloads that read the
+    /// same entries repeatedly. Any indexes loaded so far are dropped.
+    pub fn with_content_cache(mut self, budget_bytes: u64) -> Self {
+        let cache = Arc::new(ContentCache::new(budget_bytes));
+        // Entries are cached by index path, so fallbacks can share the budget.
+        for fallback in &mut self.fallbacks {
+            fallback.content_cache = Some(Arc::clone(&cache));
+            fallback.state = Arc::default();
+        }
+        self.content_cache = Some(cache);
+        self.state = Arc::default();
+        self
+    }
+
+    /// Stop reading entries once [cancellation] is cancelled. Any indexes loaded so far are
+    /// dropped.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.fallbacks = self
+            .fallbacks
+            .into_iter()
+            .map(|f| f.with_cancellation(cancellation.clone()))
+            .collect();
+        self.cancellation = Some(cancellation);
+        self.state = Arc::default();
+        self
+    }
+
+    /// Report non-fatal problems to [warnings] instead of logging them. Any indexes loaded so far
+    /// are dropped.
+    pub fn with_warnings(mut self, warnings: WarningSink) -> Self {
+        self.fallbacks = self
+            .fallbacks
+            .into_iter()
+            .map(|f| f.with_warnings(warnings.clone()))
+            .collect();
+        self.warnings = warnings;
+        self.state = Arc::default();
+        self
+    }
+
+    /// Cap the sizes read from this repository's files at [limits]. Any indexes loaded so far are
+    /// dropped.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self.state = Arc::default();
+        self.fallbacks = self
+            .fallbacks
+            .into_iter()
+            .map(|f| f.with_limits(limits))
+            .collect();
+        self
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    pub fn warnings(&self) -> &WarningSink {
+        &self.warnings
+    }
+
+    pub fn content_cache(&self) -> Option<&ContentCache> {
+        self.content_cache.as_deref()
+    }
+
+    pub fn source(&self) -> &Arc<dyn DatSource> {
+        &self.source
+    }
+
     pub fn repo_path(&self) -> &Path {
         &self.repo_path
     }
 
+    pub fn hasher(&self) -> &PathHasher {
+        &self.hasher
+    }
+
+    /// Get the index for [file_name], loading it if needed. The `.index2` file is used if it
+    /// exists, otherwise the `.index` file. The one used is the [Index2::index_path].
+    ///
+    /// With fallbacks, this is the index of the first repository that has an entry for the file.
+    /// If none do, it's the first index that could be loaded, or this repository's error.
     pub fn get_index_for<F: AsRef<SqPath>>(
         &self,
         file_name: F,
     ) -> Result<Arc<Index2>, LastLegendError> {
-        let file_name = file_name.as_ref().to_owned();
-        let index_path = file_name
-            .sqpack_index_path(&self.repo_path)
+        let file_name = file_name.as_ref();
+        if self.fallbacks.is_empty() {
+            return self.own_index_for(file_name);
+        }
+        let mut first_index = None;
+        let mut first_error = None;
+        for repo in std::iter::once(self).chain(&self.fallbacks) {
+            match repo.own_index_for(file_name) {
+                Ok(index) if index.get_entry(file_name).is_ok() => return Ok(index),
+                Ok(index) => {
+                    first_index.get_or_insert(index);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_index {
+            Some(index) => Ok(index),
+            None => Err(first_error.expect("there's always this repository")),
+        }
+    }
+
+    fn own_index_for(&self, file_name: &SqPath) -> Result<Arc<Index2>, LastLegendError> {
+        let candidates = file_name
+            .sqpack_index_paths(&self.repo_path)
             .ok_or_else(|| LastLegendError::InvalidSqPath(file_name.as_str().to_string()))?;
 
+        let index_path = {
+            let state = self.state.read();
+            candidates
+                .iter()
+                .find(|p| state.indexes.contains_key(p.as_path()))
+                .or_else(|| candidates.iter().find(|p| self.source.exists(p)))
+                // Neither exists, so let loading fail on the preferred one.
+                .unwrap_or(&candidates[0])
+                .clone()
+        };
         self.load_index_file(index_path.into())
     }
 
+    /// Find every index file in the repository, sorted. The `.index2` file is used if it exists,
+    /// otherwise the `.index` file.
+    ///
+    /// With fallbacks, each fallback's index files follow, so files a fallback has too are listed
+    /// by both.
+    pub fn index_files(&self) -> Result<Vec<PathBuf>, LastLegendError> {
+        let mut index_files = self.own_index_files()?;
+        for fallback in &self.fallbacks {
+            index_files.extend(fallback.own_index_files()?);
+        }
+        Ok(index_files)
+    }
+
+    fn own_index_files(&self) -> Result<Vec<PathBuf>, LastLegendError> {
+        let mut index_files = Vec::new();
+        let mut directories = vec![self.repo_path.clone()];
+        while let Some(directory) = directories.pop() {
+            let entries = self.source.list_dir(&directory).map_err(|e| {
+                LastLegendError::Io(format!("Couldn't list {}", directory.display()), e)
+            })?;
+            for (path, is_dir) in entries {
+                if is_dir {
+                    directories.push(path);
+                    continue;
+                }
+                match path.extension().and_then(|e| e.to_str()) {
+                    Some("index2") => index_files.push(path),
+                    Some("index") if !self.source.exists(&path.with_extension("index2")) => {
+                        index_files.push(path)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        index_files.sort();
+        Ok(index_files)
+    }
+
+    /// Replace the content of [file] with [content], so the game and this crate read the new
+    /// content instead.
+    ///
+    /// The content is written as a new entry at the end of the dat file the old one is in, and
+    /// both the `.index` and `.index2` files are updated to point at it, if they exist. The old
+    /// content is left in place, unreferenced. Only files stored as plain binary entries can be
+    /// replaced, not models or textures. Only this repository is changed, never its fallbacks.
+    ///
+    /// Both index files are checked before anything is written. If writing to one still fails,
+    /// the error says which index files already point at the new content.
+    pub fn replace_file<F: AsRef<SqPath>>(
+        &self,
+        file: F,
+        content: &[u8],
+    ) -> Result<ReplacedEntry, LastLegendError> {
+        let file = file.as_ref();
+        if !self.source.is_local() {
+            return Err(LastLegendError::Custom(
+                "Only files in local repositories can be replaced".into(),
+            ));
+        }
+        let candidates = file
+            .sqpack_index_paths(&self.repo_path)
+            .ok_or_else(|| LastLegendError::InvalidSqPath(file.as_str().to_string()))?;
+        let indexes = candidates
+            .iter()
+            .filter(|p| p.exists())
+            .map(|p| Index2::load_from_path_with(p, self.hasher, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some(primary) = indexes.first() else {
+            // Neither exists, so let loading fail on the preferred one.
+            return Err(Index2::load_from_path(&candidates[0]).unwrap_err());
+        };
+        // Check every index has the file, and can be written to, before writing anything.
+        let data_file_id = primary.get_entry(file)?.data_file_id;
+        let slots = indexes
+            .iter()
+            .map(|index| index.locate_entry(file))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (header, _) = read_file_entry_header(primary, file)?;
+        if !matches!(
+            header.content_type(),
+            ContentType::Binary | ContentType::Empty
+        ) {
+            return Err(LastLegendError::Custom(format!(
+                "'{}' is stored as {:?} content, only binary content can be replaced",
+                file.as_str(),
+                header.content_type()
+            )));
+        }
+
+        let entry = DatEntryWriter::new()
+            .encode(content)
+            .map_err(|e| LastLegendError::Io("Couldn't encode the new content".into(), e))?;
+        let offset = append_entry(&primary.dat_path(data_file_id), &entry)?;
+        let mut state = self.state.write();
+        let mut updated = Vec::new();
+        for (index, slot) in indexes.iter().zip(slots) {
+            let result = slot.set_location(data_file_id, offset);
+            state.indexes.remove(&index.index_path);
+            if let Some(cache) = &self.content_cache {
+                cache.remove(&index.index_path, index.get_entry(file)?.key());
+            }
+            if let Err(e) = result {
+                return Err(match updated.as_slice() {
+                    [] => e,
+                    _ => e.add_context(format!(
+                        "Only {} points at the new content of '{}'",
+                        updated.join(" and "),
+                        file.as_str()
+                    )),
+                });
+            }
+            updated.push(index.index_path.display().to_string());
+        }
+
+        Ok(ReplacedEntry {
+            data_file_id,
+            offset,
+            entry_size: entry.len() as u64,
+            index_files: indexes.into_iter().map(|i| i.index_path).collect(),
+        })
+    }
+
+    /// The versions of the base game and expansions, from the version files patches leave next
+    /// to the repository. Read once, then kept.
+    pub fn game_version(&self) -> GameVersion {
+        if let Some(version) = &self.state.read().game_version {
+            return version.clone();
+        }
+        let version = GameVersion::read(self.source.as_ref(), &self.repo_path);
+        self.state.write().game_version = Some(version.clone());
+        version
+    }
+
+    /// The repository, of this one and its fallbacks, that [path] is in: the one with the
+    /// longest matching path, or this one if none match.
+    fn owner_of(&self, path: &Path) -> &Repository {
+        std::iter::once(self)
+            .chain(&self.fallbacks)
+            // Reversed, so the first of equally long matches wins.
+            .rev()
+            .filter(|repo| path.starts_with(&repo.repo_path))
+            .max_by_key(|repo| repo.repo_path.components().count())
+            .unwrap_or(self)
+    }
+
+    /// Describe every index file in the repository: the category of files it holds, and its pack
+    /// header.
+    pub fn categories(&self) -> Result<Vec<CategoryInfo>, LastLegendError> {
+        self.index_files()?
+            .into_iter()
+            .map(|path| {
+                let index = self.load_index_file(Cow::Borrowed(&path))?;
+                Ok(CategoryInfo::new(&self.owner_of(&path).repo_path, &index))
+            })
+            .collect()
+    }
+
+    /// Load the index file at [index_path], or get it if it's loaded already. With fallbacks, it's
+    /// loaded by the repository it's in.
     pub fn load_index_file(&self, index_path: Cow<Path>) -> Result<Arc<Index2>, LastLegendError> {
+        let owner = self.owner_of(&index_path);
+        if !std::ptr::eq(owner, self) {
+            return owner.load_index_file(index_path);
+        }
         // Pass one: check with read lock.
         {
             let state = self.state.read();
@@ -58,16 +416,166 @@ impl Repository {
             return Ok(Arc::clone(v));
         }
         // Pass three: load it under upgradable read lock, and then write lock to save it.
-        let index2 = Arc::new(Index2::load_from_path(&index_path)?);
+        if IndexFormat::of_path(&index_path) == IndexFormat::Index {
+            log::debug!("Loading {} in the .index format", index_path.display());
+        }
+        let mut index2 = Index2::load_from_source(
+            Arc::clone(&self.source),
+            &index_path,
+            self.hasher,
+            self.content_cache.clone(),
+            self.limits,
+        )?;
+        index2.cancellation = self.cancellation.clone();
+        index2.warnings = self.warnings.clone();
+        let index2 = Arc::new(index2);
         let mut state = RwLockUpgradableReadGuard::upgrade(state);
         state
             .indexes
             .insert(index_path.into_owned(), Arc::clone(&index2));
         Ok(index2)
     }
+
+    /// Drop the index file at [index_path] and any content cached from it, so it's loaded again
+    /// the next time it's needed. With fallbacks, it's dropped by the repository it's in.
+    pub fn invalidate_index(&self, index_path: &Path) {
+        let owner = self.owner_of(index_path);
+        owner.state.write().indexes.remove(index_path);
+        if let Some(cache) = &owner.content_cache {
+            cache.remove_index(index_path);
+        }
+    }
+
+    /// Run [read] on [entry] of [index]. If reading from the dat files fails, see
+    /// [may_be_stale_index](LastLegendError::may_be_stale_index), and the index file changed since
+    /// it was loaded, the index is reloaded, and if the entry moved since, [read] is run once more
+    /// on the reloaded index. This keeps long sessions working when the game is patched under
+    /// them. Sources that can't tell when files change, see [DatSource::stamp], aren't retried.
+    pub fn retry_if_stale<T>(
+        &self,
+        index: &Arc<Index2>,
+        entry: &Index2Entry,
+        mut read: impl FnMut(&Arc<Index2>, &Index2Entry) -> Result<T, LastLegendError>,
+    ) -> Result<T, LastLegendError> {
+        let error = match read(index, entry) {
+            Err(e) if e.may_be_stale_index() && index.changed_since_loaded() => e,
+            result => return result,
+        };
+        self.invalidate_index(&index.index_path);
+        let reloaded = match self.load_index_file(Cow::Borrowed(&index.index_path)) {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                log::debug!("Couldn't reload {}: {}", index.index_path.display(), e);
+                return Err(error);
+            }
+        };
+        let moved = reloaded.entries.get(&entry.key()).filter(|new| {
+            new.data_file_id != entry.data_file_id || new.offset_bytes != entry.offset_bytes
+        });
+        let Some(new_entry) = moved else {
+            return Err(error);
+        };
+        reloaded.warnings.warn(Warning::EntryMoved {
+            hash: entry.hash,
+            index_path: index.index_path.clone(),
+        });
+        read(&reloaded, new_entry)
+    }
+}
+
+/// Where [Repository::replace_file] wrote the new content.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplacedEntry {
+    pub data_file_id: u32,
+    pub offset: u64,
+    /// The size of the entry in the dat file, including its header.
+    pub entry_size: u64,
+    /// The index files that now point at the new content.
+    pub index_files: Vec<PathBuf>,
+}
+
+/// An index file, and the category of files it holds, from [Repository::categories].
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryInfo {
+    /// The index file, relative to the repository.
+    pub index_file: PathBuf,
+    /// The category, e.g. `music`, if the index file's name has a known one.
+    pub category: Option<&'static str>,
+    /// The expansion, e.g. `ex1`, if the index file's name has a known one.
+    pub expansion: Option<&'static str>,
+    /// Which of the category's index files this is, for categories split over several.
+    pub chunk: Option<u8>,
+    pub entries: usize,
+    pub header: PackInfo,
 }
 
-#[derive(Debug)]
+impl CategoryInfo {
+    pub fn new(repo_path: &Path, index: &Index2) -> Self {
+        // Index files are named like `0c0100.win32.index2`, for category, expansion and chunk.
+        let prefix = index
+            .index_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.get(..6))
+            .and_then(|p| u32::from_str_radix(p, 16).ok())
+            .map(u32::to_be_bytes);
+        Self {
+            index_file: index
+                .index_path
+                .strip_prefix(repo_path)
+                .unwrap_or(&index.index_path)
+                .to_path_buf(),
+            category: prefix
+                .and_then(|p| FileType::from_file_name_prefix(p[1]))
+                .map(|t| t.as_str()),
+            expansion: prefix
+                .and_then(|p| Expansion::from_file_name_prefix(p[2]))
+                .map(|e| e.as_str()),
+            chunk: prefix.map(|p| p[3]),
+            entries: index.entries.len(),
+            header: index.pack_header.info(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
 struct RepoState {
     indexes: HashMap<PathBuf, Arc<Index2>>,
+    game_version: Option<GameVersion>,
+}
+
+#[cfg(test)]
+mod repo_tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::data::repo::Repository;
+
+    #[test]
+    fn fallbacks_flatten_and_own_their_paths() {
+        let repo =
+            Repository::new(PathBuf::from("/mods/sqpack")).with_fallback(Repository::layered(
+                PathBuf::from("/bench/sqpack"),
+                [PathBuf::from("/game/sqpack")],
+            ));
+        let paths = repo
+            .fallbacks()
+            .iter()
+            .map(|f| f.repo_path())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [Path::new("/bench/sqpack"), Path::new("/game/sqpack")]
+        );
+        assert!(repo.fallbacks().iter().all(|f| f.fallbacks().is_empty()));
+
+        let owner = |path: &str| repo.owner_of(Path::new(path)).repo_path().to_path_buf();
+        assert_eq!(
+            owner("/game/sqpack/ffxiv/0c0000.win32.index2"),
+            Path::new("/game/sqpack")
+        );
+        assert_eq!(
+            owner("/elsewhere/0c0000.win32.index2"),
+            Path::new("/mods/sqpack")
+        );
+    }
 }
@@ -0,0 +1,92 @@
+//! Where a repository's index and dat files are read from.
+use std::fmt::Debug;
+use std::fs::{File, Metadata};
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A readable, seekable index or dat file.
+pub trait DatReader: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> DatReader for T {}
+
+/// The size and modification time of a file, to tell whether it changed since it was read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl From<&Metadata> for FileStamp {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+/// Opens the index and dat files of a repository. Paths are the same as for a local repository,
+/// under [Repository::repo_path](crate::data::repo::Repository::repo_path).
+pub trait DatSource: Debug + Send + Sync {
+    /// Open the file at [path] for reading. Fails with [std::io::ErrorKind::NotFound] if it
+    /// doesn't exist.
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn DatReader>>;
+
+    /// Check if there's a file at [path].
+    fn exists(&self, path: &Path) -> bool;
+
+    /// List the files and directories in [directory], with whether each is a directory. Sources
+    /// that can't list directories fail with [std::io::ErrorKind::Unsupported].
+    fn list_dir(&self, directory: &Path) -> std::io::Result<Vec<(PathBuf, bool)>> {
+        let _ = directory;
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "this repository can't list directories",
+        ))
+    }
+
+    /// Whether the files are on the local filesystem, so they can be written to.
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// Get the [FileStamp] of the file at [path], or None if it doesn't exist or this source
+    /// can't tell.
+    fn stamp(&self, path: &Path) -> Option<FileStamp> {
+        let _ = path;
+        None
+    }
+}
+
+/// Reads files from the local filesystem.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalSource;
+
+impl DatSource for LocalSource {
+    fn open(&self, path: &Path) -> std::io::Result<Box<dyn DatReader>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn list_dir(&self, directory: &Path) -> std::io::Result<Vec<(PathBuf, bool)>> {
+        std::fs::read_dir(directory)?
+            .map(|entry| {
+                let path = entry?.path();
+                let is_dir = path.is_dir();
+                Ok((path, is_dir))
+            })
+            .collect()
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn stamp(&self, path: &Path) -> Option<FileStamp> {
+        std::fs::metadata(path).ok().map(|m| FileStamp::from(&m))
+    }
+}
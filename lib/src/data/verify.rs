@@ -0,0 +1,264 @@
+//! Checking that index entries point at readable content in their dat files.
+use std::fmt::{Display, Formatter};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use binrw::BinReaderExt;
+use serde::Serialize;
+
+use crate::cancel::CancellationToken;
+use crate::data::dat::{ContentType, DatEntryHeader};
+use crate::data::index2::{Index2, Index2Entry};
+use crate::error::{ErrorCategory, LastLegendError};
+use crate::limits::Limits;
+
+/// How thoroughly to check entries.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VerifyLevel {
+    /// Only check that entries start inside their dat files, which doesn't read the dat files.
+    Offsets,
+    /// Also read every entry, checking that its headers parse and its content decompresses to
+    /// the size the entry header gives.
+    Content,
+}
+
+/// An index entry that doesn't point at readable content.
+#[derive(Debug, Serialize)]
+pub struct CorruptEntry {
+    pub hash: u32,
+    pub data_file_id: u32,
+    pub offset: u64,
+    pub problem: EntryProblem,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EntryProblem {
+    /// The dat file the entry is in doesn't exist.
+    MissingDat,
+    /// The entry starts past the end of its dat file.
+    OutOfBounds { dat_size: u64 },
+    /// The reserved bit of the entry's packed location is set, so its location may be wrong.
+    ReservedBit { packed_info: u32 },
+    /// The entry header couldn't be read.
+    BadHeader { error: String },
+    /// The content couldn't be read, e.g. a block header is wrong or doesn't decompress.
+    BadContent { error: String },
+    /// The content was read, but isn't the size the entry header says.
+    SizeMismatch { expected: u64, actual: u64 },
+}
+
+impl Display for EntryProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingDat => write!(f, "the dat file is missing"),
+            Self::OutOfBounds { dat_size } => {
+                write!(
+                    f,
+                    "starts past the end of the dat file (0x{:X} bytes)",
+                    dat_size
+                )
+            }
+            Self::ReservedBit { packed_info } => write!(
+                f,
+                "the reserved bit of its location (0x{:08X}) is set",
+                packed_info
+            ),
+            Self::BadHeader { error } => write!(f, "bad entry header: {}", error),
+            Self::BadContent { error } => write!(f, "bad content: {}", error),
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "content is {} bytes, but the header says {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+/// Check every entry of [index] against its dat file, returning the ones with problems.
+///
+/// Entries are read one block at a time, in the order they're stored in each dat file, so this
+/// doesn't need much memory even for the largest dat files. [on_entry] is called after each entry
+/// is checked, e.g. to show progress. The index's cancellation token is checked between entries.
+pub fn verify_index(
+    index: &Index2,
+    level: VerifyLevel,
+    mut on_entry: impl FnMut(),
+) -> Result<Vec<CorruptEntry>, LastLegendError> {
+    let mut corrupt = Vec::new();
+    for (data_file_id, entries) in index.entries_by_dat() {
+        let dat_path = index.dat_path(data_file_id);
+        let mut dat = match index.source.open(&dat_path) {
+            Ok(f) => BufReader::new(f),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                corrupt.extend(
+                    entries
+                        .iter()
+                        .map(|e| corrupt_entry(e, EntryProblem::MissingDat)),
+                );
+                entries.iter().for_each(|_| on_entry());
+                continue;
+            }
+            Err(e) => {
+                return Err(LastLegendError::Io(
+                    format!("Couldn't open {}", dat_path.display()),
+                    e,
+                ))
+            }
+        };
+        let dat_size = dat
+            .seek(SeekFrom::End(0))
+            .map_err(|e| LastLegendError::Io("Couldn't find the dat size".into(), e))?;
+        for entry in entries {
+            if let Some(cancellation) = &index.cancellation {
+                cancellation.check()?;
+            }
+            let problem = if entry.reserved_bit_set() {
+                Some(EntryProblem::ReservedBit {
+                    packed_info: entry.packed_info,
+                })
+            } else if entry.offset_bytes >= dat_size {
+                Some(EntryProblem::OutOfBounds { dat_size })
+            } else if level == VerifyLevel::Content {
+                verify_entry_content(
+                    &mut dat,
+                    entry.offset_bytes,
+                    index.cancellation.as_ref(),
+                    &index.limits,
+                )?
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                corrupt.push(corrupt_entry(entry, problem));
+            }
+            on_entry();
+        }
+    }
+    Ok(corrupt)
+}
+
+fn corrupt_entry(entry: &Index2Entry, problem: EntryProblem) -> CorruptEntry {
+    CorruptEntry {
+        hash: entry.hash,
+        data_file_id: entry.data_file_id,
+        offset: entry.offset_bytes,
+        problem,
+    }
+}
+
+/// Read the entry at [offset] in [dat], without keeping its content. Fails only if cancelled.
+fn verify_entry_content<R: Read + Seek>(
+    dat: &mut R,
+    offset: u64,
+    cancellation: Option<&CancellationToken>,
+    limits: &Limits,
+) -> Result<Option<EntryProblem>, LastLegendError> {
+    if let Err(e) = dat.seek(SeekFrom::Start(offset)) {
+        return Ok(Some(EntryProblem::BadHeader {
+            error: e.to_string(),
+        }));
+    }
+    let header: DatEntryHeader = match dat.read_le() {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(Some(EntryProblem::BadHeader {
+                error: e.to_string(),
+            }))
+        }
+    };
+    let content = dat
+        .seek(SeekFrom::Start(offset))
+        .and_then(|_| header.read_content(&mut *dat))
+        .and_then(|content| {
+            let mut content = content
+                .with_cancellation(cancellation.cloned())
+                .with_max_block_size(limits.max_block_size);
+            std::io::copy(&mut content, &mut std::io::sink())
+        });
+    Ok(match content {
+        Err(e) => {
+            let e = LastLegendError::Io("Couldn't read entry content".into(), e);
+            if e.category() == ErrorCategory::Cancelled {
+                return Err(e);
+            }
+            Some(EntryProblem::BadContent {
+                error: e.to_string(),
+            })
+        }
+        Ok(actual)
+            if header.content_type() != ContentType::Empty
+                && actual != u64::from(header.uncompressed_size) =>
+        {
+            Some(EntryProblem::SizeMismatch {
+                expected: header.uncompressed_size.into(),
+                actual,
+            })
+        }
+        Ok(_) => None,
+    })
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use std::io::Cursor;
+
+    use crate::cancel::CancellationToken;
+    use crate::data::verify::{verify_entry_content, EntryProblem};
+    use crate::error::ErrorCategory;
+    use crate::limits::Limits;
+
+    fn le(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// A binary entry with one uncompressed block, whose entry header claims [claimed_size].
+    fn binary_entry(content: &[u8], claimed_size: u32) -> Vec<u8> {
+        const HEADER_SIZE: usize = 0x80;
+        let mut entry = le(&[HEADER_SIZE as u32, 2, claimed_size, 0, 0x80, 1]);
+        entry.extend_from_slice(&0u32.to_le_bytes());
+        entry.extend_from_slice(&0x80u16.to_le_bytes());
+        entry.extend_from_slice(&(content.len() as u16).to_le_bytes());
+        entry.resize(HEADER_SIZE, 0);
+        entry.extend(le(&[0x10, 0, 32_000, content.len() as u32]));
+        entry.extend_from_slice(content);
+        entry
+    }
+
+    #[test]
+    fn finds_bad_entries() {
+        let limits = Limits::default();
+        let good = binary_entry(b"ABCD", 4);
+        assert!(
+            verify_entry_content(&mut Cursor::new(&good), 0, None, &limits)
+                .unwrap()
+                .is_none()
+        );
+
+        let wrong_size = binary_entry(b"ABCD", 6);
+        assert!(matches!(
+            verify_entry_content(&mut Cursor::new(&wrong_size), 0, None, &limits).unwrap(),
+            Some(EntryProblem::SizeMismatch {
+                expected: 6,
+                actual: 4
+            })
+        ));
+
+        let mut truncated = binary_entry(b"ABCD", 4);
+        truncated.truncate(truncated.len() - 2);
+        assert!(matches!(
+            verify_entry_content(&mut Cursor::new(&truncated), 0, None, &limits).unwrap(),
+            Some(EntryProblem::BadContent { .. })
+        ));
+
+        assert!(matches!(
+            verify_entry_content(&mut Cursor::new(&[0u8; 8]), 0, None, &limits).unwrap(),
+            Some(EntryProblem::BadHeader { .. })
+        ));
+
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let error = verify_entry_content(&mut Cursor::new(&good), 0, Some(&cancellation), &limits)
+            .expect_err("cancelled reads should fail");
+        assert_eq!(error.category(), ErrorCategory::Cancelled);
+    }
+}
@@ -0,0 +1,136 @@
+//! Finding game installs in the places launchers usually put them, so the repository doesn't
+//! have to be given by hand.
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::LastLegendError;
+
+/// The folder the retail launcher installs to, under `Program Files (x86)`.
+const RETAIL_FOLDER: &str = "SquareEnix/FINAL FANTASY XIV - A Realm Reborn";
+/// The folder Steam installs to, under `steamapps/common`.
+const STEAM_FOLDER: &str = "FINAL FANTASY XIV Online";
+/// The Steam app id, which names the Proton prefix under `steamapps/compatdata`.
+const STEAM_APP_ID: &str = "39210";
+
+/// A game install that was found.
+#[derive(Debug, Clone, Serialize)]
+pub struct GameInstall {
+    /// The `sqpack` folder, which is the repository.
+    pub sqpack: PathBuf,
+    /// The version from `ffxivgame.ver`, if it could be read.
+    pub game_version: Option<String>,
+}
+
+/// Find the game installs on this machine, checking the usual places for the retail launcher and
+/// Steam on Windows, Steam's Proton prefixes and XIVLauncher on Linux, and the official client and
+/// XIV on Mac on macOS.
+pub fn discover() -> Vec<GameInstall> {
+    let mut installs = Vec::<GameInstall>::new();
+    for game in candidate_game_folders() {
+        let sqpack = game.join("sqpack");
+        if !sqpack.join("ffxiv").is_dir() {
+            continue;
+        }
+        // The same install can be reachable through several paths, e.g. Steam's symlinks.
+        let sqpack = sqpack.canonicalize().unwrap_or(sqpack);
+        if installs.iter().any(|i| i.sqpack == sqpack) {
+            continue;
+        }
+        log::debug!("Found game install at {}", sqpack.display());
+        installs.push(GameInstall {
+            sqpack,
+            game_version: read_game_version(&game),
+        });
+    }
+    installs
+}
+
+/// Find the one game install on this machine, failing if there are none or several.
+pub fn discover_one() -> Result<GameInstall, LastLegendError> {
+    let mut installs = discover();
+    match installs.len() {
+        1 => Ok(installs.remove(0)),
+        0 => Err(LastLegendError::Custom(
+            "Couldn't find a game install, give the path to its sqpack folder".into(),
+        )),
+        _ => Err(LastLegendError::Custom(format!(
+            "Found several game installs, give the path to one of them: {}",
+            installs
+                .iter()
+                .map(|i| i.sqpack.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// Read the version from `ffxivgame.ver` in the [game] folder, the parent of `sqpack`.
+pub fn read_game_version(game: &Path) -> Option<String> {
+    let version = std::fs::read_to_string(game.join("ffxivgame.ver")).ok()?;
+    Some(version.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// The `game` folders installs may be in, for the current OS.
+fn candidate_game_folders() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    if cfg!(windows) {
+        let program_files = std::env::var_os("ProgramFiles(x86)")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\Program Files (x86)"));
+        roots.push(program_files.join(RETAIL_FOLDER));
+        roots.push(
+            program_files
+                .join("Steam/steamapps/common")
+                .join(STEAM_FOLDER),
+        );
+    } else if cfg!(target_os = "macos") {
+        if let Some(home) = &home {
+            let support = home.join("Library/Application Support");
+            roots.push(
+                support
+                    .join("FINAL FANTASY XIV ONLINE/Bottles/published_Final_Fantasy/drive_c")
+                    .join("Program Files (x86)")
+                    .join(RETAIL_FOLDER),
+            );
+            roots.push(support.join("XIV on Mac/ffxiv"));
+        }
+    } else if let Some(home) = &home {
+        for steam in [".steam/steam", ".local/share/Steam"] {
+            let steamapps = home.join(steam).join("steamapps");
+            roots.push(steamapps.join("common").join(STEAM_FOLDER));
+            roots.push(
+                steamapps
+                    .join("compatdata")
+                    .join(STEAM_APP_ID)
+                    .join("pfx/drive_c/Program Files (x86)")
+                    .join(RETAIL_FOLDER),
+            );
+        }
+        roots.push(home.join(".xlcore/ffxiv"));
+        roots.push(
+            home.join(".wine/drive_c/Program Files (x86)")
+                .join(RETAIL_FOLDER),
+        );
+    }
+    roots.into_iter().map(|root| root.join("game")).collect()
+}
+
+#[cfg(test)]
+mod discovery_tests {
+    use super::*;
+
+    #[test]
+    fn reads_game_version() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_game_version(dir.path()), None);
+        std::fs::write(dir.path().join("ffxivgame.ver"), "2024.07.10.0000.0000\r\n").unwrap();
+        assert_eq!(
+            read_game_version(dir.path()).as_deref(),
+            Some("2024.07.10.0000.0000")
+        );
+    }
+}
@@ -3,7 +3,10 @@ use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::cancel::Cancelled;
 use crate::sqpath::SqPathBuf;
+use crate::surpass::sheet_info::Language;
+use crate::transformers::TransformerImpl;
 
 #[derive(Error, Debug)]
 pub enum LastLegendError {
@@ -15,16 +18,68 @@ pub enum LastLegendError {
     CollectionSheetLineInvalid(String),
     #[error("Sheet name is invalid: {0}")]
     SheetNameInvalid(String),
+    #[error("Sheet {sheet} has no {language:?} pages, only {available:?}")]
+    SheetLanguageMissing {
+        sheet: String,
+        language: Language,
+        available: Vec<Language>,
+    },
+    #[error("Sheet {sheet} has no row {row_id}")]
+    SheetRowMissing { sheet: String, row_id: u32 },
     #[error("{0}")]
     Custom(String),
     #[error("Additional context for error: {0}, {1}")]
     LastLegend(String, #[source] Box<LastLegendError>),
+    /// Reading an entry from the dat files of the index file failed.
+    #[error("Couldn't read an entry of '{0}', {1}")]
+    DatRead(PathBuf, #[source] Box<LastLegendError>),
     #[error("I/O error: {0}, {1}")]
     Io(String, #[source] std::io::Error),
     #[error("binrw error: {0}, {1}")]
     BinRW(String, #[source] binrw::Error),
+    #[error("JSON error: {0}, {1}")]
+    Json(String, #[source] serde_json::Error),
+    #[error("PNG error: {0}, {1}")]
+    Png(String, #[source] png::EncodingError),
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}, {1}")]
+    Sqlite(String, #[source] rusqlite::Error),
     #[error("FFMPEG failed: {0}")]
     FFMPEG(String),
+    #[error("Audio decoding failed: {0}")]
+    AudioDecode(String),
+    #[error("Unsupported audio codec: {0}")]
+    UnsupportedCodec(String),
+    #[error("Output of transformer {transformer:?} for '{file}' is mislabeled: {reason}")]
+    TransformerOutputMismatch {
+        file: SqPathBuf,
+        transformer: TransformerImpl,
+        reason: String,
+    },
+    #[error("{failed} of {total} entries failed")]
+    PartialFailure { failed: usize, total: usize },
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+/// Broad categories of [LastLegendError], for reacting to failures without matching every variant.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ErrorCategory {
+    /// A path, index entry, sheet, or file did not exist.
+    NotFound,
+    /// Data was present but could not be parsed.
+    Parse,
+    /// FFMPEG could not be run or failed.
+    Ffmpeg,
+    /// Data was understood, but is in a form this library can't convert, e.g. an audio codec.
+    Unsupported,
+    /// Any other I/O failure.
+    Io,
+    /// Some, but not all, of a bulk operation failed.
+    PartialFailure,
+    /// Work was stopped with a [CancellationToken](crate::cancel::CancellationToken).
+    Cancelled,
+    Other,
 }
 
 impl serde::de::Error for LastLegendError {
@@ -40,4 +95,64 @@ impl LastLegendError {
     pub fn add_context(self, message: impl Into<String>) -> Self {
         Self::LastLegend(message.into(), Box::new(self))
     }
+
+    /// Get the category of this error, looking through any added context.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::InvalidSqPath(..)
+            | Self::MissingEntryFromIndex(..)
+            | Self::SheetNameInvalid(..)
+            | Self::SheetLanguageMissing { .. }
+            | Self::SheetRowMissing { .. } => ErrorCategory::NotFound,
+            Self::CollectionSheetLineInvalid(..)
+            | Self::BinRW(..)
+            | Self::Json(..)
+            | Self::AudioDecode(..) => ErrorCategory::Parse,
+            Self::LastLegend(_, e) | Self::DatRead(_, e) => e.category(),
+            Self::Io(_, e) if e.get_ref().is_some_and(|inner| inner.is::<Cancelled>()) => {
+                ErrorCategory::Cancelled
+            }
+            Self::Io(_, e) if e.kind() == std::io::ErrorKind::NotFound => ErrorCategory::NotFound,
+            Self::Io(..) => ErrorCategory::Io,
+            Self::FFMPEG(..) => ErrorCategory::Ffmpeg,
+            Self::UnsupportedCodec(..) => ErrorCategory::Unsupported,
+            Self::PartialFailure { .. } => ErrorCategory::PartialFailure,
+            Self::Cancelled => ErrorCategory::Cancelled,
+            Self::Custom(..) | Self::Png(..) | Self::TransformerOutputMismatch { .. } => {
+                ErrorCategory::Other
+            }
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(..) => ErrorCategory::Other,
+        }
+    }
+
+    /// Whether this error could come from reading an entry at offsets that no longer match its
+    /// dat file, because the game was patched after the index was loaded. Only failures reading
+    /// the dat files count, not those of whatever used the content after.
+    pub fn may_be_stale_index(&self) -> bool {
+        match self {
+            Self::LastLegend(_, e) => e.may_be_stale_index(),
+            Self::DatRead(_, e) => matches!(e.category(), ErrorCategory::Parse | ErrorCategory::Io),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn only_dat_reads_may_be_stale() {
+        let io = || LastLegendError::Io("read".into(), std::io::ErrorKind::UnexpectedEof.into());
+        let dat_read =
+            |e| LastLegendError::DatRead(PathBuf::from("0c0000.win32.index2"), Box::new(e));
+        assert!(!io().may_be_stale_index());
+        assert!(dat_read(io()).may_be_stale_index());
+        assert!(dat_read(io())
+            .add_context("extracting")
+            .may_be_stale_index());
+        assert!(!dat_read(LastLegendError::Cancelled).may_be_stale_index());
+        assert!(!io().add_context("writing output").may_be_stale_index());
+    }
 }
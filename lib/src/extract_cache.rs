@@ -0,0 +1,166 @@
+//! Remembering what earlier runs extracted, so unchanged files aren't extracted again.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::data::game_version::GameVersion;
+use crate::data::index2::{Index2, Index2Entry};
+use crate::error::LastLegendError;
+use crate::simple_task::OutputMetadata;
+use crate::transformers::TransformerImpl;
+
+/// The file name extraction commands keep their cache in, in the output directory.
+pub const EXTRACT_CACHE_FILE: &str = ".lldob-extract-cache.json";
+
+/// What an entry was extracted from and how, by index file and hash. An entry whose record matches
+/// and whose output still exists doesn't need extracting again.
+#[derive(Debug)]
+pub struct ExtractCache {
+    path: PathBuf,
+    records: Mutex<BTreeMap<String, CachedOutput>>,
+}
+
+/// How an entry was extracted, see [ExtractCache].
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CachedOutput {
+    pub data_file_id: u32,
+    pub offset: u64,
+    /// The uncompressed size of the entry.
+    pub size: u32,
+    /// The [settings_fingerprint] of the transformers and metadata used.
+    pub settings: u64,
+    pub output: PathBuf,
+}
+
+impl ExtractCache {
+    /// Make an empty cache at [path], so everything is extracted again. Any existing cache there is
+    /// replaced when this is saved.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            records: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Open the cache at [path]. A missing or unreadable cache is treated as empty, so everything
+    /// is extracted again.
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        let records = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::warn!("Ignoring unreadable cache {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Ignoring unreadable cache {}: {}", path.display(), e);
+                }
+                BTreeMap::new()
+            }
+        };
+        Self {
+            path: path.to_path_buf(),
+            records: Mutex::new(records),
+        }
+    }
+
+    /// The key of [entry] of [index]. When [game_version] knows the version of the index's
+    /// expansion, it's part of the key, so a patch makes everything in it extract again.
+    pub fn key(index: &Index2, entry: &Index2Entry, game_version: &GameVersion) -> String {
+        let key = format!("{}:{:08X}", index.index_path.display(), entry.hash);
+        let version = index
+            .index_path
+            .parent()
+            .and_then(|folder| folder.file_name()?.to_str())
+            .and_then(|expansion| game_version.for_expansion(expansion));
+        match version {
+            Some(version) => format!("{}@{}", key, version),
+            None => key,
+        }
+    }
+
+    /// Check if [key] was extracted the same way as [expected] describes, to an output that's
+    /// still there. The output is taken from the cache, as it isn't known until extracting.
+    pub fn is_fresh(&self, key: &str, expected: &CachedOutput) -> Option<PathBuf> {
+        let records = self.records.lock();
+        let cached = records.get(key)?;
+        let same = cached.data_file_id == expected.data_file_id
+            && cached.offset == expected.offset
+            && cached.size == expected.size
+            && cached.settings == expected.settings;
+        (same && cached.output.exists()).then(|| cached.output.clone())
+    }
+
+    pub fn record(&self, key: String, output: CachedOutput) {
+        self.records.lock().insert(key, output);
+    }
+
+    /// Write the cache back to its file. It's written to a temporary file first, so a crash
+    /// doesn't leave a cut off cache.
+    pub fn save(&self) -> Result<(), LastLegendError> {
+        let data = serde_json::to_vec(&*self.records.lock())
+            .map_err(|e| LastLegendError::Json("Couldn't write extraction cache".into(), e))?;
+        let temp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&temp_path, data)
+            .and_then(|_| std::fs::rename(&temp_path, &self.path))
+            .map_err(|e| LastLegendError::Io(format!("Couldn't write {}", self.path.display()), e))
+    }
+}
+
+/// A fingerprint of everything besides the entry that changes the output: the [transformers],
+/// with their options, and the [metadata] embedded.
+pub fn settings_fingerprint(transformers: &[TransformerImpl], metadata: &OutputMetadata) -> u64 {
+    const CALCULATOR: crc::Crc<u64> = crc::Crc::<u64>::new(&crc::CRC_64_XZ);
+    let mut digest = CALCULATOR.digest();
+    digest.update(format!("{:?}", transformers).as_bytes());
+    for (key, value) in &metadata.tags {
+        digest.update(key.as_bytes());
+        digest.update(b"=");
+        digest.update(value.as_bytes());
+        digest.update(b"\n");
+    }
+    if let Some(cover_art) = &metadata.cover_art {
+        digest.update(cover_art);
+    }
+    digest.finalize()
+}
+
+#[cfg(test)]
+mod extract_cache_tests {
+    use std::path::PathBuf;
+
+    use crate::extract_cache::{CachedOutput, ExtractCache};
+
+    #[test]
+    fn fresh_only_when_unchanged_and_present() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.flac");
+        std::fs::write(&output, b"flac").unwrap();
+        let record = CachedOutput {
+            data_file_id: 0,
+            offset: 0x80,
+            size: 100,
+            settings: 1,
+            output: output.clone(),
+        };
+
+        let cache_path = dir.path().join("cache.json");
+        let cache = ExtractCache::open(&cache_path);
+        cache.record("a".into(), record.clone());
+        cache.save().unwrap();
+
+        let cache = ExtractCache::open(&cache_path);
+        assert_eq!(cache.is_fresh("a", &record), Some(output.clone()));
+        let moved = CachedOutput {
+            offset: 0x100,
+            output: PathBuf::new(),
+            ..record.clone()
+        };
+        assert_eq!(cache.is_fresh("a", &moved), None);
+        assert_eq!(cache.is_fresh("b", &record), None);
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(cache.is_fresh("a", &record), None);
+    }
+}
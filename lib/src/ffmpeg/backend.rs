@@ -0,0 +1,232 @@
+//! The audio operations transformers need, behind a trait so they can run without FFMPEG.
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::error::LastLegendError;
+use crate::transformers::{
+    extension_magic, AudioFormat, EncodeOptions, LoopOptions, TransformStream,
+};
+
+/// Runs the audio operations of transformers. The one in use is picked with
+/// [set_audio_backend], or [with_audio_backend] for one thread, and defaults to [FfmpegBackend].
+pub trait AudioBackend: Debug + Send + Sync {
+    /// Whether this backend can run. When it can't, `.scd` audio falls back to being decoded
+    /// in-process, with the `native-audio` feature.
+    fn available(&self) -> bool;
+
+    /// See [loop_using_metadata](super::loop_using_metadata).
+    fn loop_using_metadata(
+        &self,
+        ffmpeg_format: &str,
+        options: &LoopOptions,
+        reader: &mut dyn TransformStream,
+        output: &mut dyn Write,
+    ) -> Result<(), LastLegendError>;
+
+    /// See [encode_audio](super::encode_audio).
+    fn encode_audio(
+        &self,
+        format: AudioFormat,
+        options: &EncodeOptions,
+        tags: &[(String, String)],
+        reader: &mut (dyn Read + Send),
+        output: &mut (dyn Write + Send),
+    ) -> Result<(), LastLegendError>;
+
+    /// See [embed_metadata](super::embed_metadata).
+    fn embed_metadata(
+        &self,
+        ffmpeg_format: &str,
+        reader: &mut (dyn Read + Send),
+        cover_png: Option<&[u8]>,
+        tags: &[(String, String)],
+        output: &mut (dyn Write + Send),
+    ) -> Result<(), LastLegendError>;
+}
+
+static BACKEND: RwLock<Option<Arc<dyn AudioBackend>>> = RwLock::new(None);
+
+thread_local! {
+    /// Overrides [BACKEND] on this thread, see [with_audio_backend].
+    static THREAD_BACKEND: RefCell<Option<Arc<dyn AudioBackend>>> = const { RefCell::new(None) };
+}
+
+/// The backend transformers use.
+pub fn audio_backend() -> Arc<dyn AudioBackend> {
+    THREAD_BACKEND
+        .with_borrow(Option::clone)
+        .or_else(|| BACKEND.read().clone())
+        .unwrap_or_else(|| Arc::new(FfmpegBackend))
+}
+
+/// Make transformers use [backend] from now on, for the whole process.
+pub fn set_audio_backend(backend: Arc<dyn AudioBackend>) {
+    *BACKEND.write() = Some(backend);
+}
+
+/// Run [f] with transformers on this thread using [backend], then go back to the backend from
+/// before, even if [f] panics. Transformers [f] runs on other threads, e.g. through rayon, aren't
+/// affected.
+pub fn with_audio_backend<T>(backend: Arc<dyn AudioBackend>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Arc<dyn AudioBackend>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            THREAD_BACKEND.set(self.0.take());
+        }
+    }
+
+    let _restore = Restore(THREAD_BACKEND.replace(Some(backend)));
+    f()
+}
+
+/// Runs the `ffmpeg` binary.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FfmpegBackend;
+
+impl AudioBackend for FfmpegBackend {
+    fn available(&self) -> bool {
+        super::ffmpeg_available()
+    }
+
+    fn loop_using_metadata(
+        &self,
+        ffmpeg_format: &str,
+        options: &LoopOptions,
+        reader: &mut dyn TransformStream,
+        output: &mut dyn Write,
+    ) -> Result<(), LastLegendError> {
+        super::loop_using_metadata(ffmpeg_format, options, reader, output)
+    }
+
+    fn encode_audio(
+        &self,
+        format: AudioFormat,
+        options: &EncodeOptions,
+        tags: &[(String, String)],
+        reader: &mut (dyn Read + Send),
+        output: &mut (dyn Write + Send),
+    ) -> Result<(), LastLegendError> {
+        super::encode_audio(format, options, tags, reader, output)
+    }
+
+    fn embed_metadata(
+        &self,
+        ffmpeg_format: &str,
+        reader: &mut (dyn Read + Send),
+        cover_png: Option<&[u8]>,
+        tags: &[(String, String)],
+        output: &mut (dyn Write + Send),
+    ) -> Result<(), LastLegendError> {
+        super::embed_metadata(ffmpeg_format, reader, cover_png, tags, output)
+    }
+}
+
+/// A deterministic stand-in for FFMPEG, for tests and simulated runs on machines without it.
+///
+/// Rather than converting anything, it writes the magic bytes of the output format, then a
+/// `LLDOB-FAKE` line describing the operation and its settings, then the input as-is. Outputs
+/// pass the checks of `--strict`, and the same input and settings always give the same output.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FakeAudioBackend;
+
+impl FakeAudioBackend {
+    fn write_fake(
+        ffmpeg_format: &str,
+        label: &str,
+        reader: &mut dyn Read,
+        mut output: &mut dyn Write,
+    ) -> Result<(), LastLegendError> {
+        let extension = match ffmpeg_format {
+            "ipod" => "m4a",
+            other => other,
+        };
+        let magic = extension_magic(extension).unwrap_or_default();
+        output
+            .write_all(magic)
+            .and_then(|_| writeln!(output, "LLDOB-FAKE {}", label))
+            .and_then(|_| std::io::copy(reader, &mut output))
+            .map_err(|e| LastLegendError::Io("Couldn't write fake audio".into(), e))?;
+        Ok(())
+    }
+}
+
+fn tags_label(tags: &[(String, String)]) -> String {
+    tags.iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+impl AudioBackend for FakeAudioBackend {
+    fn available(&self) -> bool {
+        true
+    }
+
+    fn loop_using_metadata(
+        &self,
+        ffmpeg_format: &str,
+        options: &LoopOptions,
+        reader: &mut dyn TransformStream,
+        output: &mut dyn Write,
+    ) -> Result<(), LastLegendError> {
+        let label = format!("loop {} {:?}", ffmpeg_format, options);
+        Self::write_fake(ffmpeg_format, &label, reader, output)
+    }
+
+    fn encode_audio(
+        &self,
+        format: AudioFormat,
+        options: &EncodeOptions,
+        tags: &[(String, String)],
+        reader: &mut (dyn Read + Send),
+        output: &mut (dyn Write + Send),
+    ) -> Result<(), LastLegendError> {
+        let label = format!("encode {} {:?} [{}]", format, options, tags_label(tags));
+        Self::write_fake(format.ffmpeg_format(), &label, reader, output)
+    }
+
+    fn embed_metadata(
+        &self,
+        ffmpeg_format: &str,
+        reader: &mut (dyn Read + Send),
+        cover_png: Option<&[u8]>,
+        tags: &[(String, String)],
+        output: &mut (dyn Write + Send),
+    ) -> Result<(), LastLegendError> {
+        let label = format!(
+            "embed {} cover={} [{}]",
+            ffmpeg_format,
+            cover_png.map_or(0, <[u8]>::len),
+            tags_label(tags)
+        );
+        Self::write_fake(ffmpeg_format, &label, reader, output)
+    }
+}
+
+#[cfg(test)]
+mod backend_tests {
+    use super::*;
+
+    #[test]
+    fn fake_output_is_labelled_and_keeps_input() {
+        let mut output = Vec::new();
+        FakeAudioBackend
+            .encode_audio(
+                AudioFormat::Flac,
+                &EncodeOptions::default(),
+                &[("ALBUM".to_string(), "OST".to_string())],
+                &mut b"audio".as_slice(),
+                &mut output,
+            )
+            .unwrap();
+        let expected = format!(
+            "fLaCLLDOB-FAKE encode flac {:?} [ALBUM=OST]\naudio",
+            EncodeOptions::default()
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
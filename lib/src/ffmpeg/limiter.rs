@@ -0,0 +1,108 @@
+//! Limits how many FFMPEG and ffprobe processes run at once.
+//!
+//! FFMPEG takes one job per process, so every stage of every conversion still starts its own.
+//! Extracting a large library runs conversions on every worker thread though, and past the number
+//! of CPUs more processes only fight over them, so the rest wait here for a slot.
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use parking_lot::{Condvar, Mutex};
+
+static FFMPEG_LIMITER: FfmpegLimiter = FfmpegLimiter::new();
+
+/// Run at most [max] FFMPEG and ffprobe processes at once from now on, for the whole process.
+/// 0 goes back to the default, the number of CPUs.
+pub fn set_max_ffmpeg_processes(max: usize) {
+    FFMPEG_LIMITER.set_max(max);
+}
+
+/// Wait until another FFMPEG or ffprobe process can run, and hold the slot while it does.
+pub(crate) fn take_process_slot() -> ProcessSlot<'static> {
+    FFMPEG_LIMITER.take()
+}
+
+/// A counting semaphore over the running processes.
+struct FfmpegLimiter {
+    running: Mutex<usize>,
+    freed: Condvar,
+    /// 0 for the default.
+    max: AtomicUsize,
+}
+
+impl FfmpegLimiter {
+    const fn new() -> Self {
+        Self {
+            running: Mutex::new(0),
+            freed: Condvar::new(),
+            max: AtomicUsize::new(0),
+        }
+    }
+
+    fn set_max(&self, max: usize) {
+        self.max.store(max, Ordering::Release);
+        // Waiters check again, in case there's more room now.
+        self.freed.notify_all();
+    }
+
+    fn max(&self) -> usize {
+        match self.max.load(Ordering::Acquire) {
+            0 => std::thread::available_parallelism().map_or(4, NonZeroUsize::get),
+            max => max,
+        }
+    }
+
+    fn take(&self) -> ProcessSlot<'_> {
+        let mut running = self.running.lock();
+        while *running >= self.max() {
+            self.freed.wait(&mut running);
+        }
+        *running += 1;
+        ProcessSlot(self)
+    }
+}
+
+/// A place among the running processes, given back when dropped.
+pub(crate) struct ProcessSlot<'a>(&'a FfmpegLimiter);
+
+impl Drop for ProcessSlot<'_> {
+    fn drop(&mut self) {
+        *self.0.running.lock() -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod limiter_tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn slots_wait_for_room() {
+        let limiter = FfmpegLimiter::new();
+        limiter.set_max(1);
+        let slot = limiter.take();
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| drop(limiter.take()));
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!waiter.is_finished());
+
+            drop(slot);
+            waiter.join().unwrap();
+        });
+        assert_eq!(*limiter.running.lock(), 0);
+    }
+
+    #[test]
+    fn raising_the_max_wakes_waiters() {
+        let limiter = FfmpegLimiter::new();
+        limiter.set_max(1);
+        let _slot = limiter.take();
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| drop(limiter.take()));
+            std::thread::sleep(Duration::from_millis(50));
+            limiter.set_max(2);
+            waiter.join().unwrap();
+        });
+    }
+}
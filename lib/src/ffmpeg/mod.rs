@@ -1,170 +1,361 @@
-use std::fs::File;
-use std::io::{ErrorKind, Read, Write};
+//! Running the `ffmpeg` and `ffprobe` binaries.
+//!
+//! Audio is piped to FFMPEG's stdin, with cover art piped alongside it as a second input. Output
+//! is piped back from stdout, except for muxers that seek back to finish their headers, see
+//! [SEEKING_MUXERS]. Those write to a [ScratchFile] on disk, reused within each thread, which
+//! includes FLAC, WAV and `.m4a` output. How many processes run at once is capped, see
+//! [set_max_ffmpeg_processes].
+use std::ffi::OsString;
+use std::io::{ErrorKind, Read, SeekFrom, Write};
 use std::ops::{Deref, DerefMut};
 use std::process::{Child, Command, Output, Stdio};
+use std::sync::OnceLock;
+
+use base64::Engine;
 
 use crate::error::LastLegendError;
+use crate::ffmpeg::limiter::take_process_slot;
+use crate::ffmpeg::scratch::ScratchFile;
+use crate::metrics::{self, Metric};
+use crate::scd::ogg::ogg_vorbis_length;
+use crate::transformers::{AudioFormat, EncodeOptions, LoopOptions, TransformStream};
 use crate::tricks::ArgBuilder;
 
+pub use limiter::set_max_ffmpeg_processes;
+
+pub mod backend;
+mod limiter;
+pub mod probe;
+pub(crate) mod scratch;
+
 const GENERAL_FFMPEG_INSTRUCTIONS: [&str; 1] = ["-hide_banner"];
 
-/// Loop a file using the Loopstart and Loopend metadata.
+/// Formats whose muxers seek back once the audio is written to finish their headers, e.g. the
+/// sample count in FLAC's STREAMINFO or the `moov` atom of an `.m4a`. Piped, their headers would
+/// be left incomplete, so they're written to a scratch file instead, which is then copied to the
+/// output.
+const SEEKING_MUXERS: [&str; 3] = ["flac", "ipod", "wav"];
+
+/// The file descriptor FFMPEG reads the second input of [run_process] from, as [SECOND_INPUT].
+#[cfg(unix)]
+const SECOND_INPUT_FD: std::os::fd::RawFd = 3;
+#[cfg(unix)]
+const SECOND_INPUT: &str = "pipe:3";
+
+/// Check if the `ffmpeg` binary can be run. Only checked once per process.
+pub fn ffmpeg_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        let available = Command::new("ffmpeg")
+            .arg("-version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !available {
+            log::debug!("ffmpeg is not available");
+        }
+        available
+    })
+}
+
+/// Loop a file using the Loopstart and Loopend metadata, then fade out the end, as set by
+/// [options]. With [LoopOptions::crossfade_ms], the seams are crossfaded rather than cut.
+///
+/// The input is piped to each process, so it's rewound between them rather than stored.
 pub fn loop_using_metadata(
     ffmpeg_format: &str,
-    mut reader: impl Read,
+    options: &LoopOptions,
+    reader: &mut dyn TransformStream,
     mut output: impl Write,
 ) -> Result<(), LastLegendError> {
-    let mut original_cache_file = tempfile::NamedTempFile::new()
-        .map_err(|e| LastLegendError::Io("Couldn't create temporary cache file".into(), e))?;
-    let looped_cache_file = tempfile::NamedTempFile::new()
-        .map_err(|e| LastLegendError::Io("Couldn't create temporary loop cache file".into(), e))?;
-    // dump the reader to a file for probing
-    std::io::copy(&mut reader, original_cache_file.as_file_mut())
-        .map_err(|e| LastLegendError::Io("Couldn't copy to original cache file".into(), e))?;
-
     // Run FFMPEG command to tell me what the loop points are
     let probe_args = ArgBuilder::new()
         .add_all(GENERAL_FFMPEG_INSTRUCTIONS)
         .add_all(get_ffmpeg_loglevel())
-        .add_kv("-i", original_cache_file.path())
+        .add_kv("-i", "pipe:")
         .add_kv("-show_entries", "format_tags")
-        .add_kv("-of", "compact=p=0:nk=1")
+        .add_kv("-of", "compact=p=0")
         .into_vec();
-    log::debug!("Running ffprobe {:?}", probe_args);
-    let audio_probe_output = Command::new("ffprobe")
-        .args(probe_args)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .output()
-        .map_err(|e| LastLegendError::Io("Couldn't run ffprobe".into(), e))?;
-    check_exit(&audio_probe_output)?;
-    let (loop_start, loop_end): (u32, u32) = {
-        let stdout = String::from_utf8_lossy(&audio_probe_output.stdout).into_owned();
-        let output = stdout
-            .lines()
-            .next()
-            .map(|line| line.split('|').collect::<Vec<_>>())
-            .ok_or_else(|| LastLegendError::FFMPEG("no output".to_string()))?;
-        match output.as_slice() {
-            &[loop_start, loop_end, ..] => {
-                let loop_start = loop_start.parse().map_err(|_| {
-                    LastLegendError::FFMPEG(format!(
-                        "audio loop_start wasn't a u32 but: {}",
-                        loop_start
-                    ))
-                })?;
-                let loop_end = loop_end.parse().map_err(|_| {
-                    LastLegendError::FFMPEG(format!(
-                        "audio duration wasn't a u32 but: {}",
-                        loop_end
-                    ))
-                })?;
-                (loop_start, loop_end)
-            }
-            _ => (0, 0),
-        }
+    let (loop_start, loop_end) = parse_loop_tags(&run_ffprobe(probe_args, Some(rewind(reader)?))?)?;
+
+    let stream_length = if loop_start == 0 && options.fade.is_none() {
+        None
+    } else {
+        stream_length(reader)?
     };
+    let stream_bytes = reader
+        .seek(SeekFrom::End(0))
+        .map_err(|e| LastLegendError::Io("Couldn't find the input size".into(), e))?;
 
-    // Run FFMPEG command to loop the audio (if the loop point isn't just 0)
-    match loop_start {
-        0 => {
-            // N.B. do not check loop_end here, it is 0 sometimes.
-            // We can just do an in-process file copy
-            std::io::copy(
-                &mut File::open(original_cache_file.path()).map_err(|e| {
-                    LastLegendError::Io("Couldn't open original cache file".into(), e)
-                })?,
-                &mut File::create(looped_cache_file.path()).map_err(|e| {
-                    LastLegendError::Io("Couldn't open looped cache file".into(), e)
-                })?,
+    // Work out the FFMPEG filter to loop the audio (if there's a loop to make)
+    let loop_points = loop_points_in_samples(
+        loop_start,
+        loop_end,
+        stream_length.map(|(samples, _)| samples),
+        stream_bytes,
+    );
+    let looped = loop_points
+        .map(|(start, end)| (start, end, loop_count(options, start, end, stream_length)))
+        .filter(|&(_, _, loop_count)| loop_count > 0);
+    let mut filter = match looped {
+        None => None,
+        Some((loop_start, loop_end, loop_count)) => {
+            let crossfade_samples = match (options.crossfade_ms, stream_length) {
+                (Some(ms), Some((_, sample_rate))) => {
+                    (f64::from(ms) * sample_rate / 1000.0).round() as u64
+                }
+                (Some(_), None) => {
+                    log::debug!("Unknown sample rate, looping without a crossfade");
+                    0
+                }
+                (None, _) => 0,
+            };
+            Some(
+                match crossfade_loop_filter(loop_start, loop_end, loop_count, crossfade_samples) {
+                    Some(filter) => ("-filter_complex", filter),
+                    None => (
+                        "-af",
+                        format!(
+                            "aloop=loop={}:start={}:size={}",
+                            loop_count,
+                            loop_start,
+                            loop_end - loop_start
+                        ),
+                    ),
+                },
             )
-            .map_err(|e| {
-                LastLegendError::Io("Couldn't copy original file to looped file".into(), e)
-            })?;
         }
-        _ => {
-            let ffmpeg_args = ArgBuilder::new()
-                .add_all(GENERAL_FFMPEG_INSTRUCTIONS)
-                .add_all(get_ffmpeg_loglevel())
-                .add_arg("-y")
-                .add_kv("-i", original_cache_file.path())
-                .add_kv(
-                    "-af",
-                    format!(
-                        "aloop=loop=1:start={}:size={}",
-                        loop_start,
-                        loop_end - loop_start
-                    ),
-                )
-                .add_kv("-f", ffmpeg_format)
-                .add_arg(looped_cache_file.path())
-                .into_vec();
-            log::debug!("Running ffmpeg {:?}", ffmpeg_args);
-            let ffmpeg_loop_output = Command::new("ffmpeg")
-                .args(ffmpeg_args)
-                .stdin(Stdio::null())
-                .stdout(Stdio::null())
-                .output()
-                .map_err(|e| LastLegendError::Io("Couldn't run ffmpeg".into(), e))?;
-            check_exit(&ffmpeg_loop_output)?;
+    };
+
+    if let Some(fade) = options.fade {
+        // Both loop filters play the loop an extra loop_count times, so the looped length is
+        // known without probing the looped audio.
+        match stream_length {
+            Some((total_samples, sample_rate)) => {
+                let looped_samples = looped.map_or(total_samples, |(start, end, count)| {
+                    total_samples + u64::from(count) * (end - start)
+                });
+                let mut audio_len = looped_samples as f64 / sample_rate;
+                if let Some(target) = options.target_duration {
+                    audio_len = audio_len.min(target);
+                }
+                // Taper the end since most rolls are intended to "loop forever".
+                let fade_filter =
+                    format!("afade=t=out:st={}:d={}", (audio_len - fade).max(0f64), fade);
+                filter = Some(match filter {
+                    Some((kind, filter)) => (kind, format!("{},{}", filter, fade_filter)),
+                    None => ("-af", fade_filter),
+                });
+            }
+            None => log::debug!("Unknown audio length, not fading out"),
         }
     }
 
-    // Run FFMPEG command to tell me what the length is
+    if filter.is_none() && options.target_duration.is_none() {
+        std::io::copy(rewind(reader)?, &mut output)
+            .map_err(|e| LastLegendError::Io("Couldn't copy unlooped audio".into(), e))?;
+        return Ok(());
+    }
+    let mut builder = ArgBuilder::new()
+        .add_all(GENERAL_FFMPEG_INSTRUCTIONS)
+        .add_all(get_ffmpeg_loglevel())
+        .add_arg("-y")
+        .add_kv("-i", "pipe:");
+    if let Some((filter_kind, filter)) = filter {
+        builder = builder.add_kv(filter_kind, filter);
+    }
+    if let Some(target) = options.target_duration {
+        builder = builder.add_kv("-t", target.to_string());
+    }
+    run_ffmpeg(
+        builder,
+        ffmpeg_format,
+        Some(rewind(reader)?),
+        None,
+        &mut output,
+    )
+}
+
+/// Seek [reader] back to the start, to be read again.
+fn rewind(reader: &mut dyn TransformStream) -> Result<&mut (dyn Read + Send), LastLegendError> {
+    reader
+        .rewind()
+        .map_err(|e| LastLegendError::Io("Couldn't rewind audio".into(), e))?;
+    Ok(reader)
+}
+
+/// Get the number of samples and the sample rate of the first audio stream of [reader], if
+/// known.
+///
+/// An OGG stream's length is only in its last page, which ffprobe can't seek to through a pipe,
+/// so the pages are walked here instead. Other formats have it in their headers, and go to
+/// ffprobe.
+fn stream_length(reader: &mut dyn TransformStream) -> Result<Option<(u64, f64)>, LastLegendError> {
+    let mut magic = [0; 4];
+    let is_ogg = rewind(reader)?.read_exact(&mut magic).is_ok() && magic == *b"OggS";
+    if is_ogg {
+        let length = ogg_vorbis_length(reader)
+            .map_err(|e| LastLegendError::Io("Couldn't read the OGG length".into(), e))?;
+        if length.is_none() {
+            log::debug!("Couldn't find the length of the OGG stream");
+        }
+        return Ok(length);
+    }
+
     let probe_args = ArgBuilder::new()
         .add_all(GENERAL_FFMPEG_INSTRUCTIONS)
         .add_all(get_ffmpeg_loglevel())
-        .add_kv("-i", looped_cache_file.path())
-        .add_kv("-show_entries", "stream=duration")
+        .add_kv("-i", "pipe:")
+        .add_kv("-select_streams", "a:0")
+        .add_kv("-show_entries", "stream=sample_rate,duration")
         .add_kv("-of", "compact=p=0:nk=1")
         .into_vec();
-    log::debug!("Running ffprobe {:?}", probe_args);
-    let audio_probe_output = Command::new("ffprobe")
-        .args(probe_args)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .output()
-        .map_err(|e| LastLegendError::Io("Couldn't run ffprobe".into(), e))?;
-    check_exit(&audio_probe_output)?;
-    let audio_len: f64 = {
-        let duration = String::from_utf8_lossy(&audio_probe_output.stdout)
-            .trim()
-            .to_string();
-        duration.parse().map_err(|_| {
-            LastLegendError::FFMPEG(format!("audio duration wasn't a float but: {}", duration))
-        })?
+    let stdout = run_ffprobe(probe_args, Some(rewind(reader)?))?;
+    let length = match stdout.trim().split('|').collect::<Vec<_>>().as_slice() {
+        &[sample_rate, duration] => sample_rate
+            .parse::<f64>()
+            .ok()
+            .zip(duration.parse::<f64>().ok())
+            .map(|(sample_rate, duration)| ((sample_rate * duration).round() as u64, sample_rate)),
+        _ => None,
     };
+    if length.is_none() {
+        log::debug!("Couldn't get the stream length from ffprobe: {}", stdout);
+    }
+    Ok(length)
+}
 
-    // Run FFMPEG command to taper the end since most rolls are intended to "loop forever".
-    let ffmpeg_args = ArgBuilder::new()
-        .add_all(GENERAL_FFMPEG_INSTRUCTIONS)
-        .add_all(get_ffmpeg_loglevel())
-        .add_arg("-y")
-        .add_kv("-i", looped_cache_file.path())
-        .add_kv(
-            "-af",
-            format!("afade=t=out:st={}:d=5", (audio_len - 5f64).max(0f64)),
-        )
-        .add_kv("-f", ffmpeg_format)
-        .add_arg(original_cache_file.path())
-        .into_vec();
-    log::debug!("Running ffmpeg {:?}", ffmpeg_args);
-    let ffmpeg_taper_output = Command::new("ffmpeg")
-        .args(ffmpeg_args)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .output()
-        .map_err(|e| LastLegendError::Io("Couldn't run ffmpeg".into(), e))?;
-    check_exit(&ffmpeg_taper_output)?;
-
-    std::io::copy(
-        &mut File::open(original_cache_file.path())
-            .map_err(|e| LastLegendError::Io("Couldn't open original cache file".into(), e))?,
-        &mut output,
-    )
-    .map_err(|e| LastLegendError::Io("Couldn't copy from original cache file".into(), e))?;
+/// Get how many extra times to play the loop from [loop_start] to [loop_end], given the
+/// [stream_length] in samples and the sample rate.
+fn loop_count(
+    options: &LoopOptions,
+    loop_start: u64,
+    loop_end: u64,
+    stream_length: Option<(u64, f64)>,
+) -> u32 {
+    let (Some(target), Some((total_samples, sample_rate))) =
+        (options.target_duration, stream_length)
+    else {
+        return options.loop_count;
+    };
+    let missing = target * sample_rate - total_samples as f64;
+    if missing <= 0.0 {
+        return 0;
+    }
+    (missing / (loop_end - loop_start) as f64).ceil() as u32
+}
 
-    Ok(())
+/// Build a filter graph that plays the audio with the loop from [loop_start] to [loop_end]
+/// repeated [loop_count] extra times, like `aloop`, but crossfading over [crossfade_samples] at
+/// each seam instead of cutting. Returns None if there's no room to crossfade.
+///
+/// Each repeat starts [crossfade_samples] before the loop start, so the audio leading into the
+/// loop start is faded in while the end of the loop fades out. The output is the same length as
+/// `aloop`'s. The last repeat runs on to the end of the audio, as that part follows on from the
+/// loop end without a seam.
+fn crossfade_loop_filter(
+    loop_start: u64,
+    loop_end: u64,
+    loop_count: u32,
+    crossfade_samples: u64,
+) -> Option<String> {
+    let crossfade = crossfade_samples.min(loop_start).min(loop_end - loop_start);
+    if crossfade == 0 || loop_count == 0 {
+        return None;
+    }
+    let repeat_start = loop_start - crossfade;
+    let mut filter = format!("asplit={}", loop_count + 1);
+    for i in 0..=loop_count {
+        filter.push_str(&format!("[s{}]", i));
+    }
+    filter.push_str(&format!(";[s0]atrim=end_sample={}[p0]", loop_end));
+    for i in 1..=loop_count {
+        let end = if i == loop_count {
+            String::new()
+        } else {
+            format!(":end_sample={}", loop_end)
+        };
+        filter.push_str(&format!(
+            ";[s{i}]atrim=start_sample={}{},asetpts=PTS-STARTPTS[p{i}]",
+            repeat_start, end
+        ));
+    }
+    let mut previous = "p0".to_string();
+    for i in 1..=loop_count {
+        filter.push_str(&format!(
+            ";[{}][p{i}]acrossfade=ns={}:c1=tri:c2=tri",
+            previous, crossfade
+        ));
+        if i < loop_count {
+            previous = format!("x{}", i);
+            filter.push_str(&format!("[{}]", previous));
+        }
+    }
+    Some(filter)
+}
+
+/// Read the loop points from ffprobe's `tag:KEY=VALUE|...` output. Tag names aren't consistently
+/// cased, e.g. `LoopStart` in the game's own Vorbis headers. Missing tags are 0.
+fn parse_loop_tags(output: &str) -> Result<(u32, u32), LastLegendError> {
+    let (mut loop_start, mut loop_end) = (0, 0);
+    for (key, value) in output
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split('|')
+        .filter_map(|entry| entry.strip_prefix("tag:")?.split_once('='))
+    {
+        let slot = if key.eq_ignore_ascii_case("LOOPSTART") {
+            &mut loop_start
+        } else if key.eq_ignore_ascii_case("LOOPEND") {
+            &mut loop_end
+        } else {
+            continue;
+        };
+        *slot = value.trim().parse().map_err(|_| {
+            LastLegendError::FFMPEG(format!("audio {} wasn't a u32 but: {}", key, value))
+        })?;
+    }
+    Ok((loop_start, loop_end))
+}
+
+/// Work out the loop to make, in samples, or None if there's nothing to loop.
+///
+/// Loop points are normally in samples, but some files have them as byte offsets into the
+/// original stream. A loop point past the end of the audio can't be in samples, so if it fits in
+/// the stream's bytes it's scaled from bytes to samples.
+fn loop_points_in_samples(
+    loop_start: u32,
+    loop_end: u32,
+    total_samples: Option<u64>,
+    stream_bytes: u64,
+) -> Option<(u64, u64)> {
+    let (mut start, mut end) = (u64::from(loop_start), u64::from(loop_end));
+    if start == 0 {
+        return None;
+    }
+    let Some(total_samples) = total_samples.filter(|&t| t > 0) else {
+        return (end > start).then_some((start, end));
+    };
+    let furthest = start.max(end);
+    if furthest > total_samples && furthest <= stream_bytes {
+        log::debug!(
+            "Loop points {}..{} are past the end of {} samples, treating them as bytes of {}",
+            start,
+            end,
+            total_samples,
+            stream_bytes
+        );
+        start = start * total_samples / stream_bytes;
+        end = end * total_samples / stream_bytes;
+    }
+    // N.B. the end is 0 sometimes, which means the end of the audio.
+    if end <= start || end > total_samples {
+        end = total_samples;
+    }
+    (start < end).then_some((start, end))
 }
 
 pub fn format_rewrite(
@@ -172,67 +363,325 @@ pub fn format_rewrite(
     mut reader: impl Read + Send,
     mut output: impl Write + Send,
 ) -> Result<(), LastLegendError> {
-    let mut output_temp = tempfile::NamedTempFile::new()
-        .map_err(|e| LastLegendError::Io("Couldn't create temporary cache file".into(), e))?;
-    let ffmpeg_args = ArgBuilder::new()
+    let builder = ArgBuilder::new()
+        .add_all(GENERAL_FFMPEG_INSTRUCTIONS)
+        .add_all(get_ffmpeg_loglevel())
+        .add_arg("-y")
+        .add_kv("-i", "pipe:")
+        .add_kv("-map_metadata", "0:s:a:0");
+    run_ffmpeg(builder, out_format, Some(&mut reader), None, &mut output)
+}
+
+/// Mixes any channel layout down to stereo. Limiting the matrix to 1 scales the gains down so
+/// that loud surround channels don't clip once summed.
+const DOWNMIX_FILTER: &str = "aresample=out_chlayout=stereo:rematrix_maxval=1.0";
+
+/// Encode audio as [format], using the bitrate, quality and compression level from [options] where
+/// set, downmixing if asked, and adding [tags].
+pub fn encode_audio(
+    format: AudioFormat,
+    options: &EncodeOptions,
+    tags: &[(String, String)],
+    mut reader: impl Read + Send,
+    mut output: impl Write + Send,
+) -> Result<(), LastLegendError> {
+    let mut builder = ArgBuilder::new()
         .add_all(GENERAL_FFMPEG_INSTRUCTIONS)
         .add_all(get_ffmpeg_loglevel())
         .add_arg("-y")
         .add_kv("-i", "pipe:")
         .add_kv("-map_metadata", "0:s:a:0")
-        .add_kv("-f", out_format)
-        .add_arg(output_temp.path())
-        .into_vec();
-    log::debug!("Running ffmpeg {:?}", ffmpeg_args);
+        .add_kv("-c:a", format.encoder());
+    if options.downmix {
+        builder = builder.add_kv("-af", DOWNMIX_FILTER);
+    }
+    if let Some(bitrate) = options.bitrate {
+        builder = builder.add_kv("-b:a", format!("{}k", bitrate));
+    }
+    if let Some(quality) = options.quality {
+        builder = builder.add_kv("-q:a", quality.to_string());
+    }
+    if let Some(level) = options.compression_level {
+        builder = builder.add_kv("-compression_level", level.to_string());
+    }
+    for (key, value) in tags {
+        builder = builder.add_kv("-metadata", format!("{}={}", key, value));
+    }
+    run_ffmpeg(
+        builder,
+        format.ffmpeg_format(),
+        Some(&mut reader),
+        None,
+        &mut output,
+    )
+}
+
+/// Embed tags, and optionally a PNG as the cover art, in a `flac`, `ogg`, `mp3` or `ipod` (`.m4a`)
+/// file, without re-encoding the audio.
+pub fn embed_metadata(
+    ffmpeg_format: &str,
+    mut reader: impl Read + Send,
+    cover_png: Option<&[u8]>,
+    tags: &[(String, String)],
+    mut output: impl Write + Send,
+) -> Result<(), LastLegendError> {
+    let mut second_input = None;
+    // Without another pipe to read it from, the cover has to be in a file.
+    #[cfg(not(unix))]
+    let mut cover_temp = None;
+
+    let mut builder = ArgBuilder::new()
+        .add_all(GENERAL_FFMPEG_INSTRUCTIONS)
+        .add_all(get_ffmpeg_loglevel())
+        .add_arg("-y")
+        .add_kv("-i", "pipe:");
+    builder = match (ffmpeg_format, cover_png) {
+        // The OGG muxer doesn't take pictures as streams, so use the Vorbis comment instead.
+        ("ogg", Some(cover_png)) => builder
+            .add_kv("-map", "0:a")
+            .add_kv("-map_metadata", "0:s:a:0")
+            .add_kv(
+                "-metadata",
+                format!("METADATA_BLOCK_PICTURE={}", flac_picture_block(cover_png)),
+            ),
+        (_, Some(cover_png)) => {
+            #[cfg(unix)]
+            let cover_input = {
+                second_input = Some(cover_png);
+                OsString::from(SECOND_INPUT)
+            };
+            #[cfg(not(unix))]
+            let cover_input = {
+                let cover_temp = cover_temp.insert(ScratchFile::new()?);
+                cover_temp.write_all(cover_png).map_err(|e| {
+                    LastLegendError::Io("Couldn't write temporary cover file".into(), e)
+                })?;
+                OsString::from(cover_temp.path())
+            };
+            builder
+                .add_kv("-i", cover_input)
+                .add_kv("-map", "0:a")
+                .add_kv("-map", "1:v")
+                .add_kv("-disposition:v", "attached_pic")
+                .add_kv("-metadata:s:v", "comment=Cover (front)")
+        }
+        (_, None) => builder
+            .add_kv("-map", "0:a")
+            .add_kv("-map_metadata", "0:s:a:0"),
+    };
+    for (key, value) in tags {
+        builder = builder.add_kv("-metadata", format!("{}={}", key, value));
+    }
+    run_ffmpeg(
+        builder.add_kv("-c", "copy"),
+        ffmpeg_format,
+        Some(&mut reader),
+        second_input,
+        &mut output,
+    )
+}
+
+/// Build a base64 FLAC `PICTURE` block for a front cover PNG, as used in Vorbis comments.
+fn flac_picture_block(cover_png: &[u8]) -> String {
+    const FRONT_COVER: u32 = 3;
+    const MIME: &[u8] = b"image/png";
+    // Width and height live in the IHDR chunk, which is always first.
+    let (width, height) = match cover_png.get(16..24) {
+        Some(dims) => (
+            u32::from_be_bytes(dims[0..4].try_into().unwrap()),
+            u32::from_be_bytes(dims[4..8].try_into().unwrap()),
+        ),
+        None => (0, 0),
+    };
+
+    let mut block = Vec::with_capacity(cover_png.len() + 64);
+    block.extend_from_slice(&FRONT_COVER.to_be_bytes());
+    block.extend_from_slice(&(MIME.len() as u32).to_be_bytes());
+    block.extend_from_slice(MIME);
+    // No description.
+    block.extend_from_slice(&0u32.to_be_bytes());
+    block.extend_from_slice(&width.to_be_bytes());
+    block.extend_from_slice(&height.to_be_bytes());
+    // Color depth, and number of colors for indexed images.
+    block.extend_from_slice(&32u32.to_be_bytes());
+    block.extend_from_slice(&0u32.to_be_bytes());
+    block.extend_from_slice(&u32::try_from(cover_png.len()).unwrap().to_be_bytes());
+    block.extend_from_slice(cover_png);
+    base64::engine::general_purpose::STANDARD.encode(block)
+}
+
+/// Run FFMPEG with [builder]'s arguments, writing [ffmpeg_format] to [output]. The output is
+/// piped straight to [output], unless the muxer needs to seek, see [SEEKING_MUXERS].
+fn run_ffmpeg(
+    builder: ArgBuilder,
+    ffmpeg_format: &str,
+    stdin: Option<&mut (dyn Read + Send)>,
+    second_input: Option<&[u8]>,
+    output: &mut dyn Write,
+) -> Result<(), LastLegendError> {
+    let builder = builder.add_kv("-f", ffmpeg_format);
+    if !SEEKING_MUXERS.contains(&ffmpeg_format) {
+        return run_process(
+            "ffmpeg",
+            builder.add_arg("pipe:1").into_vec(),
+            stdin,
+            second_input,
+            output,
+        );
+    }
+    let mut output_temp = ScratchFile::new()?;
+    let ffmpeg_args = builder.add_arg(output_temp.path()).into_vec();
+    run_process(
+        "ffmpeg",
+        ffmpeg_args,
+        stdin,
+        second_input,
+        &mut std::io::sink(),
+    )?;
+    std::io::copy(output_temp.rewound()?, output)
+        .map_err(|e| LastLegendError::Io("Couldn't copy from temp file".into(), e))?;
+    Ok(())
+}
+
+/// Run ffprobe on [stdin], returning what it printed.
+fn run_ffprobe(
+    probe_args: Vec<OsString>,
+    stdin: Option<&mut (dyn Read + Send)>,
+) -> Result<String, LastLegendError> {
+    let mut stdout = Vec::new();
+    run_process("ffprobe", probe_args, stdin, None, &mut stdout)?;
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Run [program] once no more than the maximum FFMPEG processes are running, with [stdin]
+/// copied to its stdin, [second_input] piped to it as [SECOND_INPUT], and its stdout copied to
+/// [stdout] as it's written, and check that it exited successfully.
+///
+/// A second input can only be piped on Unix.
+fn run_process(
+    program: &str,
+    args: Vec<OsString>,
+    stdin: Option<&mut (dyn Read + Send)>,
+    second_input: Option<&[u8]>,
+    stdout: &mut dyn Write,
+) -> Result<(), LastLegendError> {
+    let _slot = take_process_slot();
+    log::debug!("Running {} {:?}", program, args);
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    #[cfg(not(unix))]
+    assert!(
+        second_input.is_none(),
+        "second inputs are only piped on Unix"
+    );
+    #[cfg(unix)]
+    let second_input = second_input
+        .map(|data| {
+            let (reader, writer) = std::io::pipe()
+                .map_err(|e| LastLegendError::Io(format!("Couldn't pipe to {}", program), e))?;
+            pass_second_input(&mut command, &reader);
+            Ok::<_, LastLegendError>((reader, writer, data))
+        })
+        .transpose()?;
     let mut child = ChildDropGuard(
-        Command::new("ffmpeg")
-            .args(ffmpeg_args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
+        command
             .spawn()
-            .map_err(|e| LastLegendError::Io("Couldn't spawn ffmpeg".into(), e))?,
+            .map_err(|e| LastLegendError::Io(format!("Couldn't spawn {}", program), e))?,
     );
-    let (stdout, stderr) = std::thread::scope(|s| {
-        let mut stdin = child.stdin.take().unwrap();
-        let to_ffmpeg = s.spawn(move || {
-            std::io::copy(&mut reader, &mut stdin)
-                .map_err(|e| LastLegendError::Io("Couldn't copy to ffmpeg".into(), e))?;
-            Ok::<(), LastLegendError>(())
+    let stderr = std::thread::scope(|s| {
+        let to_child = stdin.map(|reader| {
+            let mut child_stdin = child.stdin.take().unwrap();
+            s.spawn(move || copy_to_process(program, reader, &mut child_stdin))
         });
-        let mut stdout = child.stdout.take().unwrap();
-        let stdout_task = s.spawn(move || {
-            let mut stdout_buffer = Vec::new();
-            std::io::copy(&mut stdout, &mut stdout_buffer)
-                .map_err(|e| LastLegendError::Io("Couldn't copy stdout from ffmpeg".into(), e))?;
-            Ok::<_, LastLegendError>(stdout_buffer)
+        #[cfg(unix)]
+        let second_to_child = second_input.map(|(reader, mut writer, mut data)| {
+            // Only the child reads from the pipe now, so it closes once the child exits.
+            drop(reader);
+            s.spawn(move || copy_to_process(program, &mut data, &mut writer))
         });
-        let mut stderr = child.stderr.take().unwrap();
+        let mut child_stderr = child.stderr.take().unwrap();
         let stderr_task = s.spawn(move || {
             let mut stderr_buffer = Vec::new();
-            std::io::copy(&mut stderr, &mut stderr_buffer)
-                .map_err(|e| LastLegendError::Io("Couldn't copy stderr from ffmpeg".into(), e))?;
+            std::io::copy(&mut child_stderr, &mut stderr_buffer).map_err(|e| {
+                LastLegendError::Io(format!("Couldn't copy stderr from {}", program), e)
+            })?;
             Ok::<_, LastLegendError>(stderr_buffer)
         });
-        to_ffmpeg.join().expect("join error")?;
-        let stdout = stdout_task.join().expect("join error")?;
+        let mut child_stdout = child.stdout.take().unwrap();
+        let from_child = std::io::copy(&mut child_stdout, stdout);
+        if from_child.is_err() {
+            // Otherwise it could block writing to us, and the other copies never finish.
+            drop(child_stdout);
+            let _ = child.kill();
+        }
+        if let Some(to_child) = to_child {
+            to_child.join().expect("join error")?;
+        }
+        #[cfg(unix)]
+        if let Some(second_to_child) = second_to_child {
+            second_to_child.join().expect("join error")?;
+        }
         let stderr = stderr_task.join().expect("join error")?;
+        from_child.map_err(|e| {
+            LastLegendError::Io(format!("Couldn't copy stdout from {}", program), e)
+        })?;
 
-        Ok::<_, LastLegendError>((stdout, stderr))
+        Ok::<_, LastLegendError>(stderr)
     })?;
     let exit = child
         .0
         .wait()
-        .map_err(|e| LastLegendError::Io("Couldn't wait for ffmpeg".into(), e))?;
+        .map_err(|e| LastLegendError::Io(format!("Couldn't wait for {}", program), e))?;
     check_exit(&Output {
         status: exit,
         stderr,
-        stdout,
-    })?;
+        stdout: Vec::new(),
+    })
+}
 
-    std::io::copy(output_temp.as_file_mut(), &mut output)
-        .map_err(|e| LastLegendError::Io("Couldn't copy from temp file".into(), e))?;
-    Ok(())
+/// Copy [input] to a process. It may stop reading once it has what it needs, like ffprobe reading
+/// headers or FFMPEG cut short with `-t`, so a closed pipe is left for its exit status to judge.
+fn copy_to_process(
+    program: &str,
+    input: &mut (dyn Read + Send),
+    to_process: &mut dyn Write,
+) -> Result<(), LastLegendError> {
+    match std::io::copy(input, to_process) {
+        Err(e) if e.kind() != ErrorKind::BrokenPipe => Err(LastLegendError::Io(
+            format!("Couldn't copy to {}", program),
+            e,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Make [command] read [reader] from [SECOND_INPUT_FD].
+#[cfg(unix)]
+fn pass_second_input(command: &mut Command, reader: &std::io::PipeReader) {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::process::CommandExt;
+
+    let source = reader.as_raw_fd();
+    // SAFETY: dup2 and fcntl are async-signal-safe, and only touch the child's descriptors.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::dup2(source, SECOND_INPUT_FD) == -1
+                // If the pipe was already there, dup2 left it to close on exec.
+                || libc::fcntl(SECOND_INPUT_FD, libc::F_SETFD, 0) == -1
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
 }
 
 fn get_ffmpeg_loglevel() -> [&'static str; 2] {
@@ -244,6 +693,7 @@ fn get_ffmpeg_loglevel() -> [&'static str; 2] {
 
 fn check_exit(output: &Output) -> Result<(), LastLegendError> {
     if !output.status.success() {
+        metrics::global().increment(Metric::FfmpegFailures);
         return Err(LastLegendError::FFMPEG(format!(
             "exit code {}, {}",
             output.status,
@@ -277,3 +727,89 @@ impl DerefMut for ChildDropGuard {
         &mut self.0
     }
 }
+
+#[cfg(test)]
+mod ffmpeg_tests {
+    use super::*;
+
+    #[test]
+    fn loop_tags_are_read_by_name() {
+        assert_eq!(
+            parse_loop_tags("tag:LoopEnd=5000|tag:LoopStart=1000\n").unwrap(),
+            (1000, 5000)
+        );
+        assert_eq!(
+            parse_loop_tags("tag:LOOPSTART=20|tag:LOOPEND=30").unwrap(),
+            (20, 30)
+        );
+        assert_eq!(parse_loop_tags("").unwrap(), (0, 0));
+        assert!(parse_loop_tags("tag:LOOPSTART=soon").is_err());
+    }
+
+    #[test]
+    fn loop_points_in_samples_are_kept() {
+        assert_eq!(
+            loop_points_in_samples(1000, 5000, Some(10_000), 80_000),
+            Some((1000, 5000))
+        );
+        assert_eq!(loop_points_in_samples(0, 5000, Some(10_000), 80_000), None);
+    }
+
+    #[test]
+    fn loop_points_in_bytes_are_scaled() {
+        assert_eq!(
+            loop_points_in_samples(20_000, 60_000, Some(10_000), 80_000),
+            Some((2500, 7500))
+        );
+    }
+
+    #[test]
+    fn loop_count_reaches_target_duration() {
+        let options = LoopOptions {
+            target_duration: Some(60.0),
+            ..LoopOptions::default()
+        };
+        // 10 seconds at 1000Hz, with a 4 second loop.
+        assert_eq!(
+            loop_count(&options, 6000, 10_000, Some((10_000, 1000.0))),
+            13
+        );
+        assert_eq!(
+            loop_count(
+                &LoopOptions::default(),
+                6000,
+                10_000,
+                Some((10_000, 1000.0))
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn crossfade_loop_filter_chains_repeats() {
+        assert_eq!(
+            crossfade_loop_filter(1000, 5000, 2, 100).unwrap(),
+            "asplit=3[s0][s1][s2]\
+            ;[s0]atrim=end_sample=5000[p0]\
+            ;[s1]atrim=start_sample=900:end_sample=5000,asetpts=PTS-STARTPTS[p1]\
+            ;[s2]atrim=start_sample=900,asetpts=PTS-STARTPTS[p2]\
+            ;[p0][p1]acrossfade=ns=100:c1=tri:c2=tri[x1]\
+            ;[x1][p2]acrossfade=ns=100:c1=tri:c2=tri"
+        );
+        // The crossfade can't reach back past the start of the audio.
+        assert!(crossfade_loop_filter(50, 5000, 1, 100)
+            .unwrap()
+            .contains("acrossfade=ns=50:"));
+        assert_eq!(crossfade_loop_filter(1000, 5000, 0, 100), None);
+        assert_eq!(crossfade_loop_filter(1000, 5000, 2, 0), None);
+    }
+
+    #[test]
+    fn missing_loop_end_is_end_of_stream() {
+        assert_eq!(
+            loop_points_in_samples(1000, 0, Some(10_000), 80_000),
+            Some((1000, 10_000))
+        );
+        assert_eq!(loop_points_in_samples(1000, 0, None, 80_000), None);
+    }
+}
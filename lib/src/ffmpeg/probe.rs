@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::process::{Command, Stdio};
+
+use crate::error::LastLegendError;
+use crate::transformers::TransformerImpl;
+
+/// Something FFMPEG must provide for a transformer to work.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum FfmpegRequirement {
+    /// The `ffprobe` binary, used to read loop points and durations.
+    Ffprobe,
+    Filter(&'static str),
+    Encoder(&'static str),
+}
+
+impl Display for FfmpegRequirement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ffprobe => write!(f, "the ffprobe binary"),
+            Self::Filter(name) => write!(f, "the {} filter", name),
+            Self::Encoder(name) => write!(f, "the {} encoder", name),
+        }
+    }
+}
+
+/// Encoders and filters that are worth reporting, even if no transformer needs them yet.
+const REPORTED: [FfmpegRequirement; 6] = [
+    FfmpegRequirement::Filter("aloop"),
+    FfmpegRequirement::Filter("afade"),
+    FfmpegRequirement::Encoder("flac"),
+    FfmpegRequirement::Encoder("libvorbis"),
+    FfmpegRequirement::Encoder("libopus"),
+    FfmpegRequirement::Encoder("pcm_s16le"),
+];
+
+/// What the installed FFMPEG can do.
+#[derive(Debug)]
+pub struct FfmpegCapabilities {
+    /// The version, as FFMPEG reports it, e.g. `6.1.1`.
+    pub version: String,
+    pub ffprobe: bool,
+    filters: HashSet<String>,
+    encoders: HashSet<String>,
+}
+
+impl FfmpegCapabilities {
+    /// Run `ffmpeg` and `ffprobe` to find out what they support.
+    pub fn probe() -> Result<Self, LastLegendError> {
+        let version_output = run_for_stdout("ffmpeg", "-version")?;
+        let version = version_output
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("ffmpeg version "))
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap_or("unknown")
+            .to_string();
+        Ok(Self {
+            version,
+            ffprobe: run_for_stdout("ffprobe", "-version").is_ok(),
+            filters: parse_listing(&run_for_stdout("ffmpeg", "-filters")?),
+            encoders: parse_listing(&run_for_stdout("ffmpeg", "-encoders")?),
+        })
+    }
+
+    pub fn has(&self, requirement: FfmpegRequirement) -> bool {
+        match requirement {
+            FfmpegRequirement::Ffprobe => self.ffprobe,
+            FfmpegRequirement::Filter(name) => self.filters.contains(name),
+            FfmpegRequirement::Encoder(name) => self.encoders.contains(name),
+        }
+    }
+
+    /// Get the requirements of [transformers] that this FFMPEG doesn't meet.
+    pub fn missing_for(&self, transformers: &[TransformerImpl]) -> Vec<FfmpegRequirement> {
+        let mut missing = Vec::new();
+        for requirement in transformers.iter().flat_map(|t| t.ffmpeg_requirements()) {
+            if !self.has(*requirement) && !missing.contains(requirement) {
+                missing.push(*requirement);
+            }
+        }
+        missing
+    }
+
+    /// Describe the version and which of the commonly needed features are present.
+    pub fn summary(&self) -> String {
+        let features = REPORTED
+            .iter()
+            .chain(&[FfmpegRequirement::Ffprobe])
+            .map(|r| format!("{} {}", if self.has(*r) { "+" } else { "-" }, r))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("FFMPEG {}: {}", self.version, features)
+    }
+}
+
+fn run_for_stdout(program: &str, arg: &str) -> Result<String, LastLegendError> {
+    let output = Command::new(program)
+        .args(["-hide_banner", arg])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                LastLegendError::FFMPEG(format!("{} is not installed or not on the PATH", program))
+            }
+            _ => LastLegendError::Io(format!("Couldn't run {}", program), e),
+        })?;
+    if !output.status.success() {
+        return Err(LastLegendError::FFMPEG(format!(
+            "{} {} exited with {}",
+            program, arg, output.status
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Get the names from `ffmpeg -filters` or `ffmpeg -encoders`. Each entry is a column of flags
+/// followed by the name, the legend at the top uses `=` in place of the name.
+fn parse_listing(listing: &str) -> HashSet<String> {
+    listing
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let flags = parts.next()?;
+            let name = parts.next()?;
+            (flags.len() >= 3
+                && flags
+                    .chars()
+                    .all(|c| c == '.' || c.is_ascii_uppercase() || c == '|')
+                && name != "=")
+                .then(|| name.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod probe_tests {
+    use super::*;
+
+    #[test]
+    fn parses_filters_and_encoders() {
+        let filters = parse_listing(
+            "Filters:
+  T.. = Timeline support
+  ..C = Command support
+ ... aloop             A->A       Loop audio samples.
+ T.C afade             A->A       Fade in/out input audio.
+",
+        );
+        assert_eq!(
+            filters,
+            HashSet::from(["aloop".to_string(), "afade".to_string()])
+        );
+
+        let encoders = parse_listing(
+            "Encoders:
+ V..... = Video
+ ------
+ A....D flac                 FLAC (Free Lossless Audio Codec)
+ A..... libvorbis            libvorbis (codec vorbis)
+",
+        );
+        assert!(encoders.contains("flac"));
+        assert!(encoders.contains("libvorbis"));
+        assert!(!encoders.contains("="));
+        assert_eq!(encoders.len(), 2);
+    }
+}
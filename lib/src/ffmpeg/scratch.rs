@@ -0,0 +1,234 @@
+//! Temporary files for passing audio to and from FFMPEG, reused within each thread.
+//!
+//! FFMPEG stages are piped, but muxers that seek, such as FLAC's, need a file to write, see
+//! [ffmpeg](crate::ffmpeg), and creating fresh ones for each stage of each file under heavy
+//! parallelism churns through file descriptors. Instead, each thread keeps a few emptied files
+//! around for the next stage to use.
+//!
+//! Transformer output goes through a [Spool], which moves to a scratch file once it's large, so
+//! large output, like cutscene audio converted to WAV, isn't held in memory next to its input.
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+use tempfile::NamedTempFile;
+
+use crate::error::LastLegendError;
+use crate::transformers::TransformStream;
+
+/// The most files each thread keeps for reuse, enough for the busiest stage.
+pub(crate) const MAX_POOLED_PER_THREAD: usize = 3;
+
+/// The most a [Spool] keeps in memory before moving to a scratch file.
+const SPOOL_MEMORY_BYTES: usize = 8 * 1024 * 1024;
+
+thread_local! {
+    /// Files ready for reuse. They're deleted when the thread exits.
+    static POOL: RefCell<Vec<NamedTempFile>> = const { RefCell::new(Vec::new()) };
+}
+
+/// An empty temporary file. It's returned to this thread's pool when dropped, or deleted if the
+/// pool is full or the thread is panicking.
+#[derive(Debug)]
+pub(crate) struct ScratchFile {
+    file: Option<NamedTempFile>,
+}
+
+impl ScratchFile {
+    /// Take an empty file from this thread's pool, or create one.
+    pub(crate) fn new() -> Result<Self, LastLegendError> {
+        let file = match POOL.with_borrow_mut(Vec::pop) {
+            Some(mut file) => {
+                file.as_file_mut()
+                    .set_len(0)
+                    .and_then(|_| file.seek(SeekFrom::Start(0)).map(|_| ()))
+                    .map_err(|e| LastLegendError::Io("Couldn't empty scratch file".into(), e))?;
+                file
+            }
+            None => NamedTempFile::new()
+                .map_err(|e| LastLegendError::Io("Couldn't create scratch file".into(), e))?,
+        };
+        Ok(Self { file: Some(file) })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.inner().path()
+    }
+
+    /// The file, positioned at the start.
+    pub(crate) fn rewound(&mut self) -> Result<&mut File, LastLegendError> {
+        let file: &mut File = self;
+        file.seek(SeekFrom::Start(0))
+            .map_err(|e| LastLegendError::Io("Couldn't rewind scratch file".into(), e))?;
+        Ok(file)
+    }
+
+    fn inner(&self) -> &NamedTempFile {
+        self.file.as_ref().expect("only taken on drop")
+    }
+}
+
+impl Deref for ScratchFile {
+    type Target = File;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner().as_file()
+    }
+}
+
+impl DerefMut for ScratchFile {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.file
+            .as_mut()
+            .expect("only taken on drop")
+            .as_file_mut()
+    }
+}
+
+impl Read for ScratchFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        File::read(self, buf)
+    }
+}
+
+impl Write for ScratchFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        File::write(self, buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        File::flush(self)
+    }
+}
+
+impl Seek for ScratchFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        File::seek(self, pos)
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let Some(file) = self.file.take() else {
+            return;
+        };
+        // A panic may have left the file in any state, so don't reuse it.
+        if std::thread::panicking() {
+            return;
+        }
+        // The pool is gone if the thread is exiting, in which case the file is deleted here.
+        let _ = POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() < MAX_POOLED_PER_THREAD {
+                pool.push(file);
+            }
+        });
+    }
+}
+
+/// Somewhere to write output that's read back afterwards. It's kept in memory until it grows
+/// past [SPOOL_MEMORY_BYTES], then moved to a [ScratchFile].
+#[derive(Debug, Default)]
+pub(crate) struct Spool {
+    memory: Vec<u8>,
+    file: Option<ScratchFile>,
+}
+
+impl Spool {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read back what was written, from the start.
+    pub(crate) fn into_stream(self) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        match self.file {
+            Some(mut file) => {
+                file.rewound()?;
+                Ok(Box::new(file))
+            }
+            None => Ok(Box::new(Cursor::new(self.memory))),
+        }
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.file.is_none() && self.memory.len() + buf.len() > SPOOL_MEMORY_BYTES {
+            let mut file = ScratchFile::new().map_err(|e| std::io::Error::other(e.to_string()))?;
+            file.write_all(&self.memory)?;
+            self.memory = Vec::new();
+            self.file = Some(file);
+        }
+        match &mut self.file {
+            Some(file) => file.write(buf),
+            None => self.memory.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod scratch_tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    #[test]
+    fn reused_files_are_empty() {
+        let path = {
+            let mut file = ScratchFile::new().unwrap();
+            file.write_all(b"leftover").unwrap();
+            file.path().to_path_buf()
+        };
+        let mut file = ScratchFile::new().unwrap();
+        assert_eq!(file.path(), path);
+        let mut content = Vec::new();
+        file.rewound().unwrap().read_to_end(&mut content).unwrap();
+        assert!(content.is_empty());
+    }
+
+    #[test]
+    fn large_spools_move_to_a_file() {
+        let mut small = Spool::new();
+        small.write_all(b"short").unwrap();
+        assert!(small.file.is_none());
+
+        let mut large = Spool::new();
+        let chunk = vec![7; SPOOL_MEMORY_BYTES / 2 + 1];
+        large.write_all(&chunk).unwrap();
+        large.write_all(&chunk).unwrap();
+        assert!(large.file.is_some() && large.memory.is_empty());
+
+        let mut content = Vec::new();
+        large
+            .into_stream()
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content.len(), chunk.len() * 2);
+    }
+
+    #[test]
+    fn panicking_deletes_file() {
+        let path = std::thread::spawn(|| {
+            let file = ScratchFile::new().unwrap();
+            let path = file.path().to_path_buf();
+            let _ = std::panic::catch_unwind(move || {
+                let _file = file;
+                panic!("stage failed");
+            });
+            path
+        })
+        .join()
+        .unwrap();
+        assert!(!path.exists());
+    }
+}
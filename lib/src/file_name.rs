@@ -0,0 +1,199 @@
+//! Turning sheet strings, such as Orchestrion titles, into file names.
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Start of a SeString payload, which runs until [PAYLOAD_END].
+const PAYLOAD_START: char = '\u{2}';
+const PAYLOAD_END: char = '\u{3}';
+
+/// Names Windows won't create files with, regardless of extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// How to turn a string into a file name.
+#[derive(Debug, Copy, Clone)]
+pub struct FileNameOptions {
+    /// Transliterate to ASCII, for filesystems or players that don't handle other characters.
+    /// Characters without an ASCII form are replaced.
+    pub ascii: bool,
+    /// What to use in place of characters that can't be in a file name.
+    pub replacement: char,
+}
+
+impl Default for FileNameOptions {
+    fn default() -> Self {
+        Self {
+            ascii: false,
+            replacement: '_',
+        }
+    }
+}
+
+/// Make [name] safe to use as a file name on any platform.
+///
+/// The name is NFC normalized, and SeString payloads are removed, except for the ones that stand
+/// for text like dashes and spaces. Characters that some platforms don't allow in file names are
+/// replaced, and trailing dots and spaces are removed.
+pub fn sanitize_file_name(name: &str, options: &FileNameOptions) -> String {
+    let text = strip_payloads(name);
+    let mut output = String::with_capacity(text.len());
+    let mut last_space = true;
+    for c in text.nfc() {
+        let c = if c.is_whitespace() { ' ' } else { c };
+        if c == ' ' {
+            // Collapse runs of whitespace, which mostly come from removed payloads.
+            if !last_space {
+                output.push(' ');
+            }
+            last_space = true;
+            continue;
+        }
+        last_space = false;
+        if c.is_control() {
+            continue;
+        }
+        if "<>:\"/\\|?*".contains(c) {
+            output.push(options.replacement);
+        } else if options.ascii && !c.is_ascii() {
+            push_ascii(&mut output, c, options.replacement);
+        } else {
+            output.push(c);
+        }
+    }
+
+    let trimmed_len = output.trim_end_matches(['.', ' ']).len();
+    output.truncate(trimmed_len);
+    if output.is_empty() {
+        return options.replacement.to_string();
+    }
+    let stem = output.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        output.insert(0, options.replacement);
+    }
+    output
+}
+
+/// Remove SeString payloads from [text], keeping the text the simple ones stand for.
+fn strip_payloads(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != PAYLOAD_START {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            // New line
+            Some('\u{10}') => output.push(' '),
+            // Non-breaking space
+            Some('\u{1D}') => output.push(' '),
+            // Dash
+            Some('\u{1F}') => output.push('-'),
+            // Soft hyphen, and everything that isn't text
+            _ => {}
+        }
+        for c in chars.by_ref() {
+            if c == PAYLOAD_END {
+                break;
+            }
+        }
+    }
+    output
+}
+
+/// Push an ASCII form of [c], or [replacement] if it doesn't have one.
+fn push_ascii(output: &mut String, c: char, replacement: char) {
+    let punctuation = match c {
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => Some("-"),
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{2032}' => Some("'"),
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{2033}' => Some("''"),
+        '\u{2026}' => Some("..."),
+        '\u{00D7}' => Some("x"),
+        '\u{00C6}' => Some("AE"),
+        '\u{00E6}' => Some("ae"),
+        '\u{00DF}' => Some("ss"),
+        '\u{0152}' => Some("OE"),
+        '\u{0153}' => Some("oe"),
+        '\u{00D8}' => Some("O"),
+        '\u{00F8}' => Some("o"),
+        _ => None,
+    };
+    if let Some(text) = punctuation {
+        output.push_str(text);
+        return;
+    }
+    // Split off accents, and fold compatibility forms like full-width letters.
+    let mut found = false;
+    for d in std::iter::once(c).nfkd() {
+        if d.is_ascii() && !d.is_control() {
+            if "<>:\"/\\|?*".contains(d) {
+                output.push(replacement);
+            } else {
+                output.push(d);
+            }
+            found = true;
+        } else if !is_combining_mark(d) {
+            break;
+        }
+    }
+    if !found {
+        output.push(replacement);
+    }
+}
+
+#[cfg(test)]
+mod file_name_tests {
+    use crate::file_name::{sanitize_file_name, FileNameOptions};
+
+    #[test]
+    fn replaces_reserved_characters() {
+        let options = FileNameOptions::default();
+        assert_eq!(
+            "Answers_ Now and Then",
+            sanitize_file_name("Answers: Now and Then", &options)
+        );
+        assert_eq!("_con", sanitize_file_name("con", &options));
+        assert_eq!(
+            "Tribal Drums",
+            sanitize_file_name("Tribal Drums... ", &options)
+        );
+    }
+
+    #[test]
+    fn strips_payloads() {
+        let options = FileNameOptions::default();
+        assert_eq!(
+            "Heavensward - Dragonsong",
+            sanitize_file_name("Heavensward \u{2}\u{1F}\u{1}\u{3} Dragonsong", &options)
+        );
+        assert_eq!(
+            "Flow",
+            sanitize_file_name(
+                "\u{2}\u{48}\u{4}\u{F2}\u{2}\u{D}\u{3}Flow\u{2}\u{16}\u{1}\u{3}",
+                &options
+            )
+        );
+    }
+
+    #[test]
+    fn normalizes_and_transliterates() {
+        let default = FileNameOptions::default();
+        // `e` followed by a combining acute accent is composed.
+        assert_eq!("Café", sanitize_file_name("Cafe\u{301}", &default));
+
+        let ascii = FileNameOptions {
+            ascii: true,
+            ..default
+        };
+        assert_eq!(
+            "Cafe - ''Good'' Night... Again",
+            sanitize_file_name(
+                "Café \u{2014} \u{201C}Good\u{201D} Night\u{2026} Again",
+                &ascii
+            )
+        );
+        assert_eq!("FFXIV __", sanitize_file_name("ＦＦＸＩＶ 光の", &ascii));
+    }
+}
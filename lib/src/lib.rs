@@ -1,11 +1,31 @@
+pub mod archive;
+pub mod avfx;
+pub mod cancel;
 pub mod data;
+pub mod discovery;
 pub mod error;
-pub(crate) mod ffmpeg;
+pub mod extract_cache;
+pub mod ffmpeg;
+pub mod file_name;
 pub(crate) mod io_tricks;
+pub mod limits;
+pub mod manifest;
+pub mod metrics;
+pub mod modpack;
+#[cfg(feature = "native-audio")]
+pub(crate) mod native_audio;
+pub mod path_db;
+pub mod path_list;
+pub mod path_pack;
+pub mod playlist;
+pub mod prelude;
+pub mod references;
+pub mod scd;
 pub mod simple_task;
 pub mod sqpath;
 pub mod surpass;
 pub mod transformers;
 pub mod tricks;
 pub mod uwu_colors;
+pub mod warning;
 pub(crate) mod xor;
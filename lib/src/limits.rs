@@ -0,0 +1,79 @@
+//! Caps on the sizes read from files before allocating for them, so a corrupt or hostile
+//! repository fails to parse instead of exhausting memory.
+
+/// The largest sizes parsers accept from the files they read. The defaults are well above what
+/// the game's own files use, servers reading untrusted repositories can lower them, and they can
+/// be raised for files that legitimately go past them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The most entries an index file can have.
+    pub max_index_entries: u32,
+    /// The most bytes a dat block can decompress to.
+    pub max_block_size: u32,
+    /// The most bytes the Vorbis header, or the seek table, of an OGG sound entry can have.
+    pub max_vorbis_header_size: u32,
+    /// The most bytes a sheet row can have, including its strings and any sub-rows.
+    pub max_row_size: u32,
+    /// How deeply the blocks of an `.avfx` file are parsed. Deeper blocks are kept as values.
+    pub max_avfx_depth: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_index_entries: 1 << 22,
+            // Blocks are at most 16000 bytes, anything much larger is corrupt.
+            max_block_size: 0x10000,
+            max_vorbis_header_size: 1 << 20,
+            max_row_size: 1 << 20,
+            // The game's effects nest a handful of blocks deep.
+            max_avfx_depth: 32,
+        }
+    }
+}
+
+impl Limits {
+    /// The names [set](Self::set) accepts, the same as the fields.
+    pub const NAMES: &'static [&'static str] = &[
+        "max_index_entries",
+        "max_block_size",
+        "max_vorbis_header_size",
+        "max_row_size",
+        "max_avfx_depth",
+    ];
+
+    /// Set the limit called [name] to [value], failing if there's no such limit.
+    pub fn set(&mut self, name: &str, value: u32) -> Result<(), String> {
+        let limit = match name {
+            "max_index_entries" => &mut self.max_index_entries,
+            "max_block_size" => &mut self.max_block_size,
+            "max_vorbis_header_size" => &mut self.max_vorbis_header_size,
+            "max_row_size" => &mut self.max_row_size,
+            "max_avfx_depth" => &mut self.max_avfx_depth,
+            _ => {
+                return Err(format!(
+                    "Unknown limit '{}', expected one of {}",
+                    name,
+                    Self::NAMES.join(", ")
+                ))
+            }
+        };
+        *limit = value;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod limits_tests {
+    use super::*;
+
+    #[test]
+    fn every_name_can_be_set() {
+        for name in Limits::NAMES {
+            let mut limits = Limits::default();
+            limits.set(name, 7).unwrap();
+            assert_ne!(limits, Limits::default(), "{}", name);
+        }
+        assert!(Limits::default().set("max_everything", 7).is_err());
+    }
+}
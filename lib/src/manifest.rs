@@ -0,0 +1,325 @@
+//! A record of where entries were found in a repository, for use by external tools.
+//!
+//! Manifests are JSON. The layout is versioned by [Manifest::manifest_version]: fields may be added
+//! without changing it, so readers should ignore fields they don't know, but a new version means
+//! existing fields changed meaning. Manifests from before versioning have no version field, and
+//! read as version 0, which has the same layout as version 1.
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::data::dat_writer::ALIGNMENT as ENTRY_ALIGNMENT;
+use crate::data::index2::{Index2, Index2Entry, PackedInfo};
+use crate::error::LastLegendError;
+use crate::sqpath::SqPath;
+use crate::transformers::TransformerImpl;
+
+/// The manifest version written by this library, and the newest it can read.
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of the manifest layout, see the [module docs](self).
+    #[serde(default)]
+    pub manifest_version: u32,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+/// Where a single entry was found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The path of the entry, or the name it was extracted as if its path isn't known.
+    pub path: String,
+    /// The hash the index stores for the entry. For `.index2` files, this is the hash of the full
+    /// path, and for `.index` files, the hash of the file name.
+    pub hash: u32,
+    /// The index file, relative to the repository.
+    pub index_file: PathBuf,
+    /// Which dat file of the index the entry is in, e.g. 1 for `.dat1`.
+    pub data_file_id: u32,
+    /// Where the entry starts in the dat file, in bytes.
+    pub offset: u64,
+    /// Extra tags given for the entry's output, e.g. `ALBUM`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// The transformers the entry's output was made with, with their options, in the form
+    /// [TransformerImpl] parses. Recorded so the output can be reproduced exactly.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transformers: Vec<String>,
+}
+
+impl ManifestEntry {
+    pub fn new<P: AsRef<Path>, F: AsRef<SqPath>>(
+        repo_path: P,
+        index: &Index2,
+        entry: &Index2Entry,
+        file: F,
+    ) -> Self {
+        Self {
+            path: file.as_ref().as_str().to_string(),
+            hash: entry.hash,
+            index_file: index
+                .index_path
+                .strip_prefix(repo_path)
+                .unwrap_or(&index.index_path)
+                .to_path_buf(),
+            data_file_id: entry.data_file_id,
+            offset: entry.offset_bytes,
+            tags: BTreeMap::new(),
+            transformers: Vec::new(),
+        }
+    }
+}
+
+/// Something wrong with a manifest, found by [Manifest::validate].
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestProblem {
+    /// The position of the entry with the problem.
+    pub entry: usize,
+    pub message: String,
+}
+
+impl Manifest {
+    /// Make a manifest of [entries], at the current [MANIFEST_VERSION].
+    pub fn new(entries: Vec<ManifestEntry>) -> Self {
+        Self {
+            manifest_version: MANIFEST_VERSION,
+            entries,
+        }
+    }
+
+    /// Read a manifest, failing if it's a newer version than this library knows.
+    pub fn read_json<R: Read>(reader: R) -> Result<Self, LastLegendError> {
+        let manifest: Self = serde_json::from_reader(reader)
+            .map_err(|e| LastLegendError::Json("Couldn't read manifest".into(), e))?;
+        if manifest.manifest_version > MANIFEST_VERSION {
+            return Err(LastLegendError::Custom(format!(
+                "Manifest version {} is newer than the newest supported version, {}",
+                manifest.manifest_version, MANIFEST_VERSION
+            )));
+        }
+        Ok(manifest)
+    }
+
+    /// Check that each entry could have come from a repository: index files are relative
+    /// `.index` or `.index2` paths, data file ids and offsets are in range, transformers are valid,
+    /// and no entry is listed twice.
+    pub fn validate(&self) -> Vec<ManifestProblem> {
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            let mut problem = |message: String| {
+                problems.push(ManifestProblem { entry: i, message });
+            };
+            if entry.path.is_empty() {
+                problem("path is empty".into());
+            }
+            let index_file = &entry.index_file;
+            if index_file.is_absolute()
+                || index_file
+                    .components()
+                    .any(|c| !matches!(c, std::path::Component::Normal(_)))
+            {
+                problem(format!(
+                    "index file {} isn't relative to the repository",
+                    index_file.display()
+                ));
+            }
+            if !index_file
+                .extension()
+                .is_some_and(|e| e == "index" || e == "index2")
+            {
+                problem(format!(
+                    "index file {} isn't an index",
+                    index_file.display()
+                ));
+            }
+            if entry.data_file_id >= PackedInfo::MAX_DATA_FILES {
+                problem(format!("data file id {} is too big", entry.data_file_id));
+            }
+            if entry.offset % ENTRY_ALIGNMENT != 0 {
+                problem(format!("offset {:#x} isn't aligned", entry.offset));
+            }
+            for transformer in &entry.transformers {
+                if let Err(e) = transformer.parse::<TransformerImpl>() {
+                    problem(e.to_string());
+                }
+            }
+            if !seen.insert(Journal::key(entry)) {
+                problem("entry is listed more than once".into());
+            }
+        }
+        problems
+    }
+
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), LastLegendError> {
+        serde_json::to_writer_pretty(writer, self)
+            .map_err(|e| LastLegendError::Json("Couldn't write manifest".into(), e))
+    }
+}
+
+/// An append-only record of completed entries, one [ManifestEntry] as JSON per line.
+///
+/// Entries are matched by where they are in the repository, not by output file, so a run can be
+/// resumed even if its outputs were moved in the meantime.
+#[derive(Debug)]
+pub struct Journal {
+    completed: HashSet<(PathBuf, u32, u32, u64)>,
+    writer: Mutex<File>,
+}
+
+impl Journal {
+    /// Open the journal at [path], creating it if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LastLegendError> {
+        let path = path.as_ref();
+        let mut completed = HashSet::new();
+        let existing = match std::fs::read_to_string(path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(LastLegendError::Io("Couldn't read journal".into(), e)),
+        };
+        for line in existing.lines() {
+            // The last line may be cut off if the previous run was killed mid-write.
+            match serde_json::from_str::<ManifestEntry>(line) {
+                Ok(entry) => {
+                    completed.insert(Self::key(&entry));
+                }
+                Err(e) => log::debug!("Skipping bad journal line {:?}: {}", line, e),
+            }
+        }
+        let mut writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| LastLegendError::Io("Couldn't open journal for writing".into(), e))?;
+        if !existing.is_empty() && !existing.ends_with('\n') {
+            // Don't append to a cut off line.
+            writer
+                .write_all(b"\n")
+                .map_err(|e| LastLegendError::Io("Couldn't write journal".into(), e))?;
+        }
+        Ok(Self {
+            completed,
+            writer: Mutex::new(writer),
+        })
+    }
+
+    fn key(entry: &ManifestEntry) -> (PathBuf, u32, u32, u64) {
+        (
+            entry.index_file.clone(),
+            entry.hash,
+            entry.data_file_id,
+            entry.offset,
+        )
+    }
+
+    /// The number of entries completed by previous runs.
+    pub fn completed_count(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Check if [entry] was completed by a previous run.
+    pub fn is_completed(&self, entry: &ManifestEntry) -> bool {
+        self.completed.contains(&Self::key(entry))
+    }
+
+    /// Record that [entry] is complete. The line is written immediately, so it survives a crash.
+    pub fn record(&self, entry: &ManifestEntry) -> Result<(), LastLegendError> {
+        let mut line = serde_json::to_vec(entry)
+            .map_err(|e| LastLegendError::Json("Couldn't write journal entry".into(), e))?;
+        line.push(b'\n');
+        self.writer
+            .lock()
+            .write_all(&line)
+            .map_err(|e| LastLegendError::Io("Couldn't write journal".into(), e))
+    }
+}
+
+#[cfg(test)]
+mod manifest_tests {
+    use super::*;
+
+    fn entry(hash: u32) -> ManifestEntry {
+        ManifestEntry {
+            path: format!("{:X}.dat", hash),
+            hash,
+            index_file: PathBuf::from("ffxiv/0c0000.win32.index2"),
+            data_file_id: 0,
+            offset: 0x800,
+            tags: BTreeMap::new(),
+            transformers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn journal_survives_cut_off_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        {
+            let journal = Journal::open(&path).unwrap();
+            journal.record(&entry(1)).unwrap();
+            journal.record(&entry(2)).unwrap();
+        }
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"path\":\"3.da")
+            .unwrap();
+
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.completed_count(), 2);
+        assert!(journal.is_completed(&entry(1)));
+        assert!(!journal.is_completed(&entry(3)));
+        journal.record(&entry(3)).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.completed_count(), 3);
+    }
+
+    #[test]
+    fn reads_unversioned_and_rejects_newer_manifests() {
+        let old = Manifest::read_json(
+            br#"{"entries":[{"path":"a","hash":1,"index_file":"ffxiv/0c0000.win32.index2",
+                "data_file_id":0,"offset":128}]}"#
+                .as_slice(),
+        )
+        .unwrap();
+        assert_eq!(old.manifest_version, 0);
+        assert_eq!(old.entries.len(), 1);
+
+        let newer = format!(
+            r#"{{"manifest_version":{},"entries":[]}}"#,
+            MANIFEST_VERSION + 1
+        );
+        assert!(Manifest::read_json(newer.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn validate_finds_bad_entries() {
+        let mut bad = entry(2);
+        bad.index_file = PathBuf::from("../0c0000.win32.dat0");
+        bad.offset = 0x801;
+        bad.transformers = vec!["scd_to_flac:quality=5".to_string()];
+        let manifest = Manifest::new(vec![entry(1), bad, entry(1)]);
+        let problems = manifest.validate();
+        assert_eq!(
+            problems.iter().map(|p| p.entry).collect::<Vec<_>>(),
+            [1, 1, 1, 1, 2]
+        );
+        assert!(Manifest::new(vec![entry(1), entry(2)])
+            .validate()
+            .is_empty());
+    }
+}
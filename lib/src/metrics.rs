@@ -0,0 +1,248 @@
+//! Counters for what the process has done, shared by the CLI's summary and the daemon's
+//! `/metrics` endpoint.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use strum::{EnumIter, IntoEnumIterator};
+
+/// A metric that can be recorded.
+#[derive(EnumIter, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Metric {
+    /// Daemon requests, labelled by method.
+    Requests,
+    /// Daemon requests that failed, labelled by method.
+    RequestErrors,
+    ContentCacheHits,
+    ContentCacheMisses,
+    FilesExtracted,
+    /// Time spent extracting files with transformers.
+    TransformSeconds,
+    FfmpegFailures,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum MetricKind {
+    Counter,
+    /// A count of observations and their sum.
+    Summary,
+}
+
+impl Metric {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Requests => "lldob_requests_total",
+            Self::RequestErrors => "lldob_request_errors_total",
+            Self::ContentCacheHits => "lldob_content_cache_hits_total",
+            Self::ContentCacheMisses => "lldob_content_cache_misses_total",
+            Self::FilesExtracted => "lldob_files_extracted_total",
+            Self::TransformSeconds => "lldob_transform_seconds",
+            Self::FfmpegFailures => "lldob_ffmpeg_failures_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Self::Requests => "Requests handled, by method.",
+            Self::RequestErrors => "Requests that failed, by method.",
+            Self::ContentCacheHits => "Entry content served from the content cache.",
+            Self::ContentCacheMisses => "Entry content not in the content cache.",
+            Self::FilesExtracted => "Files extracted.",
+            Self::TransformSeconds => "Time spent extracting files with transformers.",
+            Self::FfmpegFailures => "FFMPEG runs that exited unsuccessfully.",
+        }
+    }
+
+    fn kind(self) -> MetricKind {
+        match self {
+            Self::TransformSeconds => MetricKind::Summary,
+            _ => MetricKind::Counter,
+        }
+    }
+
+    /// The name of the label this metric is split by, if any.
+    fn label_name(self) -> Option<&'static str> {
+        match self {
+            Self::Requests | Self::RequestErrors => Some("method"),
+            _ => None,
+        }
+    }
+}
+
+/// The recorded value of a metric. Counters only use [count].
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct MetricValue {
+    pub count: u64,
+    pub sum: f64,
+}
+
+/// A set of recorded metrics. The process-wide one is [global].
+#[derive(Debug, Default)]
+pub struct Metrics {
+    values: Mutex<BTreeMap<(Metric, Option<String>), MetricValue>>,
+}
+
+static GLOBAL: Metrics = Metrics::new();
+
+/// The metrics recorded by this process.
+pub fn global() -> &'static Metrics {
+    &GLOBAL
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Count one more of [metric].
+    pub fn increment(&self, metric: Metric) {
+        self.values.lock().entry((metric, None)).or_default().count += 1;
+    }
+
+    /// Count one more of [metric] with the given [label] value.
+    pub fn increment_labelled(&self, metric: Metric, label: &str) {
+        self.values
+            .lock()
+            .entry((metric, Some(label.to_string())))
+            .or_default()
+            .count += 1;
+    }
+
+    /// Record that something measured by [metric] took [duration].
+    pub fn observe(&self, metric: Metric, duration: Duration) {
+        let mut values = self.values.lock();
+        let value = values.entry((metric, None)).or_default();
+        value.count += 1;
+        value.sum += duration.as_secs_f64();
+    }
+
+    /// The value of [metric], summed over all labels.
+    pub fn get(&self, metric: Metric) -> MetricValue {
+        self.values
+            .lock()
+            .iter()
+            .filter(|((m, _), _)| *m == metric)
+            .fold(MetricValue::default(), |total, (_, value)| MetricValue {
+                count: total.count + value.count,
+                sum: total.sum + value.sum,
+            })
+    }
+
+    /// Render all metrics in the Prometheus text exposition format. Unlabelled metrics are
+    /// included even if nothing was recorded.
+    pub fn render_prometheus(&self) -> String {
+        let values = self.values.lock();
+        let mut output = String::new();
+        for metric in Metric::iter() {
+            let name = metric.name();
+            let kind = match metric.kind() {
+                MetricKind::Counter => "counter",
+                MetricKind::Summary => "summary",
+            };
+            writeln!(output, "# HELP {} {}", name, metric.help()).unwrap();
+            writeln!(output, "# TYPE {} {}", name, kind).unwrap();
+            let mut recorded = values
+                .iter()
+                .filter(|((m, _), _)| *m == metric)
+                .map(|((_, label), value)| (label.as_deref(), *value))
+                .collect::<Vec<_>>();
+            if recorded.is_empty() && metric.label_name().is_none() {
+                recorded.push((None, MetricValue::default()));
+            }
+            for (label, value) in recorded {
+                let labels = match (metric.label_name(), label) {
+                    (Some(label_name), Some(label)) => {
+                        format!("{{{}=\"{}\"}}", label_name, escape_label(label))
+                    }
+                    _ => String::new(),
+                };
+                match metric.kind() {
+                    MetricKind::Counter => {
+                        writeln!(output, "{}{} {}", name, labels, value.count).unwrap();
+                    }
+                    MetricKind::Summary => {
+                        writeln!(output, "{}_sum{} {}", name, labels, value.sum).unwrap();
+                        writeln!(output, "{}_count{} {}", name, labels, value.count).unwrap();
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    /// A one-line summary of the extraction metrics, or `None` if nothing was extracted and
+    /// nothing failed.
+    pub fn summary(&self) -> Option<String> {
+        let extracted = self.get(Metric::FilesExtracted).count;
+        let transforms = self.get(Metric::TransformSeconds);
+        let ffmpeg_failures = self.get(Metric::FfmpegFailures).count;
+        if extracted == 0 && ffmpeg_failures == 0 {
+            return None;
+        }
+        let mut summary = format!(
+            "Extracted {} file{}",
+            extracted,
+            if extracted == 1 { "" } else { "s" }
+        );
+        if transforms.count > 0 {
+            write!(
+                summary,
+                ", {:.1}s spent on {} transformed",
+                transforms.sum, transforms.count
+            )
+            .unwrap();
+        }
+        if ffmpeg_failures > 0 {
+            write!(
+                summary,
+                ", {} FFMPEG failure{}",
+                ffmpeg_failures,
+                if ffmpeg_failures == 1 { "" } else { "s" }
+            )
+            .unwrap();
+        }
+        Some(summary)
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod metrics_tests {
+    use std::time::Duration;
+
+    use crate::metrics::{Metric, Metrics};
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.increment_labelled(Metric::Requests, "extract");
+        metrics.increment_labelled(Metric::Requests, "extract");
+        metrics.increment_labelled(Metric::Requests, "say \"hi\"");
+        metrics.increment(Metric::FilesExtracted);
+        metrics.observe(Metric::TransformSeconds, Duration::from_millis(1500));
+
+        let output = metrics.render_prometheus();
+        assert!(output.contains("# TYPE lldob_requests_total counter\n"));
+        assert!(output.contains("lldob_requests_total{method=\"extract\"} 2\n"));
+        assert!(output.contains("lldob_requests_total{method=\"say \\\"hi\\\"\"} 1\n"));
+        assert!(output.contains("lldob_files_extracted_total 1\n"));
+        assert!(output.contains("lldob_ffmpeg_failures_total 0\n"));
+        assert!(output.contains("lldob_transform_seconds_sum 1.5\n"));
+        assert!(output.contains("lldob_transform_seconds_count 1\n"));
+        assert!(!output.contains("lldob_request_errors_total{"));
+        assert_eq!(metrics.get(Metric::Requests).count, 3);
+        assert_eq!(
+            metrics.summary().unwrap(),
+            "Extracted 1 file, 1.5s spent on 1 transformed"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Packaging files as mods, for TexTools (`.ttmp2`) and Penumbra.
+use std::collections::BTreeMap;
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::data::dat_writer::DatEntryWriter;
+use crate::error::LastLegendError;
+use crate::sqpath::{SqPath, SqPathBuf};
+
+/// Describes a modpack, shown by mod managers when it's installed.
+#[derive(Debug, Clone, Default)]
+pub struct ModpackMeta {
+    pub name: String,
+    pub author: String,
+    pub version: String,
+    pub description: String,
+    pub website: String,
+}
+
+/// A file to put in a modpack, replacing [game_path] in the game.
+#[derive(Debug, Clone)]
+pub struct ModpackFile {
+    pub game_path: SqPathBuf,
+    pub content: Vec<u8>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct TtmpList<'a> {
+    minimum_framework_version: &'static str,
+    #[serde(rename = "TTMPVersion")]
+    ttmp_version: &'static str,
+    name: &'a str,
+    author: &'a str,
+    version: &'a str,
+    description: &'a str,
+    url: &'a str,
+    mod_pack_pages: Option<()>,
+    simple_mods_list: Vec<TtmpSimpleMod<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct TtmpSimpleMod<'a> {
+    name: &'a str,
+    category: &'static str,
+    full_path: &'a str,
+    mod_offset: u64,
+    mod_size: u64,
+    dat_file: String,
+    is_default: bool,
+    mod_pack_entry: Option<()>,
+}
+
+/// Write a TexTools `.ttmp2` modpack of [files] to [writer].
+///
+/// TexTools stores the files as dat entries, and only plain binary entries can be written, so
+/// models and textures aren't supported.
+pub fn write_ttmp2<W: Write + Seek>(
+    meta: &ModpackMeta,
+    files: &[ModpackFile],
+    writer: W,
+) -> Result<(), LastLegendError> {
+    let mut data = Vec::new();
+    let mut simple_mods_list = Vec::with_capacity(files.len());
+    for file in files {
+        let path = file.game_path.as_str();
+        if path.ends_with(".tex") || path.ends_with(".mdl") {
+            return Err(LastLegendError::Custom(format!(
+                "Can't put '{}' in a .ttmp2, models and textures aren't supported",
+                path
+            )));
+        }
+        let entry = DatEntryWriter::new()
+            .encode(&file.content)
+            .map_err(|e| LastLegendError::Io(format!("Couldn't encode '{}'", path), e))?;
+        simple_mods_list.push(TtmpSimpleMod {
+            name: file_name(&file.game_path),
+            category: "Raw File Imports",
+            full_path: path,
+            mod_offset: data.len() as u64,
+            mod_size: entry.len() as u64,
+            dat_file: dat_file(&file.game_path)?,
+            is_default: false,
+            mod_pack_entry: None,
+        });
+        data.extend_from_slice(&entry);
+    }
+    let list = serde_json::to_vec(&TtmpList {
+        minimum_framework_version: "1.3.0.0",
+        ttmp_version: "1.3s",
+        name: &meta.name,
+        author: &meta.author,
+        version: &meta.version,
+        description: &meta.description,
+        url: &meta.website,
+        mod_pack_pages: None,
+        simple_mods_list,
+    })
+    .map_err(|e| LastLegendError::Json("Couldn't write TTMPL.mpl".into(), e))?;
+
+    let zip_error =
+        |e: zip::result::ZipError| LastLegendError::Io("Couldn't write modpack".into(), e.into());
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default();
+    zip.start_file("TTMPL.mpl", options).map_err(zip_error)?;
+    zip.write_all(&list)
+        .map_err(|e| LastLegendError::Io("Couldn't write TTMPL.mpl".into(), e))?;
+    // The data is already compressed.
+    zip.start_file(
+        "TTMPD.mpd",
+        options.compression_method(zip::CompressionMethod::Stored),
+    )
+    .map_err(zip_error)?;
+    zip.write_all(&data)
+        .map_err(|e| LastLegendError::Io("Couldn't write TTMPD.mpd".into(), e))?;
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PenumbraMeta<'a> {
+    file_version: u32,
+    name: &'a str,
+    author: &'a str,
+    description: &'a str,
+    version: &'a str,
+    website: &'a str,
+    mod_tags: [&'a str; 0],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PenumbraDefaultMod {
+    name: &'static str,
+    priority: i32,
+    files: BTreeMap<String, String>,
+    file_swaps: BTreeMap<String, String>,
+    manipulations: [(); 0],
+}
+
+/// Write a Penumbra mod folder of [files] to [directory], which is created if needed. Each file is
+/// stored at its game path inside the folder.
+pub fn write_penumbra(
+    meta: &ModpackMeta,
+    files: &[ModpackFile],
+    directory: &Path,
+) -> Result<(), LastLegendError> {
+    let mut redirections = BTreeMap::new();
+    for file in files {
+        let path = directory.join(file.game_path.as_str());
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                LastLegendError::Io(format!("Couldn't create {}", parent.display()), e)
+            })?;
+        }
+        std::fs::write(&path, &file.content)
+            .map_err(|e| LastLegendError::Io(format!("Couldn't write {}", path.display()), e))?;
+        // Penumbra uses Windows separators for paths inside the mod.
+        redirections.insert(
+            file.game_path.as_str().to_string(),
+            file.game_path.as_str().replace('/', "\\"),
+        );
+    }
+
+    write_json(
+        &directory.join("meta.json"),
+        &PenumbraMeta {
+            file_version: 3,
+            name: &meta.name,
+            author: &meta.author,
+            description: &meta.description,
+            version: &meta.version,
+            website: &meta.website,
+            mod_tags: [],
+        },
+    )?;
+    write_json(
+        &directory.join("default_mod.json"),
+        &PenumbraDefaultMod {
+            name: "",
+            priority: 0,
+            files: redirections,
+            file_swaps: BTreeMap::new(),
+            manipulations: [],
+        },
+    )
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), LastLegendError> {
+    let json = serde_json::to_vec_pretty(value)
+        .map_err(|e| LastLegendError::Json(format!("Couldn't write {}", path.display()), e))?;
+    std::fs::write(path, json)
+        .map_err(|e| LastLegendError::Io(format!("Couldn't write {}", path.display()), e))
+}
+
+fn file_name(path: &SqPath) -> &str {
+    path.as_str().rsplit('/').next().unwrap_or_default()
+}
+
+/// The name of the dat files [path] is in, e.g. `0c0000` for music.
+fn dat_file(path: &SqPath) -> Result<String, LastLegendError> {
+    let index_path = path
+        .sqpack_index_path("")
+        .ok_or_else(|| LastLegendError::InvalidSqPath(path.as_str().to_string()))?;
+    let file_name = index_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    Ok(file_name.split('.').next().unwrap_or_default().to_string())
+}
+
+#[cfg(test)]
+mod modpack_tests {
+    use crate::modpack::dat_file;
+    use crate::sqpath::SqPath;
+
+    #[test]
+    fn dat_file_names() {
+        assert_eq!(
+            dat_file(SqPath::new("music/ffxiv/bgm_system_title.scd")).unwrap(),
+            "0c0000"
+        );
+        assert_eq!(
+            dat_file(SqPath::new("music/ex1/bgm_ex1_field_01.scd")).unwrap(),
+            "0c0100"
+        );
+        assert!(dat_file(SqPath::new("nowhere/file.scd")).is_err());
+    }
+}
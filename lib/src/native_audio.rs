@@ -0,0 +1,109 @@
+use std::io::{Read, Seek};
+
+use flacenc::component::{BitRepr, MetadataBlockData};
+use flacenc::error::Verify;
+use lewton::inside_ogg::OggStreamReader;
+
+use crate::error::LastLegendError;
+
+/// FLAC metadata block type for Vorbis comments.
+const VORBIS_COMMENT_BLOCK: u8 = 4;
+
+/// Interleaved 16-bit PCM, decoded from an OGG/Vorbis stream.
+struct DecodedOgg {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<i16>,
+    comments: Vec<(String, String)>,
+}
+
+fn decode_ogg(reader: impl Read + Seek) -> Result<DecodedOgg, LastLegendError> {
+    let mut ogg = OggStreamReader::new(reader)
+        .map_err(|e| LastLegendError::AudioDecode(format!("Couldn't read OGG headers, {}", e)))?;
+    let mut samples = Vec::new();
+    while let Some(packet) = ogg
+        .read_dec_packet_itl()
+        .map_err(|e| LastLegendError::AudioDecode(format!("Couldn't decode OGG packet, {}", e)))?
+    {
+        samples.extend_from_slice(&packet);
+    }
+    Ok(DecodedOgg {
+        channels: ogg.ident_hdr.audio_channels.into(),
+        sample_rate: ogg.ident_hdr.audio_sample_rate,
+        samples,
+        comments: ogg.comment_hdr.comment_list,
+    })
+}
+
+/// Decode an OGG/Vorbis stream to a 16-bit PCM `wav` file, without FFMPEG.
+pub fn ogg_to_wav(reader: impl Read + Seek) -> Result<Vec<u8>, LastLegendError> {
+    let ogg = decode_ogg(reader)?;
+    let data_size = u32::try_from(ogg.samples.len() * 2).expect("should fit in u32");
+    let block_align = ogg.channels * 2;
+
+    let mut wav_file = Vec::with_capacity(44 + ogg.samples.len() * 2);
+    wav_file.extend_from_slice(b"RIFF");
+    wav_file.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav_file.extend_from_slice(b"WAVE");
+    wav_file.extend_from_slice(b"fmt ");
+    wav_file.extend_from_slice(&16u32.to_le_bytes());
+    // PCM
+    wav_file.extend_from_slice(&1u16.to_le_bytes());
+    wav_file.extend_from_slice(&ogg.channels.to_le_bytes());
+    wav_file.extend_from_slice(&ogg.sample_rate.to_le_bytes());
+    wav_file.extend_from_slice(&(ogg.sample_rate * u32::from(block_align)).to_le_bytes());
+    wav_file.extend_from_slice(&block_align.to_le_bytes());
+    wav_file.extend_from_slice(&16u16.to_le_bytes());
+    wav_file.extend_from_slice(b"data");
+    wav_file.extend_from_slice(&data_size.to_le_bytes());
+    for sample in ogg.samples {
+        wav_file.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    Ok(wav_file)
+}
+
+/// Decode an OGG/Vorbis stream and re-encode it as `flac`, without FFMPEG.
+/// The Vorbis comments are carried over, so loop points survive.
+pub fn ogg_to_flac(reader: impl Read + Seek) -> Result<Vec<u8>, LastLegendError> {
+    let ogg = decode_ogg(reader)?;
+    let samples: Vec<i32> = ogg.samples.iter().map(|&s| i32::from(s)).collect();
+
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .expect("default config should be valid");
+    let source = flacenc::source::MemSource::from_samples(
+        &samples,
+        ogg.channels.into(),
+        16,
+        usize::try_from(ogg.sample_rate).expect("should fit in usize"),
+    );
+    let mut stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| LastLegendError::AudioDecode(format!("Couldn't encode FLAC, {:?}", e)))?;
+    stream.add_metadata_block(
+        MetadataBlockData::new_unknown(VORBIS_COMMENT_BLOCK, &vorbis_comment(&ogg.comments))
+            .expect("Vorbis comment type should be valid"),
+    );
+
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|e| LastLegendError::AudioDecode(format!("Couldn't write FLAC, {}", e)))?;
+    Ok(sink.into_inner())
+}
+
+/// Serialize a Vorbis comment block, as stored in FLAC.
+fn vorbis_comment(comments: &[(String, String)]) -> Vec<u8> {
+    const VENDOR: &[u8] = b"last-legend-dob";
+
+    let mut block = Vec::new();
+    block.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    block.extend_from_slice(VENDOR);
+    block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for (key, value) in comments {
+        let comment = format!("{}={}", key, value);
+        block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        block.extend_from_slice(comment.as_bytes());
+    }
+    block
+}
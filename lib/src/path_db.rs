@@ -0,0 +1,178 @@
+//! Looking up the paths of index entries, which indexes only store the hashes of.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+use crate::data::index2::{Index2, Index2Entry, IndexFormat};
+use crate::error::LastLegendError;
+use crate::path_list::read_path_list;
+use crate::path_pack::PathPack;
+use crate::sqpath::{PathHasher, SqPath, SqPathBuf};
+
+/// Known paths, by the index file they'd be in and their hashes.
+///
+/// Load it from path lists, such as the ResLogger exports, or [path packs](PathPack), then look up
+/// the path of index entries with [path_for](Self::path_for). Paths that don't map to an index
+/// file are ignored.
+#[derive(Debug)]
+pub struct PathDb {
+    hasher: PathHasher,
+    paths: Vec<SqPathBuf>,
+    /// Looked in after [paths](Self::paths), without hashing their paths again.
+    packs: Vec<PathPack>,
+    /// Positions in [paths](Self::paths), by index file name without its extension, then by
+    /// [Index2Entry::key] for each index format.
+    keys: HashMap<String, FormatKeys>,
+}
+
+#[derive(Debug, Default)]
+struct FormatKeys {
+    index2: HashMap<u64, usize>,
+    index: HashMap<u64, usize>,
+}
+
+impl PathDb {
+    /// Make an empty database, hashing paths with [hasher], which must match the repository's.
+    pub fn new(hasher: PathHasher) -> Self {
+        Self {
+            hasher,
+            paths: Vec::new(),
+            packs: Vec::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Load the path lists at [files], see [read_path_list], or path packs, which are recognized
+    /// by their magic. Files ending in `.gz` are decompressed.
+    pub fn load_files<P: AsRef<Path>>(
+        files: &[P],
+        hasher: PathHasher,
+    ) -> Result<Self, LastLegendError> {
+        let mut db = Self::new(hasher);
+        for file in files {
+            let file = file.as_ref();
+            let reader = File::open(file)
+                .map_err(|e| LastLegendError::Io(format!("Couldn't open {}", file.display()), e))?;
+            let mut reader = BufReader::new(reader);
+            if PathPack::is_path_pack(&mut reader)? {
+                drop(reader);
+                let pack = PathPack::open(file)
+                    .map_err(|e| e.add_context(format!("Couldn't read {}", file.display())))?;
+                db.add_pack(pack)?;
+            } else if file.extension().is_some_and(|e| e == "gz") {
+                db.extend_from(BufReader::new(GzDecoder::new(reader)))?;
+            } else {
+                db.extend_from(reader)?;
+            }
+        }
+        log::debug!("Loaded {} paths", db.len());
+        Ok(db)
+    }
+
+    /// Add the paths of a path list.
+    pub fn extend_from<R: BufRead>(&mut self, reader: R) -> Result<(), LastLegendError> {
+        for path in read_path_list(reader) {
+            self.insert(path?);
+        }
+        Ok(())
+    }
+
+    /// Look up paths in [pack] too, after any other paths. The pack must have been made with the
+    /// same hasher as this database.
+    pub fn add_pack(&mut self, pack: PathPack) -> Result<(), LastLegendError> {
+        if pack.hasher() != self.hasher {
+            return Err(LastLegendError::Custom(format!(
+                "Path pack was hashed with {:?}, not {:?}",
+                pack.hasher(),
+                self.hasher
+            )));
+        }
+        self.packs.push(pack);
+        Ok(())
+    }
+
+    /// Make a path pack of every path in the database, to share.
+    pub fn to_pack(&self) -> PathPack {
+        PathPack::from_paths(
+            self.hasher,
+            self.paths
+                .iter()
+                .map(|p| &**p)
+                .chain(self.packs.iter().flat_map(|pack| pack.paths())),
+        )
+    }
+
+    /// Add [path], unless it doesn't map to an index file.
+    pub fn insert(&mut self, path: SqPathBuf) {
+        let Some(index_name) = path.sqpack_index_path("").as_deref().and_then(index_name) else {
+            return;
+        };
+        let position = self.paths.len();
+        let keys = self.keys.entry(index_name).or_default();
+        let (folder_hash, file_hash) = path.sq_folder_file_hash_with(&self.hasher);
+        keys.index2
+            .insert(u64::from(path.sq_index_hash_with(&self.hasher)), position);
+        keys.index
+            .insert(Index2Entry::split_key(folder_hash, file_hash), position);
+        self.paths.push(path);
+    }
+
+    /// The path of [entry] of [index], if it's known.
+    pub fn path_for(&self, index: &Index2, entry: &Index2Entry) -> Option<&SqPath> {
+        let from_paths = self
+            .keys
+            .get(&index_name(&index.index_path)?)
+            .and_then(|keys| {
+                let by_key = match index.format {
+                    IndexFormat::Index2 => &keys.index2,
+                    IndexFormat::Index => &keys.index,
+                };
+                by_key.get(&entry.key()).map(|&p| &*self.paths[p])
+            });
+        from_paths.or_else(|| {
+            self.packs
+                .iter()
+                .find_map(|pack| pack.path_for(index, entry))
+        })
+    }
+
+    /// How many paths have been added, including duplicates.
+    pub fn len(&self) -> usize {
+        self.paths.len() + self.packs.iter().map(PathPack::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The name of an index file without its extension, e.g. `0a0000.win32`.
+fn index_name(index_path: &Path) -> Option<String> {
+    Some(index_path.file_stem()?.to_str()?.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod path_db_tests {
+    use crate::path_db::PathDb;
+    use crate::sqpath::{PathHasher, SqPath};
+
+    #[test]
+    fn indexes_known_paths() {
+        let mut db = PathDb::new(PathHasher::default());
+        db.extend_from("exd/root.exl\nnowhere/file.txt\n1,music/ffxiv/bgm_title.scd\n".as_bytes())
+            .unwrap();
+        assert_eq!(db.len(), 2);
+        let exd = db.keys.get("0a0000.win32").unwrap();
+        let hash = SqPath::new("exd/root.exl").sq_index_hash();
+        assert_eq!(
+            exd.index2
+                .get(&u64::from(hash))
+                .map(|&p| db.paths[p].as_str()),
+            Some("exd/root.exl")
+        );
+        assert!(db.keys.contains_key("0c0000.win32"));
+    }
+}
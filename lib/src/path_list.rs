@@ -0,0 +1,57 @@
+//! Streaming readers for lists of known paths, such as the community ResLogger dumps.
+use std::io::BufRead;
+
+use crate::error::LastLegendError;
+use crate::sqpath::SqPathBuf;
+
+/// Read paths from a path list, one per line, without loading the entire list into memory.
+///
+/// Lines containing commas are treated as CSV, using the last column, which covers the ResLogger
+/// exports. Empty lines and lines starting with `#` are skipped.
+pub fn read_path_list<R: BufRead>(
+    reader: R,
+) -> impl Iterator<Item = Result<SqPathBuf, LastLegendError>> {
+    reader.lines().filter_map(|line| {
+        let line = match line {
+            Ok(v) => v,
+            Err(e) => return Some(Err(LastLegendError::Io("Failed to read line".into(), e))),
+        };
+        parse_path_list_line(&line).map(|p| Ok(SqPathBuf::new(p)))
+    })
+}
+
+fn parse_path_list_line(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let path = match line.rsplit_once(',') {
+        Some((_, path)) => path.trim().trim_matches('"'),
+        None => line,
+    };
+    (!path.is_empty()).then_some(path)
+}
+
+#[cfg(test)]
+mod path_list_tests {
+    use crate::path_list::read_path_list;
+    use crate::sqpath::SqPathBuf;
+
+    #[test]
+    fn plain_and_csv_lines() {
+        let list = "music/ffxiv/BGM_System_Title.scd\n\
+                    # comment\n\
+                    \n\
+                    1,2,3,4,\"exd/root.exl\"\n";
+        let paths = read_path_list(list.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                SqPathBuf::new("music/ffxiv/BGM_System_Title.scd"),
+                SqPathBuf::new("exd/root.exl"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,507 @@
+//! A compact binary format for sharing known paths, much smaller than path lists and ready to
+//! look up without hashing every path again.
+//!
+//! A pack is a header, then a body, compressed with zstd by default. The header, little-endian,
+//! is:
+//!
+//! - the magic `LLDPATHS`, and the format version, a `u32`, currently [PATH_PACK_VERSION];
+//! - how the body is compressed, a `u8`, see [PackCompression];
+//! - the [PathHasher] the hashes were made with: polynomial, init and xor-out `u32`s, then
+//!   whether it reflects, a `u8`;
+//! - how many paths there are and how many bytes of path strings, both `u32`s.
+//!
+//! The body is a table with, for each path, the index file it's in as the `u32` of the hex digits
+//! the file name starts with, e.g. `0x0c0100`, its `.index2` hash, its folder and file hashes for
+//! `.index` files, and the offset and length of the path in the strings after the table, all
+//! `u32`s. The table is sorted by index file, then `.index2` hash.
+//!
+//! Uncompressed packs are looked up in place, so with the `mmap` feature, [PathPack::open] maps
+//! them into memory instead of reading them, and they're ready as soon as they're checked.
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufRead, Cursor, Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use binrw::{binrw, BinRead, BinWrite};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use crate::data::index2::{Index2, Index2Entry, IndexFormat};
+use crate::error::LastLegendError;
+use crate::sqpath::{PathHasher, SqPath};
+
+/// The first bytes of every path pack.
+pub const PATH_PACK_MAGIC: &[u8; 8] = b"LLDPATHS";
+
+/// The format version written by this library, and the newest it can read. Version 2 added
+/// [PackCompression::Zstd].
+pub const PATH_PACK_VERSION: u32 = 2;
+
+/// Gotta keep these in sync with PackHeader and PackEntry below.
+const HEADER_SIZE: usize = 8 + 4 + 1 + 4 * 3 + 1 + 4 * 2;
+const ENTRY_SIZE: usize = 4 * 6;
+
+/// The zstd level packs are written with. Packs are written once and read many times, so it's
+/// worth compressing them hard.
+const ZSTD_LEVEL: i32 = 19;
+
+/// How the body of a path pack is compressed.
+#[binrw]
+#[brw(repr(u8))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum PackCompression {
+    /// Uncompressed, so the pack can be used in place, see [PathPack::open].
+    None = 0,
+    /// Written by version 1 packs.
+    Deflate = 1,
+    #[default]
+    Zstd = 2,
+}
+
+#[binrw]
+#[brw(little, magic = b"LLDPATHS")]
+#[derive(Debug)]
+struct PackHeader {
+    version: u32,
+    compression: PackCompression,
+    polynomial: u32,
+    init: u32,
+    xor_out: u32,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| u8::from(*v))]
+    reflect: bool,
+    path_count: u32,
+    string_bytes: u32,
+}
+
+impl PackHeader {
+    /// Read the header from the first [HEADER_SIZE] bytes of a pack, failing if it's a newer
+    /// version than this library knows.
+    fn parse(bytes: &[u8]) -> Result<Self, LastLegendError> {
+        let header = Self::read(&mut Cursor::new(bytes))
+            .map_err(|e| LastLegendError::BinRW("Couldn't read path pack header".into(), e))?;
+        if header.version > PATH_PACK_VERSION {
+            return Err(LastLegendError::Custom(format!(
+                "Path pack version {} is newer than the newest supported version, {}",
+                header.version, PATH_PACK_VERSION
+            )));
+        }
+        Ok(header)
+    }
+
+    fn hasher(&self) -> PathHasher {
+        PathHasher {
+            polynomial: self.polynomial,
+            init: self.init,
+            xor_out: self.xor_out,
+            reflect: self.reflect,
+        }
+    }
+
+    /// Read and decompress the body that follows the header from [reader].
+    fn read_body(&self, mut reader: impl Read) -> Result<Vec<u8>, LastLegendError> {
+        let mut body = Vec::new();
+        match self.compression {
+            PackCompression::None => reader.read_to_end(&mut body),
+            PackCompression::Deflate => DeflateDecoder::new(reader).read_to_end(&mut body),
+            PackCompression::Zstd => {
+                zstd::Decoder::new(reader).and_then(|mut d| d.read_to_end(&mut body))
+            }
+        }
+        .map_err(|e| LastLegendError::Io("Couldn't read path pack".into(), e))?;
+        Ok(body)
+    }
+}
+
+#[binrw]
+#[brw(little)]
+#[derive(Debug, Copy, Clone)]
+struct PackEntry {
+    index_id: u32,
+    index2_hash: u32,
+    folder_hash: u32,
+    file_hash: u32,
+    path_offset: u32,
+    path_len: u32,
+}
+
+impl PackEntry {
+    fn split_key(&self) -> u64 {
+        Index2Entry::split_key(self.folder_hash, self.file_hash)
+    }
+}
+
+/// The table and strings of a pack, decompressed, or mapped from an uncompressed pack file.
+enum PackBody {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for PackBody {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Owned(body) => body,
+            #[cfg(feature = "mmap")]
+            Self::Mapped(map) => &map[HEADER_SIZE..],
+        }
+    }
+}
+
+impl std::fmt::Debug for PackBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            Self::Owned(_) => "Owned",
+            #[cfg(feature = "mmap")]
+            Self::Mapped(_) => "Mapped",
+        };
+        write!(f, "{}({} bytes)", kind, self.len())
+    }
+}
+
+/// Known paths, loaded from a path pack, see the [module docs](self).
+#[derive(Debug)]
+pub struct PathPack {
+    hasher: PathHasher,
+    path_count: usize,
+    /// The table, sorted by index file, then `.index2` hash, then the strings.
+    body: PackBody,
+    /// Positions in the table, sorted by index file, then folder and file hash. Only made once
+    /// paths are looked up for an `.index` file.
+    by_split_key: OnceLock<Vec<u32>>,
+}
+
+impl PathPack {
+    /// Make a pack of [paths], hashed with [hasher]. Paths that don't map to an index file are
+    /// left out, and only the first of paths with the same hash is kept.
+    pub fn from_paths<'a>(hasher: PathHasher, paths: impl IntoIterator<Item = &'a SqPath>) -> Self {
+        let mut entries = Vec::new();
+        let mut strings = String::new();
+        for path in paths {
+            let Some(index_id) = path.sqpack_index_path("").as_deref().and_then(index_id_of) else {
+                continue;
+            };
+            let (folder_hash, file_hash) = path.sq_folder_file_hash_with(&hasher);
+            entries.push(PackEntry {
+                index_id,
+                index2_hash: path.sq_index_hash_with(&hasher),
+                folder_hash,
+                file_hash,
+                path_offset: strings.len() as u32,
+                path_len: path.as_str().len() as u32,
+            });
+            strings.push_str(path.as_str());
+        }
+        // Stable, so the first of duplicates stays first.
+        entries.sort_by_key(|e| (e.index_id, e.index2_hash));
+        entries.dedup_by_key(|e| (e.index_id, e.index2_hash));
+
+        let mut body = Cursor::new(Vec::with_capacity(
+            entries.len() * ENTRY_SIZE + strings.len(),
+        ));
+        for entry in &entries {
+            entry.write(&mut body).expect("writing to a Vec can't fail");
+        }
+        let mut body = body.into_inner();
+        body.extend_from_slice(strings.as_bytes());
+        Self {
+            hasher,
+            path_count: entries.len(),
+            body: PackBody::Owned(body),
+            by_split_key: OnceLock::new(),
+        }
+    }
+
+    /// Check the table and strings of [body] against [header], and make a pack of them.
+    fn with_body(header: &PackHeader, body: PackBody) -> Result<Self, LastLegendError> {
+        let path_count = header.path_count as usize;
+        let table_bytes = path_count * ENTRY_SIZE;
+        if body.len() != table_bytes + header.string_bytes as usize {
+            return Err(LastLegendError::Custom(format!(
+                "Path pack is {} bytes, but its header says it has {} paths in {} bytes of strings",
+                body.len(),
+                header.path_count,
+                header.string_bytes
+            )));
+        }
+        let pack = Self {
+            hasher: header.hasher(),
+            path_count,
+            body,
+            by_split_key: OnceLock::new(),
+        };
+        let strings = &pack.body[table_bytes..];
+        let mut previous = None;
+        for i in 0..path_count {
+            let entry = pack.entry(i);
+            let end = entry.path_offset as usize + entry.path_len as usize;
+            if strings
+                .get(entry.path_offset as usize..end)
+                .is_none_or(|path| std::str::from_utf8(path).is_err())
+            {
+                return Err(LastLegendError::Custom(
+                    "Path pack has a path outside of its strings, or that isn't UTF-8".into(),
+                ));
+            }
+            let key = (entry.index_id, entry.index2_hash);
+            if previous.is_some_and(|previous| previous > key) {
+                return Err(LastLegendError::Custom(
+                    "Path pack table isn't sorted".into(),
+                ));
+            }
+            previous = Some(key);
+        }
+        Ok(pack)
+    }
+
+    /// Read a pack, failing if it's a newer version than this library knows.
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, LastLegendError> {
+        let mut header_bytes = [0u8; HEADER_SIZE];
+        reader
+            .read_exact(&mut header_bytes)
+            .map_err(|e| LastLegendError::Io("Couldn't read path pack header".into(), e))?;
+        let header = PackHeader::parse(&header_bytes)?;
+        let body = header.read_body(reader)?;
+        Self::with_body(&header, PackBody::Owned(body))
+    }
+
+    /// Load the pack at [path]. With the `mmap` feature, uncompressed packs are mapped into
+    /// memory and looked up in place, rather than read. Other programs must not change the file
+    /// while it's mapped.
+    pub fn open(path: &Path) -> Result<Self, LastLegendError> {
+        let file = File::open(path)
+            .map_err(|e| LastLegendError::Io(format!("Couldn't open {}", path.display()), e))?;
+        #[cfg(feature = "mmap")]
+        {
+            // SAFETY: the map is only read, and changing the file while it's mapped is documented
+            // as unsupported.
+            let map = unsafe { memmap2::Mmap::map(&file) }
+                .map_err(|e| LastLegendError::Io(format!("Couldn't map {}", path.display()), e))?;
+            let header = PackHeader::parse(map.get(..HEADER_SIZE).unwrap_or(&map))?;
+            let body = match header.compression {
+                PackCompression::None => PackBody::Mapped(map),
+                _ => PackBody::Owned(header.read_body(&map[HEADER_SIZE..])?),
+            };
+            Self::with_body(&header, body)
+        }
+        #[cfg(not(feature = "mmap"))]
+        Self::read(std::io::BufReader::new(file))
+    }
+
+    /// Check whether [reader] is at the start of a path pack, without consuming anything.
+    pub fn is_path_pack<R: BufRead>(reader: &mut R) -> Result<bool, LastLegendError> {
+        let start = reader
+            .fill_buf()
+            .map_err(|e| LastLegendError::Io("Couldn't read file".into(), e))?;
+        Ok(start.starts_with(PATH_PACK_MAGIC))
+    }
+
+    /// Write the pack, with its body compressed with [compression].
+    pub fn write<W: Write>(
+        &self,
+        mut writer: W,
+        compression: PackCompression,
+    ) -> Result<(), LastLegendError> {
+        let header = PackHeader {
+            version: PATH_PACK_VERSION,
+            compression,
+            polynomial: self.hasher.polynomial,
+            init: self.hasher.init,
+            xor_out: self.hasher.xor_out,
+            reflect: self.hasher.reflect,
+            path_count: self.path_count as u32,
+            string_bytes: (self.body.len() - self.table_bytes()) as u32,
+        };
+        let mut header_bytes = Cursor::new(Vec::with_capacity(HEADER_SIZE));
+        header
+            .write(&mut header_bytes)
+            .map_err(|e| LastLegendError::BinRW("Couldn't write path pack".into(), e))?;
+        let body: &[u8] = &self.body;
+        writer
+            .write_all(header_bytes.get_ref())
+            .and_then(|_| match compression {
+                PackCompression::None => writer.write_all(body),
+                PackCompression::Deflate => {
+                    let mut encoder = DeflateEncoder::new(&mut writer, flate2::Compression::best());
+                    encoder.write_all(body)?;
+                    encoder.finish().map(|_| ())
+                }
+                PackCompression::Zstd => zstd::stream::copy_encode(body, &mut writer, ZSTD_LEVEL),
+            })
+            .map_err(|e| LastLegendError::Io("Couldn't write path pack".into(), e))
+    }
+
+    /// The hasher the pack's hashes were made with.
+    pub fn hasher(&self) -> PathHasher {
+        self.hasher
+    }
+
+    /// The path of [entry] of [index], if it's in the pack.
+    pub fn path_for(&self, index: &Index2, entry: &Index2Entry) -> Option<&SqPath> {
+        self.lookup(index_id_of(&index.index_path)?, index.format, entry.key())
+    }
+
+    fn lookup(&self, index_id: u32, format: IndexFormat, key: u64) -> Option<&SqPath> {
+        let position = match format {
+            IndexFormat::Index2 => search(self.path_count, (index_id, key), |i| {
+                let e = self.entry(i);
+                (e.index_id, u64::from(e.index2_hash))
+            })?,
+            IndexFormat::Index => {
+                let by_split_key = self.by_split_key();
+                let i = search(by_split_key.len(), (index_id, key), |i| {
+                    let e = self.entry(by_split_key[i] as usize);
+                    (e.index_id, e.split_key())
+                })?;
+                by_split_key[i] as usize
+            }
+        };
+        Some(self.path(&self.entry(position)))
+    }
+
+    fn by_split_key(&self) -> &[u32] {
+        self.by_split_key.get_or_init(|| {
+            let mut by_split_key = (0..self.path_count as u32).collect::<Vec<_>>();
+            by_split_key.sort_by_key(|&i| {
+                let entry = self.entry(i as usize);
+                (entry.index_id, entry.split_key())
+            });
+            by_split_key
+        })
+    }
+
+    fn table_bytes(&self) -> usize {
+        self.path_count * ENTRY_SIZE
+    }
+
+    /// Entry [i] of the table.
+    fn entry(&self, i: usize) -> PackEntry {
+        PackEntry::read(&mut Cursor::new(
+            &self.body[i * ENTRY_SIZE..(i + 1) * ENTRY_SIZE],
+        ))
+        .expect("entries are ENTRY_SIZE bytes")
+    }
+
+    fn path(&self, entry: &PackEntry) -> &SqPath {
+        let start = self.table_bytes() + entry.path_offset as usize;
+        let path = &self.body[start..start + entry.path_len as usize];
+        SqPath::new(std::str::from_utf8(path).expect("paths are checked when loaded"))
+    }
+
+    /// Every path in the pack, ordered by index file and hash.
+    pub fn paths(&self) -> impl Iterator<Item = &SqPath> + '_ {
+        (0..self.path_count).map(|i| self.path(&self.entry(i)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.path_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path_count == 0
+    }
+}
+
+/// Find [key] among the positions up to [len], sorted by [key_of], like
+/// [slice::binary_search_by_key], without needing a slice of them.
+fn search<K: Ord>(len: usize, key: K, key_of: impl Fn(usize) -> K) -> Option<usize> {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match key_of(middle).cmp(&key) {
+            Ordering::Less => low = middle + 1,
+            Ordering::Greater => high = middle,
+            Ordering::Equal => return Some(middle),
+        }
+    }
+    None
+}
+
+/// The index file [index_path] is, as the hex digits its name starts with, e.g. `0x0c0100` for
+/// `0c0100.win32.index2`.
+fn index_id_of(index_path: &Path) -> Option<u32> {
+    let name = index_path.file_name()?.to_str()?;
+    u32::from_str_radix(name.get(..6)?, 16).ok()
+}
+
+#[cfg(test)]
+mod path_pack_tests {
+    use crate::data::index2::{Index2Entry, IndexFormat};
+    use crate::path_pack::{PackCompression, PathPack};
+    use crate::sqpath::{PathHasher, SqPath};
+
+    #[test]
+    fn round_trips_and_finds_paths() {
+        let paths = [
+            SqPath::new("music/ffxiv/BGM_System_Title.scd"),
+            SqPath::new("exd/root.exl"),
+            SqPath::new("nowhere/file.txt"),
+            SqPath::new("exd/root.exl"),
+        ];
+        let pack = PathPack::from_paths(PathHasher::default(), paths);
+        assert_eq!(pack.len(), 2);
+
+        for compression in [
+            PackCompression::None,
+            PackCompression::Deflate,
+            PackCompression::Zstd,
+        ] {
+            let mut bytes = Vec::new();
+            pack.write(&mut bytes, compression).unwrap();
+            let read = PathPack::read(bytes.as_slice()).unwrap();
+            assert_eq!(read.hasher(), PathHasher::default());
+            assert_eq!(
+                read.paths().collect::<Vec<_>>(),
+                pack.paths().collect::<Vec<_>>()
+            );
+
+            let path = SqPath::new("exd/root.exl");
+            let (folder_hash, file_hash) = path.sq_folder_file_hash_with(&PathHasher::default());
+            assert_eq!(
+                read.lookup(0x0a0000, IndexFormat::Index2, path.sq_index_hash().into()),
+                Some(path)
+            );
+            assert_eq!(
+                read.lookup(
+                    0x0a0000,
+                    IndexFormat::Index,
+                    Index2Entry::split_key(folder_hash, file_hash)
+                ),
+                Some(path)
+            );
+            assert_eq!(
+                read.lookup(0x0c0000, IndexFormat::Index2, path.sq_index_hash().into()),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_packs() {
+        let pack = PathPack::from_paths(PathHasher::default(), [SqPath::new("exd/root.exl")]);
+        let mut bytes = Vec::new();
+        pack.write(&mut bytes, PackCompression::None).unwrap();
+        bytes.pop();
+        assert!(PathPack::read(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn opens_pack_files() {
+        let path = SqPath::new("exd/root.exl");
+        let pack = PathPack::from_paths(PathHasher::default(), [path]);
+        for compression in [PackCompression::None, PackCompression::Zstd] {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            pack.write(&mut file, compression).unwrap();
+            let opened = PathPack::open(file.path()).unwrap();
+            assert_eq!(opened.paths().collect::<Vec<_>>(), [path]);
+            assert_eq!(
+                opened.lookup(0x0a0000, IndexFormat::Index2, path.sq_index_hash().into()),
+                Some(path)
+            );
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Writing playlists of extracted audio, for music players.
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use strum::{Display, EnumString};
+
+use crate::error::LastLegendError;
+
+/// The playlist formats that can be written.
+#[derive(EnumString, Display, Debug, Copy, Clone, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum PlaylistFormat {
+    /// Extended M3U, in UTF-8.
+    M3u8,
+    /// XML Shareable Playlist Format.
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::M3u8 => "m3u8",
+            Self::Xspf => "xspf",
+        }
+    }
+}
+
+/// A playlist, in the order it should play.
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    pub title: String,
+    pub tracks: Vec<PlaylistTrack>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaylistTrack {
+    /// The audio file, relative to the playlist.
+    pub location: PathBuf,
+    pub title: String,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+}
+
+impl Playlist {
+    pub fn write<W: Write>(
+        &self,
+        format: PlaylistFormat,
+        writer: W,
+    ) -> Result<(), LastLegendError> {
+        match format {
+            PlaylistFormat::M3u8 => self.write_m3u8(writer),
+            PlaylistFormat::Xspf => self.write_xspf(writer),
+        }
+        .map_err(|e| LastLegendError::Io("Couldn't write playlist".into(), e))
+    }
+
+    fn write_m3u8<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "#EXTM3U")?;
+        writeln!(writer, "#PLAYLIST:{}", single_line(&self.title))?;
+        for track in &self.tracks {
+            writeln!(writer, "#EXTINF:-1,{}", single_line(&track.title))?;
+            if let Some(album) = &track.album {
+                writeln!(writer, "#EXTALB:{}", single_line(album))?;
+            }
+            writeln!(writer, "{}", portable_path(&track.location))?;
+        }
+        writer.flush()
+    }
+
+    fn write_xspf<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#
+        )?;
+        writeln!(writer, "  <title>{}</title>", xml_escape(&self.title))?;
+        writeln!(writer, "  <trackList>")?;
+        for track in &self.tracks {
+            writeln!(writer, "    <track>")?;
+            writeln!(
+                writer,
+                "      <location>{}</location>",
+                xml_escape(&uri_encode(&portable_path(&track.location)))
+            )?;
+            writeln!(writer, "      <title>{}</title>", xml_escape(&track.title))?;
+            if let Some(album) = &track.album {
+                writeln!(writer, "      <album>{}</album>", xml_escape(album))?;
+            }
+            if let Some(track_number) = track.track_number {
+                writeln!(writer, "      <trackNum>{}</trackNum>", track_number)?;
+            }
+            writeln!(writer, "    </track>")?;
+        }
+        writeln!(writer, "  </trackList>")?;
+        writeln!(writer, "</playlist>")?;
+        writer.flush()
+    }
+}
+
+/// [path] with `/` separators, which players on every platform understand.
+fn portable_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&apos;"),
+            // Not allowed in XML 1.0, even escaped.
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+/// Percent-encode a relative path for use as a URI, keeping the `/` separators.
+fn uri_encode(path: &str) -> String {
+    let mut output = String::with_capacity(path.len());
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            output.push(b as char);
+        } else {
+            output.push_str(&format!("%{:02X}", b));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod playlist_tests {
+    use std::path::PathBuf;
+
+    use crate::playlist::{Playlist, PlaylistFormat, PlaylistTrack};
+
+    fn playlist() -> Playlist {
+        Playlist {
+            title: "Orchestrion - Dungeons & Trials".into(),
+            tracks: vec![PlaylistTrack {
+                location: PathBuf::from("music/ffxiv/001 - Prelude <Discoveries>.flac"),
+                title: "Prelude <Discoveries>".into(),
+                album: Some("Dungeons & Trials".into()),
+                track_number: Some(1),
+            }],
+        }
+    }
+
+    #[test]
+    fn writes_m3u8() {
+        let mut output = Vec::new();
+        playlist().write(PlaylistFormat::M3u8, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "#EXTM3U\n\
+             #PLAYLIST:Orchestrion - Dungeons & Trials\n\
+             #EXTINF:-1,Prelude <Discoveries>\n\
+             #EXTALB:Dungeons & Trials\n\
+             music/ffxiv/001 - Prelude <Discoveries>.flac\n"
+        );
+    }
+
+    #[test]
+    fn writes_escaped_xspf() {
+        let mut output = Vec::new();
+        playlist().write(PlaylistFormat::Xspf, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(
+            "<location>music/ffxiv/001%20-%20Prelude%20%3CDiscoveries%3E.flac</location>"
+        ));
+        assert!(output.contains("<title>Prelude &lt;Discoveries&gt;</title>"));
+        assert!(output.contains("<album>Dungeons &amp; Trials</album>"));
+        assert!(output.contains("<trackNum>1</trackNum>"));
+    }
+}
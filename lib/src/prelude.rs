@@ -0,0 +1,15 @@
+//! The types most programs need, to `use last_legend_dob::prelude::*;`.
+//!
+//! [extract_to_file] covers extracting a single file. For more control, [read_transformed] gives
+//! a reader for the transformed content instead.
+pub use crate::cancel::CancellationToken;
+pub use crate::data::repo::Repository;
+pub use crate::error::LastLegendError;
+pub use crate::simple_task::{
+    apply_output_metadata, extract_to_file, read_transformed, OutputMetadata, TransformedReader,
+};
+pub use crate::sqpath::{SqPath, SqPathBuf};
+pub use crate::surpass::collection::Collection;
+pub use crate::surpass::known_rows::KnownRow;
+pub use crate::surpass::sheet_info::Language;
+pub use crate::transformers::{EncodeOptions, LoopOptions, ScdOptions, TransformerImpl};
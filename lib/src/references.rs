@@ -0,0 +1,108 @@
+//! Finding the other files a file refers to, e.g. the textures a UI layout or material uses.
+//!
+//! Rather than fully parsing each format, paths are found by scanning the content for strings
+//! that look like paths into the repository. The formats that refer to others store the paths as
+//! plain, usually null-terminated, strings, so this finds them without knowing every layout.
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::sqpath::{SqPath, SqPathBuf};
+
+/// Extensions of files that refer to other files.
+pub const REFERRING_EXTENSIONS: &[&str] = &["uld", "mdl", "mtrl", "avfx"];
+
+/// Extensions of files that are referred to.
+const REFERENCED_EXTENSIONS: &[&str] = &[
+    "tex", "atex", "mtrl", "mdl", "shpk", "scd", "avfx", "uld", "pap", "tmb",
+];
+
+/// Whether [file] is a format that refers to other files.
+pub fn can_refer(file: &SqPath) -> bool {
+    extension(file.as_str()).is_some_and(|e| REFERRING_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Find the files [content] of [file] refers to, in the order they appear, without duplicates.
+///
+/// Paths must have a category the repository knows, and no `..` or empty components, as they're
+/// also used as output paths. Models name their materials relative to the
+/// model, e.g. `/mt_c0101e0001_top_a.mtrl`, which are resolved to the first material variant
+/// next to the model's folder, `.../material/v0001/mt_c0101e0001_top_a.mtrl`.
+pub fn find_references(file: &SqPath, content: &[u8]) -> Vec<SqPathBuf> {
+    let mut seen = HashSet::new();
+    seen.insert(file.as_str().to_ascii_lowercase());
+    content
+        .split(|b| !is_path_byte(*b))
+        .filter_map(|run| std::str::from_utf8(run).ok())
+        .filter(|s| extension(s).is_some_and(|e| REFERENCED_EXTENSIONS.contains(&e.as_str())))
+        .filter_map(|s| match s.strip_prefix('/') {
+            Some(name) => relative_material(file, name),
+            None => {
+                let path = SqPathBuf::new(s);
+                path.check_resolvable().is_ok().then_some(path)
+            }
+        })
+        .filter(|path| !path.as_str().split('/').any(|c| c == ".." || c.is_empty()))
+        .filter(|path| seen.insert(path.as_str().to_ascii_lowercase()))
+        .collect()
+}
+
+fn relative_material(model: &SqPath, name: &str) -> Option<SqPathBuf> {
+    if !name.ends_with(".mtrl") || name.contains('/') {
+        return None;
+    }
+    let (folder, _) = model.as_str().split_once("/model/")?;
+    Some(SqPathBuf::new(&format!(
+        "{}/material/v0001/{}",
+        folder, name
+    )))
+}
+
+fn is_path_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'/' | b'.' | b'_' | b'-')
+}
+
+fn extension(s: &str) -> Option<String> {
+    Path::new(s)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod references_tests {
+    use super::*;
+
+    #[test]
+    fn finds_paths_between_binary_data() {
+        let content = b"\x01\x02ui/uld/Logo.tex\0\xff\x03ui/uld/Logo.tex\0\
+            vfx/common/texture/glow.atex\0not/a/category.tex\0readme.txt\0";
+        assert_eq!(
+            find_references(SqPath::new("ui/uld/Title.uld"), content),
+            [
+                SqPathBuf::new("ui/uld/Logo.tex"),
+                SqPathBuf::new("vfx/common/texture/glow.atex"),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_model_materials() {
+        let model = SqPath::new("chara/equipment/e0001/model/c0101e0001_top.mdl");
+        assert_eq!(
+            find_references(model, b"\0/mt_c0101e0001_top_a.mtrl\0"),
+            [SqPathBuf::new(
+                "chara/equipment/e0001/material/v0001/mt_c0101e0001_top_a.mtrl"
+            )]
+        );
+        assert!(find_references(SqPath::new("ui/uld/Title.uld"), b"/x.mtrl").is_empty());
+    }
+
+    #[test]
+    fn skips_paths_leaving_their_folder() {
+        let content = b"\0chara/../../../x.tex\0chara//x.tex\0ui/uld/../Logo.tex\0chara/x.tex\0";
+        assert_eq!(
+            find_references(SqPath::new("ui/uld/Title.uld"), content),
+            [SqPathBuf::new("chara/x.tex")]
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! The layout of `.scd` files.
+#![allow(clippy::unused_unit)]
+use std::io::{Cursor, Read, SeekFrom};
+
+use binrw::{binread, binrw, BinReaderExt, BinResult};
+
+use crate::error::LastLegendError;
+use crate::io_tricks::ReadMixer;
+use crate::xor::XorRead;
+
+#[binread]
+#[derive(Debug)]
+#[br(magic = b"SEDBSSCF")]
+pub(crate) struct Scd {
+    #[br(temp, assert(version == 3))]
+    version: u32,
+    #[br(temp, pad_before = 2)]
+    header_size: u16,
+    #[br(temp, seek_before = SeekFrom::Start(header_size.into()))]
+    offsets_header: ScdOffsetsHeader,
+    /// Offsets of each [ScdSoundEntry].
+    #[br(
+        seek_before = SeekFrom::Start(offsets_header.sound_entries_offset.into()),
+        count = offsets_header.sound_entries_size
+    )]
+    pub entry_offsets: Vec<u32>,
+}
+
+/// A sound entry, the audio data follows directly after it.
+#[binread]
+#[derive(Debug)]
+#[br(import { max_vorbis_header_size: u32 })]
+pub(crate) struct ScdSoundEntry {
+    pub sound_entry_header: SoundEntryHeader,
+    #[br(args { data_type: sound_entry_header.data_type, max_vorbis_header_size })]
+    pub sound_data: SoundData,
+}
+
+#[binread]
+#[derive(Debug)]
+pub(crate) struct ScdOffsetsHeader {
+    #[br(pad_before = 4)]
+    pub sound_entries_size: u16,
+    #[br(pad_before = 0x6)]
+    pub sound_entries_offset: u32,
+}
+
+const HAS_MARKER_CHUNK: u32 = 0x1;
+
+#[binread]
+#[derive(Debug)]
+pub(crate) struct SoundEntryHeader {
+    pub data_size: u32,
+    pub channels: u32,
+    pub frequency: u32,
+    #[br(map = Codec::from_id)]
+    pub data_type: Codec,
+    /// The start of the loop, as a byte offset into the audio data.
+    pub loop_start: u32,
+    /// The end of the loop, as a byte offset into the audio data. 0 means the end of the audio.
+    pub loop_end: u32,
+    #[br(temp)]
+    _pre_marker_sub_info_size: u32,
+    #[br(temp)]
+    flags: u32,
+    #[br(temp, if(flags & HAS_MARKER_CHUNK != 0), parse_with = skip_markers)]
+    _markers: (),
+}
+
+impl SoundEntryHeader {
+    /// The loop as byte offsets into the audio data, or None if it doesn't loop.
+    pub(crate) fn loop_bytes(&self) -> Option<(u64, u64)> {
+        let end = match self.loop_end {
+            0 => self.data_size,
+            end => end,
+        };
+        (self.loop_start != 0 || self.loop_end != 0)
+            .then_some((u64::from(self.loop_start), u64::from(end)))
+    }
+}
+
+#[binrw::parser(reader)]
+fn skip_markers() -> BinResult<()> {
+    let _id = reader.read_le::<u32>()?;
+    let size = reader.read_le::<u32>()?;
+
+    // Seek to the end of the marker chunk, including the two fields already read.
+    reader.seek(SeekFrom::Current(i64::from(size) - 8))?;
+
+    Ok(())
+}
+
+/// The codec of a sound entry. Ids are from the SCD files of every Square Enix game using the
+/// format, most of which FFXIV doesn't use, so they can be named in errors. Only
+/// [Codec::is_supported] codecs can be read.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Codec {
+    Empty,
+    PcmBigEndian,
+    Pcm,
+    PsAdpcm,
+    Ogg,
+    Mpeg,
+    NintendoDspAdpcm,
+    Xma2,
+    MsAdpcm,
+    Atrac3,
+    Atrac9,
+    Unknown(i32),
+}
+
+impl Codec {
+    pub(crate) fn from_id(id: i32) -> Self {
+        match id {
+            -1 => Self::Empty,
+            0x0 => Self::PcmBigEndian,
+            0x1 => Self::Pcm,
+            0x3 => Self::PsAdpcm,
+            0x6 => Self::Ogg,
+            0x7 => Self::Mpeg,
+            0xA => Self::NintendoDspAdpcm,
+            0xB => Self::Xma2,
+            0xC => Self::MsAdpcm,
+            0xE => Self::Atrac3,
+            0x10 => Self::Atrac9,
+            id => Self::Unknown(id),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::PcmBigEndian => "pcm_be",
+            Self::Pcm => "pcm",
+            Self::PsAdpcm => "ps_adpcm",
+            Self::Ogg => "ogg",
+            Self::Mpeg => "mpeg",
+            Self::NintendoDspAdpcm => "dsp_adpcm",
+            Self::Xma2 => "xma2",
+            Self::MsAdpcm => "ms_adpcm",
+            Self::Atrac3 => "atrac3",
+            Self::Atrac9 => "atrac9",
+            Self::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Whether sound entries in this codec can be read.
+    pub fn is_supported(&self) -> bool {
+        matches!(self, Self::Empty | Self::Pcm | Self::Ogg | Self::MsAdpcm)
+    }
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(id) => write!(f, "unknown codec 0x{:X}", id),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+#[binread]
+#[derive(Debug)]
+#[br(import { data_type: Codec, max_vorbis_header_size: u32 })]
+pub(crate) enum SoundData {
+    #[br(pre_assert(data_type == Codec::Empty))]
+    Empty,
+    /// 16-bit little endian PCM, which follows the entry header directly.
+    #[br(pre_assert(data_type == Codec::Pcm))]
+    Pcm,
+    #[br(pre_assert(data_type == Codec::Ogg))]
+    OggData(#[br(args { max_vorbis_header_size })] OggMetaHeader),
+    #[br(pre_assert(data_type == Codec::MsAdpcm))]
+    MsAdpcmData(MsAdpcmMetaHeader),
+    /// Any other codec, whose header isn't read.
+    #[br(pre_assert(!data_type.is_supported()))]
+    Unsupported(#[br(calc = data_type)] Codec),
+}
+
+#[binread]
+#[derive(Debug)]
+#[br(import { max_vorbis_header_size: u32 })]
+pub(crate) struct OggMetaHeader {
+    pub encryption_type: EncryptionType,
+    pub xor_byte: u8,
+    #[br(
+        temp,
+        pad_before = 0xD,
+        assert(
+            seek_table_size <= max_vorbis_header_size,
+            "seek table of {} bytes is over max_vorbis_header_size of {}",
+            seek_table_size,
+            max_vorbis_header_size
+        )
+    )]
+    seek_table_size: u32,
+    #[br(
+        temp,
+        pad_after = 0x8,
+        assert(
+            vorbis_header_size <= max_vorbis_header_size,
+            "Vorbis header of {} bytes is over max_vorbis_header_size of {}",
+            vorbis_header_size,
+            max_vorbis_header_size
+        )
+    )]
+    vorbis_header_size: u32,
+    #[br(temp, args { count: usize::try_from(seek_table_size).unwrap() / 4 })]
+    _seek_table: Vec<u32>,
+    /// May be encoded. Decoding is done separately.
+    #[br(args { count: vorbis_header_size.try_into().unwrap() })]
+    pub vorbis_header: Vec<u8>,
+}
+
+impl OggMetaHeader {
+    /// Read the whole OGG stream, starting with the Vorbis header, undoing any encryption. The
+    /// [data] of [data_size] bytes follows this header.
+    pub(crate) fn read_stream(
+        &self,
+        data_size: u32,
+        data: impl Read,
+    ) -> Result<Vec<u8>, LastLegendError> {
+        let xor_byte = self.xor_byte;
+        let vorbis_header = if self.encryption_type == EncryptionType::VorbisHeaderXor {
+            ReadMixer::Wrapped(XorRead::new(
+                Cursor::new(self.vorbis_header.clone()),
+                move |_| xor_byte,
+            ))
+        } else {
+            ReadMixer::Plain(Cursor::new(self.vorbis_header.clone()))
+        };
+        let base = vorbis_header.chain(data.take(data_size.into()));
+        let mut ogg_reader = if self.encryption_type == EncryptionType::InternalTableXor {
+            let static_xor = (data_size & 0x7F) as u8;
+            let table_off = (data_size & 0x3F) as u8;
+            ReadMixer::Wrapped(XorRead::new(base, move |index| {
+                XOR_TABLE[(usize::from(table_off) + index) & 0xFF] ^ static_xor
+            }))
+        } else {
+            ReadMixer::Plain(base)
+        };
+        let mut stream = Vec::new();
+        ogg_reader
+            .read_to_end(&mut stream)
+            .map_err(|e| LastLegendError::Io("Couldn't read OGG data".into(), e))?;
+        Ok(stream)
+    }
+}
+
+#[binread]
+#[derive(Debug, Eq, PartialEq)]
+#[br(repr(u16))]
+pub(crate) enum EncryptionType {
+    None,
+    VorbisHeaderXor = 0x2002,
+    InternalTableXor = 0x2003,
+}
+
+impl EncryptionType {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::VorbisHeaderXor => "vorbis_header_xor",
+            Self::InternalTableXor => "internal_table_xor",
+        }
+    }
+}
+
+#[binrw]
+#[derive(Debug)]
+pub(crate) struct MsAdpcmMetaHeader {
+    #[br(assert(format_tag == 0x2, "Only MS ADPCM is supported."))]
+    pub format_tag: u16,
+    pub channels: u16,
+    pub samples_per_second: i32,
+    pub avg_bytes_per_second: i32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    pub size: i16,
+    pub samples_per_block: u16,
+    pub num_coefficients: u16,
+    pub coefficients: [i16; 14],
+}
+
+const XOR_TABLE: &[u8; 256] = &[
+    0x3A, 0x32, 0x32, 0x32, 0x03, 0x7E, 0x12, 0xF7, 0xB2, 0xE2, 0xA2, 0x67, 0x32, 0x32, 0x22, 0x32,
+    0x32, 0x52, 0x16, 0x1B, 0x3C, 0xA1, 0x54, 0x7B, 0x1B, 0x97, 0xA6, 0x93, 0x1A, 0x4B, 0xAA, 0xA6,
+    0x7A, 0x7B, 0x1B, 0x97, 0xA6, 0xF7, 0x02, 0xBB, 0xAA, 0xA6, 0xBB, 0xF7, 0x2A, 0x51, 0xBE, 0x03,
+    0xF4, 0x2A, 0x51, 0xBE, 0x03, 0xF4, 0x2A, 0x51, 0xBE, 0x12, 0x06, 0x56, 0x27, 0x32, 0x32, 0x36,
+    0x32, 0xB2, 0x1A, 0x3B, 0xBC, 0x91, 0xD4, 0x7B, 0x58, 0xFC, 0x0B, 0x55, 0x2A, 0x15, 0xBC, 0x40,
+    0x92, 0x0B, 0x5B, 0x7C, 0x0A, 0x95, 0x12, 0x35, 0xB8, 0x63, 0xD2, 0x0B, 0x3B, 0xF0, 0xC7, 0x14,
+    0x51, 0x5C, 0x94, 0x86, 0x94, 0x59, 0x5C, 0xFC, 0x1B, 0x17, 0x3A, 0x3F, 0x6B, 0x37, 0x32, 0x32,
+    0x30, 0x32, 0x72, 0x7A, 0x13, 0xB7, 0x26, 0x60, 0x7A, 0x13, 0xB7, 0x26, 0x50, 0xBA, 0x13, 0xB4,
+    0x2A, 0x50, 0xBA, 0x13, 0xB5, 0x2E, 0x40, 0xFA, 0x13, 0x95, 0xAE, 0x40, 0x38, 0x18, 0x9A, 0x92,
+    0xB0, 0x38, 0x00, 0xFA, 0x12, 0xB1, 0x7E, 0x00, 0xDB, 0x96, 0xA1, 0x7C, 0x08, 0xDB, 0x9A, 0x91,
+    0xBC, 0x08, 0xD8, 0x1A, 0x86, 0xE2, 0x70, 0x39, 0x1F, 0x86, 0xE0, 0x78, 0x7E, 0x03, 0xE7, 0x64,
+    0x51, 0x9C, 0x8F, 0x34, 0x6F, 0x4E, 0x41, 0xFC, 0x0B, 0xD5, 0xAE, 0x41, 0xFC, 0x0B, 0xD5, 0xAE,
+    0x41, 0xFC, 0x3B, 0x70, 0x71, 0x64, 0x33, 0x32, 0x12, 0x32, 0x32, 0x36, 0x70, 0x34, 0x2B, 0x56,
+    0x22, 0x70, 0x3A, 0x13, 0xB7, 0x26, 0x60, 0xBA, 0x1B, 0x94, 0xAA, 0x40, 0x38, 0x00, 0xFA, 0xB2,
+    0xE2, 0xA2, 0x67, 0x32, 0x32, 0x12, 0x32, 0xB2, 0x32, 0x32, 0x32, 0x32, 0x75, 0xA3, 0x26, 0x7B,
+    0x83, 0x26, 0xF9, 0x83, 0x2E, 0xFF, 0xE3, 0x16, 0x7D, 0xC0, 0x1E, 0x63, 0x21, 0x07, 0xE3, 0x01,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codecs_are_named() {
+        assert_eq!(Codec::from_id(0x10).to_string(), "atrac9");
+        assert_eq!(Codec::from_id(0x1), Codec::Pcm);
+        assert_eq!(Codec::from_id(0x42).to_string(), "unknown codec 0x42");
+        assert!(!Codec::from_id(0x10).is_supported());
+    }
+}
@@ -0,0 +1,239 @@
+//! Reading the `.scd` sound files FFXIV stores audio in.
+//!
+//! An `.scd` file holds one or more sound entries. [ScdFile::parse] reads the header of each,
+//! giving its codec, format and loop, and [ScdFile::read_audio] reads the audio of one in a form
+//! other tools can play: a decrypted OGG stream, or a WAV file for PCM and MS-ADPCM audio.
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use binrw::{BinReaderExt, BinWriterExt};
+use serde::Serialize;
+
+use crate::error::LastLegendError;
+use crate::limits::Limits;
+use crate::scd::format::{Scd, ScdSoundEntry, ScdSoundEntryBinReadArgs, SoundData};
+use crate::scd::ogg::ogg_loop_samples;
+use crate::scd::wav::{ms_adpcm_loop_samples, pcm_fmt_header, pcm_loop_samples, wav_file};
+use crate::transformers::AudioFormat;
+
+pub use crate::scd::format::Codec;
+pub use crate::scd::ogg::{dump_vorbis_packets, VorbisPacket, VorbisPacketDump};
+
+mod format;
+pub(crate) mod ogg;
+mod wav;
+
+const SCD_MAGIC: &[u8] = b"SEDBSSCF";
+
+/// Whether [content] starts like an `.scd` file, for files whose path isn't known.
+pub fn is_scd(content: &[u8]) -> bool {
+    content.starts_with(SCD_MAGIC)
+}
+
+/// An `.scd` file, with the headers of its sound entries read.
+#[derive(Debug)]
+pub struct ScdFile<R> {
+    reader: R,
+    entries: Vec<ScdEntry>,
+}
+
+/// The header of a sound entry of an `.scd` file.
+#[derive(Debug)]
+pub struct ScdEntry {
+    pub codec: Codec,
+    pub channels: u32,
+    pub frequency: u32,
+    /// The size of the audio data in bytes.
+    pub data_size: u32,
+    /// The loop as byte offsets into the audio data, as stored.
+    pub loop_bytes: Option<(u64, u64)>,
+    /// Where the audio data starts in the file. For codecs that aren't supported, this is where
+    /// the codec's own header starts instead.
+    pub data_offset: u64,
+    sound_data: SoundData,
+}
+
+/// The audio of a sound entry, ready to be played or converted.
+#[derive(Debug, Clone)]
+pub struct ScdAudio {
+    /// [AudioFormat::Ogg] or [AudioFormat::Wav].
+    pub format: AudioFormat,
+    /// The OGG stream, decrypted, or the WAV file.
+    pub data: Vec<u8>,
+    /// The loop in samples, if the entry loops.
+    pub loop_samples: Option<(u64, u64)>,
+}
+
+impl<R: Read + Seek> ScdFile<R> {
+    /// Read the header of the `.scd` file in [reader], and of each of its sound entries.
+    pub fn parse(reader: R) -> Result<Self, LastLegendError> {
+        Self::parse_with_limits(reader, &Limits::default())
+    }
+
+    /// Like [parse](Self::parse), failing on Vorbis headers larger than [limits] allow.
+    pub fn parse_with_limits(mut reader: R, limits: &Limits) -> Result<Self, LastLegendError> {
+        let scd: Scd = reader
+            .read_le()
+            .map_err(|e| LastLegendError::BinRW("Couldn't read SCD".into(), e))?;
+        let entries = scd
+            .entry_offsets
+            .iter()
+            .map(|&offset| {
+                reader.seek(SeekFrom::Start(offset.into())).map_err(|e| {
+                    LastLegendError::Io("Couldn't seek to SCD sound entry".into(), e)
+                })?;
+                let entry: ScdSoundEntry = reader
+                    .read_le_args(
+                        ScdSoundEntryBinReadArgs::builder()
+                            .max_vorbis_header_size(limits.max_vorbis_header_size)
+                            .finalize(),
+                    )
+                    .map_err(|e| {
+                        LastLegendError::BinRW("Couldn't read SCD sound entry".into(), e)
+                    })?;
+                let data_offset = reader
+                    .stream_position()
+                    .map_err(|e| LastLegendError::Io("Couldn't find SCD audio data".into(), e))?;
+                let header = entry.sound_entry_header;
+                Ok(ScdEntry {
+                    codec: header.data_type,
+                    channels: header.channels,
+                    frequency: header.frequency,
+                    data_size: header.data_size,
+                    loop_bytes: header.loop_bytes(),
+                    data_offset,
+                    sound_data: entry.sound_data,
+                })
+            })
+            .collect::<Result<Vec<_>, LastLegendError>>()?;
+        Ok(Self { reader, entries })
+    }
+
+    pub fn entries(&self) -> &[ScdEntry] {
+        &self.entries
+    }
+
+    /// Get sound entry [index], failing if there's no such entry.
+    pub fn entry(&self, index: u16) -> Result<&ScdEntry, LastLegendError> {
+        self.entries.get(usize::from(index)).ok_or_else(|| {
+            LastLegendError::Custom(format!(
+                "SCD has {} sound entries, can't read entry {}",
+                self.entries.len(),
+                index
+            ))
+        })
+    }
+
+    /// Read the audio data of sound entry [index] as it's stored, still encrypted for OGG and
+    /// without a WAV header for PCM and MS-ADPCM.
+    pub fn raw_data(&mut self, index: u16) -> Result<impl Read + '_, LastLegendError> {
+        let (data_offset, data_size) = {
+            let entry = self.entry(index)?;
+            (entry.data_offset, entry.data_size)
+        };
+        self.reader
+            .seek(SeekFrom::Start(data_offset))
+            .map_err(|e| LastLegendError::Io("Couldn't seek to SCD audio data".into(), e))?;
+        Ok((&mut self.reader).take(data_size.into()))
+    }
+
+    /// Read the audio of sound entry [index]. Fails with [LastLegendError::UnsupportedCodec] if
+    /// its [Codec] isn't supported.
+    pub fn read_audio(&mut self, index: u16) -> Result<ScdAudio, LastLegendError> {
+        self.entry(index)?;
+        let entry = &self.entries[usize::from(index)];
+        self.reader
+            .seek(SeekFrom::Start(entry.data_offset))
+            .map_err(|e| LastLegendError::Io("Couldn't seek to SCD audio data".into(), e))?;
+        let mut data = (&mut self.reader).take(entry.data_size.into());
+        match &entry.sound_data {
+            SoundData::Empty => Err(LastLegendError::Custom("Empty sound data".into())),
+            SoundData::Unsupported(codec) => {
+                Err(LastLegendError::UnsupportedCodec(codec.to_string()))
+            }
+            SoundData::Pcm => {
+                let fmt_header = pcm_fmt_header(entry.channels, entry.frequency)?;
+                Ok(ScdAudio {
+                    format: AudioFormat::Wav,
+                    data: wav_file(&fmt_header, &mut data)?,
+                    loop_samples: pcm_loop_samples(entry.channels, entry.loop_bytes),
+                })
+            }
+            SoundData::OggData(ogg) => {
+                let stream = ogg.read_stream(entry.data_size, data)?;
+                let loop_samples =
+                    ogg_loop_samples(&stream, ogg.vorbis_header.len(), entry.loop_bytes);
+                Ok(ScdAudio {
+                    format: AudioFormat::Ogg,
+                    data: stream,
+                    loop_samples,
+                })
+            }
+            SoundData::MsAdpcmData(header) => {
+                let mut fmt_header = Vec::new();
+                Cursor::new(&mut fmt_header)
+                    .write_le(header)
+                    .expect("should be able to write header");
+                Ok(ScdAudio {
+                    format: AudioFormat::Wav,
+                    data: wav_file(&fmt_header, &mut data)?,
+                    loop_samples: ms_adpcm_loop_samples(header, entry.loop_bytes),
+                })
+            }
+        }
+    }
+}
+
+impl ScdEntry {
+    /// How OGG data is obscured: `none`, `vorbis_header_xor` or `internal_table_xor`. None for
+    /// other codecs.
+    pub fn encryption(&self) -> Option<&'static str> {
+        match &self.sound_data {
+            SoundData::OggData(ogg) => Some(ogg.encryption_type.name()),
+            _ => None,
+        }
+    }
+}
+
+/// What's known about a sound entry of an `.scd` file, for diagnosing extraction problems.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScdEntryInfo {
+    /// The codec the audio is in, e.g. `ogg`, `ms_adpcm`, `pcm` or `atrac9`. Only `ogg`,
+    /// `ms_adpcm` and `pcm` can be extracted.
+    pub codec: &'static str,
+    pub channels: u32,
+    pub frequency: u32,
+    /// The size of the audio data in bytes.
+    pub data_size: u32,
+    /// How OGG data is obscured: `none`, `vorbis_header_xor` or `internal_table_xor`.
+    pub encryption: Option<&'static str>,
+    /// The loop as byte offsets into the audio data, as stored.
+    pub loop_bytes: Option<(u64, u64)>,
+    /// The loop in samples, as written to `LOOPSTART` and `LOOPEND` tags.
+    pub loop_samples: Option<(u64, u64)>,
+}
+
+/// Read the headers of every sound entry in the [content] of an `.scd` file.
+pub fn read_scd_entries(content: &[u8]) -> Result<Vec<ScdEntryInfo>, LastLegendError> {
+    let mut scd = ScdFile::parse(Cursor::new(content))?;
+    let mut infos = Vec::with_capacity(scd.entries.len());
+    for index in 0..scd.entries.len() {
+        let index = u16::try_from(index).expect("entries are counted by a u16");
+        let entry = &scd.entries[usize::from(index)];
+        let loop_samples = if entry.codec.is_supported() && entry.codec != Codec::Empty {
+            scd.read_audio(index)?.loop_samples
+        } else {
+            None
+        };
+        let entry = &scd.entries[usize::from(index)];
+        infos.push(ScdEntryInfo {
+            codec: entry.codec.name(),
+            channels: entry.channels,
+            frequency: entry.frequency,
+            data_size: entry.data_size,
+            encryption: entry.encryption(),
+            loop_bytes: entry.loop_bytes,
+            loop_samples,
+        });
+    }
+    Ok(infos)
+}
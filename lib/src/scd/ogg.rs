@@ -0,0 +1,324 @@
+//! Reading the OGG streams in `.scd` files.
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom};
+
+use crate::error::LastLegendError;
+use crate::scd::format::SoundData;
+use crate::scd::ScdFile;
+
+/// The decrypted OGG data of a sound entry of an `.scd` file, split into packets, for
+/// investigating streams that don't decode.
+#[derive(Debug, Clone)]
+pub struct VorbisPacketDump {
+    /// How the data was obscured: `none`, `vorbis_header_xor` or `internal_table_xor`.
+    pub encryption: &'static str,
+    /// The decrypted Vorbis header, the OGG pages holding the identification, comment and setup
+    /// packets.
+    pub vorbis_header: Vec<u8>,
+    /// The first audio packets after the header.
+    pub packets: Vec<VorbisPacket>,
+    /// Why splitting the audio into packets stopped before the limit, if it did.
+    pub stopped: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VorbisPacket {
+    /// The byte offset of the page the packet starts in, from the start of the audio.
+    pub page_offset: usize,
+    /// The granule position of the page the packet ends in.
+    pub granule: u64,
+    pub data: Vec<u8>,
+}
+
+/// Decrypt sound [entry] of the [content] of an `.scd` file, and split the first [packet_limit]
+/// audio packets out of its OGG pages, without decoding them.
+pub fn dump_vorbis_packets(
+    content: &[u8],
+    entry: u16,
+    packet_limit: usize,
+) -> Result<VorbisPacketDump, LastLegendError> {
+    let mut scd = ScdFile::parse(Cursor::new(content))?;
+    let (encryption, vorbis_header_size) = match &scd.entry(entry)?.sound_data {
+        SoundData::OggData(ogg) => (ogg.encryption_type.name(), ogg.vorbis_header.len()),
+        _ => {
+            return Err(LastLegendError::Custom(format!(
+                "Sound entry holds {} data, not OGG",
+                scd.entry(entry)?.codec
+            )))
+        }
+    };
+    let mut stream = scd.read_audio(entry)?.data;
+    let audio = stream.split_off(vorbis_header_size.min(stream.len()));
+    let (packets, stopped) = split_ogg_packets(&audio, packet_limit);
+    Ok(VorbisPacketDump {
+        encryption,
+        vorbis_header: stream,
+        packets,
+        stopped,
+    })
+}
+
+/// Split up to [limit] packets out of the OGG pages in [audio], and why it stopped early, if it
+/// did. A packet continues onto the next page if the last segment of its page is full.
+fn split_ogg_packets(audio: &[u8], limit: usize) -> (Vec<VorbisPacket>, Option<String>) {
+    let mut packets = Vec::new();
+    let mut position = 0;
+    let mut partial: Option<(usize, Vec<u8>)> = None;
+    while packets.len() < limit {
+        let Some(page) = audio.get(position..).filter(|p| !p.is_empty()) else {
+            let stopped = partial.map(|_| "the last packet is cut off".to_string());
+            return (packets, stopped);
+        };
+        if !page.starts_with(b"OggS") {
+            return (packets, Some(format!("no OGG page at byte {}", position)));
+        }
+        let Some(segments) = page
+            .get(26)
+            .and_then(|&count| page.get(27..27 + usize::from(count)))
+        else {
+            return (
+                packets,
+                Some(format!("page at byte {} is cut off", position)),
+            );
+        };
+        let granule = u64::from_le_bytes(page[6..14].try_into().expect("checked length"));
+        let mut body = 27 + segments.len();
+        for &segment in segments {
+            let Some(data) = page.get(body..body + usize::from(segment)) else {
+                return (
+                    packets,
+                    Some(format!("page at byte {} is cut off", position)),
+                );
+            };
+            body += usize::from(segment);
+            let (_, packet) = partial.get_or_insert_with(|| (position, Vec::new()));
+            packet.extend_from_slice(data);
+            if segment < 255 {
+                let (page_offset, data) = partial.take().expect("inserted above");
+                packets.push(VorbisPacket {
+                    page_offset,
+                    granule,
+                    data,
+                });
+                if packets.len() == limit {
+                    break;
+                }
+            }
+        }
+        position += body;
+    }
+    (packets, None)
+}
+
+/// The loop in samples of an OGG [stream] that starts with a Vorbis header of
+/// [vorbis_header_size] bytes.
+pub(crate) fn ogg_loop_samples(
+    stream: &[u8],
+    vorbis_header_size: usize,
+    loop_bytes: Option<(u64, u64)>,
+) -> Option<(u64, u64)> {
+    let audio = &stream[vorbis_header_size.min(stream.len())..];
+    loop_bytes.map(|(start, end)| {
+        (
+            ogg_bytes_to_samples(audio, start),
+            ogg_bytes_to_samples(audio, end),
+        )
+    })
+}
+
+/// Convert a byte offset into OGG [audio] to a sample position, using the granule position of
+/// the last page that ends at or before it.
+fn ogg_bytes_to_samples(audio: &[u8], offset: u64) -> u64 {
+    OggPages::new(Cursor::new(audio))
+        .map_while(Result::ok)
+        .take_while(|page| page.offset + page.size() <= offset)
+        .filter_map(|page| page.granule)
+        .last()
+        .unwrap_or(0)
+}
+
+/// The number of samples and the sample rate of an OGG Vorbis [stream], which starts with a
+/// page. The sample rate is in the identification header, and the number of samples is the
+/// granule position of the last page, found by walking the pages from the start.
+pub(crate) fn ogg_vorbis_length(
+    stream: &mut (impl Read + Seek + ?Sized),
+) -> std::io::Result<Option<(u64, f64)>> {
+    let mut pages = OggPages::new(stream);
+    let Some(first) = pages.next().transpose()? else {
+        return Ok(None);
+    };
+    let mut total_samples = first.granule;
+    while let Some(page) = pages.next().transpose()? {
+        total_samples = page.granule.or(total_samples);
+    }
+
+    // The identification packet starts the first page: its type, "vorbis", the Vorbis version
+    // and the channel count, then the sample rate.
+    let mut identification = [0; 16];
+    let stream = pages.reader;
+    stream.seek(SeekFrom::Start(first.offset + first.header_size))?;
+    if first.body_size < 16 || stream.read_exact(&mut identification).is_err() {
+        return Ok(None);
+    }
+    if !identification.starts_with(b"\x01vorbis") {
+        return Ok(None);
+    }
+    let sample_rate = u32::from_le_bytes(identification[12..16].try_into().unwrap());
+    Ok(total_samples
+        .zip((sample_rate > 0).then_some(sample_rate))
+        .map(|(samples, rate)| (samples, f64::from(rate))))
+}
+
+/// The header of an OGG page.
+#[derive(Debug)]
+struct OggPage {
+    /// Where the page starts in the stream.
+    offset: u64,
+    /// The granule position, or None if no packet ends in the page.
+    granule: Option<u64>,
+    /// The size of the header, including the segment table.
+    header_size: u64,
+    body_size: u64,
+}
+
+impl OggPage {
+    /// The size of the page, with its body.
+    fn size(&self) -> u64 {
+        self.header_size + self.body_size
+    }
+}
+
+/// Walks the pages of an OGG stream from its start, reading only their headers. Stops at the
+/// end of the stream, or at anything that isn't a page.
+struct OggPages<R> {
+    reader: R,
+    offset: u64,
+}
+
+impl<R> OggPages<R> {
+    fn new(reader: R) -> Self {
+        Self { reader, offset: 0 }
+    }
+}
+
+impl<R: Read + Seek> Iterator for OggPages<R> {
+    type Item = std::io::Result<OggPage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut read_page = || {
+            self.reader.seek(SeekFrom::Start(self.offset))?;
+            let mut header = [0; 27];
+            match self.reader.read_exact(&mut header) {
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                result => result?,
+            }
+            if !header.starts_with(b"OggS") {
+                return Ok(None);
+            }
+            let mut segments = vec![0; usize::from(header[26])];
+            match self.reader.read_exact(&mut segments) {
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                result => result?,
+            }
+            let granule = u64::from_le_bytes(header[6..14].try_into().unwrap());
+            let page = OggPage {
+                offset: self.offset,
+                // Pages where no packet ends have no granule position.
+                granule: (granule != u64::MAX).then_some(granule),
+                header_size: (header.len() + segments.len()) as u64,
+                body_size: segments.iter().map(|&s| u64::from(s)).sum(),
+            };
+            self.offset += page.size();
+            Ok(Some(page))
+        };
+        read_page().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ogg_page(granule: u64, body_size: u8) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.extend_from_slice(&[0, 0]);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&[0; 12]);
+        page.extend_from_slice(&[1, body_size]);
+        page.extend(std::iter::repeat_n(0, usize::from(body_size)));
+        page
+    }
+
+    #[test]
+    fn ogg_loop_bytes_use_page_granules() {
+        // Each page is 28 bytes of header and 100 of body.
+        let audio = [
+            ogg_page(1000, 100),
+            ogg_page(u64::MAX, 100),
+            ogg_page(3000, 100),
+        ]
+        .concat();
+        assert_eq!(ogg_bytes_to_samples(&audio, 0), 0);
+        assert_eq!(ogg_bytes_to_samples(&audio, 128), 1000);
+        assert_eq!(ogg_bytes_to_samples(&audio, 300), 1000);
+        assert_eq!(ogg_bytes_to_samples(&audio, 384), 3000);
+    }
+
+    #[test]
+    fn ogg_vorbis_length_uses_the_last_granule() {
+        let mut identification = ogg_page(0, 30);
+        identification[28..35].copy_from_slice(b"\x01vorbis");
+        identification[40..44].copy_from_slice(&48_000u32.to_le_bytes());
+        let mut last = ogg_page(96_000, 100);
+        // Audio that looks like a page header isn't mistaken for one.
+        last[30..36].copy_from_slice(b"OggS\0\0");
+        let stream = [
+            identification.clone(),
+            ogg_page(1024, 200),
+            ogg_page(u64::MAX, 100),
+            last,
+        ]
+        .concat();
+        assert_eq!(
+            ogg_vorbis_length(&mut Cursor::new(&stream)).unwrap(),
+            Some((96_000, 48_000.0))
+        );
+        assert_eq!(
+            ogg_vorbis_length(&mut Cursor::new(&stream[1..])).unwrap(),
+            None
+        );
+        identification[28] = 3;
+        assert_eq!(
+            ogg_vorbis_length(&mut Cursor::new(&identification)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn ogg_packets_continue_across_pages() {
+        let mut first = ogg_page(u64::MAX, 0);
+        // One full segment, which carries on into the next page.
+        first.truncate(26);
+        first.extend_from_slice(&[1, 255]);
+        first.extend(std::iter::repeat_n(1, 255));
+        let mut second = ogg_page(500, 0);
+        second.truncate(26);
+        second.extend_from_slice(&[2, 10, 3]);
+        second.extend(std::iter::repeat_n(2, 13));
+        let audio = [first.clone(), second].concat();
+
+        let (packets, stopped) = split_ogg_packets(&audio, 10);
+        assert_eq!(stopped, None);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].page_offset, 0);
+        assert_eq!(packets[0].granule, 500);
+        assert_eq!(packets[0].data.len(), 265);
+        assert_eq!(packets[1].page_offset, first.len());
+        assert_eq!(packets[1].data, [2; 3]);
+
+        let (packets, stopped) = split_ogg_packets(&audio, 1);
+        assert_eq!((packets.len(), stopped), (1, None));
+        let (packets, stopped) = split_ogg_packets(&first, 10);
+        assert!(packets.is_empty());
+        assert_eq!(stopped.as_deref(), Some("the last packet is cut off"));
+    }
+}
@@ -0,0 +1,136 @@
+//! Wrapping the PCM and MS-ADPCM audio in `.scd` files in WAV files.
+use std::io::Read;
+
+use crate::error::LastLegendError;
+use crate::scd::format::MsAdpcmMetaHeader;
+
+/// Write a WAV file with the `fmt ` chunk [fmt_header], and all of [data] as its data chunk.
+pub(crate) fn wav_file(
+    fmt_header: &[u8],
+    data: &mut impl Read,
+) -> Result<Vec<u8>, LastLegendError> {
+    let mut data_chunk = Vec::new();
+    data.read_to_end(&mut data_chunk)
+        .map_err(|e| LastLegendError::Io("Couldn't read data".into(), e))?;
+    let chunk_size = |size: usize| {
+        u32::try_from(size)
+            .expect("should fit in u32")
+            .to_le_bytes()
+    };
+    let mut wav_file = Vec::with_capacity(28 + fmt_header.len() + data_chunk.len());
+    wav_file.extend_from_slice(b"RIFF");
+    wav_file.extend_from_slice(&chunk_size(20 + fmt_header.len() + data_chunk.len()));
+    wav_file.extend_from_slice(b"WAVE");
+    wav_file.extend_from_slice(b"fmt ");
+    wav_file.extend_from_slice(&chunk_size(fmt_header.len()));
+    wav_file.extend_from_slice(fmt_header);
+    wav_file.extend_from_slice(b"data");
+    wav_file.extend_from_slice(&chunk_size(data_chunk.len()));
+    wav_file.extend_from_slice(&data_chunk);
+    Ok(wav_file)
+}
+
+/// 16-bit PCM audio stores each sample of each channel in 2 bytes.
+const PCM_BYTES_PER_SAMPLE: u16 = 2;
+
+pub(crate) fn pcm_loop_samples(
+    channels: u32,
+    loop_bytes: Option<(u64, u64)>,
+) -> Option<(u64, u64)> {
+    let frame_size = u64::from(channels) * u64::from(PCM_BYTES_PER_SAMPLE);
+    if frame_size == 0 {
+        return None;
+    }
+    loop_bytes.map(|(start, end)| (start / frame_size, end / frame_size))
+}
+
+/// The `fmt ` chunk for 16-bit PCM audio.
+pub(crate) fn pcm_fmt_header(channels: u32, frequency: u32) -> Result<Vec<u8>, LastLegendError> {
+    let (channels, block_align) = u16::try_from(channels)
+        .ok()
+        .and_then(|c| Some((c, c.checked_mul(PCM_BYTES_PER_SAMPLE)?)))
+        .ok_or_else(|| {
+            LastLegendError::Custom(format!("Can't write PCM audio with {} channels", channels))
+        })?;
+    let mut fmt_header = Vec::with_capacity(16);
+    // WAVE_FORMAT_PCM
+    fmt_header.extend_from_slice(&1u16.to_le_bytes());
+    fmt_header.extend_from_slice(&channels.to_le_bytes());
+    fmt_header.extend_from_slice(&frequency.to_le_bytes());
+    let byte_rate = frequency.saturating_mul(u32::from(block_align));
+    fmt_header.extend_from_slice(&byte_rate.to_le_bytes());
+    fmt_header.extend_from_slice(&block_align.to_le_bytes());
+    fmt_header.extend_from_slice(&(PCM_BYTES_PER_SAMPLE * 8).to_le_bytes());
+    Ok(fmt_header)
+}
+
+pub(crate) fn ms_adpcm_loop_samples(
+    header: &MsAdpcmMetaHeader,
+    loop_bytes: Option<(u64, u64)>,
+) -> Option<(u64, u64)> {
+    loop_bytes.map(|(start, end)| {
+        (
+            ms_adpcm_bytes_to_samples(header, start),
+            ms_adpcm_bytes_to_samples(header, end),
+        )
+    })
+}
+
+/// Convert a byte offset into MS-ADPCM audio to a sample position.
+fn ms_adpcm_bytes_to_samples(header: &MsAdpcmMetaHeader, offset: u64) -> u64 {
+    let block_align = u64::from(header.block_align);
+    let channels = u64::from(header.channels);
+    if block_align == 0 || channels == 0 {
+        return 0;
+    }
+    // Each block starts with a 7 byte header per channel, which also holds the first 2 samples.
+    let block_header_size = 7 * channels;
+    let samples_in = |bytes: u64| match bytes.checked_sub(block_header_size) {
+        Some(data) => data * 2 / channels + 2,
+        None => 0,
+    };
+    (offset / block_align) * samples_in(block_align) + samples_in(offset % block_align)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcm_is_wrapped_in_wav() {
+        let fmt_header = pcm_fmt_header(2, 48000).unwrap();
+        let wav = wav_file(&fmt_header, &mut [1u8, 2, 3, 4].as_slice()).unwrap();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 40);
+        assert_eq!(&wav[12..16], b"fmt ");
+        // 2 channels, 48kHz, 4 byte frames, 16 bits
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 2);
+        assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 192000);
+        assert_eq!(u16::from_le_bytes([wav[32], wav[33]]), 4);
+        assert_eq!(u16::from_le_bytes([wav[34], wav[35]]), 16);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(&wav[44..], [1, 2, 3, 4]);
+        assert_eq!(pcm_loop_samples(2, Some((400, 4000))), Some((100, 1000)));
+    }
+
+    #[test]
+    fn ms_adpcm_loop_bytes_count_block_samples() {
+        let header = MsAdpcmMetaHeader {
+            format_tag: 2,
+            channels: 2,
+            samples_per_second: 44100,
+            avg_bytes_per_second: 0,
+            block_align: 1024,
+            bits_per_sample: 4,
+            size: 32,
+            samples_per_block: 1012,
+            num_coefficients: 7,
+            coefficients: [0; 14],
+        };
+        assert_eq!(ms_adpcm_bytes_to_samples(&header, 2048), 2024);
+        assert_eq!(
+            ms_adpcm_bytes_to_samples(&header, 1024 + 14 + 4),
+            1012 + 2 + 4
+        );
+    }
+}
@@ -1,30 +1,56 @@
+use std::cell::RefCell;
+use std::ffi::OsStr;
 use std::fs::File;
 use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use binrw::BinReaderExt;
 use owo_colors::{Style, Styled};
 
-use crate::data::dat::DatEntryHeader;
+use crate::data::dat::{DatEntryHeader, ReadScratch};
 use crate::data::index2::{Index2, Index2Entry};
+use crate::data::repo::Repository;
+use crate::data::source::DatReader;
 use crate::error::LastLegendError;
+use crate::ffmpeg::backend::audio_backend;
+use crate::ffmpeg::scratch::Spool;
+use crate::limits::Limits;
 use crate::sqpath::{SqPath, SqPathBuf};
-use crate::transformers::{Transformer, TransformerForFile, TransformerImpl};
+use crate::transformers::{
+    extension_magic, AudioFormat, PngOptions, TransformStream, Transformer, TransformerForFile,
+    TransformerImpl,
+};
 use crate::uwu_colors::{get_errstyle, ErrStyle};
+use crate::warning::{Warning, WarningSink};
 
 pub fn read_file_entry_header<F: AsRef<SqPath>>(
     index: &Index2,
     file: F,
-) -> Result<(DatEntryHeader, BufReader<File>), LastLegendError> {
+) -> Result<(DatEntryHeader, BufReader<Box<dyn DatReader>>), LastLegendError> {
     let entry = index.get_entry(file)?;
 
     read_entry_header(index, entry)
 }
 
-fn read_entry_header(
+/// Read the header of [entry], returning it and a reader positioned at its start.
+pub fn read_entry_header(
     index: &Index2,
     entry: &Index2Entry,
-) -> Result<(DatEntryHeader, BufReader<File>), LastLegendError> {
+) -> Result<(DatEntryHeader, BufReader<Box<dyn DatReader>>), LastLegendError> {
+    read_dat_header(index, entry).map_err(|e| dat_read_error(index, e))
+}
+
+/// Mark [e] as failing to read from the dat files of [index], see
+/// [may_be_stale_index](LastLegendError::may_be_stale_index).
+fn dat_read_error(index: &Index2, e: LastLegendError) -> LastLegendError {
+    LastLegendError::DatRead(index.index_path.clone(), Box::new(e))
+}
+
+fn read_dat_header(
+    index: &Index2,
+    entry: &Index2Entry,
+) -> Result<(DatEntryHeader, BufReader<Box<dyn DatReader>>), LastLegendError> {
     let mut dat_reader = BufReader::new(index.open_reader_for_entry(entry)?);
     let original_pos = dat_reader
         .stream_position()
@@ -39,33 +65,296 @@ fn read_entry_header(
     Ok((header, dat_reader))
 }
 
+thread_local! {
+    /// Reused by [read_entry_content], so extracting many entries doesn't allocate buffers for
+    /// each.
+    static READ_SCRATCH: RefCell<ReadScratch> = RefCell::default();
+}
+
+/// Read the decompressed content of [entry], through the content cache if the index has one.
+/// Fails early if the index's cancellation token is cancelled.
+pub fn read_entry_content(
+    index: &Index2,
+    entry: &Index2Entry,
+) -> Result<Arc<[u8]>, LastLegendError> {
+    if let Some(cancellation) = &index.cancellation {
+        cancellation.check()?;
+    }
+    let read_err = |e| LastLegendError::Io("Failed to read dat content".into(), e);
+    let content = match &index.content_cache {
+        Some(cache) => cache.get_or_load(&index.index_path, entry.key(), || {
+            let (header, dat_reader) = read_dat_header(index, entry)?;
+            header
+                .read_content_to_vec_with(dat_reader, index.cancellation.clone(), &index.limits)
+                .map_err(read_err)
+        }),
+        None => read_dat_header(index, entry).and_then(|(header, dat_reader)| {
+            READ_SCRATCH.with_borrow_mut(|scratch| {
+                header
+                    .read_content_with_scratch(
+                        dat_reader,
+                        scratch,
+                        index.cancellation.as_ref(),
+                        &index.limits,
+                    )
+                    .map(Arc::from)
+                    .map_err(read_err)
+            })
+        }),
+    };
+    content.map_err(|e| dat_read_error(index, e))
+}
+
 /// Create a reader for the data after applying transforms.
 pub fn create_transformed_reader(
     index: &Index2,
     entry: &Index2Entry,
-    mut file_name: SqPathBuf,
+    file_name: SqPathBuf,
     transformers: &[TransformerImpl],
 ) -> Result<TransformedReader, LastLegendError> {
-    let (header, dat_reader) = read_entry_header(index, entry)?;
-
-    let content = header
-        .read_content_to_vec(dat_reader)
-        .map_err(|e| LastLegendError::Io("Failed to read dat content".into(), e))?;
+    let content = read_entry_content(index, entry)?;
+    transform_reader(
+        Box::new(Cursor::new(content)),
+        file_name,
+        transformers,
+        &index.limits,
+    )
+}
 
-    let mut reader: Box<dyn Read + Send> = Box::new(Cursor::new(content));
+/// Apply [transformers] to [reader], the content of [file_name], in order. Transformers that
+/// parse their input apply [limits] to it.
+pub(crate) fn transform_reader(
+    mut reader: Box<dyn TransformStream>,
+    mut file_name: SqPathBuf,
+    transformers: &[TransformerImpl],
+    limits: &Limits,
+) -> Result<TransformedReader, LastLegendError> {
+    let mut last_transformer = None;
     for t in transformers {
+        let t = t.with_limits(*limits);
         if let Some(tf) = t.maybe_for(file_name.clone()) {
             file_name = tf.renamed_file().into_owned();
             reader = tf.transform(reader)?;
+            last_transformer = Some(t);
         }
     }
 
-    Ok(TransformedReader { file_name, reader })
+    Ok(TransformedReader {
+        file_name,
+        reader,
+        last_transformer,
+    })
+}
+
+/// Read [file] from [repo] and apply [transformers]. This is the starting point for extracting a
+/// single file, see [extract_to_file] to write it out as well.
+pub fn read_transformed<F: AsRef<SqPath>>(
+    repo: &Repository,
+    file: F,
+    transformers: &[TransformerImpl],
+) -> Result<TransformedReader, LastLegendError> {
+    let file = file.as_ref();
+    let index = repo.get_index_for(file)?;
+    let entry = index.get_entry(file)?;
+    repo.retry_if_stale(&index, entry, |index, entry| {
+        create_transformed_reader(index, entry, file.to_owned(), transformers)
+    })
+}
+
+/// Get the path to write a transformed file to. The extension of [output_base_name] is replaced
+/// with the extension of the transformed [file_name].
+pub fn transformed_output_path<O: AsRef<OsStr>>(
+    output_base_name: O,
+    file_name: &SqPath,
+) -> PathBuf {
+    let output_base_name = Path::new(&output_base_name);
+    match Path::new(file_name.as_str()).extension() {
+        Some(extension) => output_base_name.with_extension(extension),
+        None => output_base_name.to_path_buf(),
+    }
+}
+
+/// Extract [file] from [repo] to [output_base_name], after applying [transformers]. Any existing
+/// file is overwritten, and missing directories are created.
+///
+/// Returns the path written to, which has the extension of the transformed file.
+pub fn extract_to_file<F: AsRef<SqPath>, O: AsRef<OsStr>>(
+    repo: &Repository,
+    file: F,
+    transformers: &[TransformerImpl],
+    output_base_name: O,
+) -> Result<PathBuf, LastLegendError> {
+    let TransformedReader {
+        file_name,
+        mut reader,
+        ..
+    } = read_transformed(repo, file, transformers)?;
+    let output_path = transformed_output_path(output_base_name, &file_name);
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| LastLegendError::Io("Couldn't create output dirs".into(), e))?;
+    }
+    let mut output = File::create(&output_path)
+        .map_err(|e| LastLegendError::Io("Couldn't open output".into(), e))?;
+    std::io::copy(&mut reader, &mut output)
+        .map_err(|e| LastLegendError::Io("Couldn't write output".into(), e))?;
+    Ok(output_path)
+}
+
+/// Get the name [file_name] will have after [transformers] are applied, without reading it.
+pub fn transformed_file_name(
+    mut file_name: SqPathBuf,
+    transformers: &[TransformerImpl],
+) -> SqPathBuf {
+    for t in transformers {
+        if let Some(tf) = <TransformerImpl as Transformer<Box<dyn TransformStream>>>::maybe_for(
+            t,
+            file_name.clone(),
+        ) {
+            file_name = tf.renamed_file().into_owned();
+        }
+    }
+    file_name
 }
 
 pub struct TransformedReader {
     pub file_name: SqPathBuf,
-    pub reader: Box<dyn Read + Send>,
+    /// The transformed content. Large output from FFMPEG is read from a temporary file.
+    pub reader: Box<dyn TransformStream>,
+    /// The last transformer that applied to the file, if any.
+    pub last_transformer: Option<TransformerImpl>,
+}
+
+impl TransformedReader {
+    /// Check that the file name and content match what the last transformer should produce.
+    /// This reads the magic bytes of the content, then seeks back to the start.
+    pub fn verify_output(mut self) -> Result<Self, LastLegendError> {
+        let Some(transformer) = self.last_transformer else {
+            return Ok(self);
+        };
+        let mismatch = |reason: String| LastLegendError::TransformerOutputMismatch {
+            file: self.file_name.clone(),
+            transformer,
+            reason,
+        };
+
+        let expected = transformer.output_extension();
+        let extension = Path::new(self.file_name.as_str())
+            .extension()
+            .and_then(|e| e.to_str());
+        if extension != Some(expected) {
+            return Err(mismatch(format!(
+                "expected extension .{}, but got {:?}",
+                expected, extension
+            )));
+        }
+
+        if let Some(magic) = extension_magic(expected) {
+            let read_err = |e| LastLegendError::Io("Couldn't read transformed content".into(), e);
+            let mut start = Vec::with_capacity(magic.len());
+            (&mut self.reader)
+                .take(magic.len() as u64)
+                .read_to_end(&mut start)
+                .map_err(read_err)?;
+            self.reader.rewind().map_err(read_err)?;
+            if start != magic {
+                return Err(mismatch(format!("content is not {}", expected)));
+            }
+        }
+
+        Ok(self)
+    }
+}
+
+/// Extra metadata to embed in audio output, after all transformers have run.
+#[derive(Debug, Default, Clone)]
+pub struct OutputMetadata {
+    /// A PNG to embed as the front cover.
+    pub cover_art: Option<Arc<Vec<u8>>>,
+    /// Tags to set, as Vorbis comment names and values, e.g. `("TITLE", "Answers")`.
+    pub tags: Vec<(String, String)>,
+}
+
+/// Embed the [metadata] in the transformed output. Only `flac`, `ogg`, `mp3` and `m4a` files can
+/// hold metadata, anything else is passed through unchanged, reported to [warnings].
+pub fn apply_output_metadata(
+    transformed: TransformedReader,
+    metadata: &OutputMetadata,
+    warnings: &WarningSink,
+) -> Result<TransformedReader, LastLegendError> {
+    if metadata.cover_art.is_none() && metadata.tags.is_empty() {
+        return Ok(transformed);
+    }
+    let TransformedReader {
+        file_name,
+        mut reader,
+        last_transformer,
+    } = transformed;
+    let format = match Path::new(file_name.as_str())
+        .extension()
+        .and_then(|e| e.to_str())
+    {
+        Some(format @ ("flac" | "ogg" | "mp3")) => format,
+        Some("m4a") => AudioFormat::Aac.ffmpeg_format(),
+        _ => {
+            warnings.warn(Warning::MetadataNotEmbedded {
+                file: file_name.as_str().to_string(),
+            });
+            return Ok(TransformedReader {
+                file_name,
+                reader,
+                last_transformer,
+            });
+        }
+    };
+
+    let mut output = Spool::new();
+    audio_backend().embed_metadata(
+        format,
+        &mut reader,
+        metadata.cover_art.as_deref().map(Vec::as_slice),
+        &metadata.tags,
+        &mut output,
+    )?;
+    Ok(TransformedReader {
+        file_name,
+        reader: output.into_stream()?,
+        last_transformer,
+    })
+}
+
+/// Get the path to a UI icon, optionally the high resolution version.
+pub fn icon_path(icon_id: u32, high_resolution: bool) -> SqPathBuf {
+    SqPathBuf::new(&format!(
+        "ui/icon/{:06}/{:06}{}.tex",
+        icon_id / 1000 * 1000,
+        icon_id,
+        if high_resolution { "_hr1" } else { "" }
+    ))
+}
+
+/// Read a UI icon as a PNG, preferring the high resolution version if it exists.
+pub fn read_icon_png(repo: &Repository, icon_id: u32) -> Result<Vec<u8>, LastLegendError> {
+    let high_res = icon_path(icon_id, true);
+    let index = repo.get_index_for(&high_res)?;
+    let file = if index.get_entry(&high_res).is_ok() {
+        high_res
+    } else {
+        icon_path(icon_id, false)
+    };
+    let entry = index.get_entry(&file)?;
+
+    let TransformedReader { mut reader, .. } = create_transformed_reader(
+        &index,
+        entry,
+        file,
+        &[TransformerImpl::TexToPng(PngOptions::default())],
+    )?;
+    let mut png = Vec::new();
+    reader
+        .read_to_end(&mut png)
+        .map_err(|e| LastLegendError::Io("Couldn't read icon".into(), e))?;
+    Ok(png)
 }
 
 pub fn format_index_entry_for_console<P: AsRef<Path>, F: AsRef<SqPath>>(
@@ -94,3 +383,175 @@ pub fn format_index_entry_for_console<P: AsRef<Path>, F: AsRef<SqPath>>(
 pub fn format_index_hash_for_console(hash: u32) -> Styled<String> {
     get_errstyle(Style::new().blue()).style(format!("0x{:X}", hash))
 }
+
+#[cfg(test)]
+mod simple_task_tests {
+    use super::*;
+
+    /// Stands in for `ffmpeg`, copying the first input to the output, whether either is piped or
+    /// a file.
+    #[cfg(target_os = "linux")]
+    const FAKE_FFMPEG: &str = r#"#!/bin/sh
+[ "$1" = -version ] && exit 0
+input=; previous=; output=
+for arg; do
+    if [ "$previous" = -i ] && [ -z "$input" ]; then input=$arg; fi
+    previous=$arg; output=$arg
+done
+case "$input" in pipe:*) input=/dev/stdin ;; esac
+if [ "$output" = pipe:1 ]; then cat "$input"; else cat "$input" > "$output"; fi
+"#;
+
+    /// Stands in for `ffprobe`, giving every file a loop and a 10 second duration.
+    #[cfg(target_os = "linux")]
+    const FAKE_FFPROBE: &str = r#"#!/bin/sh
+case "$*" in
+    *format_tags*) echo "tag:LOOPSTART=1000|tag:LOOPEND=4000" ;;
+    *sample_rate*) echo "44100|10.0" ;;
+    *) echo "10.0" ;;
+esac
+"#;
+
+    /// An `.scd` file with one looping sound entry of 16-bit mono PCM.
+    fn pcm_scd(samples: usize) -> Vec<u8> {
+        let data_size = u32::try_from(samples * 2).unwrap();
+        let mut scd = b"SEDBSSCF".to_vec();
+        scd.extend_from_slice(&3u32.to_le_bytes());
+        scd.extend_from_slice(&[0, 0]);
+        // The offsets header follows this header.
+        scd.extend_from_slice(&0x30u16.to_le_bytes());
+        scd.resize(0x34, 0);
+        scd.extend_from_slice(&1u16.to_le_bytes());
+        scd.resize(0x3C, 0);
+        // The entry offsets, with the only entry after them.
+        scd.extend_from_slice(&0x40u32.to_le_bytes());
+        scd.extend_from_slice(&0x50u32.to_le_bytes());
+        scd.resize(0x50, 0);
+        // Size, channels, frequency, PCM, loop start and end, and no markers.
+        for field in [data_size, 1, 44100, 1, 2000, 0, 0, 0] {
+            scd.extend_from_slice(&field.to_le_bytes());
+        }
+        scd.extend((0..data_size).map(|i| i as u8));
+        scd
+    }
+
+    /// Run the bulk music pipeline, encoding, looping and embedding cover art, for many files in
+    /// parallel, and check that open files don't grow with the number of files.
+    ///
+    /// Counting the files open in this process would also count those of tests running at the same
+    /// time, so the pipeline runs alone in a copy of this test binary, against stand-ins for
+    /// `ffmpeg` and `ffprobe`.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn bulk_music_pipeline_keeps_file_descriptors_bounded() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::process::Command;
+
+        const CHILD_ENV: &str = "LLDOB_FD_TEST_CHILD";
+        const TEST_NAME: &str =
+            "simple_task::simple_task_tests::bulk_music_pipeline_keeps_file_descriptors_bounded";
+        if std::env::var_os(CHILD_ENV).is_some() {
+            run_bulk_music_pipeline();
+            return;
+        }
+
+        let bin_dir = tempfile::tempdir().unwrap();
+        for (name, script) in [("ffmpeg", FAKE_FFMPEG), ("ffprobe", FAKE_FFPROBE)] {
+            let path = bin_dir.path().join(name);
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let path = std::env::join_paths(std::iter::once(bin_dir.path().to_path_buf()).chain(
+            std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default()),
+        ))
+        .unwrap();
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args([TEST_NAME, "--exact", "--nocapture", "--test-threads=1"])
+            .env(CHILD_ENV, "1")
+            .env("PATH", path)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success() && stdout.contains("1 passed"),
+            "{}\n{}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    fn run_bulk_music_pipeline() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        use crate::ffmpeg::set_max_ffmpeg_processes;
+
+        fn open_fds() -> usize {
+            std::fs::read_dir("/proc/self/fd").unwrap().count()
+        }
+        const THREADS: usize = 8;
+        const FILES_PER_THREAD: usize = 25;
+
+        set_max_ffmpeg_processes(THREADS);
+        let transformers = ["scd_to_flac", "loop_flac"].map(|t| t.parse().unwrap());
+        let metadata = OutputMetadata {
+            cover_art: Some(Arc::new(b"\x89PNG cover".to_vec())),
+            tags: vec![("TITLE".to_string(), "Answers".to_string())],
+        };
+        let scd = pcm_scd(5000);
+
+        let before = open_fds();
+        let peak = AtomicUsize::new(before);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                while !done.load(Ordering::Acquire) {
+                    peak.fetch_max(open_fds(), Ordering::AcqRel);
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+            });
+            let workers = (0..THREADS)
+                .map(|_| {
+                    s.spawn(|| {
+                        for i in 0..FILES_PER_THREAD {
+                            let transformed = transform_reader(
+                                Box::new(Cursor::new(scd.clone())),
+                                SqPathBuf::new(&format!("music/ex1/track_{}.scd", i)),
+                                &transformers,
+                                &Limits::default(),
+                            )
+                            .unwrap();
+                            let mut transformed = apply_output_metadata(
+                                transformed,
+                                &metadata,
+                                &WarningSink::default(),
+                            )
+                            .unwrap();
+                            assert!(transformed.file_name.as_str().ends_with(".flac"));
+                            let mut output = Vec::new();
+                            transformed.reader.read_to_end(&mut output).unwrap();
+                            assert!(output.starts_with(b"RIFF"));
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            for worker in workers {
+                worker.join().unwrap();
+            }
+            done.store(true, Ordering::Release);
+        });
+
+        // Each thread has at most its pooled scratch files open, and the pipes of one FFMPEG
+        // process, both ends of which are open while it's spawned.
+        let per_thread = crate::ffmpeg::scratch::MAX_POOLED_PER_THREAD + 8;
+        let peak = peak.into_inner();
+        assert!(
+            peak <= before + THREADS * per_thread,
+            "{} files open at the peak, {} before",
+            peak,
+            before
+        );
+        // Pooled scratch files are closed as their threads exit.
+        assert!(open_fds() <= before);
+    }
+}
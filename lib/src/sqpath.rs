@@ -43,8 +43,57 @@ impl SqPath {
     /// a specific file within the index, as the index files are all encoded
     /// based on a specific hash of the file path.
     pub fn sq_index_hash(&self) -> u32 {
-        const CALCULATOR: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_JAMCRC);
-        CALCULATOR.checksum(self.inner.to_ascii_lowercase().as_bytes())
+        self.sq_index_hash_with(&PathHasher::default())
+    }
+
+    /// Gets the index hash of the file using a non-standard [hasher].
+    pub fn sq_index_hash_with(&self, hasher: &PathHasher) -> u32 {
+        hasher.hash(self.inner.to_ascii_lowercase().as_bytes())
+    }
+
+    /// Gets the hashes of the folder and file name, used by `.index` files, with a non-standard
+    /// [hasher]. A path without a folder has an empty folder.
+    pub fn sq_folder_file_hash_with(&self, hasher: &PathHasher) -> (u32, u32) {
+        let lower = self.inner.to_ascii_lowercase();
+        let (folder, file) = lower.rsplit_once('/').unwrap_or(("", &lower));
+        (hasher.hash(folder.as_bytes()), hasher.hash(file.as_bytes()))
+    }
+
+    /// Checks that this path maps to an index file, i.e. that its category is known, and that if
+    /// it names an expansion, the expansion is known too. Paths from content newer than this
+    /// library fail this, e.g. `music/ex6/...`.
+    pub fn check_resolvable(&self) -> Result<(), Unresolvable> {
+        let s = self.as_str();
+        let mut segments = s.split('/');
+        let category = segments.next().unwrap_or_default();
+        let Some(second) = segments.next() else {
+            return Err(Unresolvable::Malformed);
+        };
+        if FileType::parse_from_sqpath(self).is_none() {
+            return Err(Unresolvable::UnknownCategory(category.to_string()));
+        }
+        let looks_like_expansion = second
+            .strip_prefix("ex")
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        if looks_like_expansion && !Expansion::parse_from_sqpath(self).1 {
+            return Err(Unresolvable::UnknownExpansion(second.to_string()));
+        }
+        if SqPackNumber::parse_from_sqpath(self).is_none() {
+            return Err(Unresolvable::Malformed);
+        }
+        Ok(())
+    }
+
+    /// Gets the paths of the index files that may locate this SqPath within the .dat files, in
+    /// order of preference: the `.index2` file, then the `.index` file. The location of the SqPack
+    /// currently in use is specified by `sqpack`
+    ///
+    /// # Returns
+    /// The candidate index files if the proper index file could be parsed, None otherwise.
+    pub fn sqpack_index_paths<P: AsRef<Path>>(&self, sqpack: P) -> Option<[PathBuf; 2]> {
+        let index2 = self.sqpack_index_path(sqpack)?;
+        let index = index2.with_extension("index");
+        Some([index2, index])
     }
 
     /// Gets the path to the index file (v2) that locates this SqPath within the .dat files. The
@@ -55,6 +104,7 @@ impl SqPath {
     /// be parsed, None otherwise.
     pub fn sqpack_index_path<P: AsRef<Path>>(&self, sqpack: P) -> Option<PathBuf> {
         let sqpack = sqpack.as_ref();
+        self.check_resolvable().ok()?;
 
         FileType::parse_from_sqpath(self)
             .map(|file_type| (file_type, Expansion::parse_from_sqpath(self).0))
@@ -123,6 +173,93 @@ impl Deref for SqPathBuf {
     }
 }
 
+/// The CRC-32 parameters used to hash paths for the index. The default is the JAMCRC the game
+/// uses, other parameters are only useful for modified clients that changed the hash.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct PathHasher {
+    pub polynomial: u32,
+    pub init: u32,
+    pub xor_out: u32,
+    /// Whether input bytes and the result are bit-reflected.
+    pub reflect: bool,
+}
+
+impl Default for PathHasher {
+    fn default() -> Self {
+        Self {
+            polynomial: crc::CRC_32_JAMCRC.poly,
+            init: crc::CRC_32_JAMCRC.init,
+            xor_out: crc::CRC_32_JAMCRC.xorout,
+            reflect: crc::CRC_32_JAMCRC.refin,
+        }
+    }
+}
+
+impl PathHasher {
+    pub fn hash(&self, bytes: &[u8]) -> u32 {
+        const CALCULATOR: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_JAMCRC);
+        if *self == Self::default() {
+            return CALCULATOR.checksum(bytes);
+        }
+
+        // Custom parameters are rare and paths are short, so compute it bit-by-bit.
+        let mut crc = self.init;
+        if self.reflect {
+            let polynomial = self.polynomial.reverse_bits();
+            crc = crc.reverse_bits();
+            for &b in bytes {
+                crc ^= u32::from(b);
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 {
+                        (crc >> 1) ^ polynomial
+                    } else {
+                        crc >> 1
+                    };
+                }
+            }
+        } else {
+            for &b in bytes {
+                crc ^= u32::from(b) << 24;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000_0000 != 0 {
+                        (crc << 1) ^ self.polynomial
+                    } else {
+                        crc << 1
+                    };
+                }
+            }
+        }
+        crc ^ self.xor_out
+    }
+}
+
+const ALL_FILE_TYPES: [FileType; 15] = [
+    FileType::Common,
+    FileType::BGCommon,
+    FileType::BG,
+    FileType::Cut,
+    FileType::Chara,
+    FileType::Shader,
+    FileType::UI,
+    FileType::Sound,
+    FileType::VFX,
+    FileType::UIScript,
+    FileType::EXD,
+    FileType::GameScript,
+    FileType::Music,
+    FileType::SqpackTest,
+    FileType::Debug,
+];
+
+pub(crate) const ALL_EXPANSIONS: [Expansion; 6] = [
+    Expansion::FFXIV,
+    Expansion::Heavensward,
+    Expansion::Stormblood,
+    Expansion::Shadowbringers,
+    Expansion::Endwalker,
+    Expansion::Dawntrail,
+];
+
 /// The FileType of a SqPath. Specifically, not the actual file type, but rather
 /// the index file it can be found in, which are grouped by broad categories of files.
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
@@ -201,6 +338,13 @@ impl FileType {
         }
     }
 
+    /// The FileType with the given [prefix], the first byte of index and dat file names.
+    pub fn from_file_name_prefix(prefix: u8) -> Option<FileType> {
+        ALL_FILE_TYPES
+            .into_iter()
+            .find(|t| t.file_name_prefix() == prefix)
+    }
+
     /// Gets a byte representing the hex code of the FileType variant. See `file_name_prefix_str`.
     pub fn file_name_prefix(&self) -> u8 {
         match self {
@@ -290,6 +434,13 @@ impl Expansion {
         }
     }
 
+    /// The Expansion with the given [prefix], the second byte of index and dat file names.
+    pub fn from_file_name_prefix(prefix: u8) -> Option<Expansion> {
+        ALL_EXPANSIONS
+            .into_iter()
+            .find(|e| e.file_name_prefix() == prefix)
+    }
+
     /// Gets a byte representing the hex code of the Expansion variant. See `file_name_prefix_str`.
     pub fn file_name_prefix(&self) -> u8 {
         match self {
@@ -315,6 +466,27 @@ impl Expansion {
     }
 }
 
+/// Why a path doesn't map to an index file, see [SqPath::check_resolvable].
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub enum Unresolvable {
+    /// The first segment isn't a known category.
+    UnknownCategory(String),
+    /// The second segment names an expansion, e.g. `ex6`, that isn't known.
+    UnknownExpansion(String),
+    /// The path doesn't have the segments an index file is chosen by.
+    Malformed,
+}
+
+impl Display for Unresolvable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCategory(category) => write!(f, "unknown category '{}'", category),
+            Self::UnknownExpansion(expansion) => write!(f, "unknown expansion '{}'", expansion),
+            Self::Malformed => f.write_str("malformed path"),
+        }
+    }
+}
+
 #[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Debug, Hash, Default)]
 pub struct SqPackNumber(u8);
 
@@ -420,7 +592,9 @@ impl Display for SqPathBuf {
 mod sqpath_tests {
     use std::borrow::Borrow;
 
-    use crate::sqpath::{Expansion, FileType, SqPackNumber, SqPath, SqPathBuf};
+    use crate::sqpath::{
+        Expansion, FileType, PathHasher, SqPackNumber, SqPath, SqPathBuf, Unresolvable,
+    };
 
     #[test]
     fn basic_sqpath() {
@@ -468,6 +642,26 @@ mod sqpath_tests {
         assert_eq!(sq_index_path, 0xE3B71579);
     }
 
+    #[test]
+    fn custom_path_hasher() {
+        // Same as the default, but without going through the CRC library.
+        let hasher = PathHasher {
+            xor_out: 1,
+            ..PathHasher::default()
+        };
+        let sq_path = SqPath::new("BGM_System_Title.scd");
+        assert_eq!(sq_path.sq_index_hash_with(&hasher), 0xE3B71579 ^ 1);
+
+        // Plain CRC-32/BZIP2 check value.
+        let bzip2 = PathHasher {
+            polynomial: 0x04C11DB7,
+            init: 0xFFFFFFFF,
+            xor_out: 0xFFFFFFFF,
+            reflect: false,
+        };
+        assert_eq!(bzip2.hash(b"123456789"), 0xFC891918);
+    }
+
     #[test]
     fn to_owned_and_borrow() {
         let sqpath = SqPath::new("uwu");
@@ -714,6 +908,33 @@ mod sqpath_tests {
         );
     }
 
+    #[test]
+    fn sqpack_index_paths_prefer_index2() {
+        let [index2, index] = SqPath::new("music/ffxiv/BGM_System_Title.scd")
+            .sqpack_index_paths("/home/uwu/ffxiv/sqpack/")
+            .unwrap();
+        assert_eq!(
+            index2.as_os_str(),
+            "/home/uwu/ffxiv/sqpack/ffxiv/0c0000.win32.index2"
+        );
+        assert_eq!(
+            index.as_os_str(),
+            "/home/uwu/ffxiv/sqpack/ffxiv/0c0000.win32.index"
+        );
+    }
+
+    #[test]
+    fn folder_file_hash() {
+        let hasher = PathHasher::default();
+        assert_eq!(
+            SqPath::new("Music/ffxiv/BGM_System_Title.scd").sq_folder_file_hash_with(&hasher),
+            (
+                hasher.hash(b"music/ffxiv"),
+                hasher.hash(b"bgm_system_title.scd")
+            )
+        );
+    }
+
     #[test]
     fn sqpack_index_path() {
         let index = SqPath::new("music/ffxiv/BGM_System_Title.scd")
@@ -740,4 +961,38 @@ mod sqpath_tests {
             "/home/uwu/ffxiv/sqpack/ex2/0002fe.win32.index2"
         );
     }
+
+    #[test]
+    fn unresolvable_paths() {
+        assert_eq!(
+            SqPath::new("music/ex5/bgm_ex5_field_01.scd").check_resolvable(),
+            Ok(())
+        );
+        assert_eq!(
+            SqPath::new("music/ex6/bgm_ex6_field_01.scd").check_resolvable(),
+            Err(Unresolvable::UnknownExpansion("ex6".into()))
+        );
+        assert!(SqPath::new("music/ex6/bgm_ex6_field_01.scd")
+            .sqpack_index_path("/sqpack")
+            .is_none());
+        assert_eq!(
+            SqPath::new("newthing/ffxiv/file.scd").check_resolvable(),
+            Err(Unresolvable::UnknownCategory("newthing".into()))
+        );
+        assert_eq!(
+            SqPath::new("music").check_resolvable(),
+            Err(Unresolvable::Malformed)
+        );
+    }
+
+    #[test]
+    fn types_from_file_name_prefix() {
+        assert_eq!(FileType::from_file_name_prefix(0x0c), Some(FileType::Music));
+        assert_eq!(FileType::from_file_name_prefix(0x42), None);
+        assert_eq!(
+            Expansion::from_file_name_prefix(0x02),
+            Some(Expansion::Stormblood)
+        );
+        assert_eq!(Expansion::from_file_name_prefix(0x42), None);
+    }
 }
@@ -0,0 +1,121 @@
+//! Working out every file a piece of music may play. A music id can lead to more than the `BGM`
+//! row with that id: `BGMSwitch` picks between tracks by quest progress, and `BGMFade` fades
+//! into variants of a track.
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::error::LastLegendError;
+use crate::surpass::collection::Collection;
+use crate::surpass::known_rows::bgm::BGM;
+use crate::surpass::known_rows::bgm_fade::BGMFade;
+use crate::surpass::known_rows::bgm_switch::BGMSwitch;
+use crate::surpass::sheet_info::Language;
+
+/// The `BGM`, `BGMSwitch` and `BGMFade` sheets, joined to resolve music ids to `.scd` paths.
+#[derive(Debug, Default)]
+pub struct BgmVariants {
+    files: HashMap<u32, String>,
+    switches: HashMap<u32, Vec<u32>>,
+    fades: HashMap<u32, Vec<u32>>,
+}
+
+impl BgmVariants {
+    /// Read the sheets from [collection] in [language].
+    pub fn load(collection: &Collection, language: Language) -> Result<Self, LastLegendError> {
+        let mut variants = Self::default();
+        for row in collection.known_rows::<BGM>(language)? {
+            let (id, bgm) = row?;
+            variants.add_file(id, bgm.file);
+        }
+        for row in collection.known_rows::<BGMSwitch>(language)? {
+            let (id, switch) = row?;
+            variants.add_switch(id, switch.bgm.into());
+        }
+        for row in collection.known_rows::<BGMFade>(language)? {
+            let (_, fade) = row?;
+            if let (Ok(from), Ok(to)) = (u32::try_from(fade.bgm_out), u32::try_from(fade.bgm_in)) {
+                variants.add_fade(from, to);
+            }
+        }
+        Ok(variants)
+    }
+
+    fn add_file(&mut self, id: u32, file: String) {
+        if !file.is_empty() {
+            self.files.insert(id, file);
+        }
+    }
+
+    fn add_switch(&mut self, id: u32, bgm: u32) {
+        self.switches.entry(id).or_default().push(bgm);
+    }
+
+    fn add_fade(&mut self, from: u32, to: u32) {
+        self.fades.entry(from).or_default().push(to);
+    }
+
+    /// Every music id with a file, switch or fade, in order.
+    pub fn music_ids(&self) -> BTreeSet<u32> {
+        let ids = self.files.keys().chain(self.switches.keys());
+        ids.chain(self.fades.keys()).copied().collect()
+    }
+
+    /// Every `.scd` path any music id may play, sorted and without duplicates.
+    pub fn all_scd_paths(&self) -> BTreeSet<String> {
+        self.music_ids()
+            .into_iter()
+            .flat_map(|id| self.scd_paths(id))
+            .collect()
+    }
+
+    /// Every `.scd` path music [id] may play: its own file, the files of each track it switches
+    /// between, and of each variant it fades into, followed transitively. Sorted, without
+    /// duplicates, and empty if [id] doesn't lead to any file.
+    pub fn scd_paths(&self, id: u32) -> BTreeSet<String> {
+        let mut paths = BTreeSet::new();
+        let mut seen = HashSet::from([id]);
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            paths.extend(self.files.get(&id).cloned());
+            let next = self.switches.get(&id).into_iter().flatten();
+            let next = next.chain(self.fades.get(&id).into_iter().flatten());
+            for &next in next {
+                if seen.insert(next) {
+                    pending.push(next);
+                }
+            }
+        }
+        paths
+    }
+}
+
+#[cfg(test)]
+mod bgm_variants_tests {
+    use super::*;
+
+    #[test]
+    fn follows_switches_and_fades() {
+        let mut variants = BgmVariants::default();
+        variants.add_file(1, "music/base.scd".into());
+        variants.add_file(2, "music/night.scd".into());
+        variants.add_file(3, "music/quest_done.scd".into());
+        variants.add_file(4, String::new());
+        variants.add_switch(100, 1);
+        variants.add_switch(100, 3);
+        variants.add_fade(1, 2);
+        // Fades back and forth shouldn't loop forever.
+        variants.add_fade(2, 1);
+
+        assert_eq!(
+            variants.scd_paths(100),
+            BTreeSet::from([
+                "music/base.scd".to_string(),
+                "music/night.scd".to_string(),
+                "music/quest_done.scd".to_string(),
+            ])
+        );
+        assert_eq!(variants.scd_paths(3).len(), 1);
+        assert!(variants.scd_paths(4).is_empty());
+        assert_eq!(variants.music_ids(), BTreeSet::from([1, 2, 3, 100]));
+        assert_eq!(variants.all_scd_paths().len(), 3);
+    }
+}
@@ -1,27 +1,45 @@
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Cursor};
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use binrw::BinReaderExt;
 use serde::de::DeserializeOwned;
 use unicase::Ascii;
 
+use crate::data::content_cache::{CacheStats, ContentCache};
 use crate::data::repo::Repository;
 use crate::error::LastLegendError;
-use crate::simple_task::{format_index_entry_for_console, read_file_entry_header};
-use crate::surpass::page::{PageHeader, RowBufferIter};
-use crate::surpass::serde_row::from_row;
-use crate::surpass::sheet_info::{Language, SheetInfo};
+use crate::limits::Limits;
+use crate::simple_task::{
+    format_index_entry_for_console, read_entry_content, read_file_entry_header,
+};
+use crate::surpass::known_rows::KnownRow;
+use crate::surpass::page::{PageHeader, RowBuffer, RowBufferIter};
+use crate::surpass::schema::Schema;
+use crate::surpass::serde_row::{from_sheet_row, StringMode};
+use crate::surpass::sheet_info::{DataValue, Language, SheetInfo};
+use crate::warning::Warning;
 
 #[derive(Debug)]
 pub struct Collection {
     repo: Repository,
     sheets: HashMap<Ascii<String>, i32>,
+    schema: Schema,
+    /// Decompressed sheet pages by file name, shared by every [SheetIter] of the collection.
+    page_cache: Arc<ContentCache<String>>,
 }
 
+/// The row id, the sub-row id for sheets with sub-rows, and the values of each column of a row.
+pub type RowValues = (u32, Option<u16>, Vec<DataValue>);
+
 /// Magic value for the root file that points to all sheets.
 const MAGIC_ROOT: &str = "exd/root.exl";
 
+/// How many bytes of sheet pages a collection keeps by default, enough for the few sheets most
+/// commands read.
+const DEFAULT_PAGE_CACHE_BYTES: u64 = 32 * 1024 * 1024;
+
 impl Collection {
     pub fn load(repo: Repository) -> Result<Self, LastLegendError> {
         let index = repo
@@ -36,6 +54,10 @@ impl Collection {
         let mut sheets = HashMap::new();
         for line in BufReader::new(reader).lines() {
             let line = line.map_err(|e| LastLegendError::Io("Failed to read line".into(), e))?;
+            // The header line, not a sheet.
+            if line.starts_with("EXLT,") {
+                continue;
+            }
             let (name, id_str) = line
                 .split_once(',')
                 .ok_or_else(|| LastLegendError::CollectionSheetLineInvalid(line.clone()))?;
@@ -47,20 +69,183 @@ impl Collection {
             );
         }
 
-        Ok(Self { repo, sheets })
+        Ok(Self {
+            repo,
+            sheets,
+            schema: Schema::bundled(),
+            page_cache: Arc::new(ContentCache::named(
+                "Sheet page cache",
+                DEFAULT_PAGE_CACHE_BYTES,
+            )),
+        })
+    }
+
+    pub fn repository(&self) -> &Repository {
+        &self.repo
+    }
+
+    /// Cap the sizes read from the collection's files at [limits], see
+    /// [Repository::with_limits].
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.repo = self.repo.with_limits(limits);
+        self
     }
 
+    pub fn limits(&self) -> &Limits {
+        self.repo.limits()
+    }
+
+    /// Keep up to [budget_bytes] of sheet pages in memory instead of the default 32 MiB, so
+    /// reading a sheet again doesn't read and decompress its pages again. Pages cached so far are
+    /// dropped.
+    pub fn with_page_cache(mut self, budget_bytes: u64) -> Self {
+        self.page_cache = Arc::new(ContentCache::named("Sheet page cache", budget_bytes));
+        self
+    }
+
+    pub fn page_cache_stats(&self) -> CacheStats {
+        self.page_cache.stats()
+    }
+
+    /// Name columns from [schema], on top of the [bundled](Schema::bundled) definitions.
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema.merge(schema);
+        self
+    }
+
+    /// Get the names of all sheets in the collection, sorted.
+    pub fn sheet_names(&self) -> Vec<&str> {
+        let mut names = self
+            .sheets
+            .keys()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>();
+        names.sort_unstable_by_key(|&name| Ascii::new(name));
+        names
+    }
+
+    /// Get the id of a sheet, as listed in `exd/root.exl`.
+    pub fn sheet_id(&self, name: &str) -> Option<i32> {
+        self.sheets.get(&Ascii::new(name.to_string())).copied()
+    }
+
+    /// Iterate the English rows of a sheet, or the untranslated rows if it has no languages.
     pub fn sheet_iter(&self, name: &str) -> Result<SheetIter, LastLegendError> {
-        self.get_sheet_info(name).map(|sheet_info| SheetIter {
+        self.sheet_iter_lang(name, Language::English)
+    }
+
+    /// Iterate the rows of a sheet in [language]. Sheets with untranslated pages use those instead,
+    /// regardless of [language].
+    pub fn sheet_iter_lang(
+        &self,
+        name: &str,
+        language: Language,
+    ) -> Result<SheetIter, LastLegendError> {
+        let sheet_info = self.sheet_info(name)?;
+        let language = [language, Language::None]
+            .into_iter()
+            .find(|l| sheet_info.languages.contains(l))
+            .ok_or_else(|| LastLegendError::SheetLanguageMissing {
+                sheet: name.to_string(),
+                language,
+                available: sheet_info.languages.clone(),
+            })?;
+        Ok(SheetIter {
             repo: self.repo.clone(),
+            page_cache: Arc::clone(&self.page_cache),
             sheet_name: name.to_string(),
             sheet_info,
+            language,
             current_page: 0,
             current_page_iter: None,
         })
     }
 
-    fn get_sheet_info(&self, name: &str) -> Result<SheetInfo, LastLegendError> {
+    /// Iterate the rows of a [KnownRow] sheet in [language], with their row ids.
+    pub fn known_rows<T: KnownRow>(
+        &self,
+        language: Language,
+    ) -> Result<RowIdDeSheetIter<T>, LastLegendError> {
+        Ok(self
+            .sheet_iter_lang(T::SHEET, language)?
+            .deserialize_rows()
+            .with_row_ids())
+    }
+
+    /// Read the values of row [row_id] of a sheet in [language], once for each sub-row for sheets
+    /// with sub-rows. Only the page holding the row is read.
+    pub fn read_row(
+        &self,
+        name: &str,
+        language: Language,
+        row_id: u32,
+    ) -> Result<Vec<RowValues>, LastLegendError> {
+        let mut sheet_iter = self.sheet_iter_lang(name, language)?;
+        let row_missing = || LastLegendError::SheetRowMissing {
+            sheet: name.to_string(),
+            row_id,
+        };
+        let page_start = sheet_iter
+            .sheet_info
+            .page_ranges
+            .iter()
+            .find(|range| range.contains(&row_id))
+            .ok_or_else(row_missing)?
+            .start;
+        let mut rows = Vec::new();
+        for row in sheet_iter.load_page_iter(page_start)? {
+            let (id, sub_row_id, row) = row?;
+            if id != row_id {
+                continue;
+            }
+            rows.push((id, sub_row_id, sheet_iter.read_values(row)?));
+        }
+        if rows.is_empty() {
+            return Err(row_missing());
+        }
+        Ok(rows)
+    }
+
+    /// Read the values of up to [limit] rows of a sheet in [language], starting at the first row
+    /// with an id of at least [start_row_id], with the row id and sub-row id of each. Sub-rows
+    /// of a row count as one row. Pages before the start are skipped without being read, so
+    /// reading from the end of a large sheet is as quick as reading from its start.
+    pub fn read_rows(
+        &self,
+        name: &str,
+        language: Language,
+        start_row_id: u32,
+        limit: usize,
+    ) -> Result<Vec<RowValues>, LastLegendError> {
+        let mut sheet_iter = self.sheet_iter_lang(name, language)?;
+        let page_starts = sheet_iter
+            .sheet_info
+            .page_ranges
+            .iter()
+            .filter(|range| range.end > start_row_id)
+            .map(|range| range.start)
+            .collect::<Vec<_>>();
+        let mut rows = Vec::new();
+        let mut row_count = 0;
+        for page_start in page_starts {
+            for row in sheet_iter.load_page_iter(page_start)? {
+                let (id, sub_row_id, row) = row?;
+                if id < start_row_id {
+                    continue;
+                }
+                if rows.last().is_none_or(|&(last_id, _, _)| last_id != id) {
+                    if row_count == limit {
+                        return Ok(rows);
+                    }
+                    row_count += 1;
+                }
+                rows.push((id, sub_row_id, sheet_iter.read_values(row)?));
+            }
+        }
+        Ok(rows)
+    }
+
+    pub fn sheet_info(&self, name: &str) -> Result<SheetInfo, LastLegendError> {
         let name = Ascii::new(name.to_string());
         // Normalize name by getting the value used in the map.
         let (name, _id) = self
@@ -85,24 +270,23 @@ impl Collection {
             )
         );
 
-        let (header, dat_reader) = read_file_entry_header(&index, &file_name)
-            .map_err(|e| e.add_context("Failed to open data reader for collection"))?;
-        let content = header
-            .read_content_to_vec(dat_reader)
-            .map_err(|e| LastLegendError::Io("Failed to read dat content".into(), e))?;
+        let content = read_entry_content(&index, index.get_entry(&file_name)?)
+            .map_err(|e| e.add_context("Failed to read sheet info"))?;
 
-        Cursor::new(content)
-            .read_be::<SheetInfo>()
-            .map_err(|e| LastLegendError::BinRW("Failed to read sheet header".into(), e))
+        let mut sheet_info = SheetInfo::parse(&content)?;
+        sheet_info.column_names = self.schema.column_names(&name).to_vec();
+        Ok(sheet_info)
     }
 }
 
 pub struct SheetIter {
     repo: Repository,
+    page_cache: Arc<ContentCache<String>>,
     sheet_name: String,
     sheet_info: SheetInfo,
+    language: Language,
     current_page: usize,
-    current_page_iter: Option<RowBufferIter<Cursor<Vec<u8>>>>,
+    current_page_iter: Option<RowBufferIter<Cursor<Arc<[u8]>>>>,
 }
 
 impl SheetIter {
@@ -110,32 +294,47 @@ impl SheetIter {
         &self.sheet_info
     }
 
+    /// The language of the pages being read.
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
     pub fn deserialize_rows<T: DeserializeOwned>(self) -> DeSheetIter<T> {
         DeSheetIter {
             sheet_iter: self,
+            skip_unreadable: false,
             _marker: PhantomData,
         }
     }
 
+    /// Also yield the row id and sub-row id of each row. The sub-row id is only present for
+    /// sheets with sub-rows, where several rows share a row id.
+    pub fn with_sub_rows(self) -> SubRowSheetIter {
+        SubRowSheetIter(self)
+    }
+
     fn load_page_iter(
         &mut self,
         page_start: u32,
-    ) -> Result<RowBufferIter<Cursor<Vec<u8>>>, LastLegendError> {
-        let language = self
-            .sheet_info
-            .languages
-            .iter()
-            .find(|&&l| l == Language::None || l == Language::English)
-            .unwrap_or_else(|| {
-                panic!(
-                    "Language must be None or English, have {:?}",
-                    self.sheet_info.languages
-                )
-            });
-        let file_name = language.get_sheet_name(&self.sheet_name, page_start);
+    ) -> Result<RowBufferIter<Cursor<Arc<[u8]>>>, LastLegendError> {
+        let file_name = self.language.get_sheet_name(&self.sheet_name, page_start);
+        let content = self
+            .page_cache
+            .get_or_load_key(file_name.clone(), || self.read_page(&file_name))?;
+
+        let mut cursor = Cursor::new(content);
+        let page_header = cursor
+            .read_be::<PageHeader>()
+            .map_err(|e| LastLegendError::BinRW("Failed to read page header".into(), e))?;
+        Ok(page_header
+            .row_buffer_iter(cursor, &self.sheet_info)
+            .with_max_row_size(self.repo.limits().max_row_size))
+    }
+
+    fn read_page(&self, file_name: &str) -> Result<Vec<u8>, LastLegendError> {
         let index = self
             .repo
-            .get_index_for(&file_name)
+            .get_index_for(file_name)
             .map_err(|e| e.add_context("Failed to read sheet page"))?;
 
         log::debug!(
@@ -143,29 +342,29 @@ impl SheetIter {
             format_index_entry_for_console(
                 self.repo.repo_path(),
                 &index,
-                index.get_entry(&file_name)?,
-                &file_name
+                index.get_entry(file_name)?,
+                file_name
             )
         );
 
-        let (header, dat_reader) = read_file_entry_header(&index, &file_name)
-            .map_err(|e| e.add_context("Failed to open data reader for sheet page"))?;
-        let content = header
-            .read_content_to_vec(dat_reader)
-            .map_err(|e| LastLegendError::Io("Failed to read dat content".into(), e))?;
-
-        let mut cursor = Cursor::new(content);
-        let page_header = cursor
-            .read_be::<PageHeader>()
-            .map_err(|e| LastLegendError::BinRW("Failed to read page header".into(), e))?;
-        Ok(page_header.row_buffer_iter(cursor, &self.sheet_info))
+        let content = read_entry_content(&index, index.get_entry(file_name)?)
+            .map_err(|e| e.add_context("Failed to read sheet page"))?;
+        Ok(content.to_vec())
     }
-}
 
-impl Iterator for SheetIter {
-    type Item = Result<Vec<u8>, LastLegendError>;
+    /// Read the values of each column of [row].
+    pub(crate) fn read_values(&self, row: Vec<u8>) -> Result<Vec<DataValue>, LastLegendError> {
+        let fixed_row_size = u64::from(self.sheet_info.fixed_row_size);
+        let mut row = Cursor::new(row);
+        self.sheet_info
+            .columns
+            .iter()
+            .map(|c| c.read_value(&mut row, fixed_row_size))
+            .collect()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// The next row, with its row id and sub-row id.
+    pub(crate) fn next_keyed(&mut self) -> Option<Result<RowBuffer, LastLegendError>> {
         loop {
             match &mut self.current_page_iter {
                 Some(iter) => {
@@ -191,24 +390,110 @@ impl Iterator for SheetIter {
     }
 }
 
+impl Iterator for SheetIter {
+    type Item = Result<Vec<u8>, LastLegendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_keyed().map(|r| r.map(|(_, _, row)| row))
+    }
+}
+
+/// Yields each row of a [SheetIter] with its row id and sub-row id, see
+/// [with_sub_rows](SheetIter::with_sub_rows).
+pub struct SubRowSheetIter(SheetIter);
+
+impl SubRowSheetIter {
+    pub fn sheet_info(&self) -> &SheetInfo {
+        self.0.sheet_info()
+    }
+}
+
+impl Iterator for SubRowSheetIter {
+    /// The row id, the sub-row id for sheets with sub-rows, and the row buffer.
+    type Item = Result<RowBuffer, LastLegendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_keyed()
+    }
+}
+
 pub struct DeSheetIter<T> {
     sheet_iter: SheetIter,
+    skip_unreadable: bool,
     _marker: PhantomData<T>,
 }
 
+impl<T: DeserializeOwned> DeSheetIter<T> {
+    /// Also yield the row id of each row. Rows may be sparse, so prefer joining sheets by row id
+    /// over their position in the sheet.
+    pub fn with_row_ids(self) -> RowIdDeSheetIter<T> {
+        RowIdDeSheetIter(self)
+    }
+
+    /// Also yield the row id and sub-row id of each row. The sub-row id is only present for
+    /// sheets with sub-rows, where several rows share a row id.
+    pub fn with_sub_rows(self) -> SubRowDeSheetIter<T> {
+        SubRowDeSheetIter(self)
+    }
+
+    /// Leave out rows that can't be deserialized, reporting each to the repository's
+    /// [WarningSink](crate::warning::WarningSink) as [Warning::SkippedRow]. Pages that can't be
+    /// read still fail.
+    pub fn skipping_unreadable(mut self) -> Self {
+        self.skip_unreadable = true;
+        self
+    }
+
+    fn next_keyed(&mut self) -> Option<<SubRowDeSheetIter<T> as Iterator>::Item> {
+        loop {
+            let (row_id, sub_row_id, row) = match self.sheet_iter.next_keyed()? {
+                Ok(row) => row,
+                Err(e) => return Some(Err(e)),
+            };
+            match from_sheet_row(&self.sheet_iter.sheet_info, row, StringMode::default()) {
+                Ok(t) => return Some(Ok((row_id, sub_row_id, t))),
+                Err(e) if self.skip_unreadable => {
+                    self.sheet_iter.repo.warnings().warn(Warning::SkippedRow {
+                        sheet: self.sheet_iter.sheet_name.clone(),
+                        row_id,
+                        sub_row_id,
+                        reason: e.to_string(),
+                    });
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
 impl<T: DeserializeOwned> Iterator for DeSheetIter<T> {
     type Item = Result<T, LastLegendError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let next = self.sheet_iter.next();
-        next.map(|r| {
-            r.and_then(|row| {
-                from_row(
-                    &self.sheet_iter.sheet_info.columns,
-                    self.sheet_iter.sheet_info.fixed_row_size as u64,
-                    row,
-                )
-            })
-        })
+        self.next_keyed().map(|r| r.map(|(_, _, t)| t))
+    }
+}
+
+pub struct RowIdDeSheetIter<T>(DeSheetIter<T>);
+
+impl<T: DeserializeOwned> Iterator for RowIdDeSheetIter<T> {
+    /// The row id and the row.
+    type Item = Result<(u32, T), LastLegendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next_keyed()
+            .map(|r| r.map(|(row_id, _, t)| (row_id, t)))
+    }
+}
+
+pub struct SubRowDeSheetIter<T>(DeSheetIter<T>);
+
+impl<T: DeserializeOwned> Iterator for SubRowDeSheetIter<T> {
+    /// The row id, the sub-row id for sheets with sub-rows, and the row.
+    type Item = Result<(u32, Option<u16>, T), LastLegendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_keyed()
     }
 }
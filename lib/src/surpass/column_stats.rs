@@ -0,0 +1,135 @@
+//! Per-column statistics over every row of a sheet, for working out what unknown columns mean.
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use crate::error::LastLegendError;
+use crate::surpass::collection::SheetIter;
+use crate::surpass::sheet_info::{DataType, DataValue};
+
+/// A comparable form of numeric values. Columns have a single type, so variants never mix.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+enum NumericKey {
+    Int(i128),
+    Float(f64),
+}
+
+impl NumericKey {
+    fn of(value: &DataValue) -> Option<Self> {
+        Some(match *value {
+            DataValue::String(_) | DataValue::Bool(_) => return None,
+            DataValue::I8(v) => Self::Int(v.into()),
+            DataValue::U8(v) => Self::Int(v.into()),
+            DataValue::I16(v) => Self::Int(v.into()),
+            DataValue::U16(v) => Self::Int(v.into()),
+            DataValue::I32(v) => Self::Int(v.into()),
+            DataValue::U32(v) => Self::Int(v.into()),
+            DataValue::I64(v) => Self::Int(v.into()),
+            DataValue::F32(v) => Self::Float(v.into()),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ColumnStats {
+    pub data_type: DataType,
+    /// The number of values seen.
+    pub count: u64,
+    min: Option<(NumericKey, DataValue)>,
+    max: Option<(NumericKey, DataValue)>,
+    /// How many times each value was seen, by its text form.
+    occurrences: HashMap<String, u64>,
+}
+
+impl ColumnStats {
+    pub fn new(data_type: DataType) -> Self {
+        Self {
+            data_type,
+            count: 0,
+            min: None,
+            max: None,
+            occurrences: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, value: DataValue) {
+        self.count += 1;
+        *self.occurrences.entry(value.to_string()).or_default() += 1;
+        if let Some(key) = NumericKey::of(&value) {
+            if self.min.as_ref().is_none_or(|(min, _)| key < *min) {
+                self.min = Some((key, value.clone()));
+            }
+            if self.max.as_ref().is_none_or(|(max, _)| key > *max) {
+                self.max = Some((key, value));
+            }
+        }
+    }
+
+    /// The smallest value, if the column is numeric and has any rows.
+    pub fn min(&self) -> Option<&DataValue> {
+        self.min.as_ref().map(|(_, v)| v)
+    }
+
+    /// The largest value, if the column is numeric and has any rows.
+    pub fn max(&self) -> Option<&DataValue> {
+        self.max.as_ref().map(|(_, v)| v)
+    }
+
+    /// The number of different values.
+    pub fn distinct(&self) -> usize {
+        self.occurrences.len()
+    }
+
+    /// Get the [n] most common values and how often they occur, most common first.
+    pub fn top(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut top = self
+            .occurrences
+            .iter()
+            .map(|(value, &count)| (value.as_str(), count))
+            .collect::<Vec<_>>();
+        top.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        top.truncate(n);
+        top
+    }
+}
+
+/// Read every row of [sheet_iter], collecting statistics for each column.
+pub fn column_stats(sheet_iter: SheetIter) -> Result<Vec<ColumnStats>, LastLegendError> {
+    let sheet_info = sheet_iter.sheet_info().clone();
+    let fixed_row_size = u64::from(sheet_info.fixed_row_size);
+    let mut stats = sheet_info
+        .columns
+        .iter()
+        .map(|c| ColumnStats::new(c.data_type()))
+        .collect::<Vec<_>>();
+    for row in sheet_iter {
+        let mut row = Cursor::new(row?);
+        for (column, stats) in sheet_info.columns.iter().zip(&mut stats) {
+            stats.add(column.read_value(&mut row, fixed_row_size)?);
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod column_stats_tests {
+    use super::*;
+
+    #[test]
+    fn tracks_range_and_top_values() {
+        let mut numbers = ColumnStats::new(DataType::I64);
+        for v in [5, -3, i64::MAX, 5] {
+            numbers.add(DataValue::I64(v));
+        }
+        assert_eq!(numbers.count, 4);
+        assert_eq!(numbers.distinct(), 3);
+        assert!(matches!(numbers.min(), Some(DataValue::I64(-3))));
+        assert!(matches!(numbers.max(), Some(DataValue::I64(i64::MAX))));
+
+        let mut strings = ColumnStats::new(DataType::String);
+        for v in ["b", "", "a", "", "b", ""] {
+            strings.add(DataValue::String(v.to_string()));
+        }
+        assert!(strings.min().is_none());
+        assert_eq!(strings.top(2), vec![("", 3), ("b", 2)]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Exporting sheets to a SQLite database, so game data can be queried with SQL.
+//!
+//! Each sheet gets a table named after it, with a `row_id` column, a `sub_row_id` column for
+//! sheets with sub-rows, and a column for each in the sheet header, named from the
+//! [Schema](crate::surpass::schema::Schema) or else `col_0`, `col_1`, etc.
+use std::path::Path;
+
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+use crate::error::LastLegendError;
+use crate::surpass::collection::Collection;
+use crate::surpass::sheet_info::{DataType, DataValue, Language, SheetInfo, Variant};
+
+fn sqlite_err(message: &'static str) -> impl Fn(rusqlite::Error) -> LastLegendError {
+    move |e| LastLegendError::Sqlite(message.into(), e)
+}
+
+/// A SQLite database to export sheets to.
+pub struct SqliteExport {
+    connection: Connection,
+}
+
+impl SqliteExport {
+    /// Open the database at [path], creating it if it doesn't exist.
+    pub fn open(path: &Path) -> Result<Self, LastLegendError> {
+        let connection = Connection::open(path).map_err(sqlite_err("Couldn't open database"))?;
+        Ok(Self { connection })
+    }
+
+    /// Write every row of sheet [name] in [language] to its table, replacing the table if it
+    /// exists. Returns how many rows were written.
+    pub fn export_sheet(
+        &mut self,
+        collection: &Collection,
+        name: &str,
+        language: Language,
+    ) -> Result<usize, LastLegendError> {
+        let mut sheet_iter = collection.sheet_iter_lang(name, language)?;
+        let sub_rows = sheet_iter.sheet_info().variant == Variant::SubRows;
+        let table = quote_identifier(name);
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(sqlite_err("Couldn't start transaction"))?;
+        transaction
+            .execute(&format!("DROP TABLE IF EXISTS {}", table), [])
+            .map_err(sqlite_err("Couldn't drop old table"))?;
+        transaction
+            .execute(&create_table_sql(&table, sheet_iter.sheet_info()), [])
+            .map_err(sqlite_err("Couldn't create table"))?;
+        let mut rows = 0;
+        {
+            let key_count = if sub_rows { 2 } else { 1 };
+            let placeholders = vec!["?"; key_count + sheet_iter.sheet_info().columns.len()];
+            let mut insert = transaction
+                .prepare(&format!(
+                    "INSERT INTO {} VALUES ({})",
+                    table,
+                    placeholders.join(", ")
+                ))
+                .map_err(sqlite_err("Couldn't prepare insert"))?;
+            while let Some(row) = sheet_iter.next_keyed() {
+                let (row_id, sub_row_id, row) = row?;
+                let mut params = vec![Value::Integer(row_id.into())];
+                if sub_rows {
+                    params.push(sub_row_id.map_or(Value::Null, |id| Value::Integer(id.into())));
+                }
+                params.extend(sheet_iter.read_values(row)?.into_iter().map(sql_value));
+                insert
+                    .execute(rusqlite::params_from_iter(params))
+                    .map_err(sqlite_err("Couldn't insert row"))?;
+                rows += 1;
+            }
+        }
+        transaction
+            .commit()
+            .map_err(sqlite_err("Couldn't commit sheet"))?;
+        Ok(rows)
+    }
+}
+
+/// Quote [name] to use as a table or column name. Sheet names can have slashes, e.g.
+/// `quest/000/Foo`, and column names brackets, e.g. `Item[0]`.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn create_table_sql(table: &str, sheet_info: &SheetInfo) -> String {
+    let mut columns = vec!["row_id INTEGER NOT NULL".to_string()];
+    let primary_key = if sheet_info.variant == Variant::SubRows {
+        columns.push("sub_row_id INTEGER NOT NULL".to_string());
+        "row_id, sub_row_id"
+    } else {
+        "row_id"
+    };
+    for (i, column) in sheet_info.columns.iter().enumerate() {
+        columns.push(format!(
+            "{} {}",
+            quote_identifier(&sheet_info.column_name(i)),
+            sql_type(column.data_type())
+        ));
+    }
+    format!(
+        "CREATE TABLE {} ({}, PRIMARY KEY ({}))",
+        table,
+        columns.join(", "),
+        primary_key
+    )
+}
+
+fn sql_type(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::String => "TEXT",
+        DataType::F32 => "REAL",
+        // Bools are stored as 0 or 1, as SQLite has no boolean type.
+        _ => "INTEGER",
+    }
+}
+
+fn sql_value(value: DataValue) -> Value {
+    match value {
+        DataValue::String(v) => Value::Text(v),
+        DataValue::Bool(v) => Value::Integer(v.into()),
+        DataValue::I8(v) => Value::Integer(v.into()),
+        DataValue::U8(v) => Value::Integer(v.into()),
+        DataValue::I16(v) => Value::Integer(v.into()),
+        DataValue::U16(v) => Value::Integer(v.into()),
+        DataValue::I32(v) => Value::Integer(v.into()),
+        DataValue::U32(v) => Value::Integer(v.into()),
+        DataValue::F32(v) => Value::Real(v.into()),
+        DataValue::I64(v) => Value::Integer(v),
+    }
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::*;
+
+    #[test]
+    fn quotes_sheet_names() {
+        assert_eq!(quote_identifier("Item"), "\"Item\"");
+        assert_eq!(
+            quote_identifier("quest/000/Odd\"Name"),
+            "\"quest/000/Odd\"\"Name\""
+        );
+    }
+
+    #[test]
+    fn values_keep_their_types() {
+        assert_eq!(sql_value(DataValue::Bool(true)), Value::Integer(1));
+        assert_eq!(sql_value(DataValue::F32(0.5)), Value::Real(0.5));
+        assert_eq!(
+            sql_value(DataValue::String("Ifrit".into())),
+            Value::Text("Ifrit".into())
+        );
+    }
+}
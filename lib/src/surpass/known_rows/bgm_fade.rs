@@ -0,0 +1,16 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+/// A fade from one piece of music into another, e.g. into a variant of the same track.
+#[derive(Debug, Deserialize)]
+pub struct BGMFade {
+    /// The music faded out of, as a music id.
+    pub bgm_out: i32,
+    /// The music faded into, as a music id.
+    pub bgm_in: i32,
+    /// The `BGMFadeType` of the fade.
+    pub fade_type: u32,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
@@ -0,0 +1,11 @@
+use serde::Deserialize;
+
+/// The BGM played in an area at different times, as row ids of the `BGM` sheet.
+#[derive(Debug, Deserialize)]
+pub struct BGMSituation {
+    pub daytime: u16,
+    pub night: u16,
+    pub battle: u16,
+    pub daybreak: u16,
+    pub twilight: u16,
+}
@@ -0,0 +1,17 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+/// One choice of music for a `BGMSwitch` row. The sheet has sub-rows, one per choice, picked
+/// between by quest progress.
+#[derive(Debug, Deserialize)]
+pub struct BGMSwitch {
+    /// The `BGMSystemDefine` condition that picks this choice.
+    pub system_define: u8,
+    /// The quest that has to be complete for this choice, or 0.
+    pub quest: u32,
+    /// The music to play, as a row id of the `BGM` sheet.
+    pub bgm: u16,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+#[derive(Debug, Deserialize)]
+pub struct ContentFinderCondition {
+    pub short_code: String,
+    pub territory_type: u32,
+    pub content_link_type: u8,
+    /// A row id in the sheet given by [content_link_type], e.g. `InstanceContent` for `1`.
+    pub content: u32,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
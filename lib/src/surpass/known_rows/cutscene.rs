@@ -0,0 +1,11 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+#[derive(Debug, Deserialize)]
+pub struct Cutscene {
+    /// The path to the cutscene under `cut/`, e.g. `ffxiv/manfst/manfst00000/manfst00000`.
+    pub path: String,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
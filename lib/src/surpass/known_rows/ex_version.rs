@@ -0,0 +1,11 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+/// An expansion, by its number, e.g. row 1 for Heavensward, whose files are under `ex1`.
+#[derive(Debug, Deserialize)]
+pub struct ExVersion {
+    pub name: String,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
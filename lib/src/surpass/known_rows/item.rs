@@ -0,0 +1,31 @@
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+/// The [Item::filter_group] of Orchestrion rolls.
+pub const ORCHESTRION_ROLL: u8 = 32;
+
+#[derive(Debug, Deserialize)]
+pub struct Item {
+    pub singular: String,
+    pub adjective: i8,
+    pub plural: String,
+    pub possessive_pronoun: i8,
+    pub starts_with_vowel: i8,
+    _unknown_5: IgnoredAny,
+    pub pronoun: i8,
+    pub article: i8,
+    pub description: String,
+    pub name: String,
+    pub icon: u32,
+    pub level_item: u32,
+    pub rarity: u8,
+    /// What kind of item this is, which says what [additional_data] refers to.
+    pub filter_group: u8,
+    /// For Orchestrion rolls, with [filter_group] [ORCHESTRION_ROLL], the `Orchestrion` row the
+    /// roll unlocks.
+    pub additional_data: u32,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
@@ -0,0 +1,26 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+#[derive(Debug, Deserialize)]
+pub struct Map {
+    pub map_condition: u8,
+    pub priority_category_ui: u8,
+    pub priority_ui: u8,
+    pub map_index: i8,
+    pub hierarchy: u8,
+    pub map_marker_range: u16,
+    /// The map texture id, e.g. `s1d1/00`.
+    pub id: String,
+    pub size_factor: u16,
+    pub offset_x: i16,
+    pub offset_y: i16,
+    pub place_name_region: u32,
+    pub place_name: u32,
+    pub place_name_sub: u32,
+    pub discovery_index: i16,
+    pub discovery_flag: u32,
+    pub territory_type: u32,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
@@ -1,3 +1,101 @@
+use serde::de::DeserializeOwned;
+
 pub mod bgm;
+pub mod bgm_fade;
+pub mod bgm_situation;
+pub mod bgm_switch;
+pub mod content_finder_condition;
+pub mod cutscene;
+pub mod ex_version;
+pub mod item;
+pub mod map;
+pub mod mount;
 pub mod orchestrion;
+pub mod orchestrion_category;
 pub mod orchestrion_path;
+pub mod orchestrion_uiparam;
+pub mod place_name;
+pub mod quest;
+pub mod territory_type;
+
+/// A typed row of a specific sheet, read with [Collection::known_rows].
+///
+/// When the sheet's schema names its columns, fields are matched to the columns of the same name,
+/// ignoring case and punctuation. If it doesn't, or any field names no column, they're matched by
+/// position instead. Types for large sheets only describe the leading columns, ending with
+/// [RestOfRow], as later columns move around between game versions.
+///
+/// [Collection::known_rows]: crate::surpass::collection::Collection::known_rows
+/// [RestOfRow]: crate::surpass::serde_row::RestOfRow
+pub trait KnownRow: DeserializeOwned {
+    /// The name of the sheet these rows are from.
+    const SHEET: &'static str;
+}
+
+impl KnownRow for bgm::BGM {
+    const SHEET: &'static str = "BGM";
+}
+
+impl KnownRow for bgm_fade::BGMFade {
+    const SHEET: &'static str = "BGMFade";
+}
+
+impl KnownRow for bgm_situation::BGMSituation {
+    const SHEET: &'static str = "BGMSituation";
+}
+
+impl KnownRow for bgm_switch::BGMSwitch {
+    const SHEET: &'static str = "BGMSwitch";
+}
+
+impl KnownRow for content_finder_condition::ContentFinderCondition {
+    const SHEET: &'static str = "ContentFinderCondition";
+}
+
+impl KnownRow for cutscene::Cutscene {
+    const SHEET: &'static str = "Cutscene";
+}
+
+impl KnownRow for ex_version::ExVersion {
+    const SHEET: &'static str = "ExVersion";
+}
+
+impl KnownRow for item::Item {
+    const SHEET: &'static str = "Item";
+}
+
+impl KnownRow for map::Map {
+    const SHEET: &'static str = "Map";
+}
+
+impl KnownRow for mount::Mount {
+    const SHEET: &'static str = "Mount";
+}
+
+impl KnownRow for orchestrion::Orchestrion {
+    const SHEET: &'static str = "Orchestrion";
+}
+
+impl KnownRow for orchestrion_category::OrchestrionCategory {
+    const SHEET: &'static str = "OrchestrionCategory";
+}
+
+impl KnownRow for orchestrion_path::OrchestrionPath {
+    const SHEET: &'static str = "OrchestrionPath";
+}
+
+impl KnownRow for orchestrion_uiparam::OrchestrionUiparam {
+    const SHEET: &'static str = "OrchestrionUiparam";
+}
+
+impl KnownRow for place_name::PlaceName {
+    const SHEET: &'static str = "PlaceName";
+}
+
+impl KnownRow for quest::Quest {
+    const SHEET: &'static str = "Quest";
+}
+
+impl KnownRow for territory_type::TerritoryType {
+    const SHEET: &'static str = "TerritoryType";
+}
@@ -0,0 +1,18 @@
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+#[derive(Debug, Deserialize)]
+pub struct Mount {
+    pub singular: String,
+    pub adjective: i8,
+    pub plural: String,
+    pub possessive_pronoun: i8,
+    pub starts_with_vowel: i8,
+    _unknown_5: IgnoredAny,
+    pub pronoun: i8,
+    pub article: i8,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
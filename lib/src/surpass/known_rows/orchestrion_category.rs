@@ -0,0 +1,9 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct OrchestrionCategory {
+    pub name: String,
+    pub hide_order: u8,
+    pub icon: u32,
+    pub order: u8,
+}
@@ -0,0 +1,7 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct OrchestrionUiparam {
+    pub category: u8,
+    pub order: u16,
+}
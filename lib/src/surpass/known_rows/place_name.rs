@@ -0,0 +1,10 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+#[derive(Debug, Deserialize)]
+pub struct PlaceName {
+    pub name: String,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
@@ -0,0 +1,12 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+#[derive(Debug, Deserialize)]
+pub struct Quest {
+    pub name: String,
+    /// The quest's script id, e.g. `ManFst001_00039`.
+    pub id: String,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
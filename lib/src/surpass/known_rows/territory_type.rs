@@ -0,0 +1,18 @@
+use serde::Deserialize;
+
+use crate::surpass::serde_row::RestOfRow;
+
+#[derive(Debug, Deserialize)]
+pub struct TerritoryType {
+    /// The territory code, e.g. `s1t1`.
+    pub name: String,
+    /// The path to the level, e.g. `ffxiv/sea_s1/twn/s1t1/level/s1t1`.
+    pub bg: String,
+    pub battalion_mode: u8,
+    pub place_name_region: u16,
+    pub place_name_zone: u16,
+    pub place_name: u16,
+    pub map: u16,
+    #[serde(default)]
+    _rest: RestOfRow,
+}
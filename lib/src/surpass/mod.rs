@@ -1,8 +1,16 @@
 //! Surpass: Because this is kinda like Excel!
 //! Contains the data sheet readers for FFXIV.
 
+pub mod bgm_variants;
 pub mod collection;
+pub mod column_stats;
+#[cfg(feature = "sqlite")]
+pub mod export;
 pub mod known_rows;
 pub mod page;
+pub mod schema;
+pub mod se_string;
 pub mod serde_row;
+pub mod sheet_diff;
 pub mod sheet_info;
+pub mod sheet_paths;
@@ -1,7 +1,8 @@
 use crate::error::LastLegendError;
+use crate::limits::Limits;
 use crate::surpass::sheet_info::{SheetInfo, Variant};
 use binrw::{binread, BinReaderExt};
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Cursor, Read, Seek, SeekFrom};
 
 const ROW_OFFSET_SIZE: u32 = 8;
 
@@ -21,6 +22,18 @@ pub struct PageHeader {
 }
 
 impl PageHeader {
+    /// Parse the header at the start of the content of a `.exd` file.
+    pub fn parse(content: &[u8]) -> Result<Self, LastLegendError> {
+        Cursor::new(content)
+            .read_be()
+            .map_err(|e| LastLegendError::BinRW("Failed to read page header".into(), e))
+    }
+
+    /// The ids and offsets of the rows in this page.
+    pub fn row_offsets(&self) -> &[RowOffset] {
+        &self.offset_table
+    }
+
     /// Get an iterator over the row buffers, to be parsed into actual structs at a higher level.
     pub fn row_buffer_iter<R: Read + Seek + Send>(
         &self,
@@ -30,8 +43,13 @@ impl PageHeader {
         RowBufferIter {
             reader,
             fixed_row_size: sheet_info.fixed_row_size.into(),
-            row_offsets: self.offset_table.iter().map(|t| t.offset.into()).collect(),
+            row_offsets: self
+                .offset_table
+                .iter()
+                .map(|t| (t.index, t.offset.into()))
+                .collect(),
             row_offset_index: 0,
+            max_row_size: Limits::default().max_row_size,
             sub_row: match sheet_info.variant {
                 Variant::Default => SubRow::None,
                 Variant::SubRows => SubRow::Inactive,
@@ -50,20 +68,32 @@ pub struct RowOffset {
 pub struct RowBufferIter<R> {
     reader: R,
     fixed_row_size: u64,
-    row_offsets: Vec<u64>,
+    row_offsets: Vec<(u32, u64)>,
     row_offset_index: usize,
+    /// Rows with more data than this are rejected before allocating for them.
+    max_row_size: u32,
     sub_row: SubRow,
 }
 
 enum SubRow {
     None,
     Inactive,
-    Active(Box<dyn Iterator<Item = u64> + Send>),
+    /// Remaining sub-rows of the given row id, with their sub-row ids.
+    Active(u32, std::vec::IntoIter<(u16, Vec<u8>)>),
 }
 
-const ROW_HEADER_SIZE: u64 = 6;
+const SUB_ROW_ID_SIZE: usize = 2;
+
+/// The row id, the sub-row id for sheets with sub-rows, and the row buffer.
+pub type RowBuffer = (u32, Option<u16>, Vec<u8>);
 
 impl<R: Read + Seek> RowBufferIter<R> {
+    /// Reject rows with more than [max_row_size] bytes of data, see [Limits::max_row_size].
+    pub fn with_max_row_size(mut self, max_row_size: u32) -> Self {
+        self.max_row_size = max_row_size;
+        self
+    }
+
     pub fn into_reader(self) -> R {
         self.reader
     }
@@ -74,7 +104,8 @@ impl<R: Read + Seek> RowBufferIter<R> {
             .map_err(|e| LastLegendError::BinRW("Failed to read row header".into(), e))
     }
 
-    fn next_row_offset(&mut self) -> Option<u64> {
+    /// Get the next row id and its offset.
+    fn next_row_offset(&mut self) -> Option<(u32, u64)> {
         (self.row_offset_index < self.row_offsets.len()).then(|| {
             let v = self.row_offsets[self.row_offset_index];
             self.row_offset_index += 1;
@@ -82,56 +113,100 @@ impl<R: Read + Seek> RowBufferIter<R> {
         })
     }
 
-    fn default_iter(reader: &mut R, offset: u64) -> <Self as Iterator>::Item {
+    /// Read the data of the row at [offset], after its header.
+    fn read_row_data(
+        reader: &mut R,
+        offset: u64,
+        max_row_size: u32,
+    ) -> Result<(u16, Vec<u8>), LastLegendError> {
         reader
             .seek(SeekFrom::Start(offset))
             .map_err(|e| LastLegendError::Io("Failed to seek to row".into(), e))?;
         let (data_size, count) = Self::read_row_header(reader)?;
-        assert_eq!(count, 1, "default row should always be count == 1");
+        if data_size > max_row_size {
+            return Err(LastLegendError::Custom(format!(
+                "Row has {} bytes of data, more than max_row_size of {}",
+                data_size, max_row_size
+            )));
+        }
 
+        let mut row = vec![0u8; data_size as usize];
         reader
-            .bytes()
-            .take(data_size as usize)
-            .collect::<Result<_, std::io::Error>>()
-            .map_err(|e| LastLegendError::Io("Failed to read row buffer".into(), e))
+            .read_exact(&mut row)
+            .map_err(|e| LastLegendError::Io("Failed to read row buffer".into(), e))?;
+        Ok((count, row))
+    }
+
+    fn default_iter(
+        reader: &mut R,
+        row_id: u32,
+        offset: u64,
+        max_row_size: u32,
+    ) -> <Self as Iterator>::Item {
+        let (count, row) = Self::read_row_data(reader, offset, max_row_size)?;
+        assert_eq!(count, 1, "default row should always be count == 1");
+        Ok((row_id, None, row))
     }
 }
 
+/// Split the data of a row with sub-rows into a buffer per sub-row, with the sub-row id.
+///
+/// Each sub-row is its id followed by its fixed size data. The strings of all sub-rows come after
+/// the last one, so they're copied to the end of each buffer, where [Column::read_value] looks.
+///
+/// [Column::read_value]: crate::surpass::sheet_info::Column::read_value
+fn split_sub_rows(
+    data: &[u8],
+    count: u16,
+    fixed_row_size: usize,
+) -> Result<Vec<(u16, Vec<u8>)>, LastLegendError> {
+    let stride = SUB_ROW_ID_SIZE + fixed_row_size;
+    let strings = data.get(usize::from(count) * stride..).ok_or_else(|| {
+        LastLegendError::Custom(format!(
+            "Row data of {} bytes is too short for {} sub-rows",
+            data.len(),
+            count
+        ))
+    })?;
+    Ok(data
+        .chunks_exact(stride)
+        .take(count.into())
+        .map(|sub_row| {
+            let (id, fixed) = sub_row.split_at(SUB_ROW_ID_SIZE);
+            let mut buffer = Vec::with_capacity(fixed.len() + strings.len());
+            buffer.extend_from_slice(fixed);
+            buffer.extend_from_slice(strings);
+            (u16::from_be_bytes([id[0], id[1]]), buffer)
+        })
+        .collect())
+}
+
 impl<R: Read + Seek> Iterator for RowBufferIter<R> {
-    type Item = Result<Vec<u8>, LastLegendError>;
+    type Item = Result<RowBuffer, LastLegendError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let fixed_row_size = self.fixed_row_size;
         loop {
             match &mut self.sub_row {
                 SubRow::None => {
-                    return self
-                        .next_row_offset()
-                        .map(|o| Self::default_iter(&mut self.reader, o));
+                    return self.next_row_offset().map(|(id, o)| {
+                        Self::default_iter(&mut self.reader, id, o, self.max_row_size)
+                    });
                 }
                 SubRow::Inactive => {
-                    let row_offset = self.next_row_offset()?;
-                    let (data_size, row_count) = match Self::read_row_header(&mut self.reader) {
-                        Ok(v) => v,
+                    let (row_id, row_offset) = self.next_row_offset()?;
+                    let sub_rows =
+                        Self::read_row_data(&mut self.reader, row_offset, self.max_row_size)
+                            .and_then(|(count, data)| {
+                                split_sub_rows(&data, count, self.fixed_row_size as usize)
+                            });
+                    match sub_rows {
+                        Ok(v) => self.sub_row = SubRow::Active(row_id, v.into_iter()),
                         Err(e) => return Some(Err(e)),
-                    };
-                    let compute_offset = move |row_index: u64| {
-                        row_offset
-                            + ROW_HEADER_SIZE
-                            + (row_index * fixed_row_size + 2 * (row_index + 1))
-                    };
-                    assert_eq!(
-                        compute_offset(row_count.into()),
-                        data_size.into(),
-                        "Shouldn't these be equal?"
-                    );
-                    self.sub_row =
-                        SubRow::Active(Box::new((0..u64::from(row_count)).map(compute_offset)));
+                    }
                 }
-                SubRow::Active(iter) => {
-                    let item = iter.next().map(|o| Self::default_iter(&mut self.reader, o));
-                    if item.is_some() {
-                        return item;
+                SubRow::Active(row_id, iter) => {
+                    if let Some((sub_row_id, row)) = iter.next() {
+                        return Some(Ok((*row_id, Some(sub_row_id), row)));
                     }
                     // No more sub-rows from this set, revert to inactive and get next set.
                     self.sub_row = SubRow::Inactive;
@@ -140,3 +215,24 @@ impl<R: Read + Seek> Iterator for RowBufferIter<R> {
         }
     }
 }
+
+#[cfg(test)]
+mod page_tests {
+    use super::*;
+
+    #[test]
+    fn splits_sub_rows_with_shared_strings() {
+        // Two sub-rows with 4 bytes of fixed data each, then the strings.
+        let data = [0, 0, 1, 2, 3, 4, 0, 7, 5, 6, 7, 8, b'h', b'i', 0, 0];
+        let sub_rows = split_sub_rows(&data, 2, 4).unwrap();
+        assert_eq!(
+            sub_rows,
+            vec![
+                (0, vec![1, 2, 3, 4, b'h', b'i', 0, 0]),
+                (7, vec![5, 6, 7, 8, b'h', b'i', 0, 0]),
+            ]
+        );
+
+        assert!(split_sub_rows(&data, 3, 4).is_err());
+    }
+}
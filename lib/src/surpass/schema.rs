@@ -0,0 +1,185 @@
+//! Column names for sheets. Sheet headers only give each column's type, so names come from
+//! definitions in SaintCoinach's JSON format, either bundled for the sheets this library reads
+//! or from a directory of `<sheet>.json` files.
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use unicase::Ascii;
+
+use crate::error::LastLegendError;
+
+/// Definitions for the sheets in [known_rows](crate::surpass::known_rows).
+const BUNDLED: &str = include_str!("schema.json");
+
+/// Column names for any number of sheets.
+#[derive(Debug, Default, Clone)]
+pub struct Schema {
+    sheets: HashMap<Ascii<String>, Vec<Option<String>>>,
+}
+
+/// A SaintCoinach sheet definition, e.g. `{"sheet": "BGM", "definitions": [{"name": "File"}]}`.
+#[derive(Debug, Deserialize)]
+struct SheetDefinition {
+    sheet: String,
+    #[serde(default)]
+    definitions: Vec<ColumnDefinition>,
+}
+
+/// A column, or with `type` `repeat` or `group`, several columns from [index] on.
+#[derive(Debug, Deserialize)]
+struct ColumnDefinition {
+    #[serde(default)]
+    index: usize,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    name: Option<String>,
+    #[serde(default)]
+    count: usize,
+    definition: Option<Box<ColumnDefinition>>,
+    #[serde(default)]
+    members: Vec<ColumnDefinition>,
+}
+
+impl ColumnDefinition {
+    /// The names of the columns this covers, in order. Repeated columns get their number after
+    /// the name, e.g. `Item[0]`.
+    fn names(&self) -> Vec<Option<String>> {
+        match self.kind.as_deref() {
+            Some("repeat") => {
+                let Some(definition) = &self.definition else {
+                    return vec![None; self.count];
+                };
+                let names = definition.names();
+                (0..self.count)
+                    .flat_map(|i| {
+                        names
+                            .iter()
+                            .map(move |name| name.as_ref().map(|name| format!("{}[{}]", name, i)))
+                    })
+                    .collect()
+            }
+            Some("group") => self.members.iter().flat_map(|m| m.names()).collect(),
+            _ => vec![self.name.clone()],
+        }
+    }
+}
+
+impl Schema {
+    /// The definitions bundled with this library.
+    pub fn bundled() -> Self {
+        let mut schema = Self::default();
+        let definitions: Vec<SheetDefinition> =
+            serde_json::from_str(BUNDLED).expect("bundled schema should be valid");
+        for definition in definitions {
+            schema.insert(definition);
+        }
+        schema
+    }
+
+    /// Load every `.json` file in [dir] as the definition of one sheet, like SaintCoinach's
+    /// `Definitions` directory.
+    pub fn load_dir(dir: &Path) -> Result<Self, LastLegendError> {
+        let io_err = |e| LastLegendError::Io(format!("Couldn't read {}", dir.display()), e);
+        let mut schema = Self::default();
+        for entry in std::fs::read_dir(dir).map_err(io_err)? {
+            let path = entry.map_err(io_err)?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| LastLegendError::Io(format!("Couldn't read {}", path.display()), e))?;
+            schema.add_definition(&content).map_err(|e| {
+                e.add_context(format!("Invalid sheet definition {}", path.display()))
+            })?;
+        }
+        Ok(schema)
+    }
+
+    /// Add the sheet definition in [json], replacing any for the same sheet.
+    pub fn add_definition(&mut self, json: &str) -> Result<(), LastLegendError> {
+        let definition = serde_json::from_str(json)
+            .map_err(|e| LastLegendError::Json("Couldn't parse sheet definition".into(), e))?;
+        self.insert(definition);
+        Ok(())
+    }
+
+    /// Add the sheets of [other], replacing those this has too.
+    pub fn merge(&mut self, other: Schema) {
+        self.sheets.extend(other.sheets);
+    }
+
+    fn insert(&mut self, definition: SheetDefinition) {
+        let mut names = Vec::new();
+        for column in &definition.definitions {
+            for (i, name) in column.names().into_iter().enumerate() {
+                let index = column.index + i;
+                if names.len() <= index {
+                    names.resize(index + 1, None);
+                }
+                names[index] = name;
+            }
+        }
+        self.sheets.insert(Ascii::new(definition.sheet), names);
+    }
+
+    /// The names of the columns of sheet [name], by column index. Columns past the end, or
+    /// without a definition, have no name.
+    pub fn column_names(&self, name: &str) -> &[Option<String>] {
+        self.sheets
+            .get(&Ascii::new(name.to_string()))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod schema_tests {
+    use super::*;
+
+    #[test]
+    fn expands_repeats_and_groups() {
+        let mut schema = Schema::default();
+        schema
+            .add_definition(
+                r#"{
+                    "sheet": "Recipe",
+                    "definitions": [
+                        {"name": "Number"},
+                        {
+                            "index": 2,
+                            "type": "repeat",
+                            "count": 2,
+                            "definition": {
+                                "type": "group",
+                                "members": [{"name": "Item"}, {"name": "Amount"}]
+                            }
+                        }
+                    ]
+                }"#,
+            )
+            .unwrap();
+
+        let names = schema.column_names("recipe");
+        let names = names.iter().map(Option::as_deref).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                Some("Number"),
+                None,
+                Some("Item[0]"),
+                Some("Amount[0]"),
+                Some("Item[1]"),
+                Some("Amount[1]"),
+            ]
+        );
+        assert!(schema.column_names("Item").is_empty());
+    }
+
+    #[test]
+    fn bundled_schema_names_known_sheets() {
+        let schema = Schema::bundled();
+        assert_eq!(schema.column_names("BGM")[0].as_deref(), Some("File"));
+        assert_eq!(schema.column_names("Item")[5], None);
+    }
+}
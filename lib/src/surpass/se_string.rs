@@ -0,0 +1,354 @@
+//! Decoding the SeString markup in sheet strings.
+//!
+//! Sheet strings are text interleaved with payloads: a `0x02` byte, the kind of payload, the
+//! length of its body as an encoded integer, the body, and a `0x03` byte. Payloads stand for
+//! colors, item links, line breaks, branches on the player's gender and so on, and their bodies
+//! are a sequence of [Expression]s.
+use std::fmt::{Display, Formatter};
+
+use serde::de::{Error, Visitor};
+use serde::{Deserialize, Deserializer};
+
+const PAYLOAD_START: u8 = 0x02;
+const PAYLOAD_END: u8 = 0x03;
+
+/// The name [SeString] is recognized by, so sheet rows can hand over the raw bytes.
+pub(crate) const SE_STRING: &str = "$last_legend_dob::SeString";
+
+const NEW_LINE: u8 = 0x10;
+const NON_BREAKING_SPACE: u8 = 0x1D;
+const HYPHEN: u8 = 0x1F;
+
+/// Names of the payload kinds that are known, as used in [SeString]'s markup.
+const PAYLOAD_NAMES: &[(u8, &str)] = &[
+    (0x06, "reset_time"),
+    (0x07, "time"),
+    (0x08, "if"),
+    (0x09, "switch"),
+    (0x0A, "pc_name"),
+    (0x0B, "if_pc_gender"),
+    (0x0C, "if_pc_name"),
+    (0x0F, "if_self"),
+    (NEW_LINE, "new_line"),
+    (0x11, "wait"),
+    (0x12, "icon"),
+    (0x13, "color"),
+    (0x14, "edge_color"),
+    (0x16, "soft_hyphen"),
+    (0x17, "key"),
+    (0x18, "scale"),
+    (0x19, "bold"),
+    (0x1A, "italic"),
+    (NON_BREAKING_SPACE, "non_breaking_space"),
+    (0x1E, "icon2"),
+    (HYPHEN, "hyphen"),
+    (0x20, "num"),
+    (0x27, "link"),
+    (0x28, "sheet"),
+    (0x29, "string"),
+    (0x2A, "caps"),
+    (0x2B, "head"),
+    (0x2C, "split"),
+    (0x48, "color_type"),
+    (0x49, "edge_color_type"),
+    (0x60, "sound"),
+];
+
+/// A sheet string, split into text and payloads.
+///
+/// [Display] writes it as markup, with payloads as tags like `<color(4278190335)>`, and
+/// [to_plain_text](Self::to_plain_text) keeps only what a player would read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeString(pub Vec<SePart>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SePart {
+    Text(String),
+    Payload(Payload),
+}
+
+/// A payload of an [SeString].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    pub kind: u8,
+    /// The encoded arguments, see [arguments](Self::arguments).
+    pub body: Vec<u8>,
+}
+
+/// An argument of a [Payload].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Integer(u32),
+    String(SeString),
+    /// A value filled in by the game, like the time.
+    Placeholder(u8),
+    /// Comparing two expressions, for `0xE0` to `0xE5`: `>=`, `>`, `<=`, `<`, `==` and `!=`.
+    Compare {
+        op: u8,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    /// A parameter passed to the string, for `0xE8` to `0xEB`: local and global numbers, then
+    /// local and global strings.
+    Parameter {
+        kind: u8,
+        index: Box<Expression>,
+    },
+    Other(u8),
+}
+
+impl SeString {
+    /// Split [bytes] into text and payloads. Text that isn't UTF-8 is replaced, and anything
+    /// after a malformed payload is kept as text.
+    pub fn parse(mut bytes: &[u8]) -> Self {
+        let mut parts = Vec::new();
+        while !bytes.is_empty() {
+            let text_len = bytes
+                .iter()
+                .position(|&b| b == PAYLOAD_START)
+                .unwrap_or(bytes.len());
+            if text_len > 0 {
+                let (text, rest) = bytes.split_at(text_len);
+                parts.push(SePart::Text(String::from_utf8_lossy(text).into_owned()));
+                bytes = rest;
+                continue;
+            }
+            match Payload::take(&mut bytes) {
+                Some(payload) => parts.push(SePart::Payload(payload)),
+                None => {
+                    parts.push(SePart::Text(String::from_utf8_lossy(bytes).into_owned()));
+                    break;
+                }
+            }
+        }
+        Self(parts)
+    }
+
+    /// The text, with line breaks, non-breaking spaces and hyphens kept as characters, and every
+    /// other payload removed. Branches, like those on the player's gender, are removed entirely.
+    pub fn to_plain_text(&self) -> String {
+        let mut output = String::new();
+        for part in &self.0 {
+            match part {
+                SePart::Text(text) => output.push_str(text),
+                SePart::Payload(payload) => match payload.kind {
+                    NEW_LINE => output.push('\n'),
+                    NON_BREAKING_SPACE => output.push('\u{A0}'),
+                    HYPHEN => output.push('-'),
+                    _ => {}
+                },
+            }
+        }
+        output
+    }
+}
+
+impl Display for SeString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for part in &self.0 {
+            match part {
+                SePart::Text(text) => f.write_str(text)?,
+                SePart::Payload(payload) => write!(f, "{}", payload)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for SeString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SeStringVisitor;
+
+        impl<'de> Visitor<'de> for SeStringVisitor {
+            type Value = SeString;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a sheet string")
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(SeString::parse(v))
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(SeString::parse(v.as_bytes()))
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                // Other formats only have the string.
+                deserializer.deserialize_str(self)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(SE_STRING, SeStringVisitor)
+    }
+}
+
+impl Payload {
+    /// Take a payload from the start of [input], leaving it alone if there isn't a whole one.
+    fn take(input: &mut &[u8]) -> Option<Self> {
+        let mut rest = *input;
+        if take_byte(&mut rest)? != PAYLOAD_START {
+            return None;
+        }
+        let kind = take_byte(&mut rest)?;
+        let len = take_length(&mut rest)?;
+        let body = rest.get(..len)?.to_vec();
+        rest = &rest[len..];
+        if take_byte(&mut rest)? != PAYLOAD_END {
+            return None;
+        }
+        *input = rest;
+        Some(Self { kind, body })
+    }
+
+    /// The name of this kind of payload, e.g. `color`, if it's known.
+    pub fn name(&self) -> Option<&'static str> {
+        PAYLOAD_NAMES
+            .iter()
+            .find(|(kind, _)| *kind == self.kind)
+            .map(|(_, name)| *name)
+    }
+
+    /// Decode the body into expressions, stopping at the first that can't be decoded.
+    pub fn arguments(&self) -> Vec<Expression> {
+        let mut body = self.body.as_slice();
+        std::iter::from_fn(|| Expression::take(&mut body)).collect()
+    }
+}
+
+impl Display for Payload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "<{}", name)?,
+            None => write!(f, "<{:02X}", self.kind)?,
+        }
+        let arguments = self.arguments();
+        if !arguments.is_empty() {
+            f.write_str("(")?;
+            for (i, argument) in arguments.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{}", argument)?;
+            }
+            f.write_str(")")?;
+        }
+        f.write_str(">")
+    }
+}
+
+impl Expression {
+    fn take(input: &mut &[u8]) -> Option<Self> {
+        let marker = take_byte(input)?;
+        Some(match marker {
+            0x01..=0xCF => Self::Integer(u32::from(marker) - 1),
+            0xD0..=0xDF => Self::Placeholder(marker),
+            0xE0..=0xE5 => Self::Compare {
+                op: marker,
+                lhs: Box::new(Self::take(input)?),
+                rhs: Box::new(Self::take(input)?),
+            },
+            0xE8..=0xEB => Self::Parameter {
+                kind: marker,
+                index: Box::new(Self::take(input)?),
+            },
+            0xF0..=0xFE => Self::Integer(take_packed(marker, input)?),
+            0xFF => {
+                let len = take_length(input)?;
+                let string = SeString::parse(input.get(..len)?);
+                *input = &input[len..];
+                Self::String(string)
+            }
+            _ => Self::Other(marker),
+        })
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(v) => write!(f, "{}", v),
+            Self::String(v) => write!(f, "{}", v),
+            Self::Placeholder(marker) | Self::Other(marker) => write!(f, "#{:02X}", marker),
+            Self::Compare { op, lhs, rhs } => {
+                let op = [">=", ">", "<=", "<", "==", "!="][usize::from(op - 0xE0)];
+                write!(f, "[{}{}{}]", lhs, op, rhs)
+            }
+            Self::Parameter { kind, index } => {
+                let kind = ["lnum", "gnum", "lstr", "gstr"][usize::from(kind - 0xE8)];
+                write!(f, "{}({})", kind, index)
+            }
+        }
+    }
+}
+
+fn take_byte(input: &mut &[u8]) -> Option<u8> {
+    let (&byte, rest) = input.split_first()?;
+    *input = rest;
+    Some(byte)
+}
+
+/// Take a length, which is stored one more than it is, or packed for larger lengths.
+fn take_length(input: &mut &[u8]) -> Option<usize> {
+    let len = match take_byte(input)? {
+        0 | 0xFF => return None,
+        marker @ 0x01..=0xEF => u32::from(marker) - 1,
+        marker => take_packed(marker, input)?,
+    };
+    usize::try_from(len).ok()
+}
+
+/// Take a packed integer. The low bits of [marker] plus one say which of its big-endian bytes
+/// follow, the rest are zero.
+fn take_packed(marker: u8, input: &mut &[u8]) -> Option<u32> {
+    let present = marker.wrapping_add(1) & 0xF;
+    let mut value = 0;
+    for i in (0..4).rev() {
+        if present & (1 << i) != 0 {
+            value |= u32::from(take_byte(input)?) << (8 * i);
+        }
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod se_string_tests {
+    use super::*;
+
+    #[test]
+    fn splits_text_and_payloads() {
+        // "Hi", a line break, then "Ifrit" colored 0xFF0000FF.
+        let bytes =
+            b"Hi\x02\x10\x01\x03\x02\x13\x06\xFE\xFF\x00\x00\xFF\x03Ifrit\x02\x13\x02\xEC\x03";
+        let string = SeString::parse(bytes);
+
+        assert_eq!(string.0.len(), 5);
+        assert_eq!(string.to_plain_text(), "Hi\nIfrit");
+        assert_eq!(
+            string.to_string(),
+            "Hi<new_line><color(4278190335)>Ifrit<color(#EC)>"
+        );
+    }
+
+    #[test]
+    fn decodes_nested_expressions() {
+        // A gender branch between the strings "he" and "she", on global number 4.
+        let bytes = b"\x02\x08\x0E\xE4\xE9\x05\x02\xFF\x03he\xFF\x04she\x03";
+        let string = SeString::parse(bytes);
+
+        assert_eq!(string.to_plain_text(), "");
+        assert_eq!(string.to_string(), "<if([gnum(4)==1],he,she)>");
+    }
+
+    #[test]
+    fn keeps_malformed_payloads_as_text() {
+        // The payload claims more body than there is.
+        let string = SeString::parse(b"Odd\x02\x10\x09\x03");
+        assert_eq!(string.0.len(), 2);
+        assert_eq!(string.to_plain_text(), "Odd\u{2}\u{10}\u{9}\u{3}");
+    }
+}
@@ -1,21 +1,109 @@
 use std::io::Cursor;
 
-use serde::de::{DeserializeOwned, DeserializeSeed, Deserializer, Error, SeqAccess, Visitor};
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{
+    Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess,
+    SeqAccess, Visitor,
+};
 
 use crate::error::LastLegendError;
-use crate::surpass::sheet_info::{Column, DataValue};
+use crate::surpass::se_string::{SeString, SE_STRING};
+use crate::surpass::sheet_info::{Column, DataType, DataValue, SheetInfo};
+
+/// The name [RestOfRow] is recognized by.
+const REST_OF_ROW: &str = "$last_legend_dob::RestOfRow";
+
+/// Consumes every remaining column of a row. Put it last in a row type to only describe the
+/// leading columns, for large sheets where only a few columns are known or needed.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RestOfRow;
+
+impl<'de> Deserialize<'de> for RestOfRow {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RestOfRowVisitor;
+
+        impl<'de> Visitor<'de> for RestOfRowVisitor {
+            type Value = RestOfRow;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("the rest of a row")
+            }
+
+            fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+                Ok(RestOfRow)
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                // Other formats have no rows to skip, so just ignore whatever is there.
+                IgnoredAny::deserialize(deserializer).map(|_| RestOfRow)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(REST_OF_ROW, RestOfRowVisitor)
+    }
+}
+
+/// How string columns are given to [String] fields. Fields of type [SeString] always get the
+/// string with its payloads.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum StringMode {
+    /// Only the text, see [SeString::to_plain_text].
+    #[default]
+    PlainText,
+    /// The text with payloads written as tags, see [SeString]'s [Display](std::fmt::Display).
+    Markup,
+}
 
 pub fn from_row<T: DeserializeOwned>(
     columns: &[Column],
     fixed_row_size: u64,
     row: Vec<u8>,
 ) -> Result<T, LastLegendError> {
-    let mut deserializer = SerdeRowReader {
+    from_row_with(columns, fixed_row_size, row, StringMode::default())
+}
+
+/// Like [from_row], giving strings to [String] fields as [string_mode] says.
+pub fn from_row_with<T: DeserializeOwned>(
+    columns: &[Column],
+    fixed_row_size: u64,
+    row: Vec<u8>,
+    string_mode: StringMode,
+) -> Result<T, LastLegendError> {
+    deserialize_row(SerdeRowReader {
         columns,
+        column_names: &[],
         fixed_row_size,
         row,
         col_index: 0,
-    };
+        string_mode,
+    })
+}
+
+/// Like [from_row_with], for a row of the sheet [sheet_info] describes. Structs whose fields
+/// each match one of [SheetInfo::column_names] are read by name instead of in order, ignoring
+/// case and underscores, so `bgm_out` reads the `BGMOut` column.
+pub fn from_sheet_row<T: DeserializeOwned>(
+    sheet_info: &SheetInfo,
+    row: Vec<u8>,
+    string_mode: StringMode,
+) -> Result<T, LastLegendError> {
+    deserialize_row(SerdeRowReader {
+        columns: &sheet_info.columns,
+        column_names: &sheet_info.column_names,
+        fixed_row_size: sheet_info.fixed_row_size.into(),
+        row,
+        col_index: 0,
+        string_mode,
+    })
+}
+
+fn deserialize_row<T: DeserializeOwned>(
+    mut deserializer: SerdeRowReader,
+) -> Result<T, LastLegendError> {
+    let columns = deserializer.columns;
     let t = T::deserialize(&mut deserializer)?;
     if deserializer.col_index == columns.len() {
         Ok(t)
@@ -31,9 +119,80 @@ pub fn from_row<T: DeserializeOwned>(
 /// Reads a row as [serde::Deserialize] types.
 struct SerdeRowReader<'col> {
     columns: &'col [Column],
+    column_names: &'col [Option<String>],
     fixed_row_size: u64,
     row: Vec<u8>,
     col_index: usize,
+    string_mode: StringMode,
+}
+
+impl<'col> SerdeRowReader<'col> {
+    fn next_column(&mut self) -> Result<&'col Column, LastLegendError> {
+        let columns = self.columns;
+        let column = columns
+            .get(self.col_index)
+            .ok_or_else(|| LastLegendError::custom("No more columns available"))?;
+        self.col_index += 1;
+        Ok(column)
+    }
+
+    /// The column of each of [fields], if this is the start of the row and every field names a
+    /// column.
+    fn named_columns(&self, fields: &'static [&'static str]) -> Option<Vec<(&'static str, usize)>> {
+        if self.col_index != 0 || self.column_names.is_empty() {
+            return None;
+        }
+        fields
+            .iter()
+            .map(|&field| {
+                let wanted = normalize_name(field);
+                self.column_names
+                    .iter()
+                    .position(|name| name.as_deref().is_some_and(|n| normalize_name(n) == wanted))
+                    .map(|column| (field, column))
+            })
+            .collect()
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Reads the fields of a struct from the columns they name, see [from_sheet_row].
+struct NamedColumns<'a, 'col> {
+    reader: &'a mut SerdeRowReader<'col>,
+    fields: std::vec::IntoIter<(&'static str, usize)>,
+    column: usize,
+}
+
+impl<'de> MapAccess<'de> for NamedColumns<'_, '_> {
+    type Error = LastLegendError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let Some((field, column)) = self.fields.next() else {
+            // Columns no field names are skipped.
+            self.reader.col_index = self.reader.columns.len();
+            return Ok(None);
+        };
+        self.column = column;
+        seed.deserialize(BorrowedStrDeserializer::new(field))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.reader.col_index = self.column;
+        seed.deserialize(&mut *self.reader)
+    }
 }
 
 impl<'de> SeqAccess<'de> for &mut SerdeRowReader<'_> {
@@ -57,11 +216,16 @@ impl<'de> Deserializer<'de> for &mut SerdeRowReader<'_> {
     where
         V: Visitor<'de>,
     {
-        let column = match self.columns.get(self.col_index) {
-            Some(c) => c,
-            None => return Err(LastLegendError::custom("No more columns available")),
-        };
-        self.col_index += 1;
+        let column = self.next_column()?;
+        if matches!(column.data_type(), DataType::String) {
+            let bytes =
+                column.read_string_bytes(Cursor::new(&mut self.row), self.fixed_row_size)?;
+            let string = SeString::parse(&bytes);
+            return match self.string_mode {
+                StringMode::PlainText => visitor.visit_string(string.to_plain_text()),
+                StringMode::Markup => visitor.visit_string(string.to_string()),
+            };
+        }
         match column.read_value(Cursor::new(&mut self.row), self.fixed_row_size)? {
             DataValue::String(s) => visitor.visit_string(s),
             DataValue::Bool(b) => visitor.visit_bool(b),
@@ -89,9 +253,26 @@ impl<'de> Deserializer<'de> for &mut SerdeRowReader<'_> {
 
     fn deserialize_newtype_struct<V: Visitor<'de>>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
+        if name == REST_OF_ROW {
+            self.col_index = self.columns.len();
+            return visitor.visit_unit();
+        }
+        if name == SE_STRING {
+            let column = self.next_column()?;
+            if !matches!(column.data_type(), DataType::String) {
+                return Err(LastLegendError::custom(format!(
+                    "Column {} is {:?}, not a string",
+                    self.col_index - 1,
+                    column.data_type()
+                )));
+            }
+            let bytes =
+                column.read_string_bytes(Cursor::new(&mut self.row), self.fixed_row_size)?;
+            return visitor.visit_byte_buf(bytes);
+        }
         visitor.visit_newtype_struct(self)
     }
 
@@ -123,9 +304,16 @@ impl<'de> Deserializer<'de> for &mut SerdeRowReader<'_> {
     fn deserialize_struct<V: Visitor<'de>>(
         self,
         _name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
+        if let Some(columns) = self.named_columns(fields) {
+            return visitor.visit_map(NamedColumns {
+                reader: self,
+                fields: columns.into_iter(),
+                column: 0,
+            });
+        }
         visitor.visit_seq(self)
     }
 
@@ -134,3 +322,78 @@ impl<'de> Deserializer<'de> for &mut SerdeRowReader<'_> {
         bytes byte_buf option enum identifier ignored_any
     }
 }
+
+#[cfg(test)]
+mod serde_row_tests {
+    use binrw::BinReaderExt;
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::surpass::sheet_info::Variant;
+
+    #[derive(Debug, Deserialize)]
+    struct Prefix {
+        name: String,
+        #[serde(default)]
+        _rest: RestOfRow,
+    }
+
+    fn columns(raw: &[u8]) -> Vec<Column> {
+        raw.chunks(4)
+            .map(|c| Cursor::new(c).read_be().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rest_of_row_skips_remaining_columns() {
+        // A string at 0, a u8 at 4 and a u16 at 6, then the string data.
+        let columns = columns(&[0, 0, 0, 0, 0, 3, 0, 4, 0, 5, 0, 6]);
+        let row = vec![0, 0, 0, 0, 7, 0, 0, 9, b'h', b'i', 0];
+
+        let prefix: Prefix = from_row(&columns, 8, row.clone()).unwrap();
+        assert_eq!(prefix.name, "hi");
+        let only_name: Prefix = from_row(&columns[..1], 8, row.clone()).unwrap();
+        assert_eq!(only_name.name, "hi");
+        assert!(from_row::<(String, u8)>(&columns, 8, row).is_err());
+    }
+
+    #[test]
+    fn strings_follow_the_string_mode() {
+        // A string at 0, then "a", a line break, and "b".
+        let columns = columns(&[0, 0, 0, 0]);
+        let row = vec![0, 0, 0, 0, b'a', 2, 0x10, 1, 3, b'b', 0];
+
+        let (plain,): (String,) = from_row(&columns, 4, row.clone()).unwrap();
+        assert_eq!(plain, "a\nb");
+        let (markup,): (String,) =
+            from_row_with(&columns, 4, row.clone(), StringMode::Markup).unwrap();
+        assert_eq!(markup, "a<new_line>b");
+        let (se_string,): (SeString,) = from_row(&columns, 4, row).unwrap();
+        assert_eq!(se_string.0.len(), 3);
+    }
+
+    #[test]
+    fn named_columns_are_read_by_name() {
+        #[derive(Debug, Deserialize)]
+        struct Named {
+            count: u16,
+            name: String,
+        }
+
+        // A string at 0, a u8 at 4 and a u16 at 6, then the string data.
+        let sheet_info = SheetInfo {
+            fixed_row_size: 8,
+            variant: Variant::Default,
+            columns: columns(&[0, 0, 0, 0, 0, 3, 0, 4, 0, 5, 0, 6]),
+            page_ranges: Vec::new(),
+            languages: Vec::new(),
+            column_names: vec![Some("Name".into()), None, Some("Count".into())],
+        };
+        let row = vec![0, 0, 0, 0, 7, 0, 0, 9, b'h', b'i', 0];
+
+        let named: Named = from_sheet_row(&sheet_info, row.clone(), StringMode::PlainText).unwrap();
+        assert_eq!((named.count, named.name.as_str()), (9, "hi"));
+        // Without names, fields are read in order.
+        assert!(from_row::<Named>(&sheet_info.columns, 8, row).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Row-by-row differences between two versions of a sheet, e.g. before and after a patch.
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use crate::error::LastLegendError;
+use crate::surpass::collection::SheetIter;
+use crate::surpass::sheet_info::DataValue;
+
+/// The row id and sub-row id of a row. The sub-row id is only present for sheets with sub-rows.
+pub type RowKey = (u32, Option<u16>);
+
+#[derive(Debug, PartialEq)]
+pub enum RowChange {
+    Added(RowKey, Vec<DataValue>),
+    Removed(RowKey, Vec<DataValue>),
+    Changed(RowKey, Vec<ColumnChange>),
+}
+
+impl RowChange {
+    pub fn key(&self) -> RowKey {
+        match self {
+            Self::Added(key, _) | Self::Removed(key, _) | Self::Changed(key, _) => *key,
+        }
+    }
+}
+
+/// A column whose value differs. Columns may be added or removed between versions, in which case
+/// the side without the column has None.
+#[derive(Debug, PartialEq)]
+pub struct ColumnChange {
+    pub column: usize,
+    pub old: Option<DataValue>,
+    pub new: Option<DataValue>,
+}
+
+/// Read every row of [sheet_iter] into memory, keyed by row.
+pub fn read_rows(
+    sheet_iter: SheetIter,
+) -> Result<BTreeMap<RowKey, Vec<DataValue>>, LastLegendError> {
+    let sheet_info = sheet_iter.sheet_info().clone();
+    let fixed_row_size = u64::from(sheet_info.fixed_row_size);
+    let mut rows = BTreeMap::new();
+    for row in sheet_iter.with_sub_rows() {
+        let (row_id, sub_row_id, row) = row?;
+        let mut row = Cursor::new(row);
+        let columns = sheet_info
+            .columns
+            .iter()
+            .map(|c| c.read_value(&mut row, fixed_row_size))
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.insert((row_id, sub_row_id), columns);
+    }
+    Ok(rows)
+}
+
+/// Compare the rows of two versions of a sheet, giving the changes in row order.
+pub fn diff_sheets(old: SheetIter, new: SheetIter) -> Result<Vec<RowChange>, LastLegendError> {
+    Ok(diff_rows(read_rows(old)?, read_rows(new)?))
+}
+
+/// Compare two sets of rows, giving the changes in row order.
+pub fn diff_rows(
+    mut old: BTreeMap<RowKey, Vec<DataValue>>,
+    new: BTreeMap<RowKey, Vec<DataValue>>,
+) -> Vec<RowChange> {
+    let mut changes = Vec::new();
+    for (key, new_row) in new {
+        match old.remove(&key) {
+            None => changes.push(RowChange::Added(key, new_row)),
+            Some(old_row) => {
+                let columns = diff_columns(old_row, new_row);
+                if !columns.is_empty() {
+                    changes.push(RowChange::Changed(key, columns));
+                }
+            }
+        }
+    }
+    changes.extend(
+        old.into_iter()
+            .map(|(key, old_row)| RowChange::Removed(key, old_row)),
+    );
+    changes.sort_by_key(RowChange::key);
+    changes
+}
+
+fn diff_columns(old: Vec<DataValue>, new: Vec<DataValue>) -> Vec<ColumnChange> {
+    let len = old.len().max(new.len());
+    let mut old = old.into_iter();
+    let mut new = new.into_iter();
+    (0..len)
+        .filter_map(|column| {
+            let (old, new) = (old.next(), new.next());
+            (old != new).then_some(ColumnChange { column, old, new })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod sheet_diff_tests {
+    use super::*;
+
+    #[test]
+    fn finds_added_removed_and_changed_rows() {
+        let row = |values: &[u32]| values.iter().map(|&v| DataValue::U32(v)).collect();
+        let old = BTreeMap::from([
+            ((1, None), row(&[1, 2])),
+            ((2, None), row(&[3, 4])),
+            ((3, None), row(&[5, 6])),
+        ]);
+        let new = BTreeMap::from([
+            ((1, None), row(&[1, 2])),
+            ((3, None), row(&[5, 7, 8])),
+            ((4, None), row(&[9])),
+        ]);
+        assert_eq!(
+            diff_rows(old, new),
+            vec![
+                RowChange::Removed((2, None), row(&[3, 4])),
+                RowChange::Changed(
+                    (3, None),
+                    vec![
+                        ColumnChange {
+                            column: 1,
+                            old: Some(DataValue::U32(6)),
+                            new: Some(DataValue::U32(7)),
+                        },
+                        ColumnChange {
+                            column: 2,
+                            old: None,
+                            new: Some(DataValue::U32(8)),
+                        },
+                    ]
+                ),
+                RowChange::Added((4, None), row(&[9])),
+            ]
+        );
+    }
+}
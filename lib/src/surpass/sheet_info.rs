@@ -1,10 +1,15 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::io::{Cursor, Read, Seek, SeekFrom};
 use std::ops::Range;
 
 use binrw::helpers::count_with;
 use binrw::{binread, BinRead, BinReaderExt, BinResult, NullString};
+use serde::Serialize;
+use strum::EnumString;
 
 use crate::error::LastLegendError;
+use crate::surpass::se_string::SeString;
 
 #[binread]
 #[derive(Debug, Clone)]
@@ -33,6 +38,27 @@ pub struct SheetInfo {
     pub page_ranges: Vec<Range<u32>>,
     #[br(args { count: dbg!(language_count).try_into().unwrap() })]
     pub languages: Vec<Language>,
+    /// Names of the columns, by index, from a [Schema](crate::surpass::schema::Schema). Empty if
+    /// the schema has no names.
+    #[br(default)]
+    pub column_names: Vec<Option<String>>,
+}
+
+impl SheetInfo {
+    /// Parse the content of a `.exh` file.
+    pub fn parse(content: &[u8]) -> Result<Self, LastLegendError> {
+        Cursor::new(content)
+            .read_be()
+            .map_err(|e| LastLegendError::BinRW("Failed to read sheet header".into(), e))
+    }
+
+    /// The name of column [index] from the schema, or `col_<index>` if it has none.
+    pub fn column_name(&self, index: usize) -> Cow<'_, str> {
+        match self.column_names.get(index) {
+            Some(Some(name)) => Cow::Borrowed(name),
+            _ => Cow::Owned(format!("col_{}", index)),
+        }
+    }
 }
 
 #[binread]
@@ -51,6 +77,41 @@ pub struct Column {
 }
 
 impl Column {
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    /// The offset of the column in the fixed part of a row.
+    pub fn offset(&self) -> u16 {
+        self.offset
+    }
+
+    /// Read the bytes of a string column as stored, with any SeString payloads, see
+    /// [SeString::parse].
+    pub fn read_string_bytes<R: Read + Seek>(
+        &self,
+        mut reader: R,
+        fixed_row_size: u64,
+    ) -> Result<Vec<u8>, LastLegendError> {
+        reader
+            .seek(SeekFrom::Start(u64::from(self.offset)))
+            .map_err(|e| LastLegendError::Io("Failed to move to data pos".into(), e))?;
+        let str_offset = u64::from(
+            reader
+                .read_be::<u32>()
+                .map_err(|e| LastLegendError::BinRW("Failed to read str offset".into(), e))?,
+        );
+        reader
+            .seek(SeekFrom::Start(fixed_row_size + str_offset))
+            .map_err(|e| LastLegendError::Io("Failed to move to str pos".into(), e))?;
+        let nstr = reader
+            .read_be::<NullString>()
+            .map_err(|e| LastLegendError::BinRW("Failed to read str".into(), e))?;
+        Ok(nstr.0)
+    }
+
+    /// Read the value of this column. Strings are read as plain text, see
+    /// [SeString::to_plain_text].
     pub fn read_value<R: Read + Seek>(
         &self,
         mut reader: R,
@@ -61,19 +122,8 @@ impl Column {
             .map_err(|e| LastLegendError::Io("Failed to move to data pos".into(), e))?;
         match self.data_type {
             DataType::String => {
-                let str_offset =
-                    u64::from(reader.read_be::<u32>().map_err(|e| {
-                        LastLegendError::BinRW("Failed to read str offset".into(), e)
-                    })?);
-                reader
-                    .seek(SeekFrom::Start(fixed_row_size + str_offset))
-                    .map_err(|e| LastLegendError::Io("Failed to move to str pos".into(), e))?;
-                let nstr = reader
-                    .read_be::<NullString>()
-                    .map_err(|e| LastLegendError::BinRW("Failed to read str".into(), e))?;
-                Ok(DataValue::String(
-                    nstr.try_into().expect("Failed to convert string"),
-                ))
+                let bytes = self.read_string_bytes(reader, fixed_row_size)?;
+                Ok(DataValue::String(SeString::parse(&bytes).to_plain_text()))
             }
             DataType::Bool => reader
                 .read_be::<u8>()
@@ -153,7 +203,8 @@ pub enum DataType {
     PackedBool7,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
 pub enum DataValue {
     String(String),
     Bool(bool),
@@ -168,6 +219,23 @@ pub enum DataValue {
     // Packed bools are Bool
 }
 
+impl Display for DataValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::String(v) => f.write_str(v),
+            Self::Bool(v) => write!(f, "{}", v),
+            Self::I8(v) => write!(f, "{}", v),
+            Self::U8(v) => write!(f, "{}", v),
+            Self::I16(v) => write!(f, "{}", v),
+            Self::U16(v) => write!(f, "{}", v),
+            Self::I32(v) => write!(f, "{}", v),
+            Self::U32(v) => write!(f, "{}", v),
+            Self::F32(v) => write!(f, "{}", v),
+            Self::I64(v) => write!(f, "{}", v),
+        }
+    }
+}
+
 #[binrw::parser(reader, endian)]
 fn range_parser(_: ()) -> BinResult<Range<u32>> {
     #[binread]
@@ -184,26 +252,35 @@ fn range_parser(_: ()) -> BinResult<Range<u32>> {
     })
 }
 
+/// The language of a sheet's pages. Parsed from the codes used in page file names, e.g. `en`.
 #[binread]
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone, EnumString)]
 #[br(little, repr(u16))]
 pub enum Language {
+    /// Pages that aren't translated, such as those that only hold numbers and paths.
+    #[strum(serialize = "none")]
     None,
+    #[strum(serialize = "ja")]
     Japanese,
+    #[strum(serialize = "en")]
     English,
+    #[strum(serialize = "de")]
     German,
+    #[strum(serialize = "fr")]
     French,
+    #[strum(serialize = "chs")]
     ChineseSimplified,
+    #[strum(serialize = "cht")]
     ChineseTraditional,
+    #[strum(serialize = "ko")]
     Korean,
 }
 
 impl Language {
-    pub fn get_sheet_name(&self, sheet_name: &str, start_id: u32) -> String {
-        let lang_code = match self {
-            Language::None => {
-                return format!("exd/{}_{}.exd", sheet_name, start_id);
-            }
+    /// The code used for this language in file names, e.g. `en`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::None => "none",
             Language::Japanese => "ja",
             Language::English => "en",
             Language::German => "de",
@@ -211,7 +288,13 @@ impl Language {
             Language::ChineseSimplified => "chs",
             Language::ChineseTraditional => "cht",
             Language::Korean => "ko",
-        };
-        format!("exd/{}_{}_{}.exd", sheet_name, start_id, lang_code)
+        }
+    }
+
+    pub fn get_sheet_name(&self, sheet_name: &str, start_id: u32) -> String {
+        match self {
+            Language::None => format!("exd/{}_{}.exd", sheet_name, start_id),
+            _ => format!("exd/{}_{}_{}.exd", sheet_name, start_id, self.code()),
+        }
     }
 }
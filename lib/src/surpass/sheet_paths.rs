@@ -0,0 +1,116 @@
+//! Finding the files that a sheet refers to, e.g. the music files in `BGM`.
+use std::collections::VecDeque;
+use std::io::Cursor;
+
+use crate::error::LastLegendError;
+use crate::sqpath::{FileType, SqPath, SqPathBuf};
+use crate::surpass::collection::SheetIter;
+use crate::surpass::sheet_info::{DataType, DataValue};
+
+/// A path found in a sheet.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SheetPathRef {
+    pub row_id: u32,
+    /// The index of the column in the sheet.
+    pub column: usize,
+    pub path: SqPathBuf,
+}
+
+/// Check if a string from a sheet looks like a [SqPath]: it must start with a known category,
+/// have a file extension, and contain no whitespace.
+pub fn looks_like_sqpath(s: &str) -> bool {
+    let file_name = match s.rsplit_once('/') {
+        Some((_, file_name)) => file_name,
+        None => return false,
+    };
+    !s.contains(char::is_whitespace)
+        && file_name.contains('.')
+        && !file_name.ends_with('.')
+        && FileType::parse_from_sqpath(SqPath::new(s)).is_some()
+}
+
+/// Iterator over the paths in the string columns of a sheet.
+pub struct SheetPathIter {
+    sheet_iter: SheetIter,
+    columns: Option<Vec<usize>>,
+    pending: VecDeque<SheetPathRef>,
+}
+
+impl SheetIter {
+    /// Get the paths referenced by this sheet. With [columns], every non-empty string in those
+    /// columns is taken as a path. Otherwise, all string columns are checked with
+    /// [looks_like_sqpath].
+    pub fn referenced_paths(self, columns: Option<Vec<usize>>) -> SheetPathIter {
+        SheetPathIter {
+            sheet_iter: self,
+            columns,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl SheetPathIter {
+    fn queue_row(&mut self, row_id: u32, row: Vec<u8>) -> Result<(), LastLegendError> {
+        let sheet_info = self.sheet_iter.sheet_info();
+        let fixed_row_size = u64::from(sheet_info.fixed_row_size);
+        let mut row = Cursor::new(row);
+        for (index, column) in sheet_info.columns.iter().enumerate() {
+            let selected = match &self.columns {
+                Some(columns) => columns.contains(&index),
+                None => true,
+            };
+            if !selected || !matches!(column.data_type(), DataType::String) {
+                continue;
+            }
+            let DataValue::String(s) = column.read_value(&mut row, fixed_row_size)? else {
+                unreachable!("String columns must read strings");
+            };
+            let is_path = match &self.columns {
+                Some(_) => !s.is_empty(),
+                None => looks_like_sqpath(&s),
+            };
+            if is_path {
+                self.pending.push_back(SheetPathRef {
+                    row_id,
+                    column: index,
+                    path: SqPathBuf::new(&s),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for SheetPathIter {
+    type Item = Result<SheetPathRef, LastLegendError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(path) = self.pending.pop_front() {
+                return Some(Ok(path));
+            }
+            let (row_id, _, row) = match self.sheet_iter.next_keyed()? {
+                Ok(v) => v,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Err(e) = self.queue_row(row_id, row) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod sheet_paths_tests {
+    use crate::surpass::sheet_paths::looks_like_sqpath;
+
+    #[test]
+    fn detects_paths() {
+        assert!(looks_like_sqpath("music/ffxiv/BGM_System_Title.scd"));
+        assert!(looks_like_sqpath("ui/icon/000000/000001.tex"));
+        assert!(!looks_like_sqpath("music/ffxiv/"));
+        assert!(!looks_like_sqpath("Some Song Name"));
+        assert!(!looks_like_sqpath("notacategory/file.scd"));
+        assert!(!looks_like_sqpath("music/with space.scd"));
+    }
+}
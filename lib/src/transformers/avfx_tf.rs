@@ -0,0 +1,57 @@
+use std::borrow::Cow;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use crate::avfx::Avfx;
+use crate::error::LastLegendError;
+use crate::limits::Limits;
+use crate::sqpath::{SqPath, SqPathBuf};
+use crate::transformers::{TransformStream, Transformer, TransformerForFile};
+
+/// Render `.avfx` VFX files as JSON, see [Avfx::to_json].
+#[derive(Debug)]
+pub struct AvfxTf {
+    pub(crate) limits: Limits,
+}
+
+impl<R: Read> Transformer<R> for AvfxTf {
+    type ForFile = AvfxTfForFile;
+
+    fn maybe_for(&self, file: SqPathBuf) -> Option<Self::ForFile> {
+        file.as_str().ends_with(".avfx").then_some(AvfxTfForFile {
+            file,
+            limits: self.limits,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct AvfxTfForFile {
+    file: SqPathBuf,
+    limits: Limits,
+}
+
+impl<R: Read> TransformerForFile<R> for AvfxTfForFile {
+    fn renamed_file(&self) -> Cow<'_, SqPath> {
+        Cow::Owned(SqPathBuf::new(
+            Path::new(self.file.as_str())
+                .with_extension("json")
+                .as_os_str()
+                .to_str()
+                .unwrap(),
+        ))
+    }
+
+    fn transform(&self, mut content: R) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        let mut capture = Vec::<u8>::new();
+        content
+            .read_to_end(&mut capture)
+            .map_err(|e| LastLegendError::Io("Couldn't cache content".into(), e))?;
+        drop(content);
+
+        let json =
+            serde_json::to_vec_pretty(&Avfx::parse_with_limits(&capture, &self.limits)?.to_json())
+                .map_err(|e| LastLegendError::Json("Couldn't write AVFX JSON".into(), e))?;
+        Ok(Box::new(Cursor::new(json)))
+    }
+}
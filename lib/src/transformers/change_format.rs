@@ -1,18 +1,21 @@
 use std::borrow::Cow;
-use std::io::{Cursor, Read};
+use std::io::Read;
 use std::path::Path;
 
 use crate::error::LastLegendError;
-use crate::ffmpeg::format_rewrite;
+use crate::ffmpeg::backend::audio_backend;
+use crate::ffmpeg::scratch::Spool;
 use crate::sqpath::{SqPath, SqPathBuf};
-use crate::transformers::{Transformer, TransformerForFile};
+use crate::transformers::{
+    AudioFormat, EncodeOptions, TransformStream, Transformer, TransformerForFile,
+};
 
-/// Change a file format using FFMPEG.
-#[derive(Debug, Default)]
+/// Change an audio file's format using FFMPEG.
+#[derive(Debug)]
 pub struct ChangeFile {
-    pub(crate) from_extension: String,
-    pub(crate) to_extension: String,
-    pub(crate) to_ffmpeg_format: String,
+    pub(crate) from: AudioFormat,
+    pub(crate) to: AudioFormat,
+    pub(crate) encode: EncodeOptions,
 }
 
 impl<R: Read + Send> Transformer<R> for ChangeFile {
@@ -20,11 +23,11 @@ impl<R: Read + Send> Transformer<R> for ChangeFile {
 
     fn maybe_for(&self, file: SqPathBuf) -> Option<Self::ForFile> {
         file.as_str()
-            .ends_with(&format!(".{}", self.from_extension))
+            .ends_with(&format!(".{}", self.from.extension_str()))
             .then_some(ChangeFileForFile {
                 file,
-                extension: self.to_extension.clone(),
-                ffmpeg_format: self.to_ffmpeg_format.clone(),
+                to: self.to,
+                encode: self.encode,
             })
     }
 }
@@ -32,24 +35,24 @@ impl<R: Read + Send> Transformer<R> for ChangeFile {
 #[derive(Debug)]
 pub struct ChangeFileForFile {
     file: SqPathBuf,
-    extension: String,
-    ffmpeg_format: String,
+    to: AudioFormat,
+    encode: EncodeOptions,
 }
 
 impl<R: Read + Send> TransformerForFile<R> for ChangeFileForFile {
-    fn renamed_file(&self) -> Cow<SqPath> {
+    fn renamed_file(&self) -> Cow<'_, SqPath> {
         Cow::Owned(SqPathBuf::new(
             Path::new(self.file.as_str())
-                .with_extension(&self.extension)
+                .with_extension(self.to.extension_str())
                 .as_os_str()
                 .to_str()
                 .unwrap(),
         ))
     }
 
-    fn transform(&self, content: R) -> Result<Box<dyn Read + Send>, LastLegendError> {
-        let mut final_content = Vec::new();
-        format_rewrite(&self.ffmpeg_format, content, &mut final_content)?;
-        Ok(Box::new(Cursor::new(final_content)))
+    fn transform(&self, mut content: R) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        let mut output = Spool::new();
+        audio_backend().encode_audio(self.to, &self.encode, &[], &mut content, &mut output)?;
+        output.into_stream()
     }
 }
@@ -1,19 +1,73 @@
 use std::borrow::Cow;
-use std::io::{Cursor, Read};
+use std::io::{Read, Seek};
 
 use crate::error::LastLegendError;
-use crate::ffmpeg::loop_using_metadata;
+use crate::ffmpeg::backend::audio_backend;
+use crate::ffmpeg::scratch::Spool;
 use crate::sqpath::{SqPath, SqPathBuf};
-use crate::transformers::{Transformer, TransformerForFile};
+use crate::transformers::{TransformStream, Transformer, TransformerForFile};
+
+/// How looping transformers repeat and end the audio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopOptions {
+    /// How many extra times the looping part is played.
+    pub loop_count: u32,
+    /// Loop as many times as needed to reach this many seconds, then cut off there. Overrides
+    /// [loop_count](Self::loop_count).
+    pub target_duration: Option<f64>,
+    /// Seconds to fade out over at the end, or None to stop without fading.
+    pub fade: Option<f64>,
+    /// Milliseconds to crossfade over where the loop jumps back, instead of cutting straight from
+    /// the loop end to the loop start, which can click. None cuts straight, as the game does.
+    pub crossfade_ms: Option<u32>,
+}
+
+impl Default for LoopOptions {
+    fn default() -> Self {
+        Self {
+            loop_count: 1,
+            target_duration: None,
+            fade: Some(5.0),
+            crossfade_ms: None,
+        }
+    }
+}
+
+impl LoopOptions {
+    /// Parse a fade length in seconds, where `none` or zero means no fade.
+    pub fn parse_fade(s: &str) -> Result<Option<f64>, String> {
+        match s {
+            "none" => Ok(None),
+            _ => match s.parse::<f64>() {
+                Ok(seconds) if seconds > 0.0 => Ok(Some(seconds)),
+                Ok(_) => Ok(None),
+                Err(e) => Err(format!("invalid fade '{}': {}", s, e)),
+            },
+        }
+    }
+
+    /// Parse a crossfade length in milliseconds, where `none` or zero means no crossfade.
+    pub fn parse_crossfade(s: &str) -> Result<Option<u32>, String> {
+        match s {
+            "none" => Ok(None),
+            _ => match s.parse::<u32>() {
+                Ok(0) => Ok(None),
+                Ok(ms) => Ok(Some(ms)),
+                Err(e) => Err(format!("invalid crossfade '{}': {}", s, e)),
+            },
+        }
+    }
+}
 
 /// Loop a file using FFMPEG.
 #[derive(Debug, Default)]
 pub struct LoopFile {
     pub(crate) extension: String,
     pub(crate) ffmpeg_format: String,
+    pub(crate) options: LoopOptions,
 }
 
-impl<R: Read> Transformer<R> for LoopFile {
+impl<R: Read + Seek + Send> Transformer<R> for LoopFile {
     type ForFile = LoopFileForFile;
 
     fn maybe_for(&self, file: SqPathBuf) -> Option<Self::ForFile> {
@@ -22,6 +76,7 @@ impl<R: Read> Transformer<R> for LoopFile {
             .then_some(LoopFileForFile {
                 file,
                 ffmpeg_format: self.ffmpeg_format.clone(),
+                options: self.options,
             })
     }
 }
@@ -30,16 +85,22 @@ impl<R: Read> Transformer<R> for LoopFile {
 pub struct LoopFileForFile {
     file: SqPathBuf,
     ffmpeg_format: String,
+    options: LoopOptions,
 }
 
-impl<R: Read> TransformerForFile<R> for LoopFileForFile {
-    fn renamed_file(&self) -> Cow<SqPath> {
+impl<R: Read + Seek + Send> TransformerForFile<R> for LoopFileForFile {
+    fn renamed_file(&self) -> Cow<'_, SqPath> {
         Cow::Borrowed(&self.file)
     }
 
-    fn transform(&self, content: R) -> Result<Box<dyn Read + Send>, LastLegendError> {
-        let mut final_content = Vec::new();
-        loop_using_metadata(&self.ffmpeg_format, content, &mut final_content)?;
-        Ok(Box::new(Cursor::new(final_content)))
+    fn transform(&self, mut content: R) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        let mut output = Spool::new();
+        audio_backend().loop_using_metadata(
+            &self.ffmpeg_format,
+            &self.options,
+            &mut content,
+            &mut output,
+        )?;
+        output.into_stream()
     }
 }
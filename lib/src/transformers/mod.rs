@@ -1,17 +1,39 @@
 use std::borrow::Cow;
-use std::io::Read;
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{Read, Seek};
+use std::str::FromStr;
 
-use strum::EnumString;
+use parking_lot::{Mutex, RwLock};
+use thiserror::Error;
 
 use crate::error::LastLegendError;
+use crate::ffmpeg::probe::FfmpegRequirement;
+use crate::limits::Limits;
 use crate::sqpath::{SqPath, SqPathBuf};
+use crate::transformers::avfx_tf::AvfxTf;
 use crate::transformers::change_format::ChangeFile;
 use crate::transformers::loop_file::LoopFile;
-use crate::transformers::scd_tf::{ScdAudioTransform, ScdTf};
+pub use crate::transformers::loop_file::LoopOptions;
+pub use crate::transformers::plan::{plan_transformers, unused_transformers, TargetFormat};
+pub use crate::transformers::scd_tf::ScdOptions;
+use crate::transformers::scd_tf::ScdTf;
+pub use crate::transformers::scd_tf::{explode_scd_bank, AudioFormat, EncodeOptions, ScdBankWavs};
+use crate::transformers::tex_tf::TexTf;
+pub use crate::transformers::tex_tf::{PngCompression, PngOptions};
 
+mod avfx_tf;
 mod change_format;
 mod loop_file;
+mod plan;
 mod scd_tf;
+mod tex_tf;
+
+/// Content passed between transformers. It can be seeked, so transformers can look around in their
+/// input, like reading `.scd` headers. Entries are read from the dat files whole, so only large
+/// transformer output is kept out of memory, see [TransformerForFile::transform].
+pub trait TransformStream: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send + ?Sized> TransformStream for T {}
 
 pub trait Transformer<R> {
     type ForFile: TransformerForFile<R>;
@@ -22,84 +44,1140 @@ pub trait Transformer<R> {
 
 pub trait TransformerForFile<R> {
     /// Get the file name used after the transformer is applied.
-    fn renamed_file(&self) -> Cow<SqPath>;
+    fn renamed_file(&self) -> Cow<'_, SqPath>;
 
-    /// Attempt to run the transformer against the [content].
-    fn transform(&self, content: R) -> Result<Box<dyn Read + Send>, LastLegendError>;
+    /// Attempt to run the transformer against the [content]. Large output is kept in a
+    /// temporary file rather than in memory.
+    fn transform(&self, content: R) -> Result<Box<dyn TransformStream>, LastLegendError>;
 }
 
-#[derive(EnumString, Copy, Clone, Debug)]
-#[strum(serialize_all = "snake_case")]
+/// A transformer picked by name, with its options.
+///
+/// Parsed from `name` or `name:key=value,...`, e.g. `loop_ogg:count=3,fade=none`. Options that
+/// aren't given keep their defaults, and options that don't apply to the output format, or are out
+/// of its encoder's range, are rejected. [Display] writes the same form back, so the exact settings
+/// can be recorded alongside an extraction. The accepted names and keys are listed by
+/// [transformers].
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TransformerImpl {
-    ScdToFlac,
-    LoopFlac,
-    ScdToOgg,
-    LoopOgg,
-    FlacToOgg,
-    ScdToWav,
+    ScdToFlac(ScdOptions),
+    LoopFlac(LoopOptions),
+    ScdToOgg(ScdOptions),
+    LoopOgg(LoopOptions),
+    ChangeFormat {
+        from: AudioFormat,
+        to: AudioFormat,
+        encode: EncodeOptions,
+    },
+    ScdToWav(ScdOptions),
+    ScdToMp3(ScdOptions),
+    ScdToAac(ScdOptions),
+    TexToPng(PngOptions),
+    AvfxToJson(Limits),
+    /// A transformer from outside this crate, see [register_transformer].
+    Custom(&'static dyn CustomTransformer),
+}
+
+/// A transformer from outside this crate, parsed by the [TransformerInfo::parse] given to
+/// [register_transformer]. [Display] must write the form it's parsed from, like
+/// [TransformerImpl] does, and transformers that display the same are treated as equal.
+pub trait CustomTransformer: Debug + Display + Send + Sync {
+    /// The extension of files this transformer applies to.
+    fn input_extension(&self) -> &'static str;
+
+    /// The extension of files this transformer produces.
+    fn output_extension(&self) -> &'static str;
+
+    /// What FFMPEG needs to support to run this transformer.
+    fn ffmpeg_requirements(&self) -> &'static [FfmpegRequirement] {
+        &[]
+    }
+
+    /// If this transformer applies to the given file, get a new file-specific transformer.
+    fn maybe_for(
+        &self,
+        file: SqPathBuf,
+    ) -> Option<Box<dyn TransformerForFile<Box<dyn TransformStream>>>>;
+}
+
+impl PartialEq for dyn CustomTransformer {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+/// Every [CustomTransformer] made with [TransformerImpl::custom], so equal ones are shared.
+static CUSTOM_TRANSFORMERS: Mutex<Vec<&'static dyn CustomTransformer>> = Mutex::new(Vec::new());
+
+impl TransformerImpl {
+    /// Wrap a [CustomTransformer]. It's kept for the rest of the process, so that
+    /// [TransformerImpl] stays [Copy], and reused when an equal one is wrapped again.
+    pub fn custom(transformer: impl CustomTransformer + 'static) -> Self {
+        let mut custom = CUSTOM_TRANSFORMERS.lock();
+        let display = transformer.to_string();
+        if let Some(existing) = custom.iter().find(|t| t.to_string() == display) {
+            return Self::Custom(*existing);
+        }
+        let transformer: &'static dyn CustomTransformer = Box::leak(Box::new(transformer));
+        custom.push(transformer);
+        Self::Custom(transformer)
+    }
+
+    /// Replace the options of `.scd` transformers, others are unchanged.
+    pub fn with_scd_options(self, options: ScdOptions) -> Self {
+        match self {
+            Self::ScdToFlac(_) => Self::ScdToFlac(options),
+            Self::ScdToOgg(_) => Self::ScdToOgg(options),
+            Self::ScdToWav(_) => Self::ScdToWav(options),
+            Self::ScdToMp3(_) => Self::ScdToMp3(options),
+            Self::ScdToAac(_) => Self::ScdToAac(options),
+            other => other,
+        }
+    }
+
+    /// Apply [limits] when `.scd` and `.avfx` transformers read their input, others are
+    /// unchanged.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        if let Self::AvfxToJson(_) = self {
+            return Self::AvfxToJson(limits);
+        }
+        match self.scd_options() {
+            Some(options) => self.with_scd_options(ScdOptions { limits, ..options }),
+            None => self,
+        }
+    }
+
+    /// Replace the options of looping transformers, others are unchanged.
+    pub fn with_loop_options(self, options: LoopOptions) -> Self {
+        match self {
+            Self::LoopFlac(_) => Self::LoopFlac(options),
+            Self::LoopOgg(_) => Self::LoopOgg(options),
+            other => other,
+        }
+    }
+
+    /// Mix the output of `.scd` and format changing transformers down to stereo, others are
+    /// unchanged.
+    pub fn with_downmix(self) -> Self {
+        match self {
+            Self::ChangeFormat { from, to, encode } => Self::ChangeFormat {
+                from,
+                to,
+                encode: EncodeOptions {
+                    downmix: true,
+                    ..encode
+                },
+            },
+            other => match other.scd_options() {
+                Some(options) => other.with_scd_options(ScdOptions {
+                    encode: EncodeOptions {
+                        downmix: true,
+                        ..options.encode
+                    },
+                    ..options
+                }),
+                None => other,
+            },
+        }
+    }
+
+    /// The options of `.scd` transformers.
+    pub fn scd_options(&self) -> Option<ScdOptions> {
+        match self {
+            Self::ScdToFlac(options)
+            | Self::ScdToOgg(options)
+            | Self::ScdToWav(options)
+            | Self::ScdToMp3(options)
+            | Self::ScdToAac(options) => Some(*options),
+            _ => None,
+        }
+    }
+
+    /// The options of looping transformers.
+    pub fn loop_options(&self) -> Option<LoopOptions> {
+        match self {
+            Self::LoopFlac(options) | Self::LoopOgg(options) => Some(*options),
+            _ => None,
+        }
+    }
+
+    /// The audio format this transformer produces, if it produces audio.
+    pub fn output_format(&self) -> Option<AudioFormat> {
+        match self {
+            Self::ScdToFlac(_) | Self::LoopFlac(_) => Some(AudioFormat::Flac),
+            Self::ScdToOgg(_) | Self::LoopOgg(_) => Some(AudioFormat::Ogg),
+            Self::ScdToWav(_) => Some(AudioFormat::Wav),
+            Self::ScdToMp3(_) => Some(AudioFormat::Mp3),
+            Self::ScdToAac(_) => Some(AudioFormat::Aac),
+            Self::ChangeFormat { to, .. } => Some(*to),
+            Self::TexToPng(_) | Self::AvfxToJson(_) | Self::Custom(_) => None,
+        }
+    }
+
+    /// Whether this transformer loops the audio.
+    pub fn is_loop(&self) -> bool {
+        matches!(self, Self::LoopFlac(_) | Self::LoopOgg(_))
+    }
+
+    /// The extension of files this transformer applies to.
+    pub fn input_extension(&self) -> &'static str {
+        match self {
+            Self::ScdToFlac(_)
+            | Self::ScdToOgg(_)
+            | Self::ScdToWav(_)
+            | Self::ScdToMp3(_)
+            | Self::ScdToAac(_) => "scd",
+            Self::LoopFlac(_) => "flac",
+            Self::ChangeFormat { from, .. } => from.extension_str(),
+            Self::LoopOgg(_) => "ogg",
+            Self::TexToPng(_) => "tex",
+            Self::AvfxToJson(_) => "avfx",
+            Self::Custom(t) => t.input_extension(),
+        }
+    }
+
+    /// What FFMPEG needs to support to run this transformer.
+    pub fn ffmpeg_requirements(&self) -> &'static [FfmpegRequirement] {
+        use FfmpegRequirement::*;
+        match self {
+            Self::ScdToFlac(_) => &[Encoder("flac")],
+            Self::ScdToOgg(_) => &[Encoder("libvorbis")],
+            Self::ScdToWav(_) => &[Encoder("pcm_s16le")],
+            Self::ScdToMp3(_) => &[Encoder("libmp3lame")],
+            Self::ScdToAac(_) => &[Encoder("aac")],
+            Self::LoopFlac(options) if options.crossfade_ms.is_some() => &[
+                Ffprobe,
+                Filter("aloop"),
+                Filter("asplit"),
+                Filter("atrim"),
+                Filter("acrossfade"),
+                Filter("afade"),
+                Encoder("flac"),
+            ],
+            Self::LoopOgg(options) if options.crossfade_ms.is_some() => &[
+                Ffprobe,
+                Filter("aloop"),
+                Filter("asplit"),
+                Filter("atrim"),
+                Filter("acrossfade"),
+                Filter("afade"),
+                Encoder("libvorbis"),
+            ],
+            Self::LoopFlac(_) => &[Ffprobe, Filter("aloop"), Filter("afade"), Encoder("flac")],
+            Self::LoopOgg(_) => &[
+                Ffprobe,
+                Filter("aloop"),
+                Filter("afade"),
+                Encoder("libvorbis"),
+            ],
+            Self::ChangeFormat { to, .. } => match to {
+                AudioFormat::Flac => &[Encoder("flac")],
+                AudioFormat::Ogg => &[Encoder("libvorbis")],
+                AudioFormat::Wav => &[Encoder("pcm_s16le")],
+                AudioFormat::Mp3 => &[Encoder("libmp3lame")],
+                AudioFormat::Aac => &[Encoder("aac")],
+            },
+            Self::TexToPng(_) | Self::AvfxToJson(_) => &[],
+            Self::Custom(t) => t.ffmpeg_requirements(),
+        }
+    }
+
+    /// The extension of files this transformer produces.
+    pub fn output_extension(&self) -> &'static str {
+        match self {
+            Self::ScdToFlac(_) | Self::LoopFlac(_) => "flac",
+            Self::ScdToOgg(_) | Self::LoopOgg(_) => "ogg",
+            Self::ChangeFormat { to, .. } => to.extension_str(),
+            Self::ScdToWav(_) => "wav",
+            Self::ScdToMp3(_) => "mp3",
+            Self::ScdToAac(_) => "m4a",
+            Self::TexToPng(_) => "png",
+            Self::AvfxToJson(_) => "json",
+            Self::Custom(t) => t.output_extension(),
+        }
+    }
+}
+
+/// What a transformer does and which files it applies to, so transformers can be listed, and how
+/// to build it from its options.
+#[derive(Debug, Clone, Copy)]
+pub struct TransformerInfo {
+    /// The name [TransformerImpl] is parsed from.
+    pub name: &'static str,
+    /// The extensions of the files it applies to.
+    pub input_extensions: &'static [&'static str],
+    /// The extension of the files it produces, or None if that depends on its options.
+    pub output_extension: Option<&'static str>,
+    /// The option keys it accepts.
+    pub options: &'static [&'static str],
+    /// The option keys it can't be parsed without.
+    pub required_options: &'static [&'static str],
+    pub description: &'static str,
+    /// Build the transformer, taking the options it accepts. Options left over are rejected.
+    pub parse: ParseTransformer,
+}
+
+/// Builds a transformer from its options, see [TransformerInfo::parse].
+pub type ParseTransformer =
+    fn(&mut TransformerParams<'_>) -> Result<TransformerImpl, TransformerParseError>;
+
+impl TransformerInfo {
+    pub const fn new(
+        name: &'static str,
+        input_extensions: &'static [&'static str],
+        output_extension: Option<&'static str>,
+        description: &'static str,
+        parse: ParseTransformer,
+    ) -> Self {
+        Self {
+            name,
+            input_extensions,
+            output_extension,
+            options: &[],
+            required_options: &[],
+            description,
+            parse,
+        }
+    }
+
+    pub const fn with_options(mut self, options: &'static [&'static str]) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub const fn with_required_options(
+        mut self,
+        required_options: &'static [&'static str],
+    ) -> Self {
+        self.required_options = required_options;
+        self
+    }
+}
+
+const SCD_OPTIONS: &[&str] = &["entry", "bitrate", "quality", "compression", "downmix"];
+const LOOP_OPTIONS: &[&str] = &["count", "duration", "fade", "crossfade"];
+const AUDIO_EXTENSIONS: &[&str] = &["flac", "ogg", "wav", "mp3", "m4a"];
+
+/// The built-in transformers [TransformerImpl] can be parsed as. `entry` picks the sound entry of
+/// an `.scd` file, `bitrate` (kbit/s), `quality`, `compression` (FLAC only, 0 to 12) and `downmix`
+/// (`true` for stereo) are the [EncodeOptions], `count`, `duration`, `fade` and `crossfade` (ms)
+/// are the [LoopOptions], and the `compression` of `tex_to_png` is one of `fast`, `default` or
+/// `best`, see [PngOptions].
+pub const TRANSFORMERS: &[TransformerInfo] = &[
+    TransformerInfo::new(
+        "scd_to_flac",
+        &["scd"],
+        Some("flac"),
+        "Read the audio of an `.scd` file as FLAC",
+        |params| Ok(TransformerImpl::ScdToFlac(params.scd_options()?)),
+    )
+    .with_options(SCD_OPTIONS),
+    TransformerInfo::new(
+        "scd_to_ogg",
+        &["scd"],
+        Some("ogg"),
+        "Read the audio of an `.scd` file as OGG",
+        |params| Ok(TransformerImpl::ScdToOgg(params.scd_options()?)),
+    )
+    .with_options(SCD_OPTIONS),
+    TransformerInfo::new(
+        "scd_to_wav",
+        &["scd"],
+        Some("wav"),
+        "Read the audio of an `.scd` file as WAV",
+        |params| Ok(TransformerImpl::ScdToWav(params.scd_options()?)),
+    )
+    .with_options(SCD_OPTIONS),
+    TransformerInfo::new(
+        "scd_to_mp3",
+        &["scd"],
+        Some("mp3"),
+        "Read the audio of an `.scd` file as MP3",
+        |params| Ok(TransformerImpl::ScdToMp3(params.scd_options()?)),
+    )
+    .with_options(SCD_OPTIONS),
+    TransformerInfo::new(
+        "scd_to_aac",
+        &["scd"],
+        Some("m4a"),
+        "Read the audio of an `.scd` file as AAC",
+        |params| Ok(TransformerImpl::ScdToAac(params.scd_options()?)),
+    )
+    .with_options(SCD_OPTIONS),
+    TransformerInfo::new(
+        "loop_flac",
+        &["flac"],
+        Some("flac"),
+        "Repeat the loop of FLAC audio, then fade out",
+        |params| Ok(TransformerImpl::LoopFlac(params.loop_options()?)),
+    )
+    .with_options(LOOP_OPTIONS),
+    TransformerInfo::new(
+        "loop_ogg",
+        &["ogg"],
+        Some("ogg"),
+        "Repeat the loop of OGG audio, then fade out",
+        |params| Ok(TransformerImpl::LoopOgg(params.loop_options()?)),
+    )
+    .with_options(LOOP_OPTIONS),
+    TransformerInfo::new(
+        "loop",
+        &["flac", "ogg"],
+        None,
+        "`loop_flac` or `loop_ogg`, picked by `format`",
+        |params| match params.take::<AudioFormat>("format")? {
+            Some(AudioFormat::Flac) => Ok(TransformerImpl::LoopFlac(params.loop_options()?)),
+            Some(AudioFormat::Ogg) => Ok(TransformerImpl::LoopOgg(params.loop_options()?)),
+            _ => Err(params.error("needs format=flac or format=ogg")),
+        },
+    )
+    .with_options(&["format", "count", "duration", "fade", "crossfade"])
+    .with_required_options(&["format"]),
+    TransformerInfo::new(
+        "change_format",
+        AUDIO_EXTENSIONS,
+        None,
+        "Convert audio from `from` (default `flac`) to `to`",
+        |params| {
+            Ok(TransformerImpl::ChangeFormat {
+                from: params.take("from")?.unwrap_or(AudioFormat::Flac),
+                to: params
+                    .take("to")?
+                    .ok_or_else(|| params.error("needs to=<format>"))?,
+                encode: params.encode_options()?,
+            })
+        },
+    )
+    .with_options(&["from", "to", "bitrate", "quality", "compression", "downmix"])
+    .with_required_options(&["to"]),
+    TransformerInfo::new(
+        "flac_to_ogg",
+        &["flac"],
+        Some("ogg"),
+        "The same as `change_format:from=flac,to=ogg`",
+        |_| {
+            Ok(TransformerImpl::ChangeFormat {
+                from: AudioFormat::Flac,
+                to: AudioFormat::Ogg,
+                encode: EncodeOptions::default(),
+            })
+        },
+    ),
+    TransformerInfo::new(
+        "tex_to_png",
+        &["tex"],
+        Some("png"),
+        "Convert a texture to PNG",
+        |params| {
+            Ok(TransformerImpl::TexToPng(PngOptions {
+                compression: params.take("compression")?,
+            }))
+        },
+    )
+    .with_options(&["compression"]),
+    TransformerInfo::new(
+        "avfx_to_json",
+        &["avfx"],
+        Some("json"),
+        "Describe a VFX file as JSON",
+        |_| Ok(TransformerImpl::AvfxToJson(Limits::default())),
+    ),
+];
+
+/// Transformers added with [register_transformer].
+static REGISTERED: RwLock<Vec<TransformerInfo>> = RwLock::new(Vec::new());
+
+/// Make the transformer described by [info] parse by name, like the built-in [TRANSFORMERS], for
+/// the rest of the process. Its [TransformerInfo::parse] usually builds a [CustomTransformer] with
+/// [TransformerImpl::custom].
+pub fn register_transformer(info: TransformerInfo) -> Result<(), TransformerParseError> {
+    let mut registered = REGISTERED.write();
+    if TRANSFORMERS
+        .iter()
+        .chain(registered.iter())
+        .any(|other| other.name == info.name)
+    {
+        return Err(TransformerParseError(format!(
+            "Transformer '{}' is already registered",
+            info.name
+        )));
+    }
+    registered.push(info);
+    Ok(())
+}
+
+/// Every transformer [TransformerImpl] can be parsed as, the built-in [TRANSFORMERS] followed by
+/// those added with [register_transformer].
+pub fn transformers() -> Vec<TransformerInfo> {
+    TRANSFORMERS
+        .iter()
+        .chain(REGISTERED.read().iter())
+        .copied()
+        .collect()
+}
+
+/// Get the [TransformerInfo] of the transformer called [name].
+pub fn transformer_info(name: &str) -> Option<TransformerInfo> {
+    TRANSFORMERS
+        .iter()
+        .find(|info| info.name == name)
+        .copied()
+        .or_else(|| {
+            REGISTERED
+                .read()
+                .iter()
+                .find(|info| info.name == name)
+                .copied()
+        })
+}
+
+/// The transformers that parse from their name alone, without options. Covers every input format
+/// the built-in transformers accept.
+pub fn plain_transformers() -> impl Iterator<Item = TransformerImpl> {
+    transformers()
+        .into_iter()
+        .filter(|info| info.required_options.is_empty())
+        .filter_map(|info| info.name.parse().ok())
+}
+
+#[derive(Error, Debug)]
+#[error("{0}")]
+pub struct TransformerParseError(String);
+
+impl FromStr for TransformerImpl {
+    type Err = TransformerParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        let Some(info) = transformer_info(name) else {
+            let names = transformers()
+                .iter()
+                .map(|info| info.name)
+                .collect::<Vec<_>>();
+            return Err(TransformerParseError(format!(
+                "Unknown transformer '{}', expected one of {}",
+                name,
+                names.join(", ")
+            )));
+        };
+        let mut params = TransformerParams::parse(name, params)?;
+        let transformer = (info.parse)(&mut params)?;
+        params.finish()?;
+        let encode = match transformer {
+            Self::ChangeFormat { encode, .. } => Some(encode),
+            _ => transformer.scd_options().map(|o| o.encode),
+        };
+        if let (Some(format), Some(encode)) = (transformer.output_format(), encode) {
+            encode
+                .validate(format)
+                .map_err(|e| TransformerParseError(format!("Transformer '{}' {}", name, e)))?;
+        }
+        Ok(transformer)
+    }
+}
+
+impl Display for TransformerImpl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut params = Vec::<(&str, String)>::new();
+        let push_encode = |params: &mut Vec<_>, encode: &EncodeOptions| {
+            if let Some(bitrate) = encode.bitrate {
+                params.push(("bitrate", bitrate.to_string()));
+            }
+            if let Some(quality) = encode.quality {
+                params.push(("quality", quality.to_string()));
+            }
+            if let Some(level) = encode.compression_level {
+                params.push(("compression", level.to_string()));
+            }
+            if encode.downmix {
+                params.push(("downmix", true.to_string()));
+            }
+        };
+        let name = match self {
+            Self::ScdToFlac(_)
+            | Self::ScdToOgg(_)
+            | Self::ScdToWav(_)
+            | Self::ScdToMp3(_)
+            | Self::ScdToAac(_) => {
+                let options = self.scd_options().unwrap();
+                if options.entry != ScdOptions::default().entry {
+                    params.push(("entry", options.entry.to_string()));
+                }
+                push_encode(&mut params, &options.encode);
+                match self {
+                    Self::ScdToFlac(_) => "scd_to_flac",
+                    Self::ScdToOgg(_) => "scd_to_ogg",
+                    Self::ScdToWav(_) => "scd_to_wav",
+                    Self::ScdToMp3(_) => "scd_to_mp3",
+                    _ => "scd_to_aac",
+                }
+            }
+            Self::LoopFlac(options) | Self::LoopOgg(options) => {
+                let defaults = LoopOptions::default();
+                if options.loop_count != defaults.loop_count {
+                    params.push(("count", options.loop_count.to_string()));
+                }
+                if let Some(duration) = options.target_duration {
+                    params.push(("duration", duration.to_string()));
+                }
+                if options.fade != defaults.fade {
+                    let fade = options.fade.map_or("none".to_string(), |s| s.to_string());
+                    params.push(("fade", fade));
+                }
+                if let Some(crossfade) = options.crossfade_ms {
+                    params.push(("crossfade", crossfade.to_string()));
+                }
+                if matches!(self, Self::LoopFlac(_)) {
+                    "loop_flac"
+                } else {
+                    "loop_ogg"
+                }
+            }
+            Self::ChangeFormat { from, to, encode } => {
+                params.push(("from", from.to_string()));
+                params.push(("to", to.to_string()));
+                push_encode(&mut params, encode);
+                "change_format"
+            }
+            Self::TexToPng(options) => {
+                if let Some(compression) = options.compression {
+                    params.push(("compression", compression.to_string()));
+                }
+                "tex_to_png"
+            }
+            Self::AvfxToJson(_) => "avfx_to_json",
+            Self::Custom(t) => return Display::fmt(t, f),
+        };
+        f.write_str(name)?;
+        for (i, (key, value)) in params.iter().enumerate() {
+            write!(f, "{}{}={}", if i == 0 { ':' } else { ',' }, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `key=value` options given after a transformer's name, taken as they're used so that
+/// leftover keys can be reported.
+pub struct TransformerParams<'a> {
+    name: &'a str,
+    params: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> TransformerParams<'a> {
+    fn parse(name: &'a str, params: &'a str) -> Result<Self, TransformerParseError> {
+        let mut this = Self {
+            name,
+            params: Vec::new(),
+        };
+        for param in params.split(',').filter(|p| !p.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| this.error(&format!("option '{}' has no value", param)))?;
+            this.params.push((key.trim(), value.trim()));
+        }
+        Ok(this)
+    }
+
+    /// An error about this transformer, e.g. `needs to=<format>`.
+    pub fn error(&self, message: &str) -> TransformerParseError {
+        TransformerParseError(format!("Transformer '{}' {}", self.name, message))
+    }
+
+    /// Take the option [key] if it was given, parsed with [parse].
+    pub fn take_with<T, E: Display>(
+        &mut self,
+        key: &str,
+        parse: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Result<Option<T>, TransformerParseError> {
+        let Some(i) = self.params.iter().position(|(k, _)| *k == key) else {
+            return Ok(None);
+        };
+        let (_, value) = self.params.remove(i);
+        parse(value)
+            .map(Some)
+            .map_err(|e| self.error(&format!("has invalid {}={}: {}", key, value, e)))
+    }
+
+    /// Take the option [key] if it was given.
+    pub fn take<T: FromStr>(&mut self, key: &str) -> Result<Option<T>, TransformerParseError>
+    where
+        T::Err: Display,
+    {
+        self.take_with(key, T::from_str)
+    }
+
+    fn scd_options(&mut self) -> Result<ScdOptions, TransformerParseError> {
+        let defaults = ScdOptions::default();
+        Ok(ScdOptions {
+            entry: self.take("entry")?.unwrap_or(defaults.entry),
+            encode: self.encode_options()?,
+            ..defaults
+        })
+    }
+
+    fn encode_options(&mut self) -> Result<EncodeOptions, TransformerParseError> {
+        Ok(EncodeOptions {
+            bitrate: self.take("bitrate")?,
+            quality: self.take("quality")?,
+            compression_level: self.take("compression")?,
+            downmix: self.take("downmix")?.unwrap_or(false),
+        })
+    }
+
+    fn loop_options(&mut self) -> Result<LoopOptions, TransformerParseError> {
+        let defaults = LoopOptions::default();
+        Ok(LoopOptions {
+            loop_count: self.take("count")?.unwrap_or(defaults.loop_count),
+            target_duration: self.take("duration")?.or(defaults.target_duration),
+            fade: self
+                .take_with("fade", LoopOptions::parse_fade)?
+                .unwrap_or(defaults.fade),
+            crossfade_ms: self
+                .take_with("crossfade", LoopOptions::parse_crossfade)?
+                .unwrap_or(defaults.crossfade_ms),
+        })
+    }
+
+    fn finish(self) -> Result<(), TransformerParseError> {
+        match self.params.first() {
+            Some((key, _)) => Err(self.error(&format!("has no option '{}'", key))),
+            None => Ok(()),
+        }
+    }
 }
 
-impl<R: Read + Send> Transformer<R> for TransformerImpl {
+/// The magic bytes that start files with [extension], for the formats transformers produce.
+pub fn extension_magic(extension: &str) -> Option<&'static [u8]> {
+    match extension {
+        "flac" => Some(b"fLaC"),
+        "ogg" => Some(b"OggS"),
+        "wav" => Some(b"RIFF"),
+        // FFMPEG starts MP3 files with an ID3v2 tag.
+        "mp3" => Some(b"ID3"),
+        "png" => Some(b"\x89PNG\r\n\x1a\n"),
+        _ => None,
+    }
+}
+
+impl<R: Read + Seek + Send + 'static> Transformer<R> for TransformerImpl {
     type ForFile = Box<dyn TransformerForFile<R>>;
 
     fn maybe_for(&self, file: SqPathBuf) -> Option<Self::ForFile> {
         match self {
-            Self::ScdToFlac => <ScdTf as Transformer<R>>::maybe_for(
+            Self::ScdToFlac(options) => <ScdTf as Transformer<R>>::maybe_for(
                 &ScdTf {
-                    audio_transform: ScdAudioTransform::Flac,
+                    audio_transform: AudioFormat::Flac,
+                    options: *options,
                 },
                 file,
             )
             .map(|e| Box::new(e) as Self::ForFile),
-            Self::LoopFlac => <LoopFile as Transformer<R>>::maybe_for(
+            Self::LoopFlac(options) => <LoopFile as Transformer<R>>::maybe_for(
                 &LoopFile {
                     extension: "flac".to_string(),
                     ffmpeg_format: "flac".to_string(),
+                    options: *options,
                 },
                 file,
             )
             .map(|e| Box::new(e) as Self::ForFile),
-            Self::ScdToOgg => <ScdTf as Transformer<R>>::maybe_for(
+            Self::ScdToOgg(options) => <ScdTf as Transformer<R>>::maybe_for(
                 &ScdTf {
-                    audio_transform: ScdAudioTransform::Ogg,
+                    audio_transform: AudioFormat::Ogg,
+                    options: *options,
                 },
                 file,
             )
             .map(|e| Box::new(e) as Self::ForFile),
-            Self::LoopOgg => <LoopFile as Transformer<R>>::maybe_for(
+            Self::LoopOgg(options) => <LoopFile as Transformer<R>>::maybe_for(
                 &LoopFile {
                     extension: "ogg".to_string(),
                     ffmpeg_format: "ogg".to_string(),
+                    options: *options,
                 },
                 file,
             )
             .map(|e| Box::new(e) as Self::ForFile),
-            Self::FlacToOgg => <ChangeFile as Transformer<R>>::maybe_for(
+            Self::ChangeFormat { from, to, encode } => <ChangeFile as Transformer<R>>::maybe_for(
                 &ChangeFile {
-                    from_extension: "flac".to_string(),
-                    to_extension: "ogg".to_string(),
-                    to_ffmpeg_format: "ogg".to_string(),
+                    from: *from,
+                    to: *to,
+                    encode: *encode,
+                },
+                file,
+            )
+            .map(|e| Box::new(e) as Self::ForFile),
+            Self::ScdToWav(options) => <ScdTf as Transformer<R>>::maybe_for(
+                &ScdTf {
+                    audio_transform: AudioFormat::Wav,
+                    options: *options,
                 },
                 file,
             )
             .map(|e| Box::new(e) as Self::ForFile),
-            Self::ScdToWav => <ScdTf as Transformer<R>>::maybe_for(
+            Self::ScdToMp3(options) => <ScdTf as Transformer<R>>::maybe_for(
                 &ScdTf {
-                    audio_transform: ScdAudioTransform::Wav,
+                    audio_transform: AudioFormat::Mp3,
+                    options: *options,
                 },
                 file,
             )
             .map(|e| Box::new(e) as Self::ForFile),
+            Self::ScdToAac(options) => <ScdTf as Transformer<R>>::maybe_for(
+                &ScdTf {
+                    audio_transform: AudioFormat::Aac,
+                    options: *options,
+                },
+                file,
+            )
+            .map(|e| Box::new(e) as Self::ForFile),
+            Self::TexToPng(options) => {
+                <TexTf as Transformer<R>>::maybe_for(&TexTf { options: *options }, file)
+                    .map(|e| Box::new(e) as Self::ForFile)
+            }
+            Self::AvfxToJson(limits) => {
+                <AvfxTf as Transformer<R>>::maybe_for(&AvfxTf { limits: *limits }, file)
+                    .map(|e| Box::new(e) as Self::ForFile)
+            }
+            Self::Custom(t) => t
+                .maybe_for(file)
+                .map(|e| Box::new(CustomForFile(e)) as Self::ForFile),
         }
     }
 }
 
+/// Runs a [CustomTransformer] on any input, by boxing it.
+struct CustomForFile(Box<dyn TransformerForFile<Box<dyn TransformStream>>>);
+
+impl<R: Read + Seek + Send + 'static> TransformerForFile<R> for CustomForFile {
+    fn renamed_file(&self) -> Cow<'_, SqPath> {
+        self.0.renamed_file()
+    }
+
+    fn transform(&self, content: R) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        self.0.transform(Box::new(content))
+    }
+}
+
 impl<R: Read> TransformerForFile<R> for Box<dyn TransformerForFile<R>> {
-    fn renamed_file(&self) -> Cow<SqPath> {
+    fn renamed_file(&self) -> Cow<'_, SqPath> {
         Box::as_ref(self).renamed_file()
     }
 
-    fn transform(&self, content: R) -> Result<Box<dyn Read + Send>, LastLegendError> {
+    fn transform(&self, content: R) -> Result<Box<dyn TransformStream>, LastLegendError> {
         Box::as_ref(self).transform(content)
     }
 }
+
+#[cfg(test)]
+mod transformers_tests {
+    use super::*;
+
+    #[test]
+    fn plain_names_use_defaults() {
+        assert_eq!(
+            "scd_to_flac".parse::<TransformerImpl>().unwrap(),
+            TransformerImpl::ScdToFlac(ScdOptions::default())
+        );
+        assert_eq!(
+            "flac_to_ogg".parse::<TransformerImpl>().unwrap(),
+            TransformerImpl::ChangeFormat {
+                from: AudioFormat::Flac,
+                to: AudioFormat::Ogg,
+                encode: EncodeOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn plain_transformers_parse() {
+        for transformer in plain_transformers() {
+            assert_eq!(transformer.to_string().parse().ok(), Some(transformer));
+        }
+    }
+
+    #[test]
+    fn registry_describes_transformers() {
+        for info in TRANSFORMERS {
+            let mut s = info.name.to_string();
+            for (i, key) in info.required_options.iter().enumerate() {
+                let value = if *key == "format" { "ogg" } else { "wav" };
+                s.push_str(&format!(
+                    "{}{}={}",
+                    if i == 0 { ':' } else { ',' },
+                    key,
+                    value
+                ));
+            }
+            let transformer = s.parse::<TransformerImpl>().unwrap();
+            assert!(
+                info.input_extensions
+                    .contains(&transformer.input_extension()),
+                "{}",
+                s
+            );
+            if let Some(extension) = info.output_extension {
+                assert_eq!(transformer.output_extension(), extension, "{}", s);
+            }
+            for key in info.required_options {
+                assert!(info.options.contains(key), "{}", s);
+            }
+        }
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        assert_eq!(
+            "loop:format=ogg,count=3,fade=none,crossfade=20"
+                .parse::<TransformerImpl>()
+                .unwrap(),
+            TransformerImpl::LoopOgg(LoopOptions {
+                loop_count: 3,
+                fade: None,
+                crossfade_ms: Some(20),
+                ..LoopOptions::default()
+            })
+        );
+        assert_eq!(
+            "change_format:to=wav".parse::<TransformerImpl>().unwrap(),
+            TransformerImpl::ChangeFormat {
+                from: AudioFormat::Flac,
+                to: AudioFormat::Wav,
+                encode: EncodeOptions::default(),
+            }
+        );
+        assert_eq!(
+            "scd_to_ogg:entry=2".parse::<TransformerImpl>().unwrap(),
+            TransformerImpl::ScdToOgg(ScdOptions {
+                entry: 2,
+                ..ScdOptions::default()
+            })
+        );
+        assert_eq!(
+            "scd_to_mp3:bitrate=192,quality=2"
+                .parse::<TransformerImpl>()
+                .unwrap(),
+            TransformerImpl::ScdToMp3(ScdOptions {
+                encode: EncodeOptions {
+                    bitrate: Some(192),
+                    quality: Some(2.0),
+                    ..EncodeOptions::default()
+                },
+                ..ScdOptions::default()
+            })
+        );
+        assert_eq!(
+            "tex_to_png:compression=best"
+                .parse::<TransformerImpl>()
+                .unwrap(),
+            TransformerImpl::TexToPng(PngOptions {
+                compression: Some(PngCompression::Best),
+            })
+        );
+    }
+
+    #[test]
+    fn downmix_applies_to_encoding_transformers() {
+        let downmixed = ["scd_to_flac", "loop_flac", "change_format:to=ogg"]
+            .map(|t| t.parse::<TransformerImpl>().unwrap().with_downmix());
+        assert!(downmixed[0].scd_options().unwrap().encode.downmix);
+        assert_eq!(
+            downmixed[1],
+            "loop_flac".parse::<TransformerImpl>().unwrap()
+        );
+        assert_eq!(
+            downmixed[2].to_string(),
+            "change_format:from=flac,to=ogg,downmix=true"
+        );
+    }
+
+    #[test]
+    fn limits_apply_to_scd_transformers() {
+        let limits = Limits {
+            max_vorbis_header_size: 16,
+            ..Limits::default()
+        };
+        let limited = ["scd_to_ogg:entry=1", "loop_ogg"]
+            .map(|t| t.parse::<TransformerImpl>().unwrap().with_limits(limits));
+        let options = limited[0].scd_options().unwrap();
+        assert_eq!(options.limits, limits);
+        assert_eq!(options.entry, 1);
+        assert_eq!(limited[0].to_string(), "scd_to_ogg:entry=1");
+        assert_eq!(limited[1], "loop_ogg".parse::<TransformerImpl>().unwrap());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in [
+            "scd_to_flac",
+            "scd_to_flac:entry=1,compression=8",
+            "scd_to_ogg:quality=6.5",
+            "scd_to_wav:downmix=true",
+            "loop_ogg:count=3,fade=none,crossfade=20",
+            "loop_flac:duration=120.5,fade=2",
+            "change_format:from=flac,to=mp3,bitrate=320",
+            "tex_to_png:compression=fast",
+            "avfx_to_json",
+        ] {
+            let transformer = s.parse::<TransformerImpl>().unwrap();
+            assert_eq!(transformer.to_string(), s);
+            assert_eq!(
+                transformer.to_string().parse::<TransformerImpl>().unwrap(),
+                transformer
+            );
+        }
+    }
+
+    #[test]
+    fn transformers_run_with_the_fake_backend() {
+        use crate::ffmpeg::backend::{audio_backend, with_audio_backend, FakeAudioBackend};
+        use std::io::Cursor;
+        use std::sync::Arc;
+
+        let transformer = "change_format:from=wav,to=flac,compression=5"
+            .parse::<TransformerImpl>()
+            .unwrap();
+        let tf = <TransformerImpl as Transformer<Cursor<&[u8]>>>::maybe_for(
+            &transformer,
+            SqPathBuf::new("music/a.wav"),
+        )
+        .unwrap();
+        assert_eq!(tf.renamed_file().as_str(), "music/a.flac");
+        let mut output = Vec::new();
+        with_audio_backend(Arc::new(FakeAudioBackend), || {
+            tf.transform(Cursor::new(b"RIFF".as_slice()))
+                .unwrap()
+                .read_to_end(&mut output)
+                .unwrap();
+        });
+        // Other tests on this thread get the usual backend back.
+        assert_eq!(format!("{:?}", audio_backend()), "FfmpegBackend");
+        assert!(output.starts_with(b"fLaCLLDOB-FAKE encode flac"));
+        assert!(output.ends_with(b"\nRIFF"));
+    }
+
+    #[derive(Debug)]
+    struct Shout {
+        marks: usize,
+    }
+
+    impl Display for Shout {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "txt_to_shout:marks={}", self.marks)
+        }
+    }
+
+    impl CustomTransformer for Shout {
+        fn input_extension(&self) -> &'static str {
+            "txt"
+        }
+
+        fn output_extension(&self) -> &'static str {
+            "shout"
+        }
+
+        fn maybe_for(
+            &self,
+            file: SqPathBuf,
+        ) -> Option<Box<dyn TransformerForFile<Box<dyn TransformStream>>>> {
+            file.as_str().ends_with(".txt").then(|| {
+                Box::new(ShoutForFile {
+                    file: SqPathBuf::new(&file.as_str().replace(".txt", ".shout")),
+                    marks: self.marks,
+                }) as Box<dyn TransformerForFile<_>>
+            })
+        }
+    }
+
+    struct ShoutForFile {
+        file: SqPathBuf,
+        marks: usize,
+    }
+
+    impl TransformerForFile<Box<dyn TransformStream>> for ShoutForFile {
+        fn renamed_file(&self) -> Cow<'_, SqPath> {
+            Cow::Borrowed(&self.file)
+        }
+
+        fn transform(
+            &self,
+            mut content: Box<dyn TransformStream>,
+        ) -> Result<Box<dyn TransformStream>, LastLegendError> {
+            let mut text = String::new();
+            content.read_to_string(&mut text).unwrap();
+            let shout = text.to_uppercase() + &"!".repeat(self.marks);
+            Ok(Box::new(std::io::Cursor::new(shout.into_bytes())))
+        }
+    }
+
+    #[test]
+    fn registered_transformers_parse() {
+        let info = TransformerInfo::new(
+            "txt_to_shout",
+            &["txt"],
+            Some("shout"),
+            "Shout text",
+            |params| {
+                Ok(TransformerImpl::custom(Shout {
+                    marks: params.take("marks")?.unwrap_or(1),
+                }))
+            },
+        )
+        .with_options(&["marks"]);
+        register_transformer(info).unwrap();
+        assert!(register_transformer(info).is_err());
+        assert!(register_transformer(TRANSFORMERS[0]).is_err());
+        assert!(transformers()
+            .iter()
+            .any(|info| info.name == "txt_to_shout"));
+
+        let transformer = "txt_to_shout:marks=3".parse::<TransformerImpl>().unwrap();
+        assert_eq!(transformer.to_string(), "txt_to_shout:marks=3");
+        assert_eq!(
+            transformer,
+            "txt_to_shout:marks=3".parse::<TransformerImpl>().unwrap()
+        );
+        assert_ne!(
+            transformer,
+            "txt_to_shout".parse::<TransformerImpl>().unwrap()
+        );
+        assert_eq!(transformer.output_extension(), "shout");
+        assert!("txt_to_shout:volume=11".parse::<TransformerImpl>().is_err());
+
+        let tf = <TransformerImpl as Transformer<std::io::Cursor<&[u8]>>>::maybe_for(
+            &transformer,
+            SqPathBuf::new("ui/a.txt"),
+        )
+        .unwrap();
+        assert_eq!(tf.renamed_file().as_str(), "ui/a.shout");
+        let mut output = String::new();
+        tf.transform(std::io::Cursor::new(b"hello".as_slice()))
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, "HELLO!!!");
+    }
+
+    #[test]
+    fn bad_options_are_rejected() {
+        for bad in [
+            "unknown",
+            "loop",
+            "loop:format=wav",
+            "loop_flac:count=many",
+            "loop_flac:speed=2",
+            "tex_to_png:entry=1",
+            "change_format",
+            "scd_to_flac:entry",
+            "scd_to_flac:compression=13",
+            "scd_to_flac:quality=5",
+            "scd_to_wav:bitrate=128",
+            "scd_to_ogg:quality=11",
+            "scd_to_mp3:compression=5",
+            "change_format:to=flac,bitrate=320",
+            "tex_to_png:compression=9",
+        ] {
+            assert!(bad.parse::<TransformerImpl>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Working out which transformers turn a file into a wanted format, so they don't have to be
+//! listed in order by hand.
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+
+use crate::error::LastLegendError;
+use crate::transformers::{
+    plain_transformers, AudioFormat, EncodeOptions, TransformerImpl, TransformerParseError,
+};
+
+const LOOPED_SUFFIX: &str = "-looped";
+
+/// The format a file should end up in. Parsed from an extension, e.g. `flac` or `png`, or an
+/// audio format followed by `-looped` to repeat the audio's loop, e.g. `flac-looped`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetFormat {
+    pub extension: String,
+    pub looped: bool,
+}
+
+impl FromStr for TargetFormat {
+    type Err = TransformerParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, looped) = match s.strip_suffix(LOOPED_SUFFIX) {
+            Some(format) => (format, true),
+            None => (s, false),
+        };
+        // Audio formats can be given by name, e.g. `aac` for `.m4a` files.
+        let extension = match format.parse::<AudioFormat>() {
+            Ok(audio) => audio.extension_str().to_string(),
+            Err(_) if looped => {
+                return Err(TransformerParseError(format!(
+                    "Only audio can be looped, not '{}'",
+                    format
+                )))
+            }
+            Err(_) => format.to_string(),
+        };
+        Ok(Self { extension, looped })
+    }
+}
+
+impl Display for TargetFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.extension)?;
+        if self.looped {
+            f.write_str(LOOPED_SUFFIX)?;
+        }
+        Ok(())
+    }
+}
+
+/// The transformers a plan can be made of: those that need no options, and converting between
+/// each pair of audio formats.
+fn steps() -> Vec<TransformerImpl> {
+    let mut steps = plain_transformers().collect::<Vec<_>>();
+    for from in AudioFormat::iter() {
+        for to in AudioFormat::iter().filter(|&to| to != from) {
+            steps.push(TransformerImpl::ChangeFormat {
+                from,
+                to,
+                encode: EncodeOptions::default(),
+            });
+        }
+    }
+    steps
+}
+
+/// Find the shortest chain of transformers that turns files with [input_extension] into
+/// [target], in the order to run them. The chain is empty if the files are in that format
+/// already. Audio is looped at most once, and only if [target] asks for it.
+pub fn plan_transformers(
+    input_extension: &str,
+    target: &TargetFormat,
+) -> Result<Vec<TransformerImpl>, LastLegendError> {
+    type State = (String, bool);
+    let steps = steps();
+    let start: State = (input_extension.to_string(), false);
+    let goal: State = (target.extension.clone(), target.looped);
+    // How each state was first reached, to walk the chain back from the goal.
+    let mut reached_by = HashMap::<State, Option<(State, TransformerImpl)>>::new();
+    reached_by.insert(start.clone(), None);
+    let mut queue = VecDeque::from([start]);
+    while let Some(state) = queue.pop_front() {
+        if state == goal {
+            let mut chain = Vec::new();
+            let mut state = state;
+            while let Some((previous, step)) = reached_by[&state].clone() {
+                chain.push(step);
+                state = previous;
+            }
+            chain.reverse();
+            return Ok(chain);
+        }
+        let (extension, looped) = &state;
+        for step in &steps {
+            if step.input_extension() != extension.as_str()
+                || (step.is_loop() && (*looped || !target.looped))
+            {
+                continue;
+            }
+            let next = (
+                step.output_extension().to_string(),
+                *looped || step.is_loop(),
+            );
+            if !reached_by.contains_key(&next) {
+                reached_by.insert(next.clone(), Some((state.clone(), *step)));
+                queue.push_back(next);
+            }
+        }
+    }
+    Err(LastLegendError::Custom(format!(
+        "No transformers turn .{} files into {}",
+        input_extension, target
+    )))
+}
+
+/// The [transformers] that won't run on files with [input_extension], because no earlier
+/// transformer in the chain produces the format they take.
+pub fn unused_transformers(
+    input_extension: &str,
+    transformers: &[TransformerImpl],
+) -> Vec<TransformerImpl> {
+    let mut extension = input_extension;
+    let mut unused = Vec::new();
+    for t in transformers {
+        if t.input_extension() == extension {
+            extension = t.output_extension();
+        } else {
+            unused.push(*t);
+        }
+    }
+    unused
+}
+
+#[cfg(test)]
+mod plan_tests {
+    use super::*;
+    use crate::transformers::{LoopOptions, PngOptions, ScdOptions};
+
+    fn plan(input_extension: &str, target: &str) -> Result<Vec<TransformerImpl>, LastLegendError> {
+        plan_transformers(input_extension, &target.parse().unwrap())
+    }
+
+    #[test]
+    fn plans_the_shortest_chain() {
+        assert_eq!(
+            plan("scd", "flac-looped").unwrap(),
+            vec![
+                TransformerImpl::ScdToFlac(ScdOptions::default()),
+                TransformerImpl::LoopFlac(LoopOptions::default()),
+            ]
+        );
+        assert_eq!(
+            plan("scd", "aac").unwrap(),
+            vec![TransformerImpl::ScdToAac(ScdOptions::default())]
+        );
+        assert_eq!(
+            plan("tex", "png").unwrap(),
+            vec![TransformerImpl::TexToPng(PngOptions::default())]
+        );
+        assert!(plan("flac", "flac").unwrap().is_empty());
+    }
+
+    #[test]
+    fn loops_only_when_asked() {
+        let chain = plan("wav", "mp3-looped").unwrap();
+        assert_eq!(chain.iter().filter(|t| t.is_loop()).count(), 1);
+        assert_eq!(chain.last().unwrap().output_extension(), "mp3");
+        assert!(!plan("wav", "mp3").unwrap().iter().any(|t| t.is_loop()));
+    }
+
+    #[test]
+    fn missing_paths_are_errors() {
+        assert!(plan("tex", "flac").is_err());
+        assert!(plan("scd", "png").is_err());
+        assert!("png-looped".parse::<TargetFormat>().is_err());
+    }
+
+    #[test]
+    fn finds_unused_transformers() {
+        let transformers =
+            ["scd_to_ogg", "loop_flac", "loop_ogg"].map(|t| t.parse::<TransformerImpl>().unwrap());
+        assert_eq!(
+            unused_transformers("scd", &transformers),
+            vec![transformers[1]]
+        );
+    }
+}
@@ -1,48 +1,135 @@
-#![allow(clippy::unused_unit)]
 use crate::error::LastLegendError;
-use crate::ffmpeg::format_rewrite;
-use crate::io_tricks::ReadMixer;
+use crate::ffmpeg::backend::audio_backend;
+use crate::ffmpeg::scratch::Spool;
+use crate::limits::Limits;
+use crate::scd::{is_scd, Codec, ScdFile};
 use crate::sqpath::{SqPath, SqPathBuf};
-use crate::transformers::{Transformer, TransformerForFile};
-use crate::xor::XorRead;
-use binrw::io::TakeSeekExt;
-use binrw::{binread, binrw, BinReaderExt, BinResult, BinWriterExt};
+use crate::transformers::{TransformStream, Transformer, TransformerForFile};
+use crate::warning::{Warning, WarningSink};
 use std::borrow::Cow;
 use std::fmt::Debug;
-use std::io::{Cursor, Read, SeekFrom};
+use std::io::{Cursor, Read, Seek};
 use std::path::Path;
+use strum::{Display, EnumIter, EnumString};
 
-/// Known transformations for the audio from `.scd` files.
-#[derive(Debug, Clone, Copy)]
-pub enum ScdAudioTransform {
+/// Audio formats transformers can produce, such as from the audio in `.scd` files.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString, EnumIter, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum AudioFormat {
     Wav,
     Ogg,
     Flac,
+    Mp3,
+    /// AAC in an MPEG-4 container, i.e. `.m4a`.
+    Aac,
 }
 
-impl ScdAudioTransform {
+impl AudioFormat {
     pub fn extension_str(&self) -> &'static str {
         match self {
             Self::Wav => "wav",
             Self::Ogg => "ogg",
             Self::Flac => "flac",
+            Self::Mp3 => "mp3",
+            Self::Aac => "m4a",
+        }
+    }
+
+    /// The FFMPEG encoder used to write this format.
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            Self::Wav => "pcm_s16le",
+            Self::Ogg => "libvorbis",
+            Self::Flac => "flac",
+            Self::Mp3 => "libmp3lame",
+            Self::Aac => "aac",
+        }
+    }
+
+    /// The FFMPEG muxer used to write this format.
+    pub fn ffmpeg_format(&self) -> &'static str {
+        match self {
+            Self::Aac => "ipod",
+            other => other.extension_str(),
+        }
+    }
+}
+
+/// Options for encoding audio. Unset options use the encoder's defaults.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EncodeOptions {
+    /// The bitrate in kbit/s.
+    pub bitrate: Option<u32>,
+    /// The encoder's variable bitrate quality, e.g. 0 to 9 for MP3 where lower is better, or -1
+    /// to 10 for OGG where higher is better.
+    pub quality: Option<f32>,
+    /// The FLAC compression level, 0 to 12. Higher is smaller but slower, the audio is the same.
+    pub compression_level: Option<u8>,
+    /// Mix audio with more than two channels, e.g. 5.1 surround, down to stereo. Gains are
+    /// scaled so the mix doesn't clip.
+    pub downmix: bool,
+}
+
+impl EncodeOptions {
+    /// Check that these options apply to [format] and are in the range its encoder accepts.
+    pub fn validate(&self, format: AudioFormat) -> Result<(), String> {
+        if self.bitrate.is_some() && matches!(format, AudioFormat::Wav | AudioFormat::Flac) {
+            return Err(format!("bitrate doesn't apply to {:?}", format));
+        }
+        if let Some(quality) = self.quality {
+            let range = match format {
+                AudioFormat::Ogg => -1.0..=10.0,
+                AudioFormat::Mp3 => 0.0..=9.0,
+                AudioFormat::Aac => 0.1..=2.0,
+                AudioFormat::Wav | AudioFormat::Flac => {
+                    return Err(format!("quality doesn't apply to {:?}", format))
+                }
+            };
+            if !range.contains(&quality) {
+                return Err(format!(
+                    "quality for {:?} must be {} to {}",
+                    format,
+                    range.start(),
+                    range.end()
+                ));
+            }
+        }
+        match self.compression_level {
+            Some(_) if format != AudioFormat::Flac => {
+                Err(format!("compression doesn't apply to {:?}", format))
+            }
+            Some(level) if level > 12 => Err("compression for Flac must be 0 to 12".to_string()),
+            _ => Ok(()),
         }
     }
 }
 
+/// Options for reading `.scd` files.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ScdOptions {
+    /// Which sound entry to extract, most files only have one.
+    pub entry: u16,
+    /// How to encode the output. The audio is copied as-is where possible if these are unset.
+    pub encode: EncodeOptions,
+    /// Caps on the headers read from the file, the repository's when it's read from one.
+    pub limits: Limits,
+}
+
 /// Extract an audio file from the `.scd` FFXIV uses.
 #[derive(Debug)]
 pub struct ScdTf {
-    pub(crate) audio_transform: ScdAudioTransform,
+    pub(crate) audio_transform: AudioFormat,
+    pub(crate) options: ScdOptions,
 }
 
-impl<R: Read> Transformer<R> for ScdTf {
+impl<R: Read + Seek> Transformer<R> for ScdTf {
     type ForFile = ScdTfForFile;
 
     fn maybe_for(&self, file: SqPathBuf) -> Option<Self::ForFile> {
         file.as_str().ends_with(".scd").then_some(ScdTfForFile {
             file,
             audio_transform: self.audio_transform,
+            options: self.options,
         })
     }
 }
@@ -50,11 +137,12 @@ impl<R: Read> Transformer<R> for ScdTf {
 #[derive(Debug)]
 pub struct ScdTfForFile {
     file: SqPathBuf,
-    audio_transform: ScdAudioTransform,
+    audio_transform: AudioFormat,
+    options: ScdOptions,
 }
 
-impl<R: Read> TransformerForFile<R> for ScdTfForFile {
-    fn renamed_file(&self) -> Cow<SqPath> {
+impl<R: Read + Seek> TransformerForFile<R> for ScdTfForFile {
+    fn renamed_file(&self) -> Cow<'_, SqPath> {
         Cow::Owned(SqPathBuf::new(
             Path::new(self.file.as_str())
                 .with_extension(self.audio_transform.extension_str())
@@ -64,261 +152,173 @@ impl<R: Read> TransformerForFile<R> for ScdTfForFile {
         ))
     }
 
-    fn transform(&self, mut content: R) -> Result<Box<dyn Read + Send>, LastLegendError> {
-        // Re-do the content as a seekable in-memory buffer.
-        let content = {
-            let mut capture = Vec::<u8>::new();
-            content
-                .read_to_end(&mut capture)
-                .map_err(|e| LastLegendError::Io("Couldn't cache content".into(), e))?;
-            drop(content);
-            Cursor::new(capture)
-        };
-        self.decode(content)
+    fn transform(&self, content: R) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        // The audio of the entry is copied out of the file, to decrypt it and find its loop.
+        let mut scd = ScdFile::parse_with_limits(content, &self.options.limits)?;
+        if scd.entries().len() > 1 {
+            log::info!(
+                "SCD {} has {} sound entries, reading entry {}",
+                self.file,
+                scd.entries().len(),
+                self.options.entry
+            );
+        }
+        self.decode_entry(&mut scd, self.options.entry)
     }
 }
 
-const XOR_TABLE: &[u8; 256] = &[
-    0x3A, 0x32, 0x32, 0x32, 0x03, 0x7E, 0x12, 0xF7, 0xB2, 0xE2, 0xA2, 0x67, 0x32, 0x32, 0x22, 0x32,
-    0x32, 0x52, 0x16, 0x1B, 0x3C, 0xA1, 0x54, 0x7B, 0x1B, 0x97, 0xA6, 0x93, 0x1A, 0x4B, 0xAA, 0xA6,
-    0x7A, 0x7B, 0x1B, 0x97, 0xA6, 0xF7, 0x02, 0xBB, 0xAA, 0xA6, 0xBB, 0xF7, 0x2A, 0x51, 0xBE, 0x03,
-    0xF4, 0x2A, 0x51, 0xBE, 0x03, 0xF4, 0x2A, 0x51, 0xBE, 0x12, 0x06, 0x56, 0x27, 0x32, 0x32, 0x36,
-    0x32, 0xB2, 0x1A, 0x3B, 0xBC, 0x91, 0xD4, 0x7B, 0x58, 0xFC, 0x0B, 0x55, 0x2A, 0x15, 0xBC, 0x40,
-    0x92, 0x0B, 0x5B, 0x7C, 0x0A, 0x95, 0x12, 0x35, 0xB8, 0x63, 0xD2, 0x0B, 0x3B, 0xF0, 0xC7, 0x14,
-    0x51, 0x5C, 0x94, 0x86, 0x94, 0x59, 0x5C, 0xFC, 0x1B, 0x17, 0x3A, 0x3F, 0x6B, 0x37, 0x32, 0x32,
-    0x30, 0x32, 0x72, 0x7A, 0x13, 0xB7, 0x26, 0x60, 0x7A, 0x13, 0xB7, 0x26, 0x50, 0xBA, 0x13, 0xB4,
-    0x2A, 0x50, 0xBA, 0x13, 0xB5, 0x2E, 0x40, 0xFA, 0x13, 0x95, 0xAE, 0x40, 0x38, 0x18, 0x9A, 0x92,
-    0xB0, 0x38, 0x00, 0xFA, 0x12, 0xB1, 0x7E, 0x00, 0xDB, 0x96, 0xA1, 0x7C, 0x08, 0xDB, 0x9A, 0x91,
-    0xBC, 0x08, 0xD8, 0x1A, 0x86, 0xE2, 0x70, 0x39, 0x1F, 0x86, 0xE0, 0x78, 0x7E, 0x03, 0xE7, 0x64,
-    0x51, 0x9C, 0x8F, 0x34, 0x6F, 0x4E, 0x41, 0xFC, 0x0B, 0xD5, 0xAE, 0x41, 0xFC, 0x0B, 0xD5, 0xAE,
-    0x41, 0xFC, 0x3B, 0x70, 0x71, 0x64, 0x33, 0x32, 0x12, 0x32, 0x32, 0x36, 0x70, 0x34, 0x2B, 0x56,
-    0x22, 0x70, 0x3A, 0x13, 0xB7, 0x26, 0x60, 0xBA, 0x1B, 0x94, 0xAA, 0x40, 0x38, 0x00, 0xFA, 0xB2,
-    0xE2, 0xA2, 0x67, 0x32, 0x32, 0x12, 0x32, 0xB2, 0x32, 0x32, 0x32, 0x32, 0x75, 0xA3, 0x26, 0x7B,
-    0x83, 0x26, 0xF9, 0x83, 0x2E, 0xFF, 0xE3, 0x16, 0x7D, 0xC0, 0x1E, 0x63, 0x21, 0x07, 0xE3, 0x01,
-];
-
 impl ScdTfForFile {
-    fn decode(
+    fn decode_entry<R: Read + Seek>(
         &self,
-        mut content: Cursor<Vec<u8>>,
-    ) -> Result<Box<dyn Read + Send>, LastLegendError> {
-        let scd: Scd = content
-            .read_le()
-            .map_err(|e| LastLegendError::BinRW("Couldn't read SCD".into(), e))?;
-        match scd.sound_data {
-            SoundData::Empty => Err(LastLegendError::Custom("Empty sound data".into())),
-            SoundData::OggData(ogg_seek_header) => {
-                let vorbis_header =
-                    if ogg_seek_header.encryption_type == EncryptionType::VorbisHeaderXor {
-                        ReadMixer::Wrapped(XorRead::new(
-                            Cursor::new(ogg_seek_header.vorbis_header),
-                            move |_| ogg_seek_header.xor_byte,
-                        ))
-                    } else {
-                        ReadMixer::Plain(Cursor::new(ogg_seek_header.vorbis_header))
-                    };
-                let base =
-                    vorbis_header.chain(content.take(scd.sound_entry_header.data_size.into()));
-                let mut ogg_reader =
-                    if ogg_seek_header.encryption_type == EncryptionType::InternalTableXor {
-                        let static_xor = (scd.sound_entry_header.data_size & 0x7F) as u8;
-                        let table_off = (scd.sound_entry_header.data_size & 0x3F) as u8;
-                        ReadMixer::Wrapped(XorRead::new(base, move |index| {
-                            XOR_TABLE[(usize::from(table_off) + index) & 0xFF] ^ static_xor
-                        }))
-                    } else {
-                        ReadMixer::Plain(base)
-                    };
-                match self.audio_transform {
-                    ScdAudioTransform::Wav => {
-                        let mut final_content = Vec::new();
-                        format_rewrite("flac", &mut ogg_reader, &mut final_content)?;
-                        Ok(Box::new(Cursor::new(final_content)))
-                    }
-                    ScdAudioTransform::Ogg => Ok(Box::new(ogg_reader)),
-                    ScdAudioTransform::Flac => {
-                        let mut final_content = Vec::new();
-                        format_rewrite("flac", &mut ogg_reader, &mut final_content)?;
-                        Ok(Box::new(Cursor::new(final_content)))
-                    }
-                }
+        scd: &mut ScdFile<R>,
+        entry: u16,
+    ) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        let audio = scd
+            .read_audio(entry)
+            .map_err(|e| e.add_context(format!("Couldn't read the audio of {}", self.file)))?;
+        let loop_tags = loop_tags(audio.loop_samples);
+        let mut content = Cursor::new(audio.data);
+        if audio.format == AudioFormat::Ogg {
+            if self.audio_transform == AudioFormat::Ogg
+                && self.options.encode == EncodeOptions::default()
+            {
+                return with_tags(AudioFormat::Ogg, content.into_inner(), &loop_tags);
             }
-            SoundData::MsAdpcmData(header) => {
-                let mut data = content.take_seek(scd.sound_entry_header.data_size.into());
-                let mut wav_file = Vec::new();
-                {
-                    // Write RIFF header
-                    wav_file.extend_from_slice(b"RIFF");
-                    // Reserve space for the size of the file
-                    wav_file.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
-                    wav_file.extend_from_slice(b"WAVE");
-                    // Write the fmt chunk
-                    wav_file.extend_from_slice(b"fmt ");
-                    let mut fmt_header = Vec::new();
-                    Cursor::new(&mut fmt_header)
-                        .write_le(&header)
-                        .expect("should be able to write header");
-                    wav_file.extend_from_slice(
-                        &u32::try_from(fmt_header.len())
-                            .expect("should fit in u32")
-                            .to_le_bytes(),
-                    );
-                    wav_file.extend_from_slice(&fmt_header);
-                    // Write the data chunk
-                    wav_file.extend_from_slice(b"data");
-                    wav_file.extend_from_slice(
-                        &u32::try_from(data.limit())
-                            .expect("should fit in u32")
-                            .to_le_bytes(),
-                    );
-                    data.read_to_end(&mut wav_file)
-                        .map_err(|e| LastLegendError::Io("Couldn't read data".into(), e))?;
-                    // Fill in the size of the file
-                    let file_size = u32::try_from(wav_file.len() - 8).expect("should fit in u32");
-                    wav_file[4..8].copy_from_slice(&file_size.to_le_bytes());
-                }
-                let mut wav_cursor = Cursor::new(wav_file);
-                match self.audio_transform {
-                    ScdAudioTransform::Wav => Ok(Box::new(wav_cursor)),
-                    ScdAudioTransform::Ogg => {
-                        let mut final_content = Vec::new();
-                        format_rewrite("ogg", &mut wav_cursor, &mut final_content)?;
-                        Ok(Box::new(Cursor::new(final_content)))
-                    }
-                    ScdAudioTransform::Flac => {
-                        let mut final_content = Vec::new();
-                        format_rewrite("flac", &mut wav_cursor, &mut final_content)?;
-                        Ok(Box::new(Cursor::new(final_content)))
-                    }
-                }
+            #[cfg(feature = "native-audio")]
+            if !audio_backend().available() && !self.options.encode.downmix {
+                return decode_ogg_natively(self.audio_transform, content);
             }
+        } else if self.audio_transform == audio.format && !self.options.encode.downmix {
+            return Ok(Box::new(content));
         }
+        let mut output = Spool::new();
+        audio_backend().encode_audio(
+            self.audio_transform,
+            &self.options.encode,
+            &loop_tags,
+            &mut content,
+            &mut output,
+        )?;
+        output.into_stream()
     }
 }
 
-#[binread]
-#[derive(Debug)]
-#[br(magic = b"SEDBSSCF")]
-struct Scd {
-    #[br(temp, assert(version == 3))]
-    version: u32,
-    #[br(temp, pad_before = 2)]
-    header_size: u16,
-    #[br(
-        temp,
-        seek_before = SeekFrom::Start(header_size.into()),
-        assert(offsets_header.sound_entries_size == 1, "Only one entry is supported currently.")
-    )]
-    offsets_header: ScdOffsetsHeader,
-    #[br(temp, seek_before = SeekFrom::Start(offsets_header.sound_entries_offset.into()))]
-    entry_table_offset: u32,
-    #[br(seek_before = SeekFrom::Start(entry_table_offset.into()))]
-    pub sound_entry_header: SoundEntryHeader,
-    #[br(args { data_type: sound_entry_header.data_type })]
-    pub sound_data: SoundData,
-}
-
-#[binread]
-#[derive(Debug)]
-struct ScdOffsetsHeader {
-    #[br(pad_before = 4)]
-    pub sound_entries_size: u16,
-    #[br(pad_before = 0x6)]
-    pub sound_entries_offset: u32,
-}
-
-const HAS_MARKER_CHUNK: u32 = 0x1;
+/// The sound entries of an `.scd` bank, see [explode_scd_bank], each index with its WAV file.
+pub type ScdBankWavs = Vec<(u16, Box<dyn TransformStream>)>;
 
-#[binread]
-#[derive(Debug)]
-struct SoundEntryHeader {
-    pub data_size: u32,
-    #[br(temp)]
-    _channels: u32,
-    #[br(temp)]
-    _frequency: u32,
-    pub data_type: DataType,
-    #[br(temp)]
-    _loop_start: u32,
-    #[br(temp)]
-    _loop_end: u32,
-    #[br(temp)]
-    _pre_marker_sub_info_size: u32,
-    #[br(temp)]
-    flags: u32,
-    #[br(temp, if(flags & HAS_MARKER_CHUNK != 0), parse_with = skip_markers)]
-    _markers: (),
-}
-
-#[binrw::parser(reader)]
-fn skip_markers() -> BinResult<()> {
-    let _id = reader.read_le::<u32>()?;
-    let size = reader.read_le::<u32>()?;
-
-    // Seek to the end of the marker chunk, including the two fields already read.
-    reader.seek(SeekFrom::Current(i64::from(size) - 8))?;
-
-    Ok(())
+/// Read every sound entry of [content], the `.scd` file [file], as a WAV file. Sound effect banks
+/// hold many short clips in one `.scd`, where reading a single `entry` would miss the rest.
+///
+/// Returns the index of each entry with its WAV file, skipping entries without audio, or None if
+/// [content] isn't an `.scd` file with more than one sound entry. Entries in unsupported codecs
+/// are skipped too, reported to [warnings]. Headers larger than [limits] allow are errors.
+pub fn explode_scd_bank(
+    file: &SqPath,
+    content: &[u8],
+    limits: &Limits,
+    warnings: &WarningSink,
+) -> Result<Option<ScdBankWavs>, LastLegendError> {
+    if !is_scd(content) {
+        return Ok(None);
+    }
+    let mut scd = ScdFile::parse_with_limits(Cursor::new(content), limits)?;
+    if scd.entries().len() < 2 {
+        return Ok(None);
+    }
+    let tf = ScdTfForFile {
+        file: file.to_owned(),
+        audio_transform: AudioFormat::Wav,
+        options: ScdOptions {
+            limits: *limits,
+            ..ScdOptions::default()
+        },
+    };
+    let mut wavs = Vec::with_capacity(scd.entries().len());
+    for index in 0..scd.entries().len() {
+        let index = u16::try_from(index).expect("entries are counted by a u16");
+        match scd.entries()[usize::from(index)].codec {
+            Codec::Empty => continue,
+            codec if !codec.is_supported() => {
+                warnings.warn(Warning::SkippedSoundEntry {
+                    file: file.as_str().to_string(),
+                    entry: index,
+                    codec: codec.name(),
+                });
+                continue;
+            }
+            _ => {}
+        }
+        wavs.push((index, tf.decode_entry(&mut scd, index)?));
+    }
+    Ok(Some(wavs))
 }
 
-#[binread]
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-#[br(repr(i32))]
-enum DataType {
-    Empty = -1,
-    Ogg = 0x6,
-    MsAdpcm = 0xC,
+/// The `LOOPSTART` and `LOOPEND` tags for [loop_samples], if there's a loop.
+fn loop_tags(loop_samples: Option<(u64, u64)>) -> Vec<(String, String)> {
+    match loop_samples {
+        Some((start, end)) if end > start => vec![
+            ("LOOPSTART".to_string(), start.to_string()),
+            ("LOOPEND".to_string(), end.to_string()),
+        ],
+        _ => Vec::new(),
+    }
 }
 
-#[binread]
-#[derive(Debug)]
-#[br(import { data_type: DataType })]
-enum SoundData {
-    #[br(pre_assert(data_type == DataType::Empty))]
-    Empty,
-    #[br(pre_assert(data_type == DataType::Ogg))]
-    OggData(OggMetaHeader),
-    #[br(pre_assert(data_type == DataType::MsAdpcm))]
-    MsAdpcmData(MsAdpcmMetaHeader),
+/// Add [tags] to [content] without re-encoding it. Without FFMPEG the content is left as-is.
+fn with_tags(
+    format: AudioFormat,
+    content: Vec<u8>,
+    tags: &[(String, String)],
+) -> Result<Box<dyn TransformStream>, LastLegendError> {
+    if tags.is_empty() || !audio_backend().available() {
+        return Ok(Box::new(Cursor::new(content)));
+    }
+    let mut output = Spool::new();
+    audio_backend().embed_metadata(
+        format.ffmpeg_format(),
+        &mut Cursor::new(content),
+        None,
+        tags,
+        &mut output,
+    )?;
+    output.into_stream()
 }
 
-#[binread]
-#[derive(Debug)]
-struct OggMetaHeader {
-    pub encryption_type: EncryptionType,
-    pub xor_byte: u8,
-    #[br(temp, pad_before = 0xD)]
-    seek_table_size: u32,
-    #[br(temp, pad_after = 0x8)]
-    vorbis_header_size: u32,
-    #[br(temp, args { count: usize::try_from(seek_table_size).unwrap() / 4 })]
-    _seek_table: Vec<u32>,
-    /// May be encoded. Decoding is done separately.
-    #[br(args { count: vorbis_header_size.try_into().unwrap() })]
-    pub vorbis_header: Vec<u8>,
+/// Convert OGG audio without FFMPEG, for when it isn't installed.
+#[cfg(feature = "native-audio")]
+fn decode_ogg_natively(
+    audio_transform: AudioFormat,
+    ogg_reader: Cursor<Vec<u8>>,
+) -> Result<Box<dyn TransformStream>, LastLegendError> {
+    type Convert = fn(Cursor<Vec<u8>>) -> Result<Vec<u8>, LastLegendError>;
+    let convert: Convert = match audio_transform {
+        AudioFormat::Ogg => return Ok(Box::new(ogg_reader)),
+        AudioFormat::Wav => crate::native_audio::ogg_to_wav,
+        AudioFormat::Flac => crate::native_audio::ogg_to_flac,
+        AudioFormat::Mp3 | AudioFormat::Aac => {
+            return Err(LastLegendError::Custom(format!(
+                "Writing {} needs FFMPEG, which isn't available",
+                audio_transform.extension_str()
+            )))
+        }
+    };
+    Ok(Box::new(Cursor::new(convert(ogg_reader)?)))
 }
 
-#[binread]
-#[derive(Debug, Eq, PartialEq)]
-#[br(repr(u16))]
-enum EncryptionType {
-    None,
-    VorbisHeaderXor = 0x2002,
-    InternalTableXor = 0x2003,
-}
+#[cfg(test)]
+mod scd_tf_tests {
+    use super::*;
 
-#[binrw]
-#[derive(Debug)]
-struct MsAdpcmMetaHeader {
-    #[br(assert(format_tag == 0x2, "Only MS ADPCM is supported."))]
-    format_tag: u16,
-    channels: u16,
-    samples_per_second: i32,
-    avg_bytes_per_second: i32,
-    block_align: u16,
-    bits_per_sample: u16,
-    size: i16,
-    samples_per_block: u16,
-    num_coefficients: u16,
-    coefficients: [i16; 14],
+    #[test]
+    fn only_scd_files_are_banks() {
+        let file = SqPathBuf::new("sound/se/se_bank.scd");
+        let limits = Limits::default();
+        let warnings = WarningSink::default();
+        assert!(explode_scd_bank(&file, b"OggS\0\0\0\0", &limits, &warnings)
+            .unwrap()
+            .is_none());
+        assert!(explode_scd_bank(&file, b"", &limits, &warnings)
+            .unwrap()
+            .is_none());
+    }
 }
@@ -0,0 +1,361 @@
+use std::borrow::Cow;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use binrw::{binread, BinReaderExt};
+use strum::{Display, EnumString};
+
+use crate::error::LastLegendError;
+use crate::sqpath::{SqPath, SqPathBuf};
+use crate::transformers::{TransformStream, Transformer, TransformerForFile};
+
+/// How hard the PNG encoder tries to compress, see [png::Compression].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum PngCompression {
+    Fast,
+    Default,
+    Best,
+}
+
+impl From<PngCompression> for png::Compression {
+    fn from(value: PngCompression) -> Self {
+        match value {
+            PngCompression::Fast => Self::Fast,
+            PngCompression::Default => Self::Default,
+            PngCompression::Best => Self::Best,
+        }
+    }
+}
+
+/// Options for writing PNG files. Unset options use the encoder's defaults.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct PngOptions {
+    pub compression: Option<PngCompression>,
+}
+
+/// Convert the `.tex` textures FFXIV uses to PNG. VFX textures, `.atex`, are the same format.
+#[derive(Debug)]
+pub struct TexTf {
+    pub(crate) options: PngOptions,
+}
+
+impl<R: Read> Transformer<R> for TexTf {
+    type ForFile = TexTfForFile;
+
+    fn maybe_for(&self, file: SqPathBuf) -> Option<Self::ForFile> {
+        let name = file.as_str();
+        (name.ends_with(".tex") || name.ends_with(".atex")).then_some(TexTfForFile {
+            file,
+            options: self.options,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct TexTfForFile {
+    file: SqPathBuf,
+    options: PngOptions,
+}
+
+impl<R: Read> TransformerForFile<R> for TexTfForFile {
+    fn renamed_file(&self) -> Cow<'_, SqPath> {
+        Cow::Owned(SqPathBuf::new(
+            Path::new(self.file.as_str())
+                .with_extension("png")
+                .as_os_str()
+                .to_str()
+                .unwrap(),
+        ))
+    }
+
+    fn transform(&self, mut content: R) -> Result<Box<dyn TransformStream>, LastLegendError> {
+        let mut capture = Vec::<u8>::new();
+        content
+            .read_to_end(&mut capture)
+            .map_err(|e| LastLegendError::Io("Couldn't cache content".into(), e))?;
+        drop(content);
+
+        let mut png_file = Vec::new();
+        tex_to_png(Cursor::new(capture), &self.options, &mut png_file)?;
+        Ok(Box::new(Cursor::new(png_file)))
+    }
+}
+
+/// Decode the first mip level of a `.tex` file and write it out as an RGBA PNG.
+pub(crate) fn tex_to_png(
+    mut content: Cursor<Vec<u8>>,
+    options: &PngOptions,
+    output: impl std::io::Write,
+) -> Result<(), LastLegendError> {
+    let header: TexHeader = content
+        .read_le()
+        .map_err(|e| LastLegendError::BinRW("Couldn't read TEX header".into(), e))?;
+    let surface_start = header.offset_to_surface[0];
+    let surface = usize::try_from(surface_start)
+        .ok()
+        .and_then(|start| content.get_ref().get(start..))
+        .ok_or_else(|| {
+            LastLegendError::Custom(format!(
+                "Texture surface starts at {}, past the end of the {} byte file",
+                surface_start,
+                content.get_ref().len()
+            ))
+        })?;
+
+    let rgba = header.format.decode_rgba(
+        usize::from(header.width),
+        usize::from(header.height),
+        surface,
+    )?;
+
+    let mut encoder = png::Encoder::new(output, header.width.into(), header.height.into());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Some(compression) = options.compression {
+        encoder.set_compression(compression.into());
+    }
+    encoder
+        .write_header()
+        .and_then(|mut w| w.write_image_data(&rgba))
+        .map_err(|e| LastLegendError::Png("Couldn't write PNG".into(), e))
+}
+
+#[binread]
+#[derive(Debug)]
+#[br(little)]
+struct TexHeader {
+    #[br(temp)]
+    _attribute: u32,
+    pub format: TextureFormat,
+    pub width: u16,
+    pub height: u16,
+    #[br(temp)]
+    _depth: u16,
+    #[br(temp)]
+    _mip_levels: u8,
+    #[br(temp)]
+    _array_size: u8,
+    #[br(temp)]
+    _lod_offsets: [u32; 3],
+    pub offset_to_surface: [u32; 13],
+}
+
+/// Known `.tex` pixel formats. Names follow the in-memory byte order, so `B8G8R8A8` is what
+/// DirectX calls `A8R8G8B8`.
+#[binread]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[br(repr(u32))]
+#[allow(clippy::upper_case_acronyms)]
+enum TextureFormat {
+    L8 = 0x1130,
+    A8 = 0x1131,
+    B4G4R4A4 = 0x1440,
+    B5G5R5A1 = 0x1441,
+    B8G8R8A8 = 0x1450,
+    B8G8R8X8 = 0x1451,
+    R32F = 0x2150,
+    R16G16F = 0x2250,
+    R32G32F = 0x2260,
+    R16G16B16A16F = 0x2460,
+    R32G32B32A32F = 0x2470,
+    BC1 = 0x3420,
+    BC2 = 0x3430,
+    BC3 = 0x3431,
+    D16 = 0x4140,
+    D24S8 = 0x4250,
+    BC4 = 0x6120,
+    BC5 = 0x6230,
+    BC7 = 0x6432,
+}
+
+impl TextureFormat {
+    /// Decode a surface of this format into tightly packed RGBA8 pixels.
+    fn decode_rgba(
+        self,
+        width: usize,
+        height: usize,
+        surface: &[u8],
+    ) -> Result<Vec<u8>, LastLegendError> {
+        match self {
+            Self::L8 => {
+                Self::decode_pixels(width, height, surface, 1, |p| [p[0], p[0], p[0], 0xFF])
+            }
+            Self::A8 => {
+                Self::decode_pixels(width, height, surface, 1, |p| [0xFF, 0xFF, 0xFF, p[0]])
+            }
+            Self::B4G4R4A4 => Self::decode_pixels(width, height, surface, 2, |p| {
+                let v = u16::from_le_bytes([p[0], p[1]]);
+                let channel = |shift: u16| ((v >> shift) & 0xF) as u8 * 0x11;
+                [channel(8), channel(4), channel(0), channel(12)]
+            }),
+            Self::B5G5R5A1 => Self::decode_pixels(width, height, surface, 2, |p| {
+                let v = u16::from_le_bytes([p[0], p[1]]);
+                let channel = |shift: u16| {
+                    let c = ((v >> shift) & 0x1F) as u8;
+                    (c << 3) | (c >> 2)
+                };
+                let alpha = if v & 0x8000 != 0 { 0xFF } else { 0 };
+                [channel(10), channel(5), channel(0), alpha]
+            }),
+            Self::B8G8R8A8 => {
+                Self::decode_pixels(width, height, surface, 4, |p| [p[2], p[1], p[0], p[3]])
+            }
+            Self::B8G8R8X8 => {
+                Self::decode_pixels(width, height, surface, 4, |p| [p[2], p[1], p[0], 0xFF])
+            }
+            Self::BC1 => Self::decode_blocks(width, height, surface, 8, |block, out, pitch| {
+                bcdec_rs::bc1(block, out, pitch)
+            }),
+            Self::BC2 => Self::decode_blocks(width, height, surface, 16, |block, out, pitch| {
+                bcdec_rs::bc2(block, out, pitch)
+            }),
+            Self::BC3 => Self::decode_blocks(width, height, surface, 16, |block, out, pitch| {
+                bcdec_rs::bc3(block, out, pitch)
+            }),
+            Self::BC4 => Self::decode_blocks(width, height, surface, 8, |block, out, pitch| {
+                let mut red = [0u8; 16];
+                bcdec_rs::bc4(block, &mut red, 4, false);
+                for (i, r) in red.into_iter().enumerate() {
+                    let at = (i / 4) * pitch + (i % 4) * 4;
+                    out[at..at + 4].copy_from_slice(&[r, r, r, 0xFF]);
+                }
+            }),
+            Self::BC5 => Self::decode_blocks(width, height, surface, 16, |block, out, pitch| {
+                let mut red_green = [0u8; 32];
+                bcdec_rs::bc5(block, &mut red_green, 8, false);
+                for i in 0..16 {
+                    let at = (i / 4) * pitch + (i % 4) * 4;
+                    out[at..at + 4].copy_from_slice(&[
+                        red_green[i * 2],
+                        red_green[i * 2 + 1],
+                        0,
+                        0xFF,
+                    ]);
+                }
+            }),
+            Self::BC7 => Self::decode_blocks(width, height, surface, 16, |block, out, pitch| {
+                bcdec_rs::bc7(block, out, pitch)
+            }),
+            Self::R32F
+            | Self::R16G16F
+            | Self::R32G32F
+            | Self::R16G16B16A16F
+            | Self::R32G32B32A32F
+            | Self::D16
+            | Self::D24S8 => Err(LastLegendError::Custom(format!(
+                "Unsupported texture format {:?}",
+                self
+            ))),
+        }
+    }
+
+    fn decode_pixels(
+        width: usize,
+        height: usize,
+        surface: &[u8],
+        bytes_per_pixel: usize,
+        decode: impl Fn(&[u8]) -> [u8; 4],
+    ) -> Result<Vec<u8>, LastLegendError> {
+        let needed = width * height * bytes_per_pixel;
+        let surface = surface
+            .get(..needed)
+            .ok_or_else(|| Self::short_surface(needed, surface.len()))?;
+        Ok(surface
+            .chunks_exact(bytes_per_pixel)
+            .flat_map(decode)
+            .collect())
+    }
+
+    /// Decode 4x4 compressed blocks. The decoder is given the block, the output positioned at the
+    /// top-left pixel of the block, and the output pitch in bytes.
+    fn decode_blocks(
+        width: usize,
+        height: usize,
+        surface: &[u8],
+        block_size: usize,
+        decode: impl Fn(&[u8], &mut [u8], usize),
+    ) -> Result<Vec<u8>, LastLegendError> {
+        let blocks_wide = width.div_ceil(4);
+        let blocks_high = height.div_ceil(4);
+        let needed = blocks_wide * blocks_high * block_size;
+        let surface = surface
+            .get(..needed)
+            .ok_or_else(|| Self::short_surface(needed, surface.len()))?;
+
+        // Decode into a buffer padded out to whole blocks, then crop.
+        let padded_pitch = blocks_wide * 4 * 4;
+        let mut padded = vec![0u8; padded_pitch * blocks_high * 4];
+        for (i, block) in surface.chunks_exact(block_size).enumerate() {
+            let (bx, by) = (i % blocks_wide, i / blocks_wide);
+            let start = by * 4 * padded_pitch + bx * 4 * 4;
+            decode(block, &mut padded[start..], padded_pitch);
+        }
+
+        let pitch = width * 4;
+        Ok(padded
+            .chunks_exact(padded_pitch)
+            .take(height)
+            .flat_map(|row| &row[..pitch])
+            .copied()
+            .collect())
+    }
+
+    fn short_surface(needed: usize, have: usize) -> LastLegendError {
+        LastLegendError::Custom(format!(
+            "Texture surface is too short, need {} bytes but have {}",
+            needed, have
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tex_tests {
+    use std::io::Cursor;
+
+    use crate::transformers::tex_tf::{tex_to_png, PngOptions, TextureFormat};
+
+    #[test]
+    fn decode_b8g8r8a8() {
+        let rgba = TextureFormat::B8G8R8A8
+            .decode_rgba(1, 1, &[0x01, 0x02, 0x03, 0x04])
+            .unwrap();
+        assert_eq!(rgba, [0x03, 0x02, 0x01, 0x04]);
+    }
+
+    #[test]
+    fn decode_b5g5r5a1() {
+        // Pure red, opaque.
+        let rgba = TextureFormat::B5G5R5A1
+            .decode_rgba(1, 1, &0xFC00u16.to_le_bytes())
+            .unwrap();
+        assert_eq!(rgba, [0xFF, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn decode_bc1_crops_to_size() {
+        // Both endpoints white, all indices 0.
+        let block = [0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0];
+        let rgba = TextureFormat::BC1.decode_rgba(3, 2, &block).unwrap();
+        assert_eq!(rgba.len(), 3 * 2 * 4);
+        assert!(rgba.iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn short_surface_is_error() {
+        assert!(TextureFormat::BC7.decode_rgba(8, 8, &[0; 16]).is_err());
+    }
+
+    #[test]
+    fn surface_past_the_end_is_error() {
+        // A 1x1 B8G8R8A8 texture whose surface is said to start past the end of the file.
+        let mut tex = vec![0; 80];
+        tex[4..8].copy_from_slice(&0x1450u32.to_le_bytes());
+        tex[8..10].copy_from_slice(&1u16.to_le_bytes());
+        tex[10..12].copy_from_slice(&1u16.to_le_bytes());
+        tex[28..32].copy_from_slice(&1000u32.to_le_bytes());
+
+        let result = tex_to_png(Cursor::new(tex), &PngOptions::default(), Vec::new());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Reporting problems that don't stop the work they happen in, apart from errors.
+use std::fmt::{Debug, Display, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Something that went wrong without failing the work it happened in, e.g. a sound entry that
+/// was left out of an extraction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// A row of [sheet] couldn't be read, and was left out.
+    SkippedRow {
+        sheet: String,
+        row_id: u32,
+        sub_row_id: Option<u16>,
+        reason: String,
+    },
+    /// Sound entry [entry] of [file] was left out, as its codec isn't supported.
+    SkippedSoundEntry {
+        file: String,
+        entry: u16,
+        codec: &'static str,
+    },
+    /// Metadata wasn't embedded in [file], as its format can't hold any.
+    MetadataNotEmbedded { file: String },
+    /// The entry with [hash] moved since [index_path] was loaded, so it was loaded again.
+    EntryMoved { hash: u32, index_path: PathBuf },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SkippedRow {
+                sheet,
+                row_id,
+                sub_row_id,
+                reason,
+            } => {
+                write!(f, "Skipping row {}", row_id)?;
+                if let Some(sub_row_id) = sub_row_id {
+                    write!(f, ".{}", sub_row_id)?;
+                }
+                write!(f, " of {}: {}", sheet, reason)
+            }
+            Self::SkippedSoundEntry { file, entry, codec } => write!(
+                f,
+                "Skipping sound entry {} of {}, its codec {} isn't supported",
+                entry, file, codec
+            ),
+            Self::MetadataNotEmbedded { file } => {
+                write!(f, "Can't embed metadata in {}, leaving it as-is", file)
+            }
+            Self::EntryMoved { hash, index_path } => write!(
+                f,
+                "Entry {:X} moved since {} was loaded, retrying with the reloaded index",
+                hash,
+                index_path.display()
+            ),
+        }
+    }
+}
+
+/// Where [Warning]s go. They're logged unless a callback is given, which gets them instead.
+/// Clones share the callback.
+///
+/// Give it to a [Repository](crate::data::repo::Repository) with
+/// [with_warnings](crate::data::repo::Repository::with_warnings), and extracting entries and
+/// reading sheets from it report their warnings here.
+#[derive(Clone, Default)]
+pub struct WarningSink {
+    callback: Option<Arc<dyn Fn(Warning) + Send + Sync>>,
+}
+
+impl WarningSink {
+    /// Pass every warning to [callback] instead of logging it.
+    pub fn new(callback: impl Fn(Warning) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    /// Keep every warning, returning the sink and the warnings it has kept so far.
+    pub fn collecting() -> (Self, Arc<Mutex<Vec<Warning>>>) {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let kept = Arc::clone(&warnings);
+        (Self::new(move |w| kept.lock().push(w)), warnings)
+    }
+
+    pub fn warn(&self, warning: Warning) {
+        match &self.callback {
+            Some(callback) => callback(warning),
+            None => log::warn!("{}", warning),
+        }
+    }
+}
+
+impl Debug for WarningSink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WarningSink")
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod warning_tests {
+    use super::*;
+
+    #[test]
+    fn collects_warnings_instead_of_logging() {
+        let (sink, warnings) = WarningSink::collecting();
+        let warning = Warning::MetadataNotEmbedded {
+            file: "music/ffxiv/BGM_System_Title.wav".into(),
+        };
+        sink.clone().warn(warning.clone());
+
+        assert_eq!(*warnings.lock(), [warning]);
+        assert_eq!(
+            warnings.lock()[0].to_string(),
+            "Can't embed metadata in music/ffxiv/BGM_System_Title.wav, leaving it as-is"
+        );
+    }
+}
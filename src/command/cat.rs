@@ -0,0 +1,60 @@
+use std::io::{ErrorKind, Write};
+
+use clap::Args;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::simple_task::read_transformed;
+use last_legend_dob::sqpath::SqPathBuf;
+use last_legend_dob::transformers::{ScdOptions, TransformerImpl};
+
+use crate::command::extract_common::LoopArgs;
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::LastLegendCommand;
+
+/// Write a file from the repository to stdout, for piping into other tools.
+///
+/// Logs still go to stderr, so stdout only has the file content.
+#[derive(Args, Debug)]
+pub struct Cat {
+    /// The file to write.
+    file: SqPathBuf,
+    /// Transformers to run
+    #[clap(short, long, value_parser = TransformerParser)]
+    transformer: Vec<TransformerImpl>,
+    /// The sound entry to read from `.scd` files that have several. Overrides the `entry` given
+    /// to `.scd` transformers, which defaults to 0.
+    #[clap(long)]
+    scd_entry: Option<u16>,
+    #[clap(flatten)]
+    loop_args: LoopArgs,
+}
+
+impl LastLegendCommand for Cat {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        let repo = global_args.open_repository();
+        let transformers = self
+            .transformer
+            .into_iter()
+            .map(|t| match self.scd_entry {
+                Some(entry) => t.scd_options().map_or(t, |options| {
+                    t.with_scd_options(ScdOptions { entry, ..options })
+                }),
+                None => t,
+            })
+            .map(|t| self.loop_args.apply_to(t))
+            .collect::<Vec<_>>();
+
+        let mut transformed = read_transformed(&repo, &self.file, &transformers)?;
+        log::debug!("Writing {} to stdout", transformed.file_name);
+
+        let mut stdout = std::io::stdout().lock();
+        let result =
+            std::io::copy(&mut transformed.reader, &mut stdout).and_then(|_| stdout.flush());
+        match result {
+            // The reader went away, e.g. `| head`, which is fine.
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+            res => res.map_err(|e| LastLegendError::Io("Couldn't write to stdout".into(), e)),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::sync::Mutex;
+
+use clap::Args;
+use rayon::prelude::*;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::simple_task::format_index_hash_for_console;
+use last_legend_dob::sqpath::PathHasher;
+
+use crate::command::global_args::{parse_hex_u32, print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// How many candidates are tried between checks for cancellation.
+const CANCELLATION_INTERVAL: u32 = 1 << 16;
+
+/// Find paths with a hash, by trying every name made from a charset.
+///
+/// Candidates are `PREFIX`, then `--min-length` to `--max-length` characters from the charset,
+/// then `SUFFIX`. Use the full path as the prefix and suffix for `.index2` hashes, e.g.
+/// `--prefix music/ffxiv/bgm_ --suffix .scd`. CRC32 collides a lot for long names, so every match
+/// is listed and more than one may be found.
+#[derive(Args, Debug)]
+pub struct CrackHash {
+    /// The hash to find, in hexadecimal.
+    #[clap(value_parser = parse_hex_u32)]
+    hash: u32,
+    /// Text before the guessed part.
+    #[clap(short, long, default_value = "")]
+    prefix: String,
+    /// Text after the guessed part.
+    #[clap(short, long, default_value = "")]
+    suffix: String,
+    /// The characters to guess from.
+    #[clap(short, long, default_value = "abcdefghijklmnopqrstuvwxyz0123456789_")]
+    charset: String,
+    /// The fewest characters to guess.
+    #[clap(long, default_value_t = 1)]
+    min_length: usize,
+    /// The most characters to guess.
+    #[clap(long, default_value_t = 6)]
+    max_length: usize,
+}
+
+impl LastLegendCommand for CrackHash {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        if !self.charset.is_ascii() {
+            return Err(LastLegendError::Custom(
+                "The charset must only contain ASCII characters".into(),
+            ));
+        }
+        // Paths are hashed in lowercase, so uppercase guesses would only repeat the work.
+        let mut charset = self.charset.to_ascii_lowercase().into_bytes();
+        charset.sort_unstable();
+        charset.dedup();
+        if charset.is_empty() {
+            return Err(LastLegendError::Custom("The charset is empty".into()));
+        }
+        if self.min_length > self.max_length {
+            return Err(LastLegendError::Custom(
+                "--min-length can't be more than --max-length".into(),
+            ));
+        }
+
+        let candidates = (self.min_length..=self.max_length)
+            .map(|length| (charset.len() as u64).saturating_pow(length as u32))
+            .fold(0u64, u64::saturating_add);
+        log::info!(
+            "Searching {} candidates for {}",
+            candidates,
+            format_index_hash_for_console(self.hash)
+        );
+
+        let cracker = Cracker {
+            hasher: global_args.path_hasher(),
+            target: self.hash,
+            prefix: self.prefix.to_ascii_lowercase().into_bytes(),
+            suffix: self.suffix.to_ascii_lowercase().into_bytes(),
+            charset,
+            found: Mutex::new(Vec::new()),
+        };
+        for length in self.min_length..=self.max_length {
+            log::debug!("Trying {} characters", length);
+            cracker.crack_length(length)?;
+        }
+
+        let mut found = cracker.found.into_inner().unwrap();
+        found.sort();
+        log::info!("Found {} matching paths", found.len());
+        if global_args.json_output() {
+            print_json(&serde_json::json!({
+                "hash": self.hash,
+                "found": found,
+            }))?;
+        }
+        Ok(())
+    }
+}
+
+struct Cracker {
+    hasher: PathHasher,
+    target: u32,
+    prefix: Vec<u8>,
+    suffix: Vec<u8>,
+    charset: Vec<u8>,
+    found: Mutex<Vec<String>>,
+}
+
+impl Cracker {
+    /// Try every guess of [length] characters, splitting the work by the first character.
+    fn crack_length(&self, length: usize) -> Result<(), LastLegendError> {
+        if length == 0 {
+            self.try_candidate(&[self.prefix.as_slice(), &self.suffix].concat());
+            return Ok(());
+        }
+        self.charset.par_iter().try_for_each(|&first| {
+            let mut candidate = [
+                self.prefix.as_slice(),
+                &vec![self.charset[0]; length],
+                &self.suffix,
+            ]
+            .concat();
+            let guess = self.prefix.len()..self.prefix.len() + length;
+            candidate[guess.start] = first;
+            // Positions in the charset of each guessed character after the first.
+            let mut digits = vec![0usize; length - 1];
+            let mut since_check = 0;
+            loop {
+                self.try_candidate(&candidate);
+
+                since_check += 1;
+                if since_check == CANCELLATION_INTERVAL {
+                    since_check = 0;
+                    crate::CANCELLATION.check()?;
+                }
+
+                // Count up, like an odometer, from the last character.
+                let mut position = digits.len();
+                loop {
+                    if position == 0 {
+                        return Ok(());
+                    }
+                    position -= 1;
+                    digits[position] += 1;
+                    if digits[position] < self.charset.len() {
+                        candidate[guess.start + 1 + position] = self.charset[digits[position]];
+                        break;
+                    }
+                    digits[position] = 0;
+                    candidate[guess.start + 1 + position] = self.charset[0];
+                }
+            }
+        })
+    }
+
+    fn try_candidate(&self, candidate: &[u8]) {
+        if self.hasher.hash(candidate) != self.target {
+            return;
+        }
+        let path = String::from_utf8_lossy(candidate).into_owned();
+        log::info!("Found {}", path);
+        self.found.lock().unwrap().push(path);
+    }
+}
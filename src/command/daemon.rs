@@ -0,0 +1,694 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::Args;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use last_legend_dob::data::repo::Repository;
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::metrics::{self, Metric};
+use last_legend_dob::simple_task::OutputMetadata;
+use last_legend_dob::sqpath::SqPathBuf;
+use last_legend_dob::surpass::collection::Collection;
+use last_legend_dob::surpass::sheet_info::Language;
+use last_legend_dob::transformers::TransformerImpl;
+
+use crate::command::extract_common::{extract_file, ExtractConfig};
+use crate::command::global_args::GlobalArgs;
+use crate::command::LastLegendCommand;
+
+/// Serve JSON-RPC requests over a local socket, keeping indexes and sheets loaded between them.
+///
+/// Requests and responses are JSON-RPC 2.0 objects, one per line. Available methods are
+/// `extract` and `sheet`. Extraction sends `progress` notifications as each file completes.
+///
+/// Extractions that run transformers are expensive, so they're limited: requests over the limits
+/// get a "busy" or "rate limited" error to retry later, rather than queueing up.
+///
+/// With `--http-address`, request counts, cache hits, transform durations, and FFMPEG failures
+/// are served over HTTP at `/metrics`, in the Prometheus text format. Sheet rows are served as
+/// JSON at `/sheets/<name>/rows?offset=<row id>&limit=<rows>&language=<code>`, with the name
+/// percent-encoded where needed, reading only the pages holding the rows asked for, so pages of
+/// large sheets load quickly. The response's `next_offset` is the `offset` of the next page of
+/// rows, or null after the last row.
+#[derive(Args, Debug)]
+pub struct Daemon {
+    /// The socket to listen on.
+    #[clap(long)]
+    socket: PathBuf,
+    /// How many extractions with transformers may run at once, across all clients. Defaults to
+    /// the number of CPUs.
+    #[clap(long)]
+    max_transforms: Option<usize>,
+    /// How many extractions with transformers each client may start per minute. Clients are
+    /// told apart by the user they run as, so reconnecting doesn't reset the limit. Unlimited if
+    /// not given.
+    #[clap(long)]
+    rate_limit: Option<usize>,
+    /// How many clients may be connected at once.
+    #[clap(long, default_value_t = 64)]
+    max_clients: usize,
+    /// The address to serve metrics and sheet rows over HTTP on, e.g. `127.0.0.1:9100`.
+    #[clap(long, alias = "metrics-address")]
+    http_address: Option<SocketAddr>,
+}
+
+impl LastLegendCommand for Daemon {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let listener = UnixListener::bind(&self.socket)
+            .map_err(|e| LastLegendError::Io("Couldn't bind socket".into(), e))?;
+        log::info!("Listening on {}", self.socket.display());
+        let state = Arc::new(DaemonState {
+            repo: global_args.open_repository(),
+            collection: Mutex::new(None),
+            max_transforms: self
+                .max_transforms
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+            rate_limits: RateLimits::new(self.rate_limit),
+            max_clients: self.max_clients,
+            transforms_running: AtomicUsize::new(0),
+            clients: AtomicUsize::new(0),
+        });
+
+        if let Some(address) = self.http_address {
+            let http_listener = TcpListener::bind(address)
+                .map_err(|e| LastLegendError::Io("Couldn't bind HTTP address".into(), e))?;
+            log::info!("Serving metrics on http://{}/metrics", address);
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || serve_http(http_listener, state));
+        }
+
+        for stream in listener.incoming() {
+            let stream =
+                stream.map_err(|e| LastLegendError::Io("Couldn't accept connection".into(), e))?;
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || {
+                let Some(_client) = Slot::try_take(&state.clients, state.max_clients) else {
+                    log::warn!("Refusing connection, too many clients");
+                    let _ = send_message(
+                        &Mutex::new(stream),
+                        error_response(
+                            Value::Null,
+                            RpcError::new(RpcError::SERVER_BUSY, "Too many clients connected"),
+                        ),
+                    );
+                    return;
+                };
+                if let Err(e) = state.serve(stream) {
+                    log::warn!("Connection closed: {}", e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+struct DaemonState {
+    repo: Repository,
+    collection: Mutex<Option<Arc<Collection>>>,
+    max_transforms: usize,
+    rate_limits: RateLimits,
+    max_clients: usize,
+    transforms_running: AtomicUsize,
+    clients: AtomicUsize,
+}
+
+/// A place under a limit, such as a running transform, given back when dropped.
+struct Slot<'a>(&'a AtomicUsize);
+
+impl<'a> Slot<'a> {
+    /// Take a place if fewer than [max] are taken.
+    fn try_take(taken: &'a AtomicUsize, max: usize) -> Option<Self> {
+        taken
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Self(taken))
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Limits how many expensive requests each client starts per minute. Clients are keyed by the
+/// user id of the peer, see [peer_uid], which stays the same across connections.
+struct RateLimits {
+    limit: Option<usize>,
+    recent: Mutex<HashMap<u32, VecDeque<Instant>>>,
+}
+
+impl RateLimits {
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a request from [client], or fail if it has made too many recently.
+    fn check(&self, client: u32) -> Result<(), RpcError> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut recent = self.recent.lock().unwrap();
+        let recent = recent.entry(client).or_default();
+        while recent
+            .front()
+            .is_some_and(|&t| now.duration_since(t) >= Self::WINDOW)
+        {
+            recent.pop_front();
+        }
+        if recent.len() >= limit {
+            let retry_after = recent
+                .front()
+                .map_or(Self::WINDOW, |&t| Self::WINDOW - now.duration_since(t));
+            return Err(RpcError {
+                code: RpcError::RATE_LIMITED,
+                message: format!(
+                    "Rate limit of {} transforming requests per minute reached",
+                    limit
+                ),
+                data: json!({ "retry_after_secs": retry_after.as_secs_f64().ceil() }),
+            });
+        }
+        recent.push_back(now);
+        Ok(())
+    }
+}
+
+/// The user id of the process on the other end of [stream].
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    use std::os::fd::AsRawFd;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: cred and len are valid for writes of the size given.
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                (&mut cred as *mut libc::ucred).cast(),
+                &mut len,
+            )
+        };
+        if result == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(cred.uid)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let (mut uid, mut gid) = (0, 0);
+        // SAFETY: uid and gid are valid for writes.
+        if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(uid)
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct ExtractParams {
+    files: Vec<String>,
+    output_dir: PathBuf,
+    #[serde(default)]
+    transformers: Vec<String>,
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Deserialize)]
+struct SheetParams {
+    sheet: String,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+/// An error to send back to the client, with its JSON-RPC code.
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Value,
+}
+
+impl RpcError {
+    const PARSE_ERROR: i64 = -32700;
+    const METHOD_NOT_FOUND: i64 = -32601;
+    const INVALID_PARAMS: i64 = -32602;
+    const SERVER_ERROR: i64 = -32000;
+    const SERVER_BUSY: i64 = -32001;
+    const RATE_LIMITED: i64 = -32002;
+
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: Value::Null,
+        }
+    }
+}
+
+impl From<LastLegendError> for RpcError {
+    fn from(e: LastLegendError) -> Self {
+        Self {
+            code: Self::SERVER_ERROR,
+            message: e.to_string(),
+            data: json!({ "category": format!("{:?}", e.category()) }),
+        }
+    }
+}
+
+impl DaemonState {
+    fn serve(&self, stream: UnixStream) -> Result<(), LastLegendError> {
+        let reader = BufReader::new(
+            stream
+                .try_clone()
+                .map_err(|e| LastLegendError::Io("Couldn't clone socket".into(), e))?,
+        );
+        let client = peer_uid(&stream)
+            .map_err(|e| LastLegendError::Io("Couldn't identify client".into(), e))?;
+        let writer = Mutex::new(stream);
+        let send = |message: Value| send_message(&writer, message);
+
+        for line in reader.lines() {
+            let line = line.map_err(|e| LastLegendError::Io("Failed to read line".into(), e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (id, result) = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    let id = request.id.clone().unwrap_or(Value::Null);
+                    // Unknown methods share a label, so clients can't add labels without bound.
+                    let method = match request.method.as_str() {
+                        "extract" => "extract",
+                        "sheet" => "sheet",
+                        _ => "unknown",
+                    };
+                    metrics::global().increment_labelled(Metric::Requests, method);
+                    let result = self.handle(request, client, |params| {
+                        send(json!({
+                            "jsonrpc": "2.0",
+                            "method": "progress",
+                            "params": { "id": id, "progress": params },
+                        }))
+                    });
+                    if result.is_err() {
+                        metrics::global().increment_labelled(Metric::RequestErrors, method);
+                    }
+                    (id.clone(), result)
+                }
+                Err(e) => {
+                    metrics::global().increment_labelled(Metric::Requests, "invalid");
+                    metrics::global().increment_labelled(Metric::RequestErrors, "invalid");
+                    (Value::Null, Err(RpcError::new(RpcError::PARSE_ERROR, e)))
+                }
+            };
+            send(match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err(e) => error_response(id, e),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(
+        &self,
+        request: Request,
+        client: u32,
+        progress: impl Fn(Value) -> Result<(), LastLegendError>,
+    ) -> Result<Value, RpcError> {
+        match request.method.as_str() {
+            "extract" => self.extract(parse_params(request.params)?, client, progress),
+            "sheet" => self.sheet(parse_params(request.params)?),
+            method => Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("Unknown method {}", method),
+            )),
+        }
+    }
+
+    fn extract(
+        &self,
+        params: ExtractParams,
+        client: u32,
+        progress: impl Fn(Value) -> Result<(), LastLegendError>,
+    ) -> Result<Value, RpcError> {
+        let transformers = params
+            .transformers
+            .iter()
+            .map(|t| TransformerImpl::from_str(t))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RpcError::new(RpcError::INVALID_PARAMS, e))?;
+        // Check every file before extracting any, so a bad one doesn't leave the rest half done.
+        let files = params
+            .files
+            .iter()
+            .map(|file| {
+                let file = SqPathBuf::new(file);
+                let stem = Path::new(file.as_str()).file_stem().ok_or_else(|| {
+                    RpcError::new(
+                        RpcError::INVALID_PARAMS,
+                        format!("{} isn't a file path", file.as_str()),
+                    )
+                })?;
+                let base_name = params.output_dir.join(stem);
+                Ok((file, base_name))
+            })
+            .collect::<Result<Vec<_>, RpcError>>()?;
+        let _slot = if transformers.is_empty() {
+            None
+        } else {
+            self.rate_limits.check(client)?;
+            Some(
+                Slot::try_take(&self.transforms_running, self.max_transforms).ok_or_else(|| {
+                    RpcError::new(
+                        RpcError::SERVER_BUSY,
+                        "Too many transforming requests running, try again later",
+                    )
+                })?,
+            )
+        };
+        let config = ExtractConfig::new(params.overwrite, transformers);
+
+        let total = files.len();
+        for (done, (file, base_name)) in files.into_iter().enumerate() {
+            extract_file(
+                &self.repo,
+                &config,
+                &file,
+                base_name,
+                &OutputMetadata::default(),
+            )?;
+            progress(json!({ "file": file.as_str(), "done": done + 1, "total": total }))?;
+        }
+
+        Ok(json!({ "extracted": total }))
+    }
+
+    fn sheet(&self, params: SheetParams) -> Result<Value, RpcError> {
+        let collection = self.collection()?;
+        let rows = collection
+            .sheet_iter(&params.sheet)?
+            .deserialize_rows::<Vec<Value>>()
+            .skip(params.offset)
+            .take(params.limit.unwrap_or(usize::MAX))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Array(rows.into_iter().map(Value::Array).collect()))
+    }
+
+    /// Get the collection, loading it on first use.
+    fn collection(&self) -> Result<Arc<Collection>, LastLegendError> {
+        let mut collection = self.collection.lock().unwrap();
+        if let Some(c) = &*collection {
+            return Ok(Arc::clone(c));
+        }
+        let loaded = Arc::new(Collection::load(self.repo.clone())?);
+        *collection = Some(Arc::clone(&loaded));
+        Ok(loaded)
+    }
+}
+
+fn send_message(writer: &Mutex<UnixStream>, message: Value) -> Result<(), LastLegendError> {
+    let mut writer = writer.lock().unwrap();
+    serde_json::to_writer(&mut *writer, &message)
+        .map_err(|e| LastLegendError::Json("Couldn't write message".into(), e))?;
+    writer
+        .write_all(b"\n")
+        .map_err(|e| LastLegendError::Io("Couldn't write message".into(), e))
+}
+
+fn error_response(id: Value, e: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": e.code, "message": e.message, "data": e.data },
+    })
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(RpcError::INVALID_PARAMS, e))
+}
+
+/// The most rows a page of sheet rows can have, and how many it has if no limit is given.
+const MAX_SHEET_PAGE_ROWS: usize = 1000;
+const DEFAULT_SHEET_PAGE_ROWS: usize = 100;
+
+/// How many HTTP connections are answered at once. Others wait to be accepted.
+const HTTP_WORKERS: usize = 8;
+
+/// Answer HTTP requests for `/metrics` and sheet rows on [HTTP_WORKERS] threads, so a slow sheet
+/// read doesn't hold up metrics scrapes.
+fn serve_http(listener: TcpListener, state: Arc<DaemonState>) {
+    std::thread::scope(|s| {
+        for _ in 0..HTTP_WORKERS {
+            s.spawn(|| {
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| answer_http_request(stream, &state));
+                    if let Err(e) = result {
+                        log::debug!("HTTP request failed: {}", e);
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// An HTTP response's status, content type and body.
+type HttpResponse = (&'static str, &'static str, String);
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const TEXT_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+fn answer_http_request(stream: TcpStream, state: &DaemonState) -> std::io::Result<()> {
+    // A worker is tied up for as long as a client takes.
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, nothing in them matters here.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            PROMETHEUS_CONTENT_TYPE,
+            metrics::global().render_prometheus(),
+        ),
+        (Some("GET"), Some(target)) => match sheet_rows_target(target) {
+            Some((sheet, query)) => {
+                metrics::global().increment_labelled(Metric::Requests, "sheet_rows");
+                let response = match percent_decode(sheet) {
+                    Some(sheet) => state.sheet_rows(&sheet, query),
+                    None => (
+                        "400 Bad Request",
+                        TEXT_CONTENT_TYPE,
+                        format!("Invalid sheet name {}\n", sheet),
+                    ),
+                };
+                if !response.0.starts_with("200") {
+                    metrics::global().increment_labelled(Metric::RequestErrors, "sheet_rows");
+                }
+                response
+            }
+            None => (
+                "404 Not Found",
+                TEXT_CONTENT_TYPE,
+                "Not found\n".to_string(),
+            ),
+        },
+        _ => (
+            "405 Method Not Allowed",
+            TEXT_CONTENT_TYPE,
+            "Only GET is supported\n".to_string(),
+        ),
+    };
+    let mut writer = &stream;
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+/// Split a `/sheets/<name>/rows?<query>` request target into the sheet name and query. Sheet
+/// names can have slashes in them, e.g. `quest/000/ClsHyu001_00001`.
+fn sheet_rows_target(target: &str) -> Option<(&str, &str)> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let sheet = path.strip_prefix("/sheets/")?.strip_suffix("/rows")?;
+    (!sheet.is_empty()).then_some((sheet, query))
+}
+
+/// Decode the `%XX` escapes of a request target segment, or None if they're invalid or don't
+/// decode to UTF-8.
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(segment.len());
+    let mut bytes = segment.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            decoded.push(b);
+            continue;
+        }
+        let hex = [bytes.next()?, bytes.next()?];
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// The parameters of a page of sheet rows, parsed from a query string.
+#[derive(Debug, PartialEq)]
+struct SheetRowsQuery {
+    offset: u32,
+    limit: usize,
+    language: Language,
+}
+
+impl FromStr for SheetRowsQuery {
+    type Err = String;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let mut parsed = Self {
+            offset: 0,
+            limit: DEFAULT_SHEET_PAGE_ROWS,
+            language: Language::English,
+        };
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let invalid = || format!("Invalid value '{}' for {}", value, key);
+            match key {
+                "offset" => parsed.offset = value.parse().map_err(|_| invalid())?,
+                "limit" => parsed.limit = value.parse().map_err(|_| invalid())?,
+                "language" => parsed.language = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("Unknown parameter {}", key)),
+            }
+        }
+        if parsed.limit > MAX_SHEET_PAGE_ROWS {
+            return Err(format!(
+                "limit can be at most {}, not {}",
+                MAX_SHEET_PAGE_ROWS, parsed.limit
+            ));
+        }
+        Ok(parsed)
+    }
+}
+
+impl DaemonState {
+    /// Read a page of rows of [sheet], as asked for by [query].
+    fn sheet_rows(&self, sheet: &str, query: &str) -> HttpResponse {
+        let query = match query.parse::<SheetRowsQuery>() {
+            Ok(query) => query,
+            Err(e) => return ("400 Bad Request", TEXT_CONTENT_TYPE, format!("{}\n", e)),
+        };
+        // Read one row past the limit, to know whether there's another page.
+        let result = self
+            .collection()
+            .and_then(|c| c.read_rows(sheet, query.language, query.offset, query.limit + 1));
+        let mut rows = match result {
+            Ok(rows) => rows,
+            Err(e) => {
+                let status = match e.category() {
+                    ErrorCategory::NotFound => "404 Not Found",
+                    _ => "500 Internal Server Error",
+                };
+                return (status, TEXT_CONTENT_TYPE, format!("{}\n", e));
+            }
+        };
+        let mut row_ids = rows.iter().map(|&(id, _, _)| id).collect::<Vec<_>>();
+        row_ids.dedup();
+        let next_offset = row_ids.get(query.limit).copied();
+        if let Some(next_offset) = next_offset {
+            rows.retain(|&(id, _, _)| id < next_offset);
+        }
+        let body = json!({
+            "sheet": sheet,
+            "rows": rows
+                .into_iter()
+                .map(|(row_id, sub_row_id, values)| json!({
+                    "row_id": row_id,
+                    "sub_row_id": sub_row_id,
+                    "values": values,
+                }))
+                .collect::<Vec<_>>(),
+            "next_offset": next_offset,
+        });
+        ("200 OK", JSON_CONTENT_TYPE, body.to_string())
+    }
+}
+
+#[cfg(test)]
+mod daemon_tests {
+    use super::*;
+
+    #[test]
+    fn reconnecting_keeps_the_rate_limit() {
+        let limits = RateLimits::new(Some(2));
+        let connect = || {
+            let (client, _server) = UnixStream::pair().unwrap();
+            peer_uid(&client).unwrap()
+        };
+        for _ in 0..2 {
+            assert!(limits.check(connect()).is_ok());
+        }
+        let limited = limits.check(connect()).unwrap_err();
+        assert_eq!(limited.code, RpcError::RATE_LIMITED);
+    }
+
+    #[test]
+    fn sheet_names_are_percent_decoded() {
+        let (sheet, query) = sheet_rows_target("/sheets/quest%2F000%2FA%20b/rows?limit=1").unwrap();
+        assert_eq!(percent_decode(sheet).as_deref(), Some("quest/000/A b"));
+        assert_eq!(query, "limit=1");
+        assert_eq!(percent_decode("Item").as_deref(), Some("Item"));
+        assert_eq!(percent_decode("bad%2"), None);
+        assert_eq!(percent_decode("bad%zz"), None);
+        assert_eq!(percent_decode("bad%+1"), None);
+    }
+}
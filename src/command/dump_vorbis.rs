@@ -0,0 +1,130 @@
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use clap::Args;
+use serde_json::json;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::scd::dump_vorbis_packets;
+use last_legend_dob::simple_task::read_entry_content;
+use last_legend_dob::sqpath::SqPathBuf;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Dump the decrypted Vorbis header and first audio packets of a sound file, without decoding
+/// them.
+///
+/// Useful for looking into sound files that don't decode, e.g. with a new kind of encryption or a
+/// corrupted stream. Prints a hexdump, or writes raw files with `--output`.
+#[derive(Args, Debug)]
+pub struct DumpVorbis {
+    /// The `.scd` file to dump.
+    file: SqPathBuf,
+    /// The sound entry to dump, for files that have several.
+    #[clap(long, default_value_t = 0)]
+    entry: u16,
+    /// How many audio packets to dump.
+    #[clap(short = 'n', long, default_value_t = 4)]
+    packets: usize,
+    /// Write `header.bin` and `packet_N.bin` files to this directory instead of printing.
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl LastLegendCommand for DumpVorbis {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let repo = global_args.open_repository();
+        let index = repo.get_index_for(&self.file)?;
+        let content = read_entry_content(&index, index.get_entry(&self.file)?)?;
+        let dump = dump_vorbis_packets(&content, self.entry, self.packets)?;
+        if let Some(stopped) = &dump.stopped {
+            log::warn!("Stopped after {} packets: {}", dump.packets.len(), stopped);
+        }
+
+        if let Some(output) = &self.output {
+            std::fs::create_dir_all(output).map_err(|e| {
+                LastLegendError::Io(format!("Couldn't create {}", output.display()), e)
+            })?;
+            let files = std::iter::once(("header.bin".to_string(), &dump.vorbis_header)).chain(
+                dump.packets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| (format!("packet_{}.bin", i), &p.data)),
+            );
+            for (name, data) in files {
+                let path = output.join(name);
+                std::fs::write(&path, data).map_err(|e| {
+                    LastLegendError::Io(format!("Couldn't write {}", path.display()), e)
+                })?;
+            }
+            log::info!(
+                "Wrote the header and {} packets to {}",
+                dump.packets.len(),
+                output.display()
+            );
+            return Ok(());
+        }
+
+        if global_args.json_output() {
+            return print_json(&json!({
+                "encryption": dump.encryption,
+                "vorbis_header": hex(&dump.vorbis_header),
+                "packets": dump.packets.iter().map(|p| json!({
+                    "page_offset": p.page_offset,
+                    "granule": p.granule,
+                    "data": hex(&p.data),
+                })).collect::<Vec<_>>(),
+                "stopped": dump.stopped,
+            }));
+        }
+
+        println!("Encryption: {}", dump.encryption);
+        println!("Vorbis header, {} bytes:", dump.vorbis_header.len());
+        print!("{}", hexdump(&dump.vorbis_header));
+        for (i, packet) in dump.packets.iter().enumerate() {
+            println!(
+                "Packet {}, in the page at 0x{:X} (granule {}), {} bytes:",
+                i,
+                packet.page_offset,
+                packet.granule,
+                packet.data.len()
+            );
+            print!("{}", hexdump(&packet.data));
+        }
+        Ok(())
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
+}
+
+/// Format [data] as lines of offset, 16 bytes in hex, and the printable ones as text.
+fn hexdump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let _ = write!(out, "  {:08x} ", line * 16);
+        for i in 0..16 {
+            match chunk.get(i) {
+                Some(b) => {
+                    let _ = write!(out, " {:02x}", b);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                char::from(b)
+            } else {
+                '.'
+            }
+        }));
+        out.push_str("|\n");
+    }
+    out
+}
@@ -0,0 +1,276 @@
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use serde_json::{json, Value};
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::surpass::collection::Collection;
+use last_legend_dob::surpass::column_stats::column_stats;
+use last_legend_dob::surpass::sheet_diff::{diff_sheets, RowChange, RowKey};
+use last_legend_dob::surpass::sheet_info::{DataValue, Language};
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Inspect sheets, to help work out what their columns mean.
+#[derive(Args, Debug)]
+pub struct Exd {
+    #[clap(subcommand)]
+    command: ExdCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ExdCommand {
+    Stats(Stats),
+    Diff(Diff),
+    Show(Show),
+}
+
+/// Show statistics for each column of a sheet: the range and distinct count of numbers, and the
+/// most common strings.
+#[derive(Args, Debug)]
+struct Stats {
+    /// The sheet to read, e.g. `Orchestrion`.
+    sheet: String,
+    /// How many of the most common strings to show.
+    #[clap(long, default_value_t = 5)]
+    top: usize,
+    /// The language to read, for sheets that are translated.
+    #[clap(long, default_value = "en")]
+    language: Language,
+}
+
+/// Compare a sheet against an older version of the game, showing the rows that were added,
+/// removed or changed.
+///
+/// The repository given before the command is the new version.
+#[derive(Args, Debug)]
+struct Diff {
+    /// The repository of the old version, e.g. `ffxiv-6.5/sqpack`.
+    old_repository: PathBuf,
+    /// The sheet to compare, e.g. `Orchestrion`.
+    sheet: String,
+    /// The language to read, for sheets that are translated.
+    #[clap(long, default_value = "en")]
+    language: Language,
+}
+
+/// Show one row of a sheet, a column per line with its type.
+#[derive(Args, Debug)]
+struct Show {
+    /// The sheet to read, e.g. `Orchestrion`.
+    sheet: String,
+    /// The id of the row to show. Every sub-row is shown for sheets with sub-rows.
+    row_id: u32,
+    /// The language to read, for sheets that are translated.
+    #[clap(long, default_value = "en")]
+    language: Language,
+}
+
+impl LastLegendCommand for Exd {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let collection = global_args.open_collection()?;
+        match self.command {
+            ExdCommand::Stats(v) => v.run(&global_args, &collection),
+            ExdCommand::Diff(v) => v.run(&global_args, &collection),
+            ExdCommand::Show(v) => v.run(&global_args, &collection),
+        }
+    }
+}
+
+impl Stats {
+    fn run(self, global_args: &GlobalArgs, collection: &Collection) -> Result<(), LastLegendError> {
+        let stats = column_stats(collection.sheet_iter_lang(&self.sheet, self.language)?)?;
+        if global_args.json_output() {
+            let report = stats
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    let top = column
+                        .top(self.top)
+                        .into_iter()
+                        .map(|(value, count)| json!({ "value": value, "count": count }))
+                        .collect::<Vec<_>>();
+                    json!({
+                        "column": i,
+                        "type": format!("{:?}", column.data_type),
+                        "count": column.count,
+                        "distinct": column.distinct(),
+                        "min": column.min(),
+                        "max": column.max(),
+                        "top": top,
+                    })
+                })
+                .collect::<Vec<_>>();
+            return print_json(&report);
+        }
+        for (i, column) in stats.iter().enumerate() {
+            print!(
+                "col_{} ({:?}): {} distinct",
+                i,
+                column.data_type,
+                column.distinct()
+            );
+            match (column.min(), column.max()) {
+                (Some(min), Some(max)) => println!(", min {}, max {}", min, max),
+                _ => {
+                    let top = column
+                        .top(self.top)
+                        .into_iter()
+                        .map(|(value, count)| format!("{:?} x{}", value, count))
+                        .collect::<Vec<_>>();
+                    println!(", top {}", top.join(", "));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Diff {
+    fn run(self, global_args: &GlobalArgs, collection: &Collection) -> Result<(), LastLegendError> {
+        let old_collection =
+            Collection::load(global_args.open_other_repository(self.old_repository))
+                .map_err(|e| e.add_context("Failed to load old collection"))?;
+        let changes = diff_sheets(
+            old_collection.sheet_iter_lang(&self.sheet, self.language)?,
+            collection.sheet_iter_lang(&self.sheet, self.language)?,
+        )?;
+
+        if global_args.json_output() {
+            return print_json(&diff_report(&changes));
+        }
+
+        let (mut added, mut removed, mut changed) = (0, 0, 0);
+        for change in &changes {
+            match change {
+                RowChange::Added(key, row) => {
+                    added += 1;
+                    println!("+ {}: {}", format_key(*key), format_row(row));
+                }
+                RowChange::Removed(key, row) => {
+                    removed += 1;
+                    println!("- {}: {}", format_key(*key), format_row(row));
+                }
+                RowChange::Changed(key, columns) => {
+                    changed += 1;
+                    println!("~ {}:", format_key(*key));
+                    for column in columns {
+                        println!(
+                            "    col_{}: {} -> {}",
+                            column.column,
+                            column.old.as_ref().map_or("(none)".into(), format_value),
+                            column.new.as_ref().map_or("(none)".into(), format_value)
+                        );
+                    }
+                }
+            }
+        }
+        println!(
+            "{} rows added, {} removed, {} changed",
+            added, removed, changed
+        );
+
+        Ok(())
+    }
+}
+
+impl Show {
+    fn run(self, global_args: &GlobalArgs, collection: &Collection) -> Result<(), LastLegendError> {
+        let sheet_info = collection.sheet_info(&self.sheet)?;
+        let rows = collection.read_row(&self.sheet, self.language, self.row_id)?;
+
+        if global_args.json_output() {
+            let report = rows
+                .iter()
+                .map(|(_, sub_row_id, values)| {
+                    let columns = sheet_info
+                        .columns
+                        .iter()
+                        .zip(values)
+                        .enumerate()
+                        .map(|(i, (column, value))| {
+                            json!({
+                                "column": i,
+                                "type": format!("{:?}", column.data_type()),
+                                "value": value,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    json!({
+                        "row_id": self.row_id,
+                        "sub_row_id": sub_row_id,
+                        "columns": columns,
+                    })
+                })
+                .collect::<Vec<_>>();
+            return print_json(&report);
+        }
+
+        let names = (0..sheet_info.columns.len())
+            .map(|i| sheet_info.column_name(i))
+            .collect::<Vec<_>>();
+        let name_width = names.iter().map(|n| n.len()).max().unwrap_or_default();
+        for (_, sub_row_id, values) in &rows {
+            println!("{} {}", self.sheet, format_key((self.row_id, *sub_row_id)));
+            for ((column, value), name) in sheet_info.columns.iter().zip(values).zip(&names) {
+                println!(
+                    "  {:<name_width$}  {:<11}  {}",
+                    name,
+                    format!("{:?}", column.data_type()),
+                    format_value(value),
+                    name_width = name_width
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn diff_report(changes: &[RowChange]) -> Value {
+    let row = |change: &str, (row_id, sub_row_id): RowKey| json!({ "change": change, "row_id": row_id, "sub_row_id": sub_row_id });
+    changes
+        .iter()
+        .map(|change| match change {
+            RowChange::Added(key, values) => {
+                let mut report = row("added", *key);
+                report["values"] = json!(values);
+                report
+            }
+            RowChange::Removed(key, values) => {
+                let mut report = row("removed", *key);
+                report["values"] = json!(values);
+                report
+            }
+            RowChange::Changed(key, columns) => {
+                let mut report = row("changed", *key);
+                report["columns"] = columns
+                    .iter()
+                    .map(|c| json!({ "column": c.column, "old": c.old, "new": c.new }))
+                    .collect();
+                report
+            }
+        })
+        .collect()
+}
+
+fn format_key((row_id, sub_row_id): RowKey) -> String {
+    match sub_row_id {
+        Some(sub_row_id) => format!("{}.{}", row_id, sub_row_id),
+        None => row_id.to_string(),
+    }
+}
+
+fn format_row(row: &[DataValue]) -> String {
+    row.iter().map(format_value).collect::<Vec<_>>().join(", ")
+}
+
+/// Quote strings so empty and padded values stand out.
+fn format_value(value: &DataValue) -> String {
+    match value {
+        DataValue::String(s) => format!("{:?}", s),
+        other => other.to_string(),
+    }
+}
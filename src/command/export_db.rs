@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::surpass::export::SqliteExport;
+use last_legend_dob::surpass::sheet_info::Language;
+
+use crate::command::global_args::GlobalArgs;
+use crate::command::LastLegendCommand;
+use crate::progress::count_progress;
+
+/// Export sheets to a SQLite database, one table per sheet.
+///
+/// Tables are named after their sheet, with the row id in `row_id`, and columns named from the
+/// schema, see `--schema`, or else `col_0`, `col_1`, etc. in the order the sheet header lists
+/// them. Columns are typed `INTEGER`, `REAL` or `TEXT`.
+/// Sheets with sub-rows also have a `sub_row_id`. Tables of sheets exported again are replaced.
+#[derive(Args, Debug)]
+pub struct ExportDb {
+    /// The database to write, created if it doesn't exist.
+    output: PathBuf,
+    /// The sheets to export, e.g. `Item`. Exports every sheet if none are given.
+    #[clap(short, long)]
+    sheet: Vec<String>,
+    /// The language to read, for sheets that are translated.
+    #[clap(long, default_value = "en")]
+    language: Language,
+}
+
+impl LastLegendCommand for ExportDb {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let collection = global_args.open_collection()?;
+        let export_all = self.sheet.is_empty();
+        let sheets = if export_all {
+            collection
+                .sheet_names()
+                .into_iter()
+                .map(str::to_string)
+                .collect()
+        } else {
+            self.sheet
+        };
+        let mut export = SqliteExport::open(&self.output)?;
+
+        let progress = count_progress(sheets.len() as u64, "sheets");
+        let mut rows = 0;
+        for sheet in &sheets {
+            match export.export_sheet(&collection, sheet, self.language) {
+                Ok(count) => rows += count,
+                // Exporting every sheet shouldn't stop at the few that can't be read.
+                Err(e) if export_all && e.category() != ErrorCategory::Cancelled => {
+                    log::warn!("Skipping sheet {}: {}", sheet, e);
+                }
+                Err(e) => return Err(e.add_context(format!("Failed to export sheet {}", sheet))),
+            }
+            progress.inc(1);
+        }
+        progress.finish_and_clear();
+        log::info!(
+            "Exported {} rows of {} sheets to {}",
+            rows,
+            sheets.len(),
+            self.output.display()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{BufWriter, Read};
+use std::path::PathBuf;
+
+use clap::Args;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::modpack::{write_penumbra, write_ttmp2, ModpackFile, ModpackMeta};
+use last_legend_dob::simple_task::read_transformed;
+use last_legend_dob::sqpath::SqPathBuf;
+use last_legend_dob::transformers::TransformerImpl;
+
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::{make_open_options, LastLegendCommand};
+
+/// Package files as a mod, for TexTools or Penumbra.
+///
+/// If the output ends in `.ttmp2`, a TexTools modpack is written, otherwise a Penumbra mod folder.
+/// Files are taken from the repository, unless given as `PATH=LOCAL_FILE`, which packs the local
+/// file as the replacement for `PATH`.
+#[derive(Args, Debug)]
+pub struct ExportModpack {
+    /// The files to pack, as `PATH` or `PATH=LOCAL_FILE`.
+    #[clap(required = true, value_parser = parse_modpack_file)]
+    files: Vec<(SqPathBuf, Option<PathBuf>)>,
+    /// Where to write the modpack.
+    #[clap(short, long)]
+    output: PathBuf,
+    /// Should an existing modpack be overwritten?
+    #[clap(long)]
+    overwrite: bool,
+    /// Transformers to run on files from the repository. They must keep the file's format, since
+    /// the game reads it in place of the original.
+    #[clap(short, long, value_parser = TransformerParser)]
+    transformer: Vec<TransformerImpl>,
+    /// The name of the mod, defaults to the output's file name.
+    #[clap(long)]
+    name: Option<String>,
+    #[clap(long, default_value = "")]
+    author: String,
+    #[clap(long = "mod-version", default_value = "1.0.0")]
+    version: String,
+    #[clap(long, default_value = "")]
+    description: String,
+    #[clap(long, default_value = "")]
+    website: String,
+}
+
+fn parse_modpack_file(s: &str) -> Result<(SqPathBuf, Option<PathBuf>), String> {
+    Ok(match s.split_once('=') {
+        Some((path, local)) => (SqPathBuf::new(path), Some(PathBuf::from(local))),
+        None => (SqPathBuf::new(s), None),
+    })
+}
+
+impl LastLegendCommand for ExportModpack {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        let repo = global_args.open_repository();
+
+        let mut files = Vec::with_capacity(self.files.len());
+        for (game_path, local) in self.files {
+            let content = match local {
+                Some(local) => std::fs::read(&local).map_err(|e| {
+                    LastLegendError::Io(format!("Couldn't read {}", local.display()), e)
+                })?,
+                None => {
+                    let mut transformed = read_transformed(&repo, &game_path, &self.transformer)?;
+                    if transformed.file_name != game_path {
+                        return Err(LastLegendError::Custom(format!(
+                            "Transformers turned '{}' into '{}', but modded files must keep \
+                            their format",
+                            game_path, transformed.file_name
+                        )));
+                    }
+                    let mut content = Vec::new();
+                    transformed.reader.read_to_end(&mut content).map_err(|e| {
+                        LastLegendError::Io(format!("Couldn't read '{}'", game_path), e)
+                    })?;
+                    content
+                }
+            };
+            files.push(ModpackFile { game_path, content });
+        }
+
+        let meta = ModpackMeta {
+            name: self.name.unwrap_or_else(|| {
+                self.output
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }),
+            author: self.author,
+            version: self.version,
+            description: self.description,
+            website: self.website,
+        };
+        if self.output.extension().is_some_and(|e| e == "ttmp2") {
+            let output = make_open_options(self.overwrite)
+                .open(&self.output)
+                .map_err(|e| {
+                    LastLegendError::Io(format!("Couldn't create {}", self.output.display()), e)
+                })?;
+            write_ttmp2(&meta, &files, BufWriter::new(output))?;
+        } else {
+            if !self.overwrite && self.output.join("meta.json").exists() {
+                return Err(LastLegendError::Custom(format!(
+                    "{} already has a mod, use --overwrite to replace it",
+                    self.output.display()
+                )));
+            }
+            write_penumbra(&meta, &files, &self.output)?;
+        }
+        log::info!("Wrote {} files to {}", files.len(), self.output.display());
+        Ok(())
+    }
+}
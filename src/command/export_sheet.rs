@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::PathBuf;
+
+use clap::Args;
+use serde::Serialize;
+use strum::EnumString;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::surpass::sheet_info::{DataValue, Variant};
+
+use crate::command::global_args::GlobalArgs;
+use crate::command::LastLegendCommand;
+
+/// Export all rows of any sheet.
+///
+/// Columns are named from the schema, see `--schema`, or else `col_0`, `col_1`, etc. in the order
+/// the sheet header lists them. Sheets with sub-rows also have a `sub_row_id` after the `row_id`.
+#[derive(Args, Debug)]
+pub struct ExportSheet {
+    /// The sheet to export, e.g. `Orchestrion`.
+    sheet: String,
+    /// The format to export as.
+    #[clap(short, long, default_value = "csv")]
+    format: SheetFormat,
+    /// Write to this file instead of stdout.
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(EnumString, Copy, Clone, Debug)]
+#[strum(serialize_all = "snake_case")]
+enum SheetFormat {
+    Csv,
+    /// A JSON array of `{"row_id": .., "columns": [..]}` objects.
+    Json,
+}
+
+#[derive(Serialize)]
+struct JsonRow {
+    row_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub_row_id: Option<u16>,
+    columns: Vec<DataValue>,
+}
+
+impl LastLegendCommand for ExportSheet {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let collection = global_args.open_collection()?;
+        let sheet_iter = collection.sheet_iter(&self.sheet)?;
+        let sheet_info = sheet_iter.sheet_info().clone();
+        let fixed_row_size = u64::from(sheet_info.fixed_row_size);
+
+        let mut output: Box<dyn Write> = match &self.output {
+            Some(path) => {
+                Box::new(BufWriter::new(File::create(path).map_err(|e| {
+                    LastLegendError::Io("Couldn't create output file".into(), e)
+                })?))
+            }
+            None => Box::new(BufWriter::new(std::io::stdout().lock())),
+        };
+        let write_err = |e| LastLegendError::Io("Couldn't write sheet".into(), e);
+
+        match self.format {
+            SheetFormat::Csv => {
+                let mut header = vec!["row_id".to_string()];
+                if sheet_info.variant == Variant::SubRows {
+                    header.push("sub_row_id".to_string());
+                }
+                header.extend(
+                    (0..sheet_info.columns.len()).map(|i| csv_field(&sheet_info.column_name(i))),
+                );
+                writeln!(output, "{}", header.join(",")).map_err(write_err)?;
+            }
+            SheetFormat::Json => write!(output, "[").map_err(write_err)?,
+        }
+        for (i, row) in sheet_iter.with_sub_rows().enumerate() {
+            let (row_id, sub_row_id, row) = row?;
+            let mut row = Cursor::new(row);
+            let columns = sheet_info
+                .columns
+                .iter()
+                .map(|c| c.read_value(&mut row, fixed_row_size))
+                .collect::<Result<Vec<_>, _>>()?;
+            match self.format {
+                SheetFormat::Csv => {
+                    let mut line = row_id.to_string();
+                    if let Some(sub_row_id) = sub_row_id {
+                        line.push_str(&format!(",{}", sub_row_id));
+                    }
+                    for value in &columns {
+                        line.push(',');
+                        line.push_str(&csv_field(&value.to_string()));
+                    }
+                    writeln!(output, "{}", line).map_err(write_err)?;
+                }
+                SheetFormat::Json => {
+                    if i > 0 {
+                        write!(output, ",").map_err(write_err)?;
+                    }
+                    serde_json::to_writer(
+                        &mut output,
+                        &JsonRow {
+                            row_id,
+                            sub_row_id,
+                            columns,
+                        },
+                    )
+                    .map_err(|e| LastLegendError::Json("Couldn't write row".into(), e))?;
+                }
+            }
+        }
+        if let SheetFormat::Json = self.format {
+            writeln!(output, "]").map_err(write_err)?;
+        }
+        output.flush().map_err(write_err)
+    }
+}
+
+/// Quote a CSV field if it contains anything that would break the line apart.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
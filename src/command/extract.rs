@@ -1,14 +1,22 @@
 use clap::Args;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::{BTreeSet, HashSet};
+use std::io::Read;
 use std::path::Path;
 
 use last_legend_dob::data::repo::Repository;
 use last_legend_dob::error::LastLegendError;
+use last_legend_dob::references::{can_refer, find_references};
+use last_legend_dob::simple_task::{read_transformed, OutputMetadata};
 use last_legend_dob::sqpath::SqPathBuf;
-use last_legend_dob::transformers::TransformerImpl;
+use last_legend_dob::transformers::{TargetFormat, TransformerImpl};
 
-use crate::command::extract_common::extract_file;
-use crate::command::global_args::GlobalArgs;
-use crate::command::{make_open_options, LastLegendCommand};
+use crate::command::extract_common::{
+    extract_file, parse_tag, resolve_transformers, run_with_jobs, ExtractConfig, LoopArgs,
+};
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::LastLegendCommand;
+use crate::progress::ExtractProgress;
 
 /// Extract files from the repository.
 #[derive(Args, Debug)]
@@ -19,29 +27,138 @@ pub struct Extract {
     #[clap(short, long)]
     overwrite: bool,
     /// Transformers to run
-    #[clap(short, long)]
+    #[clap(short, long, value_parser = TransformerParser)]
     transformer: Vec<TransformerImpl>,
+    /// Pick the transformers that turn files into this format, instead of listing them with
+    /// `--transformer`, e.g. `flac`, `ogg`, or `flac-looped` to also repeat the loop.
+    #[clap(long, conflicts_with = "transformer")]
+    output_format: Option<TargetFormat>,
+    /// Fail if a transformer's output doesn't match the format it should produce.
+    #[clap(long)]
+    strict: bool,
+    /// Instead of writing outputs to files, run this command for each, writing the output to its
+    /// stdin, with `{}` replaced by the output path, e.g. `--pipe-to 'rclone rcat remote:{}'`.
+    #[clap(long)]
+    pipe_to: Option<String>,
+    /// Add this tag to audio outputs, e.g. `ALBUM=FFXIV OST`, replacing any tag of the same name.
+    /// Can be given several times.
+    #[clap(long, value_parser = parse_tag)]
+    tag_set: Vec<(String, String)>,
+    /// The sound entry to extract from `.scd` files that have several. Overrides the `entry`
+    /// given to `.scd` transformers, which defaults to 0.
+    #[clap(long)]
+    scd_entry: Option<u16>,
+    /// How many files to extract at once, defaults to the number of CPUs.
+    #[clap(short, long)]
+    jobs: Option<usize>,
+    /// Also extract the files that extracted files refer to, and the files those refer to in
+    /// turn, e.g. the textures of `.uld`, `.mtrl` and `.avfx` files and the materials of `.mdl`
+    /// files. Outputs are written to their full paths in the repository rather than just their
+    /// names, so the references between them still line up.
+    #[clap(short, long)]
+    recursive: bool,
+    #[clap(flatten)]
+    loop_args: LoopArgs,
 }
 
 impl LastLegendCommand for Extract {
     fn run(mut self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
-        let output_open_options = make_open_options(self.overwrite);
+        let extensions = self
+            .files
+            .iter()
+            .map(|f| {
+                Path::new(f.as_str())
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or("")
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        self.transformer = resolve_transformers(
+            self.output_format.as_ref(),
+            &extensions,
+            std::mem::take(&mut self.transformer),
+        )?;
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        let progress = ExtractProgress::new(Some(self.files.len() as u64));
+        let config = ExtractConfig::new(self.overwrite, self.transformer)
+            .with_progress(progress.clone())
+            .with_json_report(global_args.json_output())
+            .with_dry_run(global_args.dry_run)
+            .with_pipe_to(self.pipe_to)
+            .with_journal(global_args.journal.clone())
+            .with_strict(self.strict)
+            .with_tags(self.tag_set)
+            .with_scd_entry(self.scd_entry)
+            .with_loop_args(&self.loop_args);
 
-        let repo = Repository::new(global_args.repository);
+        let repo = global_args.open_repository();
 
         self.files.sort();
 
-        for file in self.files.into_iter() {
-            let base_name = Path::new(file.as_str()).file_stem().unwrap();
-            extract_file(
-                &repo,
-                &file,
-                base_name,
-                &output_open_options,
-                &self.transformer,
-            )?;
-        }
-
-        Ok(())
+        let recursive = self.recursive;
+        let mut seen = self
+            .files
+            .iter()
+            .map(|f| f.as_str().to_ascii_lowercase())
+            .collect::<HashSet<_>>();
+        let mut level = self.files;
+        let result = run_with_jobs(self.jobs, || {
+            let mut referred = false;
+            while !level.is_empty() {
+                let references = std::mem::take(&mut level)
+                    .into_par_iter()
+                    .map(|file| {
+                        let base_name = if recursive {
+                            Path::new(file.as_str())
+                        } else {
+                            Path::new(Path::new(file.as_str()).file_stem().unwrap())
+                        };
+                        let result = extract_file(
+                            &repo,
+                            &config,
+                            &file,
+                            base_name,
+                            &OutputMetadata::default(),
+                        );
+                        match result {
+                            // References are found by scanning, so may not all exist.
+                            Err(e) if referred => {
+                                log::warn!("Couldn't extract referred file {}: {}", file, e);
+                                return Ok(Vec::new());
+                            }
+                            result => result?,
+                        }
+                        if !recursive || !can_refer(&file) {
+                            return Ok(Vec::new());
+                        }
+                        read_references(&repo, &file)
+                    })
+                    .collect::<Result<Vec<_>, LastLegendError>>()?;
+                level = references
+                    .into_iter()
+                    .flatten()
+                    .filter(|f| seen.insert(f.as_str().to_ascii_lowercase()))
+                    .collect();
+                level.sort();
+                progress.add_files(level.len() as u64);
+                referred = true;
+            }
+            Ok(())
+        });
+        progress.finish();
+        result
     }
 }
+
+fn read_references(repo: &Repository, file: &SqPathBuf) -> Result<Vec<SqPathBuf>, LastLegendError> {
+    let mut content = Vec::new();
+    read_transformed(repo, file, &[])?
+        .reader
+        .read_to_end(&mut content)
+        .map_err(|e| LastLegendError::Io(format!("Couldn't read {}", file), e))?;
+    let references = find_references(file, &content);
+    log::debug!("{} refers to {} files", file, references.len());
+    Ok(references)
+}
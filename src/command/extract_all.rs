@@ -1,23 +1,42 @@
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use clap::Args;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use last_legend_dob::data::repo::Repository;
-use last_legend_dob::error::LastLegendError;
+use last_legend_dob::data::index2::{Index2, Index2Entry};
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::manifest::{Journal, ManifestEntry};
+use last_legend_dob::path_db::PathDb;
+use last_legend_dob::simple_task::OutputMetadata;
 use last_legend_dob::sqpath::SqPathBuf;
 use last_legend_dob::transformers::TransformerImpl;
 
-use crate::command::extract_common::extract_entry;
-use crate::command::global_args::GlobalArgs;
-use crate::command::{make_open_options, LastLegendCommand};
+use crate::command::extract_common::{
+    extract_entry, parse_tag, run_with_jobs, ExtractConfig, LoopArgs,
+};
+use crate::command::extract_report::{ExtractReport, ReportEntry, ReportStatus};
+use crate::command::fs_checks::check_output_filesystem;
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::LastLegendCommand;
+use crate::progress::ExtractProgress;
 
 /// Extract files from an index file.
+///
+/// Files are named by their hash, under a directory named after their index file. With
+/// `--path-db`, files whose path is known are written to that path instead.
+///
+/// With `--explode-scd-banks`, `.scd` files with several sound entries, such as the sound effect
+/// banks under `sound/`, are written as a directory of WAV files numbered by entry, e.g.
+/// `sound/se/se_foo/003.wav`, instead of a single output. Transformers and tags don't apply to
+/// them.
 #[derive(Args, Debug)]
 pub struct ExtractAll {
     /// The index file to extract all from.
     files: Vec<PathBuf>,
-    /// The extension to use for the output files.
+    /// The extension to use for output files whose path isn't known.
     #[clap(short = 'e', long, default_value = "dat")]
     output_extension: String,
     /// Should errors be accepted?
@@ -27,39 +46,226 @@ pub struct ExtractAll {
     #[clap(short, long)]
     overwrite: bool,
     /// Transformers to run
-    #[clap(short, long)]
+    #[clap(short, long, value_parser = TransformerParser)]
     transformer: Vec<TransformerImpl>,
+    /// Fail if a transformer's output doesn't match the format it should produce.
+    #[clap(long)]
+    strict: bool,
+    /// Instead of writing outputs to files, run this command for each, writing the output to its
+    /// stdin, with `{}` replaced by the output path, e.g. `--pipe-to 'rclone rcat remote:{}'`.
+    #[clap(long)]
+    pipe_to: Option<String>,
+    /// Add this tag to audio outputs, e.g. `ALBUM=FFXIV OST`, replacing any tag of the same name.
+    /// Can be given several times.
+    #[clap(long, value_parser = parse_tag)]
+    tag_set: Vec<(String, String)>,
+    /// The sound entry to extract from `.scd` files that have several. Overrides the `entry`
+    /// given to `.scd` transformers, which defaults to 0.
+    #[clap(long)]
+    scd_entry: Option<u16>,
+    /// Write every sound entry of `.scd` files that have several as a numbered WAV file.
+    #[clap(long)]
+    explode_scd_banks: bool,
+    /// How many entries to extract at once, defaults to the number of CPUs.
+    #[clap(short, long)]
+    jobs: Option<usize>,
+    /// Record completed entries in this journal, and skip entries it says are already done.
+    /// Lets an interrupted run pick up where it left off, even if outputs were moved.
+    #[clap(long)]
+    journal: Option<PathBuf>,
+    /// Don't check the output filesystem for free space and path limits before starting.
+    #[clap(long)]
+    skip_fs_checks: bool,
+    /// Path lists or packs to look up the paths of entries in, such as the ResLogger exports.
+    /// Lists ending in `.gz` are decompressed. Can be given several times.
+    #[clap(long)]
+    path_db: Vec<PathBuf>,
+    /// Write a JSON report of every entry processed to this file: whether it was extracted,
+    /// the error if not, its output path, sizes and timing.
+    #[clap(long)]
+    report: Option<PathBuf>,
+    #[clap(flatten)]
+    loop_args: LoopArgs,
 }
 
 impl LastLegendCommand for ExtractAll {
     fn run(mut self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
-        let output_open_options = make_open_options(self.overwrite);
-
-        let repo = Repository::new(global_args.repository);
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        let repo = global_args.open_repository();
 
         self.files.sort();
 
-        for file in self.files.into_iter() {
-            let index = repo.load_index_file(Cow::Borrowed(file.as_path()))?;
-            for entry in index.entries() {
-                let entry_hash_hex = format!("{:X}", entry.hash);
-                let res = extract_entry(
-                    &repo,
-                    SqPathBuf::new(&format!("{}.{}", entry_hash_hex, self.output_extension)),
-                    Path::new(file.file_name().unwrap()).join(&entry_hash_hex),
-                    &output_open_options,
-                    &self.transformer,
-                    &index,
-                    entry,
-                );
-                if let Err(e) = res {
-                    if self.force_extract {
-                        eprintln!("Error extracting {}: {}", entry_hash_hex, e);
-                    } else {
-                        return Err(e);
-                    }
+        let indexes = self
+            .files
+            .iter()
+            .map(|file| repo.load_index_file(Cow::Borrowed(file.as_path())))
+            .collect::<Result<Vec<_>, _>>()?;
+        let total = indexes.iter().map(|i| i.entries.len()).sum();
+
+        let path_db = match self.path_db.as_slice() {
+            [] => None,
+            files => Some(PathDb::load_files(files, global_args.path_hasher())?),
+        };
+        // The path to extract an entry as, and the base name of its output.
+        let output_for = |file: &Path, index: &Index2, entry: &Index2Entry| {
+            let known = path_db
+                .as_ref()
+                .and_then(|db| db.path_for(index, entry))
+                .filter(|p| !p.as_str().split('/').any(|c| c == ".." || c.is_empty()));
+            match known {
+                Some(path) => (path.to_owned(), Path::new(path.as_str()).with_extension("")),
+                None => (
+                    SqPathBuf::new(&format!("{:X}.{}", entry.hash, self.output_extension)),
+                    Path::new(file.file_name().unwrap()).join(format!("{:X}", entry.hash)),
+                ),
+            }
+        };
+        if let Some(path_db) = &path_db {
+            let known = indexes
+                .iter()
+                .flat_map(|index| {
+                    index
+                        .entries()
+                        .filter(|e| path_db.path_for(index, e).is_some())
+                })
+                .count();
+            log::info!("Found the paths of {} of {} entries", known, total);
+        }
+
+        if !self.skip_fs_checks {
+            let output_paths = self
+                .files
+                .iter()
+                .zip(&indexes)
+                .flat_map(|(file, index)| {
+                    index.entries().map(|entry| {
+                        let (path, base_name) = output_for(file, index, entry);
+                        match Path::new(path.as_str()).extension() {
+                            Some(extension) => base_name.with_extension(extension),
+                            None => base_name,
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            // Entries are compressed in the dat files, so this is a lower bound.
+            let mut estimated_bytes = 0;
+            for index in &indexes {
+                for dat in index.dat_summaries()? {
+                    estimated_bytes += dat.dat_size.unwrap_or(0);
                 }
             }
+            check_output_filesystem(&output_paths, Some(estimated_bytes))?;
+        }
+
+        let journal = match &self.journal {
+            Some(path) => Some(Arc::new(Journal::open(path)?)),
+            None => global_args.journal.clone(),
+        };
+        let report = self.report.as_ref().map(|_| Arc::new(ExtractReport::new()));
+        let config = ExtractConfig::new(self.overwrite, self.transformer)
+            .with_json_report(global_args.json_output())
+            .with_dry_run(global_args.dry_run)
+            .with_pipe_to(self.pipe_to)
+            .with_report(report.clone())
+            .with_strict(self.strict)
+            .with_tags(self.tag_set.clone())
+            .with_scd_entry(self.scd_entry)
+            .with_scd_banks(self.explode_scd_banks)
+            .with_loop_args(&self.loop_args);
+        // Record the transformers as they run, after the overrides above.
+        let transformers = config
+            .transformers
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        let manifest_entry = |file: &Path, index: &Index2, entry: &Index2Entry| ManifestEntry {
+            tags: self.tag_set.iter().cloned().collect(),
+            transformers: transformers.clone(),
+            ..ManifestEntry::new(
+                repo.repo_path(),
+                index,
+                entry,
+                output_for(file, index, entry).0,
+            )
+        };
+        let already_done = match &journal {
+            Some(journal) => self
+                .files
+                .iter()
+                .zip(&indexes)
+                .flat_map(|(file, index)| index.entries().map(|e| manifest_entry(file, index, e)))
+                .filter(|e| journal.is_completed(e))
+                .count(),
+            None => 0,
+        };
+        if already_done > 0 {
+            log::info!(
+                "Skipping {} entries completed by a previous run",
+                already_done
+            );
+        }
+
+        let progress = ExtractProgress::new(Some((total - already_done) as u64));
+        let config = config.with_progress(progress.clone());
+
+        let failed = AtomicUsize::new(0);
+        let result = run_with_jobs(self.jobs, || {
+            self.files
+                .iter()
+                .zip(&indexes)
+                .try_for_each(|(file, index)| {
+                    index.entries.par_iter().try_for_each(|(_, entry)| {
+                        let journal_entry = manifest_entry(file, index, entry);
+                        if journal
+                            .as_ref()
+                            .is_some_and(|j| j.is_completed(&journal_entry))
+                        {
+                            if let Some(report) = &report {
+                                let mut report_entry = ReportEntry::new(&journal_entry.path);
+                                report_entry.hash = Some(format!("{:X}", entry.hash));
+                                report_entry.status = ReportStatus::Unchanged;
+                                report.record(report_entry);
+                            }
+                            return Ok(());
+                        }
+                        let entry_hash_hex = format!("{:X}", entry.hash);
+                        let (path, base_name) = output_for(file, index, entry);
+                        let res = extract_entry(
+                            &repo,
+                            &config,
+                            path,
+                            base_name,
+                            &OutputMetadata::default(),
+                            index,
+                            entry,
+                        )
+                        .and_then(|()| match &journal {
+                            Some(journal) => journal.record(&journal_entry),
+                            None => Ok(()),
+                        });
+                        match res {
+                            Err(e)
+                                if self.force_extract
+                                    && e.category() != ErrorCategory::Cancelled =>
+                            {
+                                log::error!("Error extracting {}: {}", entry_hash_hex, e);
+                                failed.fetch_add(1, Ordering::Relaxed);
+                                Ok(())
+                            }
+                            res => res,
+                        }
+                    })
+                })
+        });
+        progress.finish();
+        if let (Some(path), Some(report)) = (&self.report, &report) {
+            report.write(path, &repo.game_version())?;
+        }
+        result?;
+
+        let failed = failed.into_inner();
+        if failed > 0 {
+            return Err(LastLegendError::PartialFailure { failed, total });
         }
 
         Ok(())
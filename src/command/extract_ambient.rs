@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use owo_colors::Style;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use strum::EnumString;
+
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::file_name::{sanitize_file_name, FileNameOptions};
+use last_legend_dob::simple_task::OutputMetadata;
+use last_legend_dob::surpass::collection::Collection;
+use last_legend_dob::surpass::known_rows::bgm::BGM;
+use last_legend_dob::surpass::known_rows::bgm_situation::BGMSituation;
+use last_legend_dob::surpass::known_rows::place_name::PlaceName;
+use last_legend_dob::surpass::known_rows::territory_type::TerritoryType;
+use last_legend_dob::surpass::sheet_info::Language;
+use last_legend_dob::transformers::{TargetFormat, TransformerImpl};
+use last_legend_dob::uwu_colors::ErrStyle;
+
+use crate::command::extract_common::{
+    extract_file, parse_tag, report_unresolvable, resolve_transformers, scd_paths_under,
+    split_unresolvable, ExtractConfig, FileNameArgs, LoopArgs,
+};
+use crate::command::fs_checks::check_output_filesystem;
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::LastLegendCommand;
+use crate::progress::ExtractProgress;
+
+/// Extract zone music and ambient sound from the repository.
+///
+/// This can extract:
+///
+/// - Music that changes with the time of day or battle, from the `BGMSituation` sheet. Files are
+///   named by the situation and the time they play at.
+///
+/// - Environment loops and ambience under `sound/env`. No sheet lists these, so they're found
+///   using `--path-list`. Files named after a territory, e.g. `s1t1`, are put in a directory named
+///   after the place, using `TerritoryType` and `PlaceName` sheets.
+#[derive(Args, Debug)]
+pub struct ExtractAmbient {
+    /// Should files be overwritten?
+    #[clap(short, long)]
+    overwrite: bool,
+    /// Ambient sources to include
+    #[clap(short, long, required(true))]
+    ambient_source: Vec<AmbientSource>,
+    /// Transformers to run
+    #[clap(short, long, value_parser = TransformerParser)]
+    transformer: Vec<TransformerImpl>,
+    /// Pick the transformers that turn files into this format, instead of listing them with
+    /// `--transformer`, e.g. `flac`, `ogg`, or `flac-looped` to also repeat the loop.
+    #[clap(long, conflicts_with = "transformer")]
+    output_format: Option<TargetFormat>,
+    /// Fail if a transformer's output doesn't match the format it should produce.
+    #[clap(long)]
+    strict: bool,
+    /// Instead of writing outputs to files, run this command for each, writing the output to its
+    /// stdin, with `{}` replaced by the output path, e.g. `--pipe-to 'rclone rcat remote:{}'`.
+    #[clap(long)]
+    pipe_to: Option<String>,
+    /// Add this tag to audio outputs, e.g. `ALBUM=FFXIV OST`, replacing any tag of the same name.
+    /// Can be given several times.
+    #[clap(long, value_parser = parse_tag)]
+    tag_set: Vec<(String, String)>,
+    /// Tag the output with titles. The output must be FLAC or OGG.
+    #[clap(long)]
+    tags: bool,
+    /// The language of place names, e.g. `ja`, `de`, `fr`, `ko` or `chs`.
+    #[clap(long, default_value = "en")]
+    language: Language,
+    /// A path list to find environment sounds in, such as a ResLogger dump. Required for the
+    /// `environment` source.
+    #[clap(long)]
+    path_list: Option<PathBuf>,
+    /// Don't check the output filesystem for path limits before starting.
+    #[clap(long)]
+    skip_fs_checks: bool,
+    #[clap(flatten)]
+    loop_args: LoopArgs,
+    #[clap(flatten)]
+    file_names: FileNameArgs,
+}
+
+impl LastLegendCommand for ExtractAmbient {
+    fn run(mut self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        self.transformer = resolve_transformers(
+            self.output_format.as_ref(),
+            &["scd"],
+            std::mem::take(&mut self.transformer),
+        )?;
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        let repo = global_args.open_repository();
+        let collection = Collection::load(repo.clone())
+            .map_err(|e| e.add_context("Failed to load collection"))?;
+
+        let mut entries = Vec::new();
+        for source in &self.ambient_source {
+            entries.extend(source.provide(
+                &collection,
+                self.language,
+                self.path_list.as_deref(),
+                &self.file_names.options(),
+            )?);
+        }
+        let (mut entries, unresolvable) = split_unresolvable(entries, |e| &e.file);
+        if !self.tags {
+            for entry in &mut entries {
+                entry.title = None;
+            }
+        }
+
+        if !self.skip_fs_checks {
+            let extension = self
+                .transformer
+                .last()
+                .map_or("scd", |t| t.output_extension());
+            let output_paths = entries
+                .iter()
+                .map(|e| Path::new(&e.output_name).with_extension(extension))
+                .collect::<Vec<_>>();
+            check_output_filesystem(&output_paths, None)?;
+        }
+
+        let progress = ExtractProgress::new(Some(entries.len() as u64));
+        let config = ExtractConfig::new(self.overwrite, self.transformer)
+            .with_progress(progress.clone())
+            .with_json_report(global_args.json_output())
+            .with_dry_run(global_args.dry_run)
+            .with_pipe_to(self.pipe_to)
+            .with_journal(global_args.journal.clone())
+            .with_strict(self.strict)
+            .with_tags(self.tag_set)
+            .with_loop_args(&self.loop_args);
+
+        entries.into_par_iter().for_each(|entry| {
+            let AmbientEntry {
+                output_name,
+                file,
+                title,
+            } = entry;
+            let metadata = OutputMetadata {
+                cover_art: None,
+                tags: title
+                    .map(|title| vec![("TITLE".to_string(), title)])
+                    .unwrap_or_default(),
+            };
+            match extract_file(&repo, &config, &file, output_name, &metadata) {
+                // Reported once below.
+                Err(e) if e.category() == ErrorCategory::Cancelled => {}
+                Err(e) => log::warn!(
+                    "Failed to extract {}: {:#?}",
+                    file.errstyle(Style::new().green()),
+                    e
+                ),
+                Ok(()) => {}
+            }
+        });
+        progress.finish();
+        report_unresolvable(&unresolvable, global_args.json_output())?;
+        crate::CANCELLATION.check()
+    }
+}
+
+#[derive(EnumString, Copy, Clone, Debug)]
+#[strum(serialize_all = "snake_case")]
+enum AmbientSource {
+    Situation,
+    Environment,
+}
+
+/// Directories environment sounds are stored under.
+const ENVIRONMENT_DIRECTORIES: [&str; 1] = ["sound/env/"];
+
+struct AmbientEntry {
+    output_name: OsString,
+    file: String,
+    title: Option<String>,
+}
+
+impl AmbientSource {
+    fn provide(
+        &self,
+        collection: &Collection,
+        language: Language,
+        path_list: Option<&Path>,
+        file_names: &FileNameOptions,
+    ) -> Result<Vec<AmbientEntry>, LastLegendError> {
+        match self {
+            Self::Situation => {
+                let bgm_files: HashMap<u32, String> = collection
+                    .known_rows::<BGM>(language)?
+                    .map(|r| r.map(|(id, b)| (id, b.file)))
+                    .collect::<Result<_, LastLegendError>>()?;
+                let mut entries = Vec::new();
+                for row in collection.known_rows::<BGMSituation>(language)? {
+                    let (id, situation) = row?;
+                    let times = [
+                        ("daytime", situation.daytime),
+                        ("night", situation.night),
+                        ("battle", situation.battle),
+                        ("daybreak", situation.daybreak),
+                        ("twilight", situation.twilight),
+                    ];
+                    for (time, bgm) in times {
+                        let Some(file) = bgm_files.get(&u32::from(bgm)) else {
+                            continue;
+                        };
+                        if bgm == 0 || file.is_empty() {
+                            continue;
+                        }
+                        let title = format!("{:03} - {}", id, time);
+                        entries.push(AmbientEntry {
+                            output_name: Path::new("bgm_situation").join(&title).into_os_string(),
+                            file: file.clone(),
+                            title: Some(title),
+                        });
+                    }
+                }
+                Ok(entries)
+            }
+            Self::Environment => {
+                let path_list = path_list.ok_or_else(|| {
+                    LastLegendError::Custom(
+                        "The environment ambient source needs --path-list".into(),
+                    )
+                })?;
+                let place_names: HashMap<u32, String> = collection
+                    .known_rows::<PlaceName>(language)?
+                    .map(|r| r.map(|(id, p)| (id, p.name)))
+                    .collect::<Result<_, LastLegendError>>()?;
+                let territory_places: HashMap<String, String> = collection
+                    .known_rows::<TerritoryType>(language)?
+                    .filter_map(|r| match r {
+                        Ok((_, t)) => place_names
+                            .get(&u32::from(t.place_name))
+                            .filter(|name| !t.name.is_empty() && !name.is_empty())
+                            .map(|name| Ok((t.name.to_ascii_lowercase(), name.clone()))),
+                        Err(e) => Some(Err(e)),
+                    })
+                    .collect::<Result<_, LastLegendError>>()?;
+
+                scd_paths_under(collection.repository(), path_list, &ENVIRONMENT_DIRECTORIES)?
+                    .map(|path| {
+                        let path = path?;
+                        let stem = Path::new(path.as_str())
+                            .file_stem()
+                            .unwrap()
+                            .to_string_lossy()
+                            .into_owned();
+                        let place = stem
+                            .to_ascii_lowercase()
+                            .split(['_', '-', '.'])
+                            .find_map(|part| territory_places.get(part));
+                        let mut output_name = PathBuf::from("ambient");
+                        if let Some(place) = place {
+                            output_name.push(sanitize_file_name(place, file_names));
+                        }
+                        output_name.push(&stem);
+                        Ok(AmbientEntry {
+                            output_name: output_name.into_os_string(),
+                            file: path.as_str().to_string(),
+                            title: Some(match place {
+                                Some(place) => format!("{} - {}", place, stem),
+                                None => stem,
+                            }),
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+}
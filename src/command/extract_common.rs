@@ -1,33 +1,302 @@
+use clap::Args;
+use indicatif::HumanBytes;
 use last_legend_dob::data::index2::{Index2, Index2Entry};
+use std::borrow::Cow;
 use std::ffi::OsStr;
-use std::fs::OpenOptions;
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::sync::Arc;
+use std::time::Instant;
 
 use last_legend_dob::data::repo::Repository;
 use last_legend_dob::error::LastLegendError;
-use last_legend_dob::simple_task::format_index_entry_for_console;
-use last_legend_dob::simple_task::{create_transformed_reader, TransformedReader};
-use last_legend_dob::sqpath::{SqPath, SqPathBuf};
-use last_legend_dob::transformers::TransformerImpl;
+use last_legend_dob::extract_cache::{settings_fingerprint, CachedOutput, ExtractCache};
+use last_legend_dob::file_name::FileNameOptions;
+use last_legend_dob::manifest::{Journal, ManifestEntry};
+use last_legend_dob::metrics::{self, Metric};
+use last_legend_dob::path_list::read_path_list;
+use last_legend_dob::simple_task::{
+    apply_output_metadata, create_transformed_reader, read_entry_header, transformed_output_path,
+    OutputMetadata, TransformedReader,
+};
+use last_legend_dob::simple_task::{format_index_entry_for_console, read_entry_content};
+use last_legend_dob::sqpath::{SqPath, SqPathBuf, Unresolvable};
+use last_legend_dob::transformers::{
+    explode_scd_bank, plan_transformers, unused_transformers, LoopOptions, ScdOptions,
+    TargetFormat, TransformerImpl,
+};
+
+use crate::command::extract_report::{ExtractReport, ReportEntry, ReportStatus};
+use crate::command::make_open_options;
+use crate::progress::{ExtractProgress, FileProgress};
+
+/// Settings shared by every file extracted by a command.
+#[derive(Debug)]
+pub(crate) struct ExtractConfig {
+    pub output_open_options: OpenOptions,
+    pub transformers: Vec<TransformerImpl>,
+    pub progress: ExtractProgress,
+    /// Fail if transformer output doesn't match what the transformer should produce.
+    pub strict: bool,
+    /// Tags to add to every audio output, replacing any of the same name.
+    pub tags: Vec<(String, String)>,
+    /// Print a JSON line to stdout for each file written, for `--format json`.
+    pub json_report: bool,
+    /// Skip entries already recorded here, and record entries once written.
+    pub journal: Option<Arc<Journal>>,
+    /// Skip entries extracted the same way by an earlier run, and record entries once written.
+    pub cache: Option<Arc<ExtractCache>>,
+    /// Added to cache keys, so a second output of the same entry has its own record.
+    pub cache_variant: Option<&'static str>,
+    /// Run the transformers but don't write anything, only report what would be written.
+    pub dry_run: bool,
+    /// Record what happened to each file here, for `--report`.
+    pub report: Option<Arc<ExtractReport>>,
+    /// Write each output to the stdin of this command instead of a file, for `--pipe-to`.
+    pub pipe_to: Option<String>,
+    /// Write each sound entry of `.scd` files with several as its own WAV, for
+    /// `--explode-scd-banks`.
+    pub scd_banks: bool,
+}
+
+impl ExtractConfig {
+    pub fn new(overwrite: bool, transformers: Vec<TransformerImpl>) -> Self {
+        Self {
+            output_open_options: make_open_options(overwrite),
+            transformers,
+            progress: ExtractProgress::hidden(),
+            strict: false,
+            tags: Vec::new(),
+            json_report: false,
+            journal: None,
+            cache: None,
+            cache_variant: None,
+            dry_run: false,
+            report: None,
+            pipe_to: None,
+            scd_banks: false,
+        }
+    }
+
+    /// Read [entry] from `.scd` files with all `.scd` transformers, if given.
+    pub fn with_scd_entry(mut self, entry: Option<u16>) -> Self {
+        if let Some(entry) = entry {
+            for t in &mut self.transformers {
+                if let Some(options) = t.scd_options() {
+                    *t = t.with_scd_options(ScdOptions { entry, ..options });
+                }
+            }
+        }
+        self
+    }
+
+    /// Apply the loop options given in [args] to all looping transformers.
+    pub fn with_loop_args(mut self, args: &LoopArgs) -> Self {
+        for t in &mut self.transformers {
+            *t = args.apply_to(*t);
+        }
+        self
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<(String, String)>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// The [metadata] for a file, with this config's tags added.
+    fn metadata_for<'a>(&self, metadata: &'a OutputMetadata) -> Cow<'a, OutputMetadata> {
+        if self.tags.is_empty() {
+            return Cow::Borrowed(metadata);
+        }
+        let mut metadata = metadata.clone();
+        metadata
+            .tags
+            .retain(|(key, _)| !self.tags.iter().any(|(k, _)| k.eq_ignore_ascii_case(key)));
+        metadata.tags.extend(self.tags.iter().cloned());
+        Cow::Owned(metadata)
+    }
+
+    pub fn with_json_report(mut self, json_report: bool) -> Self {
+        self.json_report = json_report;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_journal(mut self, journal: Option<Arc<Journal>>) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Option<Arc<ExtractCache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_cache_variant(mut self, cache_variant: Option<&'static str>) -> Self {
+        self.cache_variant = cache_variant;
+        self
+    }
+
+    pub fn with_report(mut self, report: Option<Arc<ExtractReport>>) -> Self {
+        self.report = report;
+        self
+    }
+
+    pub fn with_pipe_to(mut self, pipe_to: Option<String>) -> Self {
+        self.pipe_to = pipe_to;
+        self
+    }
+
+    pub fn with_scd_banks(mut self, scd_banks: bool) -> Self {
+        self.scd_banks = scd_banks;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: ExtractProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+}
+
+/// The transformers to run on files with [input_extensions]: planned to produce [output_format]
+/// if it's given, otherwise [transformers], warning about any that won't run.
+pub(crate) fn resolve_transformers(
+    output_format: Option<&TargetFormat>,
+    input_extensions: &[&str],
+    transformers: Vec<TransformerImpl>,
+) -> Result<Vec<TransformerImpl>, LastLegendError> {
+    let Some(target) = output_format else {
+        if let [input_extension] = input_extensions {
+            for t in unused_transformers(input_extension, &transformers) {
+                log::warn!(
+                    "Transformer {} won't run on .{} files, nothing before it produces .{} files",
+                    t,
+                    input_extension,
+                    t.input_extension()
+                );
+            }
+        }
+        return Ok(transformers);
+    };
+    match input_extensions {
+        [] => Ok(Vec::new()),
+        [input_extension] => {
+            let planned = plan_transformers(input_extension, target)?;
+            log::info!(
+                "Turning .{} files into {} with: {}",
+                input_extension,
+                target,
+                planned
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            Ok(planned)
+        }
+        _ => Err(LastLegendError::Custom(format!(
+            "--output-format needs files of one type, but these are .{}",
+            input_extensions.join(", .")
+        ))),
+    }
+}
+
+/// Options for the `loop_flac` and `loop_ogg` transformers. When given, these override the
+/// options set on the transformers themselves, e.g. `-t loop_ogg:count=2`.
+#[derive(Args, Debug)]
+pub(crate) struct LoopArgs {
+    /// How many extra times to play the looping part of looped audio. Defaults to 1.
+    #[clap(long)]
+    loop_count: Option<u32>,
+    /// Loop audio until it's this many seconds long, then cut it off. Overrides `--loop-count`.
+    #[clap(long)]
+    loop_duration: Option<f64>,
+    /// Seconds to fade out the end of looped audio over, or `none` to stop without fading.
+    /// Defaults to 5.
+    #[clap(long, value_parser = parse_fade)]
+    fade: Option<Fade>,
+    /// Milliseconds to crossfade over where looped audio jumps back to the loop start, which
+    /// avoids clicks at the seam, or `none` to cut straight. Defaults to none.
+    #[clap(long, value_parser = parse_crossfade)]
+    loop_crossfade_ms: Option<Crossfade>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Fade(Option<f64>);
+
+fn parse_fade(s: &str) -> Result<Fade, String> {
+    LoopOptions::parse_fade(s).map(Fade)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Crossfade(Option<u32>);
+
+fn parse_crossfade(s: &str) -> Result<Crossfade, String> {
+    LoopOptions::parse_crossfade(s).map(Crossfade)
+}
+
+impl LoopArgs {
+    /// Apply the given options to [transformer] if it loops.
+    pub fn apply_to(&self, transformer: TransformerImpl) -> TransformerImpl {
+        let Some(mut options) = transformer.loop_options() else {
+            return transformer;
+        };
+        if let Some(loop_count) = self.loop_count {
+            options.loop_count = loop_count;
+        }
+        if let Some(loop_duration) = self.loop_duration {
+            options.target_duration = Some(loop_duration);
+        }
+        if let Some(fade) = self.fade {
+            options.fade = fade.0;
+        }
+        if let Some(crossfade) = self.loop_crossfade_ms {
+            options.crossfade_ms = crossfade.0;
+        }
+        transformer.with_loop_options(options)
+    }
+}
 
 pub(crate) fn extract_file<F: AsRef<SqPath>, O: AsRef<OsStr>>(
     repo: &Repository,
+    config: &ExtractConfig,
     file: F,
     output_base_name: O,
-    output_open_options: &OpenOptions,
-    transformers: &[TransformerImpl],
+    metadata: &OutputMetadata,
 ) -> Result<(), LastLegendError> {
     let file = file.as_ref();
-    let index = repo.get_index_for(file)?;
+    let found = repo.get_index_for(file).and_then(|index| {
+        index.get_entry(file)?;
+        Ok(index)
+    });
+    let index = match found {
+        Ok(index) => index,
+        Err(e) => {
+            if let Some(report) = &config.report {
+                report.record(ReportEntry::new(file.as_str()).with_error(&e));
+            }
+            return Err(e);
+        }
+    };
     let entry = index.get_entry(file)?;
 
     extract_entry(
         repo,
+        config,
         file.to_owned(),
         output_base_name,
-        output_open_options,
-        transformers,
+        metadata,
         &index,
         entry,
     )
@@ -35,33 +304,426 @@ pub(crate) fn extract_file<F: AsRef<SqPath>, O: AsRef<OsStr>>(
 
 pub(crate) fn extract_entry<O: AsRef<OsStr>>(
     repo: &Repository,
+    config: &ExtractConfig,
     file_name: SqPathBuf,
     output_base_name: O,
-    output_open_options: &OpenOptions,
-    transformers: &[TransformerImpl],
+    metadata: &OutputMetadata,
     index: &Arc<Index2>,
     entry: &Index2Entry,
 ) -> Result<(), LastLegendError> {
+    let output_base_name = output_base_name.as_ref();
+    let Some(report) = &config.report else {
+        return repo.retry_if_stale(index, entry, |index, entry| {
+            extract_entry_reported(
+                repo,
+                config,
+                file_name.clone(),
+                output_base_name,
+                metadata,
+                index,
+                entry,
+                &mut ReportEntry::new(""),
+            )
+        });
+    };
+    let started = Instant::now();
+    let mut report_entry = ReportEntry::new(file_name.as_str());
+    report_entry.hash = Some(format!("{:X}", entry.hash));
+    let result = repo.retry_if_stale(index, entry, |index, entry| {
+        extract_entry_reported(
+            repo,
+            config,
+            file_name.clone(),
+            output_base_name,
+            metadata,
+            index,
+            entry,
+            &mut report_entry,
+        )
+    });
+    if let Err(e) = &result {
+        report_entry = report_entry.with_error(e);
+    }
+    report_entry.duration_secs = started.elapsed().as_secs_f64();
+    report.record(report_entry);
+    result
+}
+
+/// [extract_entry], filling in [report_entry] as it goes.
+#[allow(clippy::too_many_arguments)]
+fn extract_entry_reported<O: AsRef<OsStr>>(
+    repo: &Repository,
+    config: &ExtractConfig,
+    file_name: SqPathBuf,
+    output_base_name: O,
+    metadata: &OutputMetadata,
+    index: &Arc<Index2>,
+    entry: &Index2Entry,
+    report_entry: &mut ReportEntry,
+) -> Result<(), LastLegendError> {
+    let journal_entry = config
+        .journal
+        .as_ref()
+        .map(|_| ManifestEntry::new(repo.repo_path(), index, entry, &file_name));
+    if let (Some(journal), Some(journal_entry)) = (&config.journal, &journal_entry) {
+        if journal.is_completed(journal_entry) {
+            log::debug!("Skipping unchanged {}", file_name);
+            config.progress.skip_file();
+            report_entry.status = ReportStatus::Unchanged;
+            return Ok(());
+        }
+    }
+    let cache_record = match &config.cache {
+        Some(cache) => {
+            let (header, _) = read_entry_header(index, entry)?;
+            let expected = CachedOutput {
+                data_file_id: entry.data_file_id,
+                offset: entry.offset_bytes,
+                size: header.uncompressed_size,
+                settings: settings_fingerprint(
+                    &config.transformers,
+                    &config.metadata_for(metadata),
+                ),
+                output: PathBuf::new(),
+            };
+            let mut key = ExtractCache::key(index, entry, &repo.game_version());
+            if let Some(variant) = config.cache_variant {
+                key = format!("{}#{}", key, variant);
+            }
+            if let Some(output) = cache.is_fresh(&key, &expected) {
+                log::debug!(
+                    "Skipping unchanged {}, already extracted to {}",
+                    file_name,
+                    output.display()
+                );
+                config.progress.skip_file();
+                report_entry.status = ReportStatus::Unchanged;
+                report_entry.output = Some(output.clone());
+                return Ok(());
+            }
+            Some((cache, key, expected))
+        }
+        None => None,
+    };
     log::info!(
         "Extracting {}...",
         format_index_entry_for_console(repo.repo_path(), index, entry, &file_name)
     );
-    let TransformedReader {
-        file_name,
-        mut reader,
-    } = create_transformed_reader(index, entry, file_name, transformers)?;
+    if config.report.is_some() {
+        let (header, _) = read_entry_header(index, entry)?;
+        report_entry.input_size = Some(header.uncompressed_size.into());
+    }
+    let mut file_progress = config.progress.start_file(file_name.as_str());
+    let source_path = file_name.as_str().to_string();
+    let started = Instant::now();
+    let bank = if config.scd_banks {
+        explode_scd_bank(
+            &file_name,
+            &read_entry_content(index, entry)?,
+            &index.limits,
+            &index.warnings,
+        )?
+    } else {
+        None
+    };
+    let (output_path, size) = match bank {
+        Some(wavs) => {
+            // The entries go in a directory named like the single output would have been.
+            let output_dir = PathBuf::from(output_base_name.as_ref());
+            let mut size = 0;
+            for (number, mut wav) in wavs {
+                let output_path = output_dir.join(format!("{:03}.wav", number));
+                size += write_output(config, &output_path, &mut wav, &mut file_progress)?;
+            }
+            (output_dir, size)
+        }
+        None => {
+            let mut transformed =
+                create_transformed_reader(index, entry, file_name, &config.transformers)?;
+            if config.strict {
+                transformed = transformed.verify_output()?;
+            }
+            let TransformedReader {
+                file_name,
+                mut reader,
+                ..
+            } = apply_output_metadata(
+                transformed,
+                &config.metadata_for(metadata),
+                &index.warnings,
+            )?;
+            let output_path = transformed_output_path(output_base_name, &file_name);
+            let size = write_output(config, &output_path, &mut reader, &mut file_progress)?;
+            (output_path, size)
+        }
+    };
+    report_entry.output_size = Some(size);
+    if config.dry_run {
+        report_entry.status = ReportStatus::DryRun;
+    } else {
+        file_progress.finish(&output_path);
+        report_entry.status = ReportStatus::Extracted;
+        metrics::global().increment(Metric::FilesExtracted);
+    }
+    // Transformers run lazily as the output is read, so this covers them.
+    if !config.transformers.is_empty() {
+        metrics::global().observe(Metric::TransformSeconds, started.elapsed());
+    }
+
+    report_entry.output = Some(output_path.clone());
+    if config.json_report {
+        let mut report = serde_json::json!({
+            "path": source_path,
+            "hash": entry.hash,
+            "output": output_path,
+            "game_version": repo.game_version(),
+        });
+        if config.dry_run {
+            report["dry_run"] = true.into();
+        }
+        println!(
+            "{}",
+            serde_json::to_string(&report)
+                .map_err(|e| LastLegendError::Json("Couldn't write extraction report".into(), e))?
+        );
+    }
+    if config.dry_run {
+        return Ok(());
+    }
+    if let (Some(journal), Some(journal_entry)) = (&config.journal, &journal_entry) {
+        journal.record(journal_entry)?;
+    }
+    if let Some((cache, key, expected)) = cache_record {
+        cache.record(
+            key,
+            CachedOutput {
+                output: output_path,
+                ..expected
+            },
+        );
+    }
 
-    let output_path = Path::new(&output_base_name)
-        .with_extension(Path::new(file_name.as_str()).extension().unwrap());
+    Ok(())
+}
+
+/// Write all of [reader] to [output_path], or to the `--pipe-to` command, or for dry runs only
+/// read it. Returns how many bytes were written.
+fn write_output<R: Read + ?Sized>(
+    config: &ExtractConfig,
+    output_path: &Path,
+    reader: &mut R,
+    file_progress: &mut FileProgress,
+) -> Result<u64, LastLegendError> {
+    if config.dry_run {
+        let size = std::io::copy(reader, &mut std::io::sink())
+            .map_err(|e| LastLegendError::Io("Couldn't read output".into(), e))?;
+        log::info!(
+            "Would write {} ({})",
+            output_path.display(),
+            HumanBytes(size)
+        );
+        return Ok(size);
+    }
+    if let Some(command) = &config.pipe_to {
+        return pipe_output(command, output_path, reader, file_progress);
+    }
     std::fs::create_dir_all(output_path.parent().unwrap())
         .map_err(|e| LastLegendError::Io("Couldn't create output dirs".into(), e))?;
-    let mut output = output_open_options
+    let mut output = config
+        .output_open_options
         .open(output_path)
         .map_err(|e| LastLegendError::Io("Couldn't open output".into(), e))?;
-    std::io::copy(&mut reader, &mut output)
-        .map_err(|e| LastLegendError::Io("Couldn't write output".into(), e))?;
+    std::io::copy(reader, &mut file_progress.wrap_write(&mut output))
+        .map_err(|e| LastLegendError::Io("Couldn't write output".into(), e))
+}
 
-    log::info!("Done!");
+/// Run [command] with `{}` replaced by [output_path], writing all of [reader] to its stdin.
+/// Returns how many bytes were written.
+fn pipe_output<R: Read + ?Sized>(
+    command: &str,
+    output_path: &Path,
+    reader: &mut R,
+    file_progress: &mut FileProgress,
+) -> Result<u64, LastLegendError> {
+    let mut child = shell_command(command, output_path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| LastLegendError::Io(format!("Couldn't run '{}'", command), e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let copied = std::io::copy(reader, &mut file_progress.wrap_write(&mut stdin));
+    // Close stdin so the command sees the end of the output.
+    drop(stdin);
+    let status = child
+        .wait()
+        .map_err(|e| LastLegendError::Io(format!("Couldn't wait for '{}'", command), e))?;
+    if !status.success() {
+        return Err(LastLegendError::Custom(format!(
+            "'{}' failed for {} with {}",
+            command,
+            output_path.display(),
+            status
+        )));
+    }
+    match copied {
+        Ok(size) => Ok(size),
+        // The command is allowed to stop reading early, e.g. `head`, though then how much it
+        // read isn't known.
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(0),
+        Err(e) => Err(LastLegendError::Io("Couldn't write output".into(), e)),
+    }
+}
+
+/// A command running [command] in the shell, with `{}` standing for [output_path].
+fn shell_command(command: &str, output_path: &Path) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell
+            .arg("/C")
+            .arg(command.replace("{}", &format!("\"{}\"", output_path.display())));
+        shell
+    } else {
+        // Pass the path as an argument rather than pasting it in, so it needs no quoting.
+        let mut shell = Command::new("sh");
+        shell
+            .arg("-c")
+            .arg(command.replace("{}", "\"$1\""))
+            .arg("sh")
+            .arg(output_path);
+        shell
+    }
+}
 
+/// Parse a `KEY=VALUE` tag.
+pub(crate) fn parse_tag(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", s)),
+    }
+}
+
+/// Run [op] in a thread pool with [jobs] threads, or rayon's default number of threads if unset.
+pub(crate) fn run_with_jobs<T: Send>(
+    jobs: Option<usize>,
+    op: impl FnOnce() -> Result<T, LastLegendError> + Send,
+) -> Result<T, LastLegendError> {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(jobs) = jobs {
+        builder = builder.num_threads(jobs);
+    }
+    builder
+        .build()
+        .map_err(|e| LastLegendError::Custom(format!("Couldn't create thread pool: {}", e)))?
+        .install(op)
+}
+
+/// Options for file names made from sheet strings, such as Orchestrion titles.
+#[derive(Args, Debug)]
+pub(crate) struct FileNameArgs {
+    /// Transliterate file names made from sheet strings to ASCII, e.g. `Café — Night` becomes
+    /// `Cafe - Night`. Characters without an ASCII form are replaced.
+    #[clap(long)]
+    ascii_file_names: bool,
+    /// The character to use in place of characters that can't be in file names.
+    #[clap(long, default_value_t = '_')]
+    file_name_replacement: char,
+}
+
+impl FileNameArgs {
+    pub fn options(&self) -> FileNameOptions {
+        FileNameOptions {
+            ascii: self.ascii_file_names,
+            replacement: self.file_name_replacement,
+        }
+    }
+}
+
+/// Read the `.scd` files under any of [directories] from the [path_list], skipping those that
+/// aren't in [repo].
+pub(crate) fn scd_paths_under(
+    repo: &Repository,
+    path_list: &Path,
+    directories: &'static [&'static str],
+) -> Result<impl Iterator<Item = Result<SqPathBuf, LastLegendError>>, LastLegendError> {
+    let path_list = File::open(path_list)
+        .map_err(|e| LastLegendError::Io("Couldn't open path list".into(), e))?;
+    let repo = repo.clone();
+    Ok(read_path_list(BufReader::new(path_list))
+        .filter(move |path| {
+            path.as_ref().map_or(true, |p| {
+                let p = p.as_str().to_ascii_lowercase();
+                p.ends_with(".scd") && directories.iter().any(|dir| p.starts_with(dir))
+            })
+        })
+        .filter(move |path| {
+            path.as_ref().map_or(true, |p| {
+                let found = repo
+                    .get_index_for(p)
+                    .is_ok_and(|index| index.get_entry(p).is_ok());
+                if !found {
+                    log::debug!("Not in repository: {}", p);
+                }
+                found
+            })
+        }))
+}
+
+/// A file named by a sheet that doesn't map to an index file, usually because it's from a patch
+/// newer than this tool.
+pub(crate) struct UnresolvablePath {
+    pub path: String,
+    pub reason: Unresolvable,
+}
+
+/// Split off the [entries] whose [file] doesn't map to an index file, so the rest can still be
+/// extracted.
+pub(crate) fn split_unresolvable<T>(
+    entries: Vec<T>,
+    file: impl Fn(&T) -> &str,
+) -> (Vec<T>, Vec<UnresolvablePath>) {
+    let mut resolvable = Vec::with_capacity(entries.len());
+    let mut unresolvable = Vec::new();
+    for entry in entries {
+        match SqPath::new(file(&entry)).check_resolvable() {
+            Ok(()) => resolvable.push(entry),
+            Err(reason) => unresolvable.push(UnresolvablePath {
+                path: file(&entry).to_string(),
+                reason,
+            }),
+        }
+    }
+    (resolvable, unresolvable)
+}
+
+/// Report the [unresolvable] paths, as a warning, or a JSON line each if [json_report] is set.
+pub(crate) fn report_unresolvable(
+    unresolvable: &[UnresolvablePath],
+    json_report: bool,
+) -> Result<(), LastLegendError> {
+    if json_report {
+        for UnresolvablePath { path, reason } in unresolvable {
+            let report = serde_json::json!({
+                "path": path,
+                "unresolvable": reason.to_string(),
+            });
+            println!(
+                "{}",
+                serde_json::to_string(&report).map_err(|e| LastLegendError::Json(
+                    "Couldn't write unresolvable report".into(),
+                    e
+                ))?
+            );
+        }
+    } else if !unresolvable.is_empty() {
+        let lines = unresolvable
+            .iter()
+            .map(|u| format!("  {}: {}", u.path, u.reason))
+            .collect::<Vec<_>>()
+            .join("\n");
+        log::warn!(
+            "Skipped {} unresolvable files, which may be from a newer patch:\n{}",
+            unresolvable.len(),
+            lines
+        );
+    }
     Ok(())
 }
@@ -1,23 +1,45 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsString;
-use std::path::Path;
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 use clap::Args;
 use owo_colors::Style;
-use rayon::iter::{IntoParallelIterator, ParallelBridge, ParallelIterator};
-use strum::EnumString;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use strum::{Display, EnumString};
 
 use last_legend_dob::data::repo::Repository;
-use last_legend_dob::error::LastLegendError;
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::extract_cache::{ExtractCache, EXTRACT_CACHE_FILE};
+use last_legend_dob::file_name::{sanitize_file_name, FileNameOptions};
+use last_legend_dob::playlist::{Playlist, PlaylistFormat, PlaylistTrack};
+use last_legend_dob::scd::ScdFile;
+use last_legend_dob::simple_task::{read_entry_content, read_icon_png, OutputMetadata};
+use last_legend_dob::sqpath::{Expansion, SqPath};
+use last_legend_dob::surpass::bgm_variants::BgmVariants;
 use last_legend_dob::surpass::collection::Collection;
-use last_legend_dob::surpass::known_rows::bgm::BGM;
+use last_legend_dob::surpass::known_rows::ex_version::ExVersion;
+use last_legend_dob::surpass::known_rows::item::{Item, ORCHESTRION_ROLL};
 use last_legend_dob::surpass::known_rows::orchestrion::Orchestrion;
+use last_legend_dob::surpass::known_rows::orchestrion_category::OrchestrionCategory;
 use last_legend_dob::surpass::known_rows::orchestrion_path::OrchestrionPath;
-use last_legend_dob::transformers::TransformerImpl;
+use last_legend_dob::surpass::known_rows::orchestrion_uiparam::OrchestrionUiparam;
+use last_legend_dob::surpass::sheet_info::Language;
+use last_legend_dob::transformers::{TargetFormat, TransformerImpl};
 use last_legend_dob::uwu_colors::ErrStyle;
 
-use crate::command::extract_common::extract_file;
-use crate::command::global_args::GlobalArgs;
-use crate::command::{make_open_options, LastLegendCommand};
+use crate::command::extract_common::{
+    extract_file, parse_tag, report_unresolvable, resolve_transformers, scd_paths_under,
+    split_unresolvable, ExtractConfig, FileNameArgs, LoopArgs,
+};
+use crate::command::extract_report::ExtractReport;
+use crate::command::fs_checks::check_output_filesystem;
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::LastLegendCommand;
+use crate::progress::ExtractProgress;
 
 /// Extract all music files from the repository.
 ///
@@ -25,7 +47,31 @@ use crate::command::{make_open_options, LastLegendCommand};
 ///
 /// - All Orchestrion parts, with titles and comments. Uses `Orchestrion` and `OrchestrionPath` sheets.
 ///
-/// - All baked-in music pieces, e.g. mount music. Uses `BGM` sheet.
+/// - All baked-in music pieces, e.g. mount music. Uses `BGM` sheet, and `BGMSwitch` and `BGMFade`
+///   for the variants a piece switches or fades between.
+///
+/// - Jingles, i.e. short fanfares and stingers under `sound/zingle` and `sound/battle`. Unlike the
+///   other sources, these aren't resolved from sheets, as no sheet lists them: only the jingles
+///   named in `--path-list` are extracted, and the `jingle` source is rejected without one. Loop
+///   transformers are skipped for them.
+///
+/// `Orchestrion` and `OrchestrionPath` rows are matched by row ID. Mid-patch, one sheet can have
+/// rows the other doesn't; those are skipped with a warning, or fail the run with
+/// `--strict-sheets`.
+///
+/// Orchestrion parts can also get the icon of the roll that unlocks them as cover art. Uses the
+/// `Item` sheet.
+///
+/// With `--playlist`, playlists of the Orchestrion parts are written next to the outputs, one for
+/// each Orchestrion category or expansion, in the order the in-game Orchestrion lists them.
+///
+/// What was extracted is recorded in `.lldob-extract-cache.json`, and later runs skip files whose
+/// content, transformers and tags haven't changed, so re-running after a patch only extracts what
+/// it changed. Use `--overwrite` so changed files can replace their old outputs.
+///
+/// Files that fail to extract are skipped, and the run continues. At the end, a summary of how
+/// many files from each source were extracted and failed is shown, and the command exits with
+/// an error if any failed, unless `--lenient` is given.
 #[derive(Args, Debug)]
 pub struct ExtractMusic {
     /// Should files be overwritten?
@@ -35,107 +81,665 @@ pub struct ExtractMusic {
     #[clap(short, long, required(true))]
     music_source: Vec<MusicSource>,
     /// Transformers to run
-    #[clap(short, long)]
+    #[clap(short, long, value_parser = TransformerParser)]
     transformer: Vec<TransformerImpl>,
+    /// Pick the transformers that turn files into this format, instead of listing them with
+    /// `--transformer`, e.g. `flac`, `ogg`, or `flac-looped` to also repeat the loop.
+    #[clap(long, conflicts_with = "transformer")]
+    output_format: Option<TargetFormat>,
+    /// Fail if a transformer's output doesn't match the format it should produce.
+    #[clap(long)]
+    strict: bool,
+    /// Instead of writing outputs to files, run this command for each, writing the output to its
+    /// stdin, with `{}` replaced by the output path, e.g. `--pipe-to 'rclone rcat remote:{}'`.
+    #[clap(long)]
+    pipe_to: Option<String>,
+    /// Fail instead of skipping Orchestrion parts whose `OrchestrionPath` row is missing or empty.
+    #[clap(long)]
+    strict_sheets: bool,
+    /// Add this tag to audio outputs, e.g. `ALBUM=FFXIV OST`, replacing any tag of the same name.
+    /// Can be given several times.
+    #[clap(long, value_parser = parse_tag)]
+    tag_set: Vec<(String, String)>,
+    /// Embed cover art in Orchestrion parts. The output must be FLAC or OGG.
+    #[clap(long)]
+    cover_art: bool,
+    /// Tag the output with titles, and for Orchestrion parts, the expansion as album, the category
+    /// as grouping and the Orchestrion number as track number. The output must be FLAC or OGG.
+    #[clap(long)]
+    tags: bool,
+    /// The language of Orchestrion names and categories, e.g. `ja`, `de`, `fr`, `ko` or `chs`.
+    #[clap(long, default_value = "en")]
+    language: Language,
+    /// A path list to find jingles in, such as a ResLogger dump. Required for the `jingle` source,
+    /// which can't find jingles any other way.
+    #[clap(long, required_if_eq("music_source", "jingle"))]
+    path_list: Option<PathBuf>,
+    /// Write playlists of the extracted Orchestrion parts, in this format: `m3u8` or `xspf`.
+    #[clap(long)]
+    playlist: Option<PlaylistFormat>,
+    /// How to split Orchestrion parts into playlists: by `category` or by `expansion`.
+    #[clap(long, default_value = "category")]
+    playlist_group: PlaylistGroup,
+    /// Don't check the output filesystem for path limits before starting.
+    #[clap(long)]
+    skip_fs_checks: bool,
+    /// Extract every file, even those that haven't changed since the last run into this
+    /// directory.
+    #[clap(long)]
+    force: bool,
+    /// Write a JSON report of every file processed to this file: whether it was extracted, the
+    /// error if not, its output path, sizes and timing.
+    #[clap(long)]
+    report: Option<PathBuf>,
+    /// Exit successfully even if some files failed to extract.
+    #[clap(long)]
+    lenient: bool,
+    /// Also write a stereo downmix of tracks with more than two channels, such as 5.1 surround
+    /// BGM, next to the original with ` (stereo)` added to its name. The original keeps its
+    /// channel layout. Needs a transformer that encodes audio, e.g. `scd_to_flac`.
+    #[clap(long)]
+    stereo_downmix: bool,
+    #[clap(flatten)]
+    loop_args: LoopArgs,
+    #[clap(flatten)]
+    file_names: FileNameArgs,
 }
 
 impl LastLegendCommand for ExtractMusic {
-    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
-        let output_open_options = make_open_options(self.overwrite);
-
-        let repo = Repository::new(global_args.repository);
+    fn run(mut self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        self.transformer = resolve_transformers(
+            self.output_format.as_ref(),
+            &["scd"],
+            std::mem::take(&mut self.transformer),
+        )?;
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        if self.stereo_downmix && !self.transformer.iter().any(|t| t.with_downmix() != *t) {
+            return Err(LastLegendError::Custom(
+                "--stereo-downmix needs a transformer that encodes audio, e.g. scd_to_flac".into(),
+            ));
+        }
+        let repo = global_args.open_repository();
         let collection = Collection::load(repo.clone())
             .map_err(|e| e.add_context("Failed to load collection"))?;
 
-        let music_sources = self
+        let music_entries = self
             .music_source
+            .iter()
+            .map(|&source| {
+                let entries = source.provide(&SourceOptions {
+                    collection: &collection,
+                    cover_art: self.cover_art,
+                    write_tags: self.tags,
+                    language: self.language,
+                    strict_sheets: self.strict_sheets,
+                    playlists: self.playlist.is_some(),
+                    path_list: self.path_list.as_deref(),
+                    file_names: self.file_names.options(),
+                })?;
+                Ok(entries.map(move |e| e.map(|e| (source, e))))
+            })
+            .collect::<Result<Vec<_>, LastLegendError>>()?
             .into_iter()
-            .map(|source| source.provide(&collection))
+            .flatten()
             .collect::<Result<Vec<_>, LastLegendError>>()?;
-        music_sources
-            .into_par_iter()
-            .flat_map(|i| i.par_bridge())
-            .try_for_each(|entry| -> Result<(), LastLegendError> {
-                let (output_name, file) = entry?;
-                if let Err(e) = extract_file(
-                    &repo,
-                    &file,
+        let (music_entries, unresolvable) = split_unresolvable(music_entries, |(_, e)| &e.file);
+
+        let extension = self
+            .transformer
+            .last()
+            .map_or("scd", |t| t.output_extension());
+        if !self.skip_fs_checks {
+            let output_paths = music_entries
+                .iter()
+                .map(|(_, e)| Path::new(&e.output_name).with_extension(extension))
+                .collect::<Vec<_>>();
+            check_output_filesystem(&output_paths, None)?;
+        }
+
+        let progress = ExtractProgress::new(Some(music_entries.len() as u64));
+        let report = self.report.as_ref().map(|_| Arc::new(ExtractReport::new()));
+        let extract_cache = Arc::new(if self.force {
+            ExtractCache::new(EXTRACT_CACHE_FILE)
+        } else {
+            ExtractCache::open(EXTRACT_CACHE_FILE)
+        });
+        let make_config = |transformers: Vec<TransformerImpl>| {
+            ExtractConfig::new(self.overwrite, transformers)
+                .with_progress(progress.clone())
+                .with_json_report(global_args.json_output())
+                .with_dry_run(global_args.dry_run)
+                .with_pipe_to(self.pipe_to.clone())
+                .with_journal(global_args.journal.clone())
+                .with_report(report.clone())
+                .with_strict(self.strict)
+                .with_tags(self.tag_set.clone())
+                .with_loop_args(&self.loop_args)
+                .with_cache(Some(Arc::clone(&extract_cache)))
+        };
+        // Jingles don't loop, so looping them would just play them twice.
+        let loop_free = |transformers: &[TransformerImpl]| {
+            transformers
+                .iter()
+                .copied()
+                .filter(|t| !t.is_loop())
+                .collect::<Vec<_>>()
+        };
+        let loop_free_config = make_config(loop_free(&self.transformer));
+        let config = make_config(self.transformer.clone());
+        let downmix_configs = self.stereo_downmix.then(|| {
+            let transformers = self
+                .transformer
+                .iter()
+                .map(|t| t.with_downmix())
+                .collect::<Vec<_>>();
+            (
+                make_config(transformers.clone()).with_cache_variant(Some("stereo")),
+                make_config(loop_free(&transformers)).with_cache_variant(Some("stereo")),
+            )
+        });
+
+        let mut counts = Vec::<(MusicSource, SourceCounts)>::new();
+        for &source in &self.music_source {
+            if !counts.iter().any(|(s, _)| *s == source) {
+                counts.push((source, SourceCounts::default()));
+            }
+        }
+        let counts_for =
+            |source: MusicSource| &counts.iter().find(|(s, _)| *s == source).unwrap().1;
+        let cover_art_cache = Mutex::new(HashMap::new());
+        let playlist_parts = Mutex::new(Vec::new());
+        let result = music_entries.into_par_iter().try_for_each(
+            |(source, entry)| -> Result<(), LastLegendError> {
+                let MusicEntry {
                     output_name,
-                    &output_open_options,
-                    &self.transformer,
-                ) {
-                    log::warn!(
-                        "Failed to extract {}: {:#?}",
-                        file.errstyle(Style::new().green()),
-                        e
-                    );
+                    file,
+                    cover_icon,
+                    tags,
+                    loops,
+                    playlist_slot,
+                } = entry;
+                let metadata = OutputMetadata {
+                    cover_art: cover_icon
+                        .and_then(|icon| load_cover_art(&repo, &cover_art_cache, icon)),
+                    tags,
+                };
+                let config = if loops { &config } else { &loop_free_config };
+                match extract_file(&repo, config, &file, &output_name, &metadata) {
+                    Err(e) if e.category() == ErrorCategory::Cancelled => return Err(e),
+                    Err(e) => {
+                        log::warn!(
+                            "Failed to extract {}: {:#?}",
+                            file.errstyle(Style::new().green()),
+                            e
+                        );
+                        counts_for(source).failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(()) => {
+                        counts_for(source).extracted.fetch_add(1, Ordering::Relaxed);
+                        if let Some(slot) = playlist_slot {
+                            let output = Path::new(&output_name).with_extension(extension);
+                            playlist_parts.lock().unwrap().push((output, slot));
+                        }
+                    }
+                }
+                if let Some((downmix_config, loop_free_downmix_config)) = &downmix_configs {
+                    let config = if loops {
+                        downmix_config
+                    } else {
+                        loop_free_downmix_config
+                    };
+                    let result = scd_channels(&repo, &file).and_then(|channels| {
+                        if channels <= 2 {
+                            return Ok(());
+                        }
+                        progress.add_files(1);
+                        let mut output_name = output_name.clone();
+                        output_name.push(STEREO_SUFFIX);
+                        extract_file(&repo, config, &file, &output_name, &metadata)
+                    });
+                    match result {
+                        Err(e) if e.category() == ErrorCategory::Cancelled => return Err(e),
+                        Err(e) => {
+                            log::warn!(
+                                "Failed to extract a stereo downmix of {}: {:#?}",
+                                file.errstyle(Style::new().green()),
+                                e
+                            );
+                            counts_for(source).failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok(()) => {}
+                    }
                 }
 
                 Ok(())
-            })?;
+            },
+        );
+        progress.finish();
+        extract_cache.save()?;
+        if let (Some(path), Some(report)) = (&self.report, &report) {
+            report.write(path, &repo.game_version())?;
+        }
+        if let (Some(format), Ok(())) = (self.playlist, &result) {
+            write_playlists(
+                playlist_parts.into_inner().unwrap(),
+                self.playlist_group,
+                format,
+                &self.file_names.options(),
+                global_args.dry_run,
+            )?;
+        }
+        report_unresolvable(&unresolvable, global_args.json_output())?;
+        result?;
 
+        report_summary(&counts, unresolvable.len(), global_args.json_output())?;
+        let failed = counts
+            .iter()
+            .map(|(_, c)| c.failed.load(Ordering::Relaxed))
+            .sum();
+        if failed > 0 && !self.lenient {
+            let total = counts
+                .iter()
+                .map(|(_, c)| c.extracted.load(Ordering::Relaxed))
+                .sum::<usize>()
+                + failed;
+            return Err(LastLegendError::PartialFailure { failed, total });
+        }
         Ok(())
     }
 }
 
-#[derive(EnumString, Copy, Clone, Debug)]
+/// How many files from a source were extracted, and how many failed.
+#[derive(Default)]
+struct SourceCounts {
+    extracted: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+/// Show how many files from each source were extracted and failed, as a table, or a JSON line if
+/// [json_report] is set.
+fn report_summary(
+    counts: &[(MusicSource, SourceCounts)],
+    unresolvable: usize,
+    json_report: bool,
+) -> Result<(), LastLegendError> {
+    let rows = counts
+        .iter()
+        .map(|(source, c)| {
+            (
+                source.to_string(),
+                c.extracted.load(Ordering::Relaxed),
+                c.failed.load(Ordering::Relaxed),
+            )
+        })
+        .collect::<Vec<_>>();
+    if json_report {
+        let report = serde_json::json!({
+            "summary": rows
+                .iter()
+                .map(|(source, extracted, failed)| serde_json::json!({
+                    "source": source,
+                    "extracted": extracted,
+                    "failed": failed,
+                }))
+                .collect::<Vec<_>>(),
+            "unresolvable": unresolvable,
+        });
+        println!(
+            "{}",
+            serde_json::to_string(&report)
+                .map_err(|e| LastLegendError::Json("Couldn't write summary".into(), e))?
+        );
+        return Ok(());
+    }
+    let mut table = format!("{:<12} {:>10} {:>8}", "Source", "Extracted", "Failed");
+    for (source, extracted, failed) in &rows {
+        table.push_str(&format!("\n{:<12} {:>10} {:>8}", source, extracted, failed));
+    }
+    if unresolvable > 0 {
+        table.push_str(&format!("\n{} unresolvable files skipped", unresolvable));
+    }
+    log::info!("Summary:\n{}", table);
+    Ok(())
+}
+
+#[derive(EnumString, Display, Copy, Clone, Debug, Eq, PartialEq)]
 #[strum(serialize_all = "snake_case")]
 enum MusicSource {
     Bgm,
     Orchestrion,
+    Jingle,
+}
+
+#[derive(EnumString, Copy, Clone, Debug)]
+#[strum(serialize_all = "snake_case")]
+enum PlaylistGroup {
+    Category,
+    Expansion,
+}
+
+/// Where an Orchestrion part goes in playlists.
+struct PlaylistSlot {
+    number: u32,
+    title: String,
+    /// The part's category, if it has one.
+    category: Option<PlaylistCategory>,
+    /// The part's position in its category.
+    order: u16,
+    /// The expansion's number and name.
+    expansion: (u8, String),
 }
 
-type MusicSourceProvider =
-    Box<dyn Iterator<Item = Result<(OsString, String), LastLegendError>> + Send>;
+struct PlaylistCategory {
+    id: u32,
+    order: u8,
+    name: String,
+}
+
+/// Write a playlist of [parts] for each group, in the order the in-game Orchestrion lists them.
+fn write_playlists(
+    parts: Vec<(PathBuf, PlaylistSlot)>,
+    group: PlaylistGroup,
+    format: PlaylistFormat,
+    file_names: &FileNameOptions,
+    dry_run: bool,
+) -> Result<(), LastLegendError> {
+    // Playlist titles and tracks by the playlist's position, with tracks by their position.
+    type Groups = BTreeMap<(u8, u32), (String, Vec<((u8, u32, u16, u32), PlaylistTrack)>)>;
+    let mut groups = Groups::new();
+    for (location, slot) in parts {
+        let category_key = slot
+            .category
+            .as_ref()
+            .map_or((u8::MAX, u32::MAX), |c| (c.order, c.id));
+        let (group_key, title) = match group {
+            PlaylistGroup::Category => (
+                category_key,
+                slot.category
+                    .as_ref()
+                    .map_or_else(|| "Uncategorized".to_string(), |c| c.name.clone()),
+            ),
+            PlaylistGroup::Expansion => ((slot.expansion.0, 0), slot.expansion.1.clone()),
+        };
+        let track = PlaylistTrack {
+            location,
+            title: slot.title,
+            album: Some(slot.expansion.1),
+            track_number: Some(slot.number),
+        };
+        groups
+            .entry(group_key)
+            .or_insert_with(|| (title, Vec::new()))
+            .1
+            .push((
+                (category_key.0, category_key.1, slot.order, slot.number),
+                track,
+            ));
+    }
+
+    for (i, (title, mut tracks)) in groups.into_values().enumerate() {
+        tracks.sort_by_key(|(key, _)| *key);
+        let playlist = Playlist {
+            title: format!("Orchestrion - {}", title),
+            tracks: tracks.into_iter().map(|(_, track)| track).collect(),
+        };
+        let path = PathBuf::from(format!(
+            "Orchestrion {:02} - {}.{}",
+            i + 1,
+            sanitize_file_name(&title, file_names),
+            format.extension()
+        ));
+        if dry_run {
+            log::info!(
+                "Would write playlist {} ({} tracks)",
+                path.display(),
+                playlist.tracks.len()
+            );
+            continue;
+        }
+        let file = File::create(&path)
+            .map_err(|e| LastLegendError::Io(format!("Couldn't create {}", path.display()), e))?;
+        playlist.write(format, BufWriter::new(file))?;
+        log::info!(
+            "Wrote playlist {} ({} tracks)",
+            path.display(),
+            playlist.tracks.len()
+        );
+    }
+    Ok(())
+}
+
+/// Directories jingles are stored under.
+const JINGLE_DIRECTORIES: [&str; 2] = ["sound/zingle/", "sound/battle/"];
+
+/// Added to the output names of stereo downmixes, see `--stereo-downmix`.
+const STEREO_SUFFIX: &str = " (stereo)";
+
+/// How many channels the first sound entry of the `.scd` file [file] has.
+fn scd_channels(repo: &Repository, file: &str) -> Result<u32, LastLegendError> {
+    let index = repo.get_index_for(file)?;
+    let content = read_entry_content(&index, index.get_entry(file)?)?;
+    Ok(ScdFile::parse(Cursor::new(&*content))?.entry(0)?.channels)
+}
+
+/// Load the cover art for an icon, sharing it between all tracks that use the same icon.
+fn load_cover_art(
+    repo: &Repository,
+    cache: &Mutex<HashMap<u32, Option<Arc<Vec<u8>>>>>,
+    icon: u32,
+) -> Option<Arc<Vec<u8>>> {
+    if let Some(art) = cache.lock().unwrap().get(&icon) {
+        return art.clone();
+    }
+    let art = match read_icon_png(repo, icon) {
+        Ok(png) => Some(Arc::new(png)),
+        Err(e) => {
+            log::warn!("Failed to load cover art from icon {}: {:#?}", icon, e);
+            None
+        }
+    };
+    cache.lock().unwrap().insert(icon, art.clone());
+    art
+}
+
+struct MusicEntry {
+    output_name: OsString,
+    file: String,
+    /// The icon to use as cover art, if any.
+    cover_icon: Option<u32>,
+    /// Vorbis comments to write to the output.
+    tags: Vec<(String, String)>,
+    /// Whether the piece is meant to loop, and loop transformers should apply.
+    loops: bool,
+    /// Where the piece goes in playlists, for Orchestrion parts when playlists are written.
+    playlist_slot: Option<PlaylistSlot>,
+}
+
+struct SourceOptions<'a> {
+    collection: &'a Collection,
+    cover_art: bool,
+    write_tags: bool,
+    language: Language,
+    strict_sheets: bool,
+    playlists: bool,
+    path_list: Option<&'a Path>,
+    file_names: FileNameOptions,
+}
+
+type MusicSourceProvider = Box<dyn Iterator<Item = Result<MusicEntry, LastLegendError>> + Send>;
 
 impl MusicSource {
-    fn provide(&self, collection: &Collection) -> Result<MusicSourceProvider, LastLegendError> {
+    fn provide(&self, options: &SourceOptions) -> Result<MusicSourceProvider, LastLegendError> {
+        let SourceOptions {
+            collection,
+            cover_art,
+            write_tags,
+            language,
+            strict_sheets,
+            playlists,
+            path_list,
+            file_names,
+        } = *options;
         let iter: MusicSourceProvider = match self {
-            Self::Bgm => Box::new(
-                collection
-                    .sheet_iter("BGM")?
-                    .deserialize_rows::<BGM>()
-                    .filter_map(|row| {
-                        let row = match row {
-                            Ok(v) => v,
-                            Err(e) => return Some(Err(e)),
-                        };
-                        (!row.file.is_empty()).then(|| {
-                            Ok((
-                                Path::new(&row.file).with_extension("").into_os_string(),
-                                row.file,
-                            ))
-                        })
-                    }),
-            ),
+            Self::Bgm => {
+                // Switches and fades can only lead to tracks with a BGM row, but resolving them
+                // keeps tracks that several ids share from being extracted more than once.
+                let files = BgmVariants::load(collection, language)?.all_scd_paths();
+                Box::new(files.into_iter().map(move |file| {
+                    let output_name = Path::new(&file).with_extension("");
+                    // BGM has no names, so the file name is the best title there is.
+                    let tags = match output_name.file_name() {
+                        Some(title) if write_tags => {
+                            vec![("TITLE".to_string(), title.to_string_lossy().into_owned())]
+                        }
+                        _ => Vec::new(),
+                    };
+                    Ok(MusicEntry {
+                        output_name: output_name.into_os_string(),
+                        file,
+                        cover_icon: None,
+                        tags,
+                        loops: true,
+                        playlist_slot: None,
+                    })
+                }))
+            }
             Self::Orchestrion => {
-                let orch_paths: Vec<String> = collection
-                    .sheet_iter("OrchestrionPath")?
-                    .deserialize_rows::<OrchestrionPath>()
-                    .map(|r| r.map(|o| o.file_name))
+                let orch_paths: HashMap<u32, String> = collection
+                    .known_rows::<OrchestrionPath>(language)?
+                    .filter(|r| r.as_ref().map_or(true, |(_, o)| !o.file_name.is_empty()))
+                    .map(|r| r.map(|(id, o)| (id, o.file_name)))
                     .collect::<Result<_, LastLegendError>>()?;
-                Box::new(
+                let parts: Vec<(u32, Orchestrion)> = collection
+                    .known_rows::<Orchestrion>(language)?
+                    .collect::<Result<_, LastLegendError>>()?;
+                let part_ids = parts.iter().map(|(id, _)| *id).collect::<HashSet<_>>();
+                let mut orphan_paths = orch_paths
+                    .keys()
+                    .filter(|id| !part_ids.contains(id))
+                    .collect::<Vec<_>>();
+                orphan_paths.sort();
+                for id in orphan_paths {
+                    log::warn!("OrchestrionPath {} has no Orchestrion row, skipping", id);
+                }
+                let categories: HashMap<u32, OrchestrionCategory> = collection
+                    .known_rows(language)?
+                    .collect::<Result<_, LastLegendError>>()?;
+                let part_params: HashMap<u32, OrchestrionUiparam> = collection
+                    .known_rows(language)?
+                    .collect::<Result<_, LastLegendError>>()?;
+                // Rolls are found through their item, which refers to the Orchestrion part.
+                let roll_icons: HashMap<u32, u32> = if cover_art {
+                    collection
+                        .known_rows::<Item>(language)?
+                        .filter_map(|r| match r {
+                            Ok((_, item)) => (item.filter_group == ORCHESTRION_ROLL
+                                && item.icon != 0)
+                                .then_some(Ok((item.additional_data, item.icon))),
+                            Err(e) => Some(Err(e)),
+                        })
+                        .collect::<Result<_, LastLegendError>>()?
+                } else {
+                    HashMap::new()
+                };
+                let expansion_names: HashMap<u32, String> = if playlists || write_tags {
                     collection
-                        .sheet_iter("Orchestrion")?
-                        .deserialize_rows::<Orchestrion>()
-                        .enumerate()
-                        .filter_map(move |(i, row)| {
-                            let row = match row {
-                                Ok(v) => v,
-                                Err(e) => return Some(Err(e)),
+                        .known_rows::<ExVersion>(language)?
+                        .map(|r| r.map(|(id, e)| (id, e.name)))
+                        .collect::<Result<_, LastLegendError>>()?
+                } else {
+                    HashMap::new()
+                };
+                Box::new(parts.into_iter().filter_map(move |(i, row)| {
+                    if row.name.is_empty() {
+                        return None;
+                    }
+                    let Some(orch_path) = orch_paths.get(&i).cloned() else {
+                        if strict_sheets {
+                            return Some(Err(LastLegendError::Custom(format!(
+                                "Orchestrion {} ({}) has no path",
+                                i, row.name
+                            ))));
+                        }
+                        log::warn!("Orchestrion {} ({}) has no path, skipping", i, row.name);
+                        return None;
+                    };
+                    let extract_name = Path::new(&orch_path).with_file_name(format!(
+                        "{:03} - {}",
+                        i,
+                        sanitize_file_name(&row.name, &file_names)
+                    ));
+                    let params = part_params.get(&i);
+                    let category_id = params.map(|p| u32::from(p.category));
+                    let category = category_id.and_then(|c| categories.get(&c));
+                    let expansion =
+                        Expansion::parse_from_sqpath(SqPath::new(&orch_path.to_ascii_lowercase()))
+                            .0
+                            .file_name_prefix();
+                    let expansion_name = expansion_names
+                        .get(&u32::from(expansion))
+                        .filter(|n| !n.is_empty())
+                        .cloned()
+                        .unwrap_or_else(|| format!("Expansion {}", expansion));
+                    let mut tags = Vec::new();
+                    if write_tags {
+                        tags.push(("ALBUM".to_string(), expansion_name.clone()));
+                        if let Some(category) = category {
+                            tags.push(("GROUPING".to_string(), category.name.clone()));
+                        }
+                        tags.push(("TRACKNUMBER".to_string(), i.to_string()));
+                        if !row.description.is_empty() {
+                            tags.push(("DESCRIPTION".to_string(), row.description));
+                        }
+                        tags.push(("TITLE".to_string(), row.name.clone()));
+                    }
+                    let playlist_slot = playlists.then(|| PlaylistSlot {
+                        number: i,
+                        title: row.name.clone(),
+                        category: category.zip(category_id).map(|(c, id)| PlaylistCategory {
+                            id,
+                            order: c.order,
+                            name: c.name.clone(),
+                        }),
+                        order: params.map_or(u16::MAX, |p| p.order),
+                        expansion: (expansion, expansion_name),
+                    });
+                    Some(Ok(MusicEntry {
+                        output_name: extract_name.into_os_string(),
+                        file: orch_path,
+                        cover_icon: roll_icons.get(&i).copied(),
+                        tags,
+                        loops: true,
+                        playlist_slot,
+                    }))
+                }))
+            }
+            Self::Jingle => {
+                let path_list = path_list.ok_or_else(|| {
+                    LastLegendError::Custom("The jingle music source needs --path-list".into())
+                })?;
+                Box::new(
+                    scd_paths_under(collection.repository(), path_list, &JINGLE_DIRECTORIES)?.map(
+                        move |path| {
+                            let path = path?;
+                            let output_name = Path::new(path.as_str()).with_extension("");
+                            let tags = match output_name.file_name() {
+                                Some(title) if write_tags => vec![(
+                                    "TITLE".to_string(),
+                                    title.to_string_lossy().into_owned(),
+                                )],
+                                _ => Vec::new(),
                             };
-                            (!row.name.is_empty()).then(|| {
-                                let orch_path = String::from(&orch_paths[i]);
-                                let safe_file_name = row
-                                    .name
-                                    .chars()
-                                    .map(|c| if "<>:\"/\\|?*".contains(c) { '_' } else { c })
-                                    .collect::<String>();
-                                let extract_name = Path::new(&orch_path)
-                                    .with_file_name(format!("{:03} - {}", i, safe_file_name));
-                                Ok((extract_name.into_os_string(), orch_path))
+                            Ok(MusicEntry {
+                                output_name: output_name.into_os_string(),
+                                file: path.as_str().to_string(),
+                                cover_icon: None,
+                                tags,
+                                loops: false,
+                                playlist_slot: None,
                             })
-                        }),
+                        },
+                    ),
                 )
             }
         };
@@ -0,0 +1,143 @@
+//! The report of what an extraction did, written with `--report`.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use last_legend_dob::data::game_version::GameVersion;
+use last_legend_dob::error::LastLegendError;
+
+/// Collects an entry for each file a command processed, from any thread.
+#[derive(Debug)]
+pub(crate) struct ExtractReport {
+    started: Instant,
+    entries: Mutex<Vec<ReportEntry>>,
+}
+
+/// What happened to a single file.
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct ReportEntry {
+    pub path: String,
+    /// The entry's hash in its index file, if it was found.
+    pub hash: Option<String>,
+    pub status: ReportStatus,
+    /// Why the file failed, for [ReportStatus::Failed].
+    pub error: Option<String>,
+    pub error_category: Option<String>,
+    pub output: Option<PathBuf>,
+    /// The size of the entry in the repository, before transformers ran.
+    pub input_size: Option<u64>,
+    /// The size of the output, after transformers ran.
+    pub output_size: Option<u64>,
+    pub duration_secs: f64,
+}
+
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReportStatus {
+    Extracted,
+    /// Not written, for `--dry-run`.
+    DryRun,
+    /// Already extracted by an earlier run, according to the journal or extract cache.
+    Unchanged,
+    Failed,
+}
+
+/// How many files ended up in each status.
+#[derive(Serialize, Debug, Default, Copy, Clone)]
+pub(crate) struct ReportCounts {
+    pub extracted: usize,
+    pub dry_run: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+}
+
+#[derive(Serialize)]
+struct ReportFile<'a> {
+    game_version: &'a GameVersion,
+    duration_secs: f64,
+    counts: ReportCounts,
+    entries: &'a [ReportEntry],
+}
+
+impl ReportEntry {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            hash: None,
+            status: ReportStatus::Failed,
+            error: None,
+            error_category: None,
+            output: None,
+            input_size: None,
+            output_size: None,
+            duration_secs: 0.0,
+        }
+    }
+
+    /// Mark the file as failed with [error].
+    pub fn with_error(mut self, error: &LastLegendError) -> Self {
+        self.status = ReportStatus::Failed;
+        self.error = Some(error.to_string());
+        self.error_category = Some(format!("{:?}", error.category()));
+        self
+    }
+}
+
+impl ExtractReport {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn record(&self, entry: ReportEntry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    pub fn counts(&self) -> ReportCounts {
+        let mut counts = ReportCounts::default();
+        for entry in self.entries.lock().unwrap().iter() {
+            match entry.status {
+                ReportStatus::Extracted => counts.extracted += 1,
+                ReportStatus::DryRun => counts.dry_run += 1,
+                ReportStatus::Unchanged => counts.unchanged += 1,
+                ReportStatus::Failed => counts.failed += 1,
+            }
+        }
+        counts
+    }
+
+    /// The entries recorded so far, sorted by path.
+    pub fn entries(&self) -> Vec<ReportEntry> {
+        let mut entries = self.entries.lock().unwrap().clone();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    }
+
+    /// Write the report as JSON to [path].
+    pub fn write(&self, path: &Path, game_version: &GameVersion) -> Result<(), LastLegendError> {
+        let file = File::create(path)
+            .map_err(|e| LastLegendError::Io("Couldn't create report file".into(), e))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(
+            &mut writer,
+            &ReportFile {
+                game_version,
+                duration_secs: self.started.elapsed().as_secs_f64(),
+                counts: self.counts(),
+                entries: &self.entries(),
+            },
+        )
+        .map_err(|e| LastLegendError::Json("Couldn't write report".into(), e))?;
+        writer
+            .flush()
+            .map_err(|e| LastLegendError::Io("Couldn't write report".into(), e))?;
+        log::info!("Wrote report to {}", path.display());
+        Ok(())
+    }
+}
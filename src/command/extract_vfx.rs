@@ -0,0 +1,91 @@
+use std::io::Read;
+use std::path::Path;
+
+use clap::Args;
+
+use last_legend_dob::avfx::Avfx;
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::simple_task::{read_transformed, OutputMetadata};
+use last_legend_dob::sqpath::{SqPath, SqPathBuf};
+use last_legend_dob::transformers::TransformerImpl;
+
+use crate::command::extract_common::{extract_file, ExtractConfig};
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::LastLegendCommand;
+
+/// Extract `.avfx` VFX files as JSON, listing their timelines, emitters and textures.
+#[derive(Args, Debug)]
+pub struct ExtractVfx {
+    /// The `.avfx` files to extract.
+    files: Vec<SqPathBuf>,
+    /// Should files be overwritten?
+    #[clap(short, long)]
+    overwrite: bool,
+    /// Also extract the textures each file uses, into a directory named after it.
+    #[clap(long)]
+    assets: bool,
+    /// Transformers to run on the textures, e.g. `tex_to_png`.
+    #[clap(short, long, value_parser = TransformerParser)]
+    transformer: Vec<TransformerImpl>,
+}
+
+impl LastLegendCommand for ExtractVfx {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        let repo = global_args.open_repository();
+        let json_config = ExtractConfig::new(
+            self.overwrite,
+            vec![TransformerImpl::AvfxToJson(*repo.limits())],
+        )
+        .with_json_report(global_args.json_output())
+        .with_dry_run(global_args.dry_run)
+        .with_journal(global_args.journal.clone());
+        let asset_config = ExtractConfig::new(self.overwrite, self.transformer)
+            .with_json_report(global_args.json_output())
+            .with_dry_run(global_args.dry_run)
+            .with_journal(global_args.journal.clone());
+
+        for file in self.files {
+            let base_name = Path::new(file.as_str()).file_stem().unwrap();
+            extract_file(
+                &repo,
+                &json_config,
+                &file,
+                base_name,
+                &OutputMetadata::default(),
+            )?;
+            if !self.assets {
+                continue;
+            }
+
+            let mut content = Vec::new();
+            read_transformed(&repo, &file, &[])?
+                .reader
+                .read_to_end(&mut content)
+                .map_err(|e| LastLegendError::Io("Couldn't read AVFX".into(), e))?;
+            for texture in Avfx::parse_with_limits(&content, repo.limits())?.textures() {
+                let texture = SqPath::new(texture);
+                let Some(texture_name) = Path::new(texture.as_str()).file_stem() else {
+                    log::warn!(
+                        "Skipping texture {} of {}, it isn't a file path",
+                        texture,
+                        file
+                    );
+                    continue;
+                };
+                let result = extract_file(
+                    &repo,
+                    &asset_config,
+                    texture,
+                    Path::new(base_name).join(texture_name),
+                    &OutputMetadata::default(),
+                );
+                if let Err(e) = result {
+                    log::warn!("Couldn't extract texture {} of {}: {}", texture, file, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
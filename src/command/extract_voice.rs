@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use owo_colors::Style;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::file_name::{sanitize_file_name, FileNameOptions};
+use last_legend_dob::simple_task::OutputMetadata;
+use last_legend_dob::sqpath::SqPathBuf;
+use last_legend_dob::surpass::collection::Collection;
+use last_legend_dob::surpass::known_rows::cutscene::Cutscene;
+use last_legend_dob::surpass::known_rows::quest::Quest;
+use last_legend_dob::surpass::sheet_info::Language;
+use last_legend_dob::transformers::{TargetFormat, TransformerImpl};
+use last_legend_dob::uwu_colors::ErrStyle;
+
+use crate::command::extract_common::{
+    extract_file, parse_tag, resolve_transformers, scd_paths_under, ExtractConfig, FileNameArgs,
+};
+use crate::command::fs_checks::check_output_filesystem;
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::LastLegendCommand;
+use crate::progress::ExtractProgress;
+
+/// Extract voice lines from cutscenes and quests.
+///
+/// No sheet lists voice files, so they're found under `cut/` and `sound/voice/` using
+/// `--path-list`. Files are put in a directory named after the quest they belong to, using the
+/// `Quest` sheet, or the cutscene, using the `Cutscene` sheet. Files that match neither keep the
+/// name of the directory they're in.
+///
+/// Voice lines don't loop, so loop transformers are skipped.
+#[derive(Args, Debug)]
+pub struct ExtractVoice {
+    /// A path list to find voice files in, such as a ResLogger dump.
+    #[clap(long)]
+    path_list: PathBuf,
+    /// Should files be overwritten?
+    #[clap(short, long)]
+    overwrite: bool,
+    /// Transformers to run
+    #[clap(short, long, value_parser = TransformerParser)]
+    transformer: Vec<TransformerImpl>,
+    /// Pick the transformers that turn files into this format, instead of listing them with
+    /// `--transformer`, e.g. `flac`, `ogg`, or `flac-looped` to also repeat the loop.
+    #[clap(long, conflicts_with = "transformer")]
+    output_format: Option<TargetFormat>,
+    /// Fail if a transformer's output doesn't match the format it should produce.
+    #[clap(long)]
+    strict: bool,
+    /// Instead of writing outputs to files, run this command for each, writing the output to its
+    /// stdin, with `{}` replaced by the output path, e.g. `--pipe-to 'rclone rcat remote:{}'`.
+    #[clap(long)]
+    pipe_to: Option<String>,
+    /// Add this tag to audio outputs, e.g. `ALBUM=FFXIV OST`, replacing any tag of the same name.
+    /// Can be given several times.
+    #[clap(long, value_parser = parse_tag)]
+    tag_set: Vec<(String, String)>,
+    /// Tag the output with titles. The output must be FLAC or OGG.
+    #[clap(long)]
+    tags: bool,
+    /// The language of voice lines and quest names, e.g. `ja`, `de` or `fr`. Voice files for
+    /// other languages are skipped.
+    #[clap(long, default_value = "en")]
+    language: Language,
+    /// Don't check the output filesystem for path limits before starting.
+    #[clap(long)]
+    skip_fs_checks: bool,
+    #[clap(flatten)]
+    file_names: FileNameArgs,
+}
+
+/// Directories voice files are stored under.
+const VOICE_DIRECTORIES: [&str; 2] = ["cut/", "sound/voice/"];
+
+/// Language codes used at the end of voice file names, e.g. `_en`.
+const VOICE_LANGUAGES: [Language; 7] = [
+    Language::Japanese,
+    Language::English,
+    Language::German,
+    Language::French,
+    Language::ChineseSimplified,
+    Language::ChineseTraditional,
+    Language::Korean,
+];
+
+impl LastLegendCommand for ExtractVoice {
+    fn run(mut self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        self.transformer = resolve_transformers(
+            self.output_format.as_ref(),
+            &["scd"],
+            std::mem::take(&mut self.transformer),
+        )?;
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        let repo = global_args.open_repository();
+        let collection = Collection::load(repo.clone())
+            .map_err(|e| e.add_context("Failed to load collection"))?;
+        let namer = VoiceNamer::load(&collection, self.language, &self.file_names.options())?;
+
+        let entries = scd_paths_under(&repo, &self.path_list, &VOICE_DIRECTORIES)?
+            .filter(|path| {
+                path.as_ref()
+                    .map_or(true, |p| is_for_language(p.as_str(), self.language))
+            })
+            .map(|path| path.map(|p| (namer.output_name(&p), p)))
+            .collect::<Result<Vec<_>, LastLegendError>>()?;
+
+        if !self.skip_fs_checks {
+            let extension = self
+                .transformer
+                .last()
+                .map_or("scd", |t| t.output_extension());
+            let output_paths = entries
+                .iter()
+                .map(|(output_name, _)| output_name.with_extension(extension))
+                .collect::<Vec<_>>();
+            check_output_filesystem(&output_paths, None)?;
+        }
+
+        let progress = ExtractProgress::new(Some(entries.len() as u64));
+        let config = ExtractConfig::new(
+            self.overwrite,
+            self.transformer
+                .into_iter()
+                .filter(|t| !t.is_loop())
+                .collect(),
+        )
+        .with_progress(progress.clone())
+        .with_json_report(global_args.json_output())
+        .with_dry_run(global_args.dry_run)
+        .with_pipe_to(self.pipe_to)
+        .with_journal(global_args.journal.clone())
+        .with_strict(self.strict)
+        .with_tags(self.tag_set);
+
+        entries.into_par_iter().for_each(|(output_name, file)| {
+            let mut tags = Vec::new();
+            if self.tags {
+                if let Some(title) = output_name.file_name() {
+                    tags.push(("TITLE".to_string(), title.to_string_lossy().into_owned()));
+                }
+                if let Some(album) = output_name.parent().and_then(Path::file_name) {
+                    tags.push(("ALBUM".to_string(), album.to_string_lossy().into_owned()));
+                }
+            }
+            let metadata = OutputMetadata {
+                cover_art: None,
+                tags,
+            };
+            match extract_file(&repo, &config, &file, output_name, &metadata) {
+                // Reported once below.
+                Err(e) if e.category() == ErrorCategory::Cancelled => {}
+                Err(e) => log::warn!(
+                    "Failed to extract {}: {:#?}",
+                    file.errstyle(Style::new().green()),
+                    e
+                ),
+                Ok(()) => {}
+            }
+        });
+        progress.finish();
+        crate::CANCELLATION.check()
+    }
+}
+
+/// Check if [path] is a voice line for [language], or isn't for any language in particular.
+fn is_for_language(path: &str, language: Language) -> bool {
+    let stem = Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match stem.rsplit_once('_') {
+        Some((_, code)) if VOICE_LANGUAGES.iter().any(|l| l.code() == code) => {
+            code == language.code()
+        }
+        _ => true,
+    }
+}
+
+/// Names voice files after the quest or cutscene they're part of.
+struct VoiceNamer {
+    /// Quest names, ready to use as file names, by the lowercase start of their id, e.g.
+    /// `manfst001`.
+    quests: HashMap<String, String>,
+    /// Cutscene ids by the lowercase last part of their path, e.g. `manfst00000`.
+    cutscenes: HashMap<String, u32>,
+}
+
+impl VoiceNamer {
+    fn load(
+        collection: &Collection,
+        language: Language,
+        file_names: &FileNameOptions,
+    ) -> Result<Self, LastLegendError> {
+        let mut quests = HashMap::new();
+        for row in collection.known_rows::<Quest>(language)? {
+            let (_, quest) = row?;
+            if quest.name.is_empty() || quest.id.is_empty() {
+                continue;
+            }
+            let id = quest.id.to_ascii_lowercase();
+            let code = id.split_once('_').map_or(id.as_str(), |(code, _)| code);
+            quests.insert(
+                code.to_string(),
+                sanitize_file_name(&quest.name, file_names),
+            );
+        }
+        let mut cutscenes = HashMap::new();
+        for row in collection.known_rows::<Cutscene>(language)? {
+            let (id, cutscene) = row?;
+            if let Some(name) = cutscene.path.rsplit('/').next().filter(|n| !n.is_empty()) {
+                cutscenes.insert(name.to_ascii_lowercase(), id);
+            }
+        }
+        Ok(Self { quests, cutscenes })
+    }
+
+    /// Get the output name for [file], without an extension.
+    fn output_name(&self, file: &SqPathBuf) -> PathBuf {
+        let path = Path::new(file.as_str());
+        let lowercase = file.as_str().to_ascii_lowercase();
+        let parts = lowercase
+            .trim_end_matches(".scd")
+            .split(['/', '_'])
+            .collect::<Vec<_>>();
+        let group = parts
+            .iter()
+            .find_map(|part| self.quests.get(*part).cloned())
+            .or_else(|| {
+                parts.iter().find_map(|part| {
+                    self.cutscenes
+                        .get(*part)
+                        .map(|id| format!("Cutscene {}", id))
+                })
+            })
+            .or_else(|| {
+                path.parent()
+                    .and_then(Path::file_name)
+                    .map(|n| n.to_string_lossy().into_owned())
+            })
+            .unwrap_or_default();
+        Path::new("voice")
+            .join(group)
+            .join(path.file_stem().unwrap_or_default())
+    }
+}
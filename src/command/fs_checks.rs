@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use last_legend_dob::error::LastLegendError;
+
+/// The longest path most systems can open. Windows is limited to 260 unless long paths are enabled.
+const MAX_PATH_LEN: usize = if cfg!(windows) { 260 } else { 4096 };
+/// The longest single file or directory name most filesystems allow.
+const MAX_NAME_LEN: usize = 255;
+
+/// Check that the output directory can hold the [output_paths] of a large run, before starting it.
+///
+/// Problems that would only fail some files are logged as warnings. Running out of space would
+/// fail everything after it, so if the free space is less than [estimated_bytes] this aborts.
+pub(crate) fn check_output_filesystem(
+    output_paths: &[PathBuf],
+    estimated_bytes: Option<u64>,
+) -> Result<(), LastLegendError> {
+    let output_dir = std::env::current_dir()
+        .map_err(|e| LastLegendError::Io("Couldn't get output directory".into(), e))?;
+
+    if let Some(estimated_bytes) = estimated_bytes {
+        match fs4::available_space(&output_dir) {
+            Ok(free) if free < estimated_bytes => {
+                return Err(LastLegendError::Custom(format!(
+                    "Output filesystem has {} bytes free, but this needs roughly {} bytes. \
+                     Pass --skip-fs-checks to try anyway.",
+                    free, estimated_bytes
+                )));
+            }
+            Ok(_) => {}
+            Err(e) => log::debug!("Couldn't check free space: {}", e),
+        }
+    }
+
+    let dir_len = output_dir.as_os_str().len() + 1;
+    let too_long = output_paths
+        .iter()
+        .filter(|p| dir_len + p.as_os_str().len() > MAX_PATH_LEN)
+        .count();
+    if too_long > 0 {
+        log::warn!(
+            "{} output paths are longer than {} characters and will probably fail to write{}",
+            too_long,
+            MAX_PATH_LEN,
+            if cfg!(windows) {
+                ", unless long paths are enabled"
+            } else {
+                ""
+            }
+        );
+    }
+    if let Some(long_name) = output_paths
+        .iter()
+        .find(|p| p.components().any(|c| c.as_os_str().len() > MAX_NAME_LEN))
+    {
+        log::warn!(
+            "Output path {} has a name longer than {} characters and will probably fail to write",
+            long_name.display(),
+            MAX_NAME_LEN
+        );
+    }
+
+    let mut lowercase_paths = HashSet::with_capacity(output_paths.len());
+    let collisions = output_paths
+        .iter()
+        .filter(|p| !lowercase_paths.insert(p.to_string_lossy().to_lowercase()))
+        .count();
+    if collisions > 0 && !is_case_sensitive(&output_dir)? {
+        log::warn!(
+            "Output filesystem is case-insensitive, {} output paths differ only by case and will \
+             overwrite each other",
+            collisions
+        );
+    }
+
+    Ok(())
+}
+
+/// Check if [dir] is on a case-sensitive filesystem, by creating a probe file in it.
+fn is_case_sensitive(dir: &Path) -> Result<bool, LastLegendError> {
+    let probe = dir.join(".lldob-case-probe");
+    OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(&probe)
+        .map_err(|e| LastLegendError::Io("Couldn't create case sensitivity probe".into(), e))?;
+    let case_sensitive = !dir.join(".LLDOB-CASE-PROBE").exists();
+    std::fs::remove_file(&probe)
+        .map_err(|e| LastLegendError::Io("Couldn't remove case sensitivity probe".into(), e))?;
+    Ok(case_sensitive)
+}
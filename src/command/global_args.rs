@@ -1,11 +1,298 @@
-use clap::Args;
-use std::path::PathBuf;
+use clap::builder::{PossibleValue, TypedValueParser};
+use clap::{Arg, Args, Command, ValueEnum};
+use serde::Serialize;
+use std::ffi::OsStr;
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 
-#[derive(Args, Debug)]
+#[cfg(feature = "http")]
+use last_legend_dob::data::http_source::{HttpSource, DEFAULT_CACHE_BYTES};
+use last_legend_dob::data::repo::Repository;
+use last_legend_dob::discovery::discover_one;
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::ffmpeg::probe::FfmpegCapabilities;
+use last_legend_dob::limits::Limits;
+use last_legend_dob::manifest::Journal;
+use last_legend_dob::sqpath::PathHasher;
+use last_legend_dob::surpass::collection::Collection;
+use last_legend_dob::surpass::schema::Schema;
+use last_legend_dob::transformers::{transformers, TransformerImpl, TransformerParseError};
+
+#[derive(Args, Debug, Clone)]
 pub struct GlobalArgs {
-    /// Path the the SqPack you wish to examine.
+    /// Path the the SqPack you wish to examine. With the `http` feature, this can also be the
+    /// `http://` or `https://` URL of a copy of it on a web server that supports range requests.
+    /// If not given, the game install is looked for in the usual places, see `--auto`.
+    #[clap(id = "repository", value_name = "REPOSITORY")]
+    pub repository_arg: Option<PathBuf>,
+    /// A repository to look for files in before the main one, e.g. a benchmark client or a mod
+    /// preview. Files it doesn't have are read from the main repository. Can be given several
+    /// times, the first is searched first.
+    #[clap(long, global = true)]
+    pub overlay: Vec<PathBuf>,
+    /// Find the game install in the places launchers usually put it, instead of giving its path.
+    /// Fails if there isn't exactly one.
+    #[clap(long, conflicts_with = "repository")]
+    pub auto: bool,
+    /// The repository to use, set by [resolve_repository](Self::resolve_repository).
+    #[clap(skip)]
     pub repository: PathBuf,
     /// Verbosity level, repeat to increase.
     #[clap(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
+    /// CRC-32 polynomial for path hashes, for clients that changed the hash.
+    #[clap(long, global = true, value_parser = parse_hex_u32)]
+    pub hash_polynomial: Option<u32>,
+    /// CRC-32 initial value for path hashes.
+    #[clap(long, global = true, value_parser = parse_hex_u32)]
+    pub hash_init: Option<u32>,
+    /// Value XORed with path hashes at the end.
+    #[clap(long, global = true, value_parser = parse_hex_u32)]
+    pub hash_xor_out: Option<u32>,
+    /// Check that FFMPEG supports everything the requested transformers need before starting.
+    #[clap(long, global = true)]
+    pub verify_ffmpeg: bool,
+    /// Run at most this many FFMPEG processes at once, defaults to the number of CPUs.
+    #[clap(long, global = true)]
+    pub max_ffmpeg_processes: Option<usize>,
+    /// Keep up to this many MiB of decompressed entries in memory, for reuse within a run.
+    #[clap(long, global = true)]
+    pub content_cache_mib: Option<u64>,
+    /// Change a cap on the sizes read from the repository's files, given as `name=value`, e.g.
+    /// `max_row_size=4194304`. The caps are `max_index_entries`, `max_block_size`,
+    /// `max_vorbis_header_size`, `max_row_size` and `max_avfx_depth`. Can be given several times.
+    #[clap(long, global = true, value_parser = parse_limit)]
+    pub limit: Vec<(String, u32)>,
+    /// Read local repositories through memory maps, which is faster when reading many small
+    /// entries. Other programs mustn't shrink the repository's files while this runs.
+    #[cfg(feature = "mmap")]
+    #[clap(long, global = true)]
+    pub mmap: bool,
+    /// Run extractions without writing anything, only reporting what would be written.
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+    /// With `--dry-run`, run audio transformers with a fake, deterministic stand-in for FFMPEG,
+    /// so extractions can be checked on machines without it.
+    #[clap(long, global = true, requires = "dry_run")]
+    pub simulate: bool,
+    /// A directory of SaintCoinach-style `<sheet>.json` definitions to name sheet columns with,
+    /// on top of the bundled ones for the sheets this tool reads.
+    #[clap(long, global = true, value_name = "DIR")]
+    pub schema: Option<PathBuf>,
+    /// How to print results. `json` prints machine-readable JSON to stdout, logs still go to
+    /// stderr.
+    #[clap(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// Used by [open_repository](Self::open_repository) instead of opening a new one, so
+    /// commands run together share loaded indexes and cached content.
+    #[clap(skip)]
+    pub shared_repository: Option<Repository>,
+    /// Skip entries this says were already extracted, and record the ones that are, for commands
+    /// re-run by `watch`.
+    #[clap(skip)]
+    pub journal: Option<Arc<Journal>>,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl GlobalArgs {
+    /// The path hasher, from the defaults with any overrides applied.
+    pub fn path_hasher(&self) -> PathHasher {
+        let default = PathHasher::default();
+        PathHasher {
+            polynomial: self.hash_polynomial.unwrap_or(default.polynomial),
+            init: self.hash_init.unwrap_or(default.init),
+            xor_out: self.hash_xor_out.unwrap_or(default.xor_out),
+            reflect: default.reflect,
+        }
+    }
+
+    /// The size caps, from the defaults with any `--limit` overrides applied.
+    pub fn limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        for (name, value) in &self.limit {
+            limits
+                .set(name, *value)
+                .expect("limit names are checked when parsed");
+        }
+        limits
+    }
+
+    /// Whether results should be printed as JSON.
+    pub fn json_output(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Set [repository](Self::repository) to the path given, or to the game install found if
+    /// there isn't one.
+    pub fn resolve_repository(&mut self) -> Result<(), LastLegendError> {
+        self.repository = match &self.repository_arg {
+            Some(path) if !self.auto => path.clone(),
+            _ => {
+                let install = discover_one()?;
+                log::info!(
+                    "Using the game install at {}{}",
+                    install.sqpack.display(),
+                    install
+                        .game_version
+                        .map(|v| format!(", version {}", v))
+                        .unwrap_or_default()
+                );
+                install.sqpack
+            }
+        };
+        Ok(())
+    }
+
+    /// Open the repository these arguments point to, on top of any overlays.
+    pub fn open_repository(&self) -> Repository {
+        if let Some(repo) = &self.shared_repository {
+            return repo.clone();
+        }
+        let repo = self.open_other_repository(self.repository.clone());
+        match self.overlay.split_first() {
+            Some((top, rest)) => rest
+                .iter()
+                .map(|path| self.open_other_repository(path.clone()))
+                .chain([repo])
+                .fold(
+                    self.open_other_repository(top.clone()),
+                    Repository::with_fallback,
+                ),
+            None => repo,
+        }
+    }
+
+    /// Load the sheets of the repository these arguments point to, naming columns with any
+    /// `--schema` definitions.
+    pub fn open_collection(&self) -> Result<Collection, LastLegendError> {
+        let collection = Collection::load(self.open_repository())
+            .map_err(|e| e.add_context("Failed to load collection"))?;
+        match &self.schema {
+            Some(dir) => Ok(collection.with_schema(Schema::load_dir(dir)?)),
+            None => Ok(collection),
+        }
+    }
+
+    /// Whether the repository is on a web server rather than a local path.
+    pub fn is_remote(&self) -> bool {
+        remote_url(&self.repository).is_some()
+    }
+
+    /// Open another repository at [path] with the same settings, e.g. to compare against.
+    pub fn open_other_repository(&self, path: PathBuf) -> Repository {
+        let repo =
+            match remote_url(&path) {
+                #[cfg(feature = "http")]
+                Some(url) => Repository::new(PathBuf::new()).with_source(Arc::new(
+                    HttpSource::new(url, PathBuf::new(), DEFAULT_CACHE_BYTES),
+                )),
+                #[cfg(not(feature = "http"))]
+                Some(_) => {
+                    log::warn!("Reading repositories from web servers needs the `http` feature");
+                    Repository::new(path)
+                }
+                #[cfg(feature = "mmap")]
+                None if self.mmap => Repository::new(path).with_mmap(),
+                None => Repository::new(path),
+            };
+        let repo = repo
+            .with_hasher(self.path_hasher())
+            .with_limits(self.limits())
+            .with_cancellation(crate::CANCELLATION.clone());
+        match self.content_cache_mib {
+            Some(mib) => repo.with_content_cache(mib * 1024 * 1024),
+            None => repo,
+        }
+    }
+}
+
+/// The URL [path] is, if it's an `http://` or `https://` URL rather than a local path.
+fn remote_url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|p| p.starts_with("http://") || p.starts_with("https://"))
+}
+
+/// With `--verify-ffmpeg`, check that FFMPEG can run all of the [transformers].
+pub(crate) fn verify_ffmpeg(
+    global_args: &GlobalArgs,
+    transformers: &[TransformerImpl],
+) -> Result<(), LastLegendError> {
+    if !global_args.verify_ffmpeg || global_args.simulate {
+        return Ok(());
+    }
+    let capabilities = FfmpegCapabilities::probe()?;
+    log::info!("{}", capabilities.summary());
+    let missing = capabilities.missing_for(transformers);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(LastLegendError::FFMPEG(format!(
+        "FFMPEG {} can't run the requested transformers, it is missing {}",
+        capabilities.version,
+        missing
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )))
+}
+
+/// Print [value] to stdout as pretty JSON, for `--format json`.
+pub(crate) fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), LastLegendError> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| LastLegendError::Json("Couldn't write JSON output".into(), e))?;
+    println!("{}", json);
+    Ok(())
+}
+
+pub(crate) fn parse_hex_u32(s: &str) -> Result<u32, ParseIntError> {
+    u32::from_str_radix(s.trim_start_matches("0x"), 16)
+}
+
+fn parse_limit(s: &str) -> Result<(String, u32), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected name=value, got '{}'", s))?;
+    let value = value.parse().map_err(|e| format!("{}: {}", name, e))?;
+    Limits::default().set(name, value)?;
+    Ok((name.to_string(), value))
+}
+
+/// Parses [TransformerImpl], listing the registered transformers and their options in `--help`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TransformerParser;
+
+impl TypedValueParser for TransformerParser {
+    type Value = TransformerImpl;
+
+    fn parse_ref(
+        &self,
+        cmd: &Command,
+        arg: Option<&Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        type Parse = fn(&str) -> Result<TransformerImpl, TransformerParseError>;
+        (TransformerImpl::from_str as Parse).parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(transformers().into_iter().map(|info| {
+            let mut help = format!(
+                "{}, from .{}",
+                info.description,
+                info.input_extensions.join(", .")
+            );
+            if !info.options.is_empty() {
+                help.push_str(&format!(". Options: {}", info.options.join(", ")));
+            }
+            PossibleValue::new(info.name).help(help)
+        })))
+    }
 }
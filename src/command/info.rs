@@ -0,0 +1,135 @@
+use std::io::Empty;
+
+use clap::Args;
+use serde_json::{json, Map, Value};
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::manifest::ManifestEntry;
+use last_legend_dob::simple_task::{read_entry_content, read_file_entry_header};
+use last_legend_dob::sqpath::SqPathBuf;
+use last_legend_dob::transformers::{
+    extension_magic, plain_transformers, Transformer, TransformerForFile, TransformerImpl,
+};
+
+use crate::command::global_args::{print_json, GlobalArgs, TransformerParser};
+use crate::command::probe::{dat_header_report, print_value, to_value};
+use crate::command::LastLegendCommand;
+
+/// Show everything needed to debug a single file: its hashes, the index, dat file and offset it
+/// resolves to, its dat entry header, what its content looks like, and what transformers would
+/// make of it.
+///
+/// Without `--transformer`, each transformer that applies to the file is shown on its own, with
+/// its default options. With `--transformer`, the given transformers are shown as they'd run, one
+/// after another, as `extract` runs them.
+#[derive(Args, Debug)]
+pub struct Info {
+    /// The file to show.
+    file: SqPathBuf,
+    /// Transformers to preview.
+    #[clap(short, long, value_parser = TransformerParser)]
+    transformer: Vec<TransformerImpl>,
+}
+
+impl LastLegendCommand for Info {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let hasher = global_args.path_hasher();
+        let (folder_hash, file_hash) = self.file.sq_folder_file_hash_with(&hasher);
+        let mut report = Map::new();
+        report.insert("path".into(), self.file.as_str().into());
+        report.insert(
+            "hash".into(),
+            json!({
+                "index2": format!("{:08X}", self.file.sq_index_hash_with(&hasher)),
+                "folder": format!("{:08X}", folder_hash),
+                "file": format!("{:08X}", file_hash),
+            }),
+        );
+
+        let repo = global_args.open_repository();
+        report.insert("game_version".into(), to_value(&repo.game_version())?);
+        let index = repo.get_index_for(&self.file)?;
+        let entry = index.get_entry(&self.file)?;
+        let mut location = to_value(&ManifestEntry::new(
+            repo.repo_path(),
+            &index,
+            entry,
+            &self.file,
+        ))?;
+        let dat_path = index.dat_path(entry.data_file_id);
+        location["index_format"] = format!("{:?}", index.format).into();
+        location["packed_info"] = format!("0x{:08X}", entry.packed_info).into();
+        location["dat_file"] = dat_path
+            .strip_prefix(repo.repo_path())
+            .unwrap_or(&dat_path)
+            .display()
+            .to_string()
+            .into();
+        report.insert("location".into(), location);
+
+        let (header, _) = read_file_entry_header(&index, &self.file)?;
+        report.insert("dat_header".into(), dat_header_report(&header));
+        let content = read_entry_content(&index, entry)?;
+        report.insert(
+            "detected_format".into(),
+            detect_format(&content).map_or(Value::Null, Value::from),
+        );
+        report.insert(
+            "transformers".into(),
+            Value::Array(self.transform_preview()),
+        );
+
+        if global_args.json_output() {
+            return print_json(&report);
+        }
+        print_value(&Value::Object(report), 0);
+        Ok(())
+    }
+}
+
+impl Info {
+    /// The transformers that apply to the file, with the file names they'd produce.
+    fn transform_preview(&self) -> Vec<Value> {
+        let step = |t: &TransformerImpl, file: &SqPathBuf| {
+            <TransformerImpl as Transformer<Empty>>::maybe_for(t, file.clone())
+                .map(|tf| tf.renamed_file().into_owned())
+        };
+        if self.transformer.is_empty() {
+            return plain_transformers()
+                .filter_map(|t| {
+                    let output = step(&t, &self.file)?;
+                    Some(json!({"transformer": t.to_string(), "output": output.as_str()}))
+                })
+                .collect();
+        }
+        let mut file = self.file.clone();
+        let mut preview = Vec::new();
+        for t in &self.transformer {
+            if let Some(output) = step(t, &file) {
+                preview.push(json!({"transformer": t.to_string(), "output": output.as_str()}));
+                file = output;
+            }
+        }
+        preview
+    }
+}
+
+/// Guess the format of [content] from its magic bytes.
+fn detect_format(content: &[u8]) -> Option<&'static str> {
+    const GAME_MAGIC: &[(&str, &[u8])] = &[
+        ("scd", b"SEDBSSCF"),
+        ("exh", b"EXHF"),
+        ("exd", b"EXDF"),
+        ("avfx", b"AVFX"),
+    ];
+    GAME_MAGIC
+        .iter()
+        .copied()
+        .chain(
+            ["flac", "ogg", "wav", "mp3", "png"]
+                .into_iter()
+                .filter_map(|e| Some((e, extension_magic(e)?))),
+        )
+        .find(|(_, magic)| content.starts_with(magic))
+        .map(|(format, _)| format)
+}
@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use clap::Args;
+use serde_json::json;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::path_db::PathDb;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// List the entries in index files, grouped by the dat file they're in.
+#[derive(Args, Debug)]
+pub struct List {
+    /// The index files to list.
+    files: Vec<PathBuf>,
+    /// Path lists or packs to look up the paths of entries in, such as the ResLogger exports.
+    /// Lists ending in `.gz` are decompressed. Can be given several times.
+    #[clap(long)]
+    path_db: Vec<PathBuf>,
+}
+
+impl LastLegendCommand for List {
+    fn run(mut self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let repo = global_args.open_repository();
+
+        self.files.sort();
+        let path_db = match self.path_db.as_slice() {
+            [] => None,
+            files => Some(PathDb::load_files(files, global_args.path_hasher())?),
+        };
+
+        if global_args.json_output() {
+            let mut report = Vec::new();
+            for file in &self.files {
+                let index = repo.load_index_file(Cow::Borrowed(file.as_path()))?;
+                let dats = index
+                    .entries_by_dat()
+                    .into_iter()
+                    .map(|(data_file_id, entries)| {
+                        let entries = entries
+                            .iter()
+                            .map(|e| {
+                                let path = path_db.as_ref().and_then(|db| db.path_for(&index, e));
+                                json!({
+                                    "hash": e.hash,
+                                    "offset": e.offset_bytes,
+                                    "path": path.map(|p| p.as_str()),
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        json!({ "data_file_id": data_file_id, "entries": entries })
+                    })
+                    .collect::<Vec<_>>();
+                report.push(json!({ "file": file, "dats": dats }));
+            }
+            return print_json(&report);
+        }
+
+        for file in self.files.into_iter() {
+            let index = repo.load_index_file(Cow::Borrowed(file.as_path()))?;
+            println!("{}", file.display());
+            for (data_file_id, entries) in index.entries_by_dat() {
+                println!("  dat{} ({} entries)", data_file_id, entries.len());
+                for entry in entries {
+                    match path_db.as_ref().and_then(|db| db.path_for(&index, entry)) {
+                        Some(path) => println!(
+                            "    0x{:08X} at 0x{:X} {}",
+                            entry.hash, entry.offset_bytes, path
+                        ),
+                        None => println!("    0x{:08X} at 0x{:X}", entry.hash, entry.offset_bytes),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use clap::Args;
+use serde_json::json;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::surpass::collection::Collection;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// List every sheet in the collection, with its id, row count, variant, and languages.
+#[derive(Args, Debug)]
+pub struct ListSheets {
+    /// Only list sheets whose name contains this, ignoring case.
+    filter: Option<String>,
+}
+
+impl LastLegendCommand for ListSheets {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let collection = Collection::load(global_args.open_repository())
+            .map_err(|e| e.add_context("Failed to load collection"))?;
+        let filter = self.filter.map(|f| f.to_lowercase());
+        let mut report = Vec::new();
+
+        for name in collection.sheet_names() {
+            if filter
+                .as_ref()
+                .is_some_and(|f| !name.to_lowercase().contains(f))
+            {
+                continue;
+            }
+            let id = collection
+                .sheet_id(name)
+                .expect("listed sheets must have ids");
+            match collection.sheet_info(name) {
+                Ok(info) => {
+                    let rows: u32 = info.page_ranges.iter().map(|r| r.len() as u32).sum();
+                    if global_args.json_output() {
+                        report.push(json!({
+                            "name": name,
+                            "id": id,
+                            "rows": rows,
+                            "variant": format!("{:?}", info.variant),
+                            "languages": info.languages.iter().map(|l| l.code()).collect::<Vec<_>>(),
+                        }));
+                        continue;
+                    }
+                    println!(
+                        "{} (id {}): {} rows, {:?}, languages {:?}",
+                        name, id, rows, info.variant, info.languages
+                    );
+                }
+                Err(e) => {
+                    log::warn!("Couldn't read sheet info for {}: {}", name, e);
+                    if global_args.json_output() {
+                        report.push(json!({ "name": name, "id": id }));
+                        continue;
+                    }
+                    println!("{} (id {}): unreadable", name, id);
+                }
+            }
+        }
+
+        if global_args.json_output() {
+            print_json(&report)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use serde_json::json;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::manifest::Manifest;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Work with manifests written by `search`, for external tools.
+#[derive(Args, Debug)]
+pub struct ManifestArgs {
+    #[clap(subcommand)]
+    command: ManifestCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ManifestCommand {
+    Validate(Validate),
+}
+
+/// Check that a manifest can be read by this version, and that its entries make sense.
+#[derive(Args, Debug)]
+struct Validate {
+    /// The manifest to check.
+    manifest: PathBuf,
+}
+
+impl LastLegendCommand for ManifestArgs {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        match self.command {
+            ManifestCommand::Validate(v) => v.run(&global_args),
+        }
+    }
+}
+
+impl Validate {
+    fn run(self, global_args: &GlobalArgs) -> Result<(), LastLegendError> {
+        let file = File::open(&self.manifest).map_err(|e| {
+            LastLegendError::Io(format!("Couldn't open {}", self.manifest.display()), e)
+        })?;
+        let manifest = Manifest::read_json(BufReader::new(file))?;
+        let problems = manifest.validate();
+
+        if global_args.json_output() {
+            print_json(&json!({
+                "manifest_version": manifest.manifest_version,
+                "entries": manifest.entries.len(),
+                "problems": problems,
+            }))?;
+        } else {
+            for problem in &problems {
+                log::warn!(
+                    "Entry {} ({}): {}",
+                    problem.entry,
+                    manifest.entries[problem.entry].path,
+                    problem.message
+                );
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(LastLegendError::Custom(format!(
+                "Found {} problems in {}",
+                problems.len(),
+                self.manifest.display()
+            )));
+        }
+        log::info!(
+            "Manifest version {} with {} entries is valid",
+            manifest.manifest_version,
+            manifest.entries.len()
+        );
+        Ok(())
+    }
+}
@@ -6,20 +6,50 @@ use last_legend_dob::error::LastLegendError;
 use last_legend_dob::simple_task::format_index_hash_for_console;
 use last_legend_dob::sqpath::SqPathBuf;
 
-use crate::command::global_args::GlobalArgs;
+use crate::command::global_args::{print_json, GlobalArgs};
 
+mod cat;
+mod crack_hash;
+#[cfg(unix)]
+mod daemon;
+mod dump_vorbis;
+mod exd;
+#[cfg(feature = "sqlite")]
+mod export_db;
+mod export_modpack;
+mod export_sheet;
 mod extract;
 mod extract_all;
+mod extract_ambient;
 pub(crate) mod extract_common;
 mod extract_music;
+mod extract_report;
+mod extract_vfx;
+mod extract_voice;
+mod fs_checks;
 mod global_args;
+mod info;
+mod list;
+mod list_sheets;
+mod manifest;
+#[cfg(all(unix, feature = "fuse"))]
+mod mount;
+mod pack_paths;
+mod probe;
+mod replace;
+mod run_jobs;
+mod search;
+mod stats;
+mod verify;
+mod version;
+mod watch;
 
 pub trait LastLegendCommand {
     fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError>;
 }
 
 #[derive(Parser, Debug)]
-#[clap(about = "FFXIV file extractor", version)]
+#[clap(about = "FFXIV file extractor", version, after_help = crate::EXIT_CODE_HELP)]
 pub struct LastLegendDob {
     #[clap(flatten)]
     pub global_args: GlobalArgs,
@@ -30,29 +60,103 @@ pub struct LastLegendDob {
 
 #[derive(Subcommand, Debug)]
 pub enum LLDCommand {
+    Cat(cat::Cat),
+    CrackHash(crack_hash::CrackHash),
+    #[cfg(unix)]
+    Daemon(daemon::Daemon),
+    DumpVorbis(dump_vorbis::DumpVorbis),
+    Exd(exd::Exd),
+    #[cfg(feature = "sqlite")]
+    ExportDb(export_db::ExportDb),
+    ExportModpack(export_modpack::ExportModpack),
+    ExportSheet(export_sheet::ExportSheet),
     Extract(extract::Extract),
     ExtractAll(extract_all::ExtractAll),
+    ExtractAmbient(extract_ambient::ExtractAmbient),
     ExtractMusic(extract_music::ExtractMusic),
+    ExtractVfx(extract_vfx::ExtractVfx),
+    ExtractVoice(extract_voice::ExtractVoice),
     /// Get the hash of the path, used to retrieve data from the index.
     HashPath {
         /// Path to compute the hash for.
         path: SqPathBuf,
     },
+    Info(info::Info),
+    List(list::List),
+    ListSheets(list_sheets::ListSheets),
+    Manifest(manifest::ManifestArgs),
+    #[cfg(all(unix, feature = "fuse"))]
+    Mount(mount::Mount),
+    PackPaths(pack_paths::PackPaths),
+    Probe(probe::Probe),
+    Replace(replace::Replace),
+    RunJobs(run_jobs::RunJobs),
+    Search(search::Search),
+    Stats(stats::Stats),
+    Verify(verify::Verify),
+    Version(version::Version),
+    Watch(watch::Watch),
 }
 
 impl LastLegendCommand for LLDCommand {
     fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
         match self {
+            Self::Cat(v) => v.run(global_args),
+            Self::CrackHash(v) => v.run(global_args),
+            #[cfg(unix)]
+            Self::Daemon(v) => v.run(global_args),
+            Self::DumpVorbis(v) => v.run(global_args),
+            Self::Exd(v) => v.run(global_args),
+            #[cfg(feature = "sqlite")]
+            Self::ExportDb(v) => v.run(global_args),
+            Self::ExportModpack(v) => v.run(global_args),
+            Self::ExportSheet(v) => v.run(global_args),
             Self::Extract(v) => v.run(global_args),
             Self::ExtractAll(v) => v.run(global_args),
+            Self::ExtractAmbient(v) => v.run(global_args),
             Self::ExtractMusic(v) => v.run(global_args),
+            Self::ExtractVfx(v) => v.run(global_args),
+            Self::ExtractVoice(v) => v.run(global_args),
             Self::HashPath { path } => {
-                log::info!(
-                    "Hash of path is {}",
-                    format_index_hash_for_console(path.sq_index_hash())
-                );
+                let hash = path.sq_index_hash_with(&global_args.path_hasher());
+                if global_args.json_output() {
+                    return print_json(&serde_json::json!({
+                        "path": path.as_str(),
+                        "hash": hash,
+                    }));
+                }
+                log::info!("Hash of path is {}", format_index_hash_for_console(hash));
                 Ok(())
             }
+            Self::Info(v) => v.run(global_args),
+            Self::List(v) => v.run(global_args),
+            Self::ListSheets(v) => v.run(global_args),
+            Self::Manifest(v) => v.run(global_args),
+            #[cfg(all(unix, feature = "fuse"))]
+            Self::Mount(v) => v.run(global_args),
+            Self::PackPaths(v) => v.run(global_args),
+            Self::Probe(v) => v.run(global_args),
+            Self::Replace(v) => v.run(global_args),
+            Self::RunJobs(v) => v.run(global_args),
+            Self::Search(v) => v.run(global_args),
+            Self::Stats(v) => v.run(global_args),
+            Self::Verify(v) => v.run(global_args),
+            Self::Version(v) => v.run(global_args),
+            Self::Watch(v) => v.run(global_args),
+        }
+    }
+}
+
+impl LLDCommand {
+    /// Whether the command stops cleanly on the first Ctrl-C. Servers don't, so Ctrl-C exits them
+    /// right away.
+    pub fn handles_ctrl_c(&self) -> bool {
+        match self {
+            #[cfg(unix)]
+            Self::Daemon(_) => false,
+            #[cfg(all(unix, feature = "fuse"))]
+            Self::Mount(_) => false,
+            _ => true,
         }
     }
 }
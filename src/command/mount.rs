@@ -0,0 +1,361 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use clap::Args;
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
+    ReplyOpen, Request,
+};
+
+use last_legend_dob::data::repo::Repository;
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::path_list::read_path_list;
+use last_legend_dob::simple_task::{read_transformed, transformed_file_name};
+use last_legend_dob::sqpath::SqPathBuf;
+use last_legend_dob::transformers::TransformerImpl;
+
+use crate::command::global_args::{verify_ffmpeg, GlobalArgs, TransformerParser};
+use crate::command::LastLegendCommand;
+
+/// The repository never changes while mounted, so the kernel can cache for a long time.
+const TTL: Duration = Duration::from_secs(3600);
+const ROOT_INO: u64 = 1;
+
+/// Mount the repository as a read-only filesystem, to browse it in a file manager.
+///
+/// Only paths from the path list that exist in the repository are shown. Files are read, and
+/// transformed, when first opened or when their size is needed, so listing a large directory with
+/// transformers can be slow.
+#[derive(Args, Debug)]
+pub struct Mount {
+    /// The directory to mount on.
+    mountpoint: PathBuf,
+    /// The paths to show, one path per line.
+    #[clap(long)]
+    path_list: PathBuf,
+    /// Transformers to run when files are read, e.g. `scd_to_flac`. File names are changed to match.
+    #[clap(short, long, value_parser = TransformerParser)]
+    transformer: Vec<TransformerImpl>,
+}
+
+impl LastLegendCommand for Mount {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        verify_ffmpeg(&global_args, &self.transformer)?;
+        let repo = global_args.open_repository();
+
+        let path_list = File::open(&self.path_list)
+            .map_err(|e| LastLegendError::Io("Couldn't open path list".into(), e))?;
+        let mut fs = RepositoryFs::new(repo, self.transformer);
+        for path in read_path_list(BufReader::new(path_list)) {
+            fs.add_file(path?);
+        }
+        log::info!(
+            "Mounting {} files on {}, unmount to exit",
+            fs.file_count(),
+            self.mountpoint.display()
+        );
+
+        fuser::mount2(
+            fs,
+            &self.mountpoint,
+            &[
+                MountOption::RO,
+                MountOption::FSName("lldob".to_string()),
+                MountOption::DefaultPermissions,
+            ],
+        )
+        .map_err(|e| LastLegendError::Io("Couldn't mount filesystem".into(), e))
+    }
+}
+
+enum Node {
+    Dir {
+        parent: u64,
+        children: BTreeMap<String, u64>,
+    },
+    File {
+        path: SqPathBuf,
+        /// The size after transforming, known once the file has been read.
+        size: Option<u64>,
+    },
+}
+
+struct RepositoryFs {
+    repo: Repository,
+    transformers: Vec<TransformerImpl>,
+    /// Nodes by inode number minus one.
+    nodes: Vec<Node>,
+    open_files: HashMap<u64, Arc<[u8]>>,
+    next_fh: u64,
+    mounted_at: SystemTime,
+}
+
+impl RepositoryFs {
+    fn new(repo: Repository, transformers: Vec<TransformerImpl>) -> Self {
+        Self {
+            repo,
+            transformers,
+            nodes: vec![Node::Dir {
+                parent: ROOT_INO,
+                children: BTreeMap::new(),
+            }],
+            open_files: HashMap::new(),
+            next_fh: 1,
+            mounted_at: SystemTime::now(),
+        }
+    }
+
+    fn file_count(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|n| matches!(n, Node::File { .. }))
+            .count()
+    }
+
+    /// Add [path] to the tree, if it's in the repository.
+    fn add_file(&mut self, path: SqPathBuf) {
+        let found = self
+            .repo
+            .get_index_for(&path)
+            .is_ok_and(|index| index.get_entry(&path).is_ok());
+        if !found {
+            log::debug!("Not in repository: {}", path);
+            return;
+        }
+
+        let shown = transformed_file_name(path.clone(), &self.transformers);
+        let mut components = shown.as_str().split('/').filter(|c| !c.is_empty());
+        let Some(file_name) = components.next_back() else {
+            return;
+        };
+        let mut dir = ROOT_INO;
+        for component in components {
+            dir = match self.child(dir, component) {
+                Some(ino) if matches!(self.node(ino), Some(Node::Dir { .. })) => ino,
+                Some(_) => {
+                    log::warn!(
+                        "{} is both a file and a directory, skipping {}",
+                        component,
+                        path
+                    );
+                    return;
+                }
+                None => self.insert(
+                    dir,
+                    component,
+                    Node::Dir {
+                        parent: dir,
+                        children: BTreeMap::new(),
+                    },
+                ),
+            };
+        }
+        if self.child(dir, file_name).is_none() {
+            self.insert(dir, file_name, Node::File { path, size: None });
+        }
+    }
+
+    fn insert(&mut self, parent: u64, name: &str, node: Node) -> u64 {
+        self.nodes.push(node);
+        let ino = self.nodes.len() as u64;
+        if let Some(Node::Dir { children, .. }) = self.node_mut(parent) {
+            children.insert(name.to_string(), ino);
+        }
+        ino
+    }
+
+    fn node(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get(ino.checked_sub(1)? as usize)
+    }
+
+    fn node_mut(&mut self, ino: u64) -> Option<&mut Node> {
+        self.nodes.get_mut(ino.checked_sub(1)? as usize)
+    }
+
+    fn child(&self, parent: u64, name: &str) -> Option<u64> {
+        match self.node(parent)? {
+            Node::Dir { children, .. } => children.get(name).copied(),
+            Node::File { .. } => None,
+        }
+    }
+
+    /// Read and transform the file at [ino], remembering its size.
+    fn read_content(&mut self, ino: u64) -> Result<Arc<[u8]>, LastLegendError> {
+        let Some(Node::File { path, .. }) = self.node(ino) else {
+            return Err(LastLegendError::Custom(format!("{} is not a file", ino)));
+        };
+        let mut transformed = read_transformed(&self.repo, path, &self.transformers)?;
+        let mut content = Vec::new();
+        transformed
+            .reader
+            .read_to_end(&mut content)
+            .map_err(|e| LastLegendError::Io("Couldn't read transformed content".into(), e))?;
+
+        if let Some(Node::File { size, .. }) = self.node_mut(ino) {
+            *size = Some(content.len() as u64);
+        }
+        Ok(content.into())
+    }
+
+    fn attr(&mut self, ino: u64) -> Result<FileAttr, LastLegendError> {
+        let (kind, perm, size) = match self.node(ino) {
+            Some(Node::Dir { .. }) => (FileType::Directory, 0o555, 0),
+            Some(Node::File {
+                size: Some(size), ..
+            }) => (FileType::RegularFile, 0o444, *size),
+            Some(Node::File { size: None, .. }) => (
+                FileType::RegularFile,
+                0o444,
+                self.read_content(ino)?.len() as u64,
+            ),
+            None => return Err(LastLegendError::Custom(format!("No inode {}", ino))),
+        };
+        Ok(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: self.mounted_at,
+            mtime: self.mounted_at,
+            ctime: self.mounted_at,
+            crtime: self.mounted_at,
+            kind,
+            perm,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            // SAFETY: these can't fail.
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+}
+
+/// Log [e] and get the errno to reply with.
+fn errno(e: LastLegendError) -> libc::c_int {
+    log::warn!("{}", e);
+    libc::EIO
+}
+
+impl Filesystem for RepositoryFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let Some(ino) = name.to_str().and_then(|name| self.child(parent, name)) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        match self.attr(ino) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(e) => reply.error(errno(e)),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        if self.node(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        match self.attr(ino) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => reply.error(errno(e)),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            reply.error(libc::EROFS);
+            return;
+        }
+        match self.node(ino) {
+            Some(Node::File { .. }) => {}
+            Some(Node::Dir { .. }) => {
+                reply.error(libc::EISDIR);
+                return;
+            }
+            None => {
+                reply.error(libc::ENOENT);
+                return;
+            }
+        }
+        match self.read_content(ino) {
+            Ok(content) => {
+                let fh = self.next_fh;
+                self.next_fh += 1;
+                self.open_files.insert(fh, content);
+                reply.opened(fh, 0);
+            }
+            Err(e) => reply.error(errno(e)),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(content) = self.open_files.get(&fh) else {
+            reply.error(libc::EBADF);
+            return;
+        };
+        let start = (offset.max(0) as usize).min(content.len());
+        let end = start.saturating_add(size as usize).min(content.len());
+        reply.data(&content[start..end]);
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.open_files.remove(&fh);
+        reply.ok();
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(Node::Dir { parent, children }) = self.node(ino) else {
+            reply.error(libc::ENOTDIR);
+            return;
+        };
+        let entries = [
+            (ino, FileType::Directory, "."),
+            (*parent, FileType::Directory, ".."),
+        ]
+        .into_iter()
+        .chain(children.iter().map(|(name, &child)| {
+            let kind = match self.node(child) {
+                Some(Node::Dir { .. }) => FileType::Directory,
+                _ => FileType::RegularFile,
+            };
+            (child, kind, name.as_str())
+        }));
+        for (i, (child, kind, name)) in entries.enumerate().skip(offset.max(0) as usize) {
+            // The offset is that of the next entry.
+            if reply.add(child, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::Args;
+use serde_json::json;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::path_db::PathDb;
+use last_legend_dob::path_pack::PackCompression;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Combine path lists into a path pack, a compact binary format that's much smaller than the
+/// lists and faster to load. Packs can be given anywhere path lists are, e.g. `list --path-db`.
+///
+/// Packs are compressed with zstd. Uncompressed packs are larger, but with the `mmap` feature
+/// they're memory-mapped and used in place, so they load instantly.
+#[derive(Args, Debug)]
+pub struct PackPaths {
+    /// The file to write the pack to.
+    output: PathBuf,
+    /// Path lists or packs to include, such as the ResLogger exports. Lists ending in `.gz` are
+    /// decompressed.
+    #[clap(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Don't compress the pack, so it can be memory-mapped.
+    #[clap(long)]
+    uncompressed: bool,
+}
+
+impl LastLegendCommand for PackPaths {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let db = PathDb::load_files(&self.inputs, global_args.path_hasher())?;
+        let pack = db.to_pack();
+        let compression = if self.uncompressed {
+            PackCompression::None
+        } else {
+            PackCompression::Zstd
+        };
+        let output = File::create(&self.output).map_err(|e| {
+            LastLegendError::Io(format!("Couldn't create {}", self.output.display()), e)
+        })?;
+        let mut output = BufWriter::new(output);
+        pack.write(&mut output, compression)?;
+        output.flush().map_err(|e| {
+            LastLegendError::Io(format!("Couldn't write {}", self.output.display()), e)
+        })?;
+
+        if global_args.json_output() {
+            return print_json(&json!({
+                "read": db.len(),
+                "packed": pack.len(),
+                "output": self.output,
+            }));
+        }
+        log::info!(
+            "Packed {} of {} paths into {}",
+            pack.len(),
+            db.len(),
+            self.output.display()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+use std::path::Path;
+
+use clap::Args;
+use serde_json::{json, Map, Value};
+
+use last_legend_dob::data::dat::{DatEntryHeader, DatEntryHeaderBlocks};
+use last_legend_dob::data::index2::Index2;
+use last_legend_dob::data::repo::Repository;
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::manifest::ManifestEntry;
+use last_legend_dob::scd::read_scd_entries;
+use last_legend_dob::simple_task::{read_entry_content, read_file_entry_header};
+use last_legend_dob::sqpath::SqPathBuf;
+use last_legend_dob::surpass::page::PageHeader;
+use last_legend_dob::surpass::sheet_info::SheetInfo;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Show what's known about files: where they are in the repository, their dat entry header, and
+/// the headers of their content.
+///
+/// Sound files (`.scd`) show each sound entry's codec, encryption, channels, frequency and loop
+/// points. Sheet headers (`.exh`) and pages (`.exd`) show their columns, pages and rows. Index
+/// files (`.index`, `.index2`) on disk show their headers and dat files.
+#[derive(Args, Debug)]
+pub struct Probe {
+    /// The files to probe, in the repository, or on disk with `--local`.
+    files: Vec<String>,
+    /// Read the files from disk instead of the repository.
+    #[clap(long)]
+    local: bool,
+}
+
+impl LastLegendCommand for Probe {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let json_output = global_args.json_output();
+        let repo = (!self.local).then(|| global_args.open_repository());
+        let mut reports = Vec::new();
+        for file in &self.files {
+            let report = match &repo {
+                Some(repo) => probe_repo_file(repo, &SqPathBuf::new(file))?,
+                None => probe_local_file(Path::new(file))?,
+            };
+            if json_output {
+                reports.push(report);
+            } else {
+                println!("{}", file);
+                print_value(&report, 1);
+            }
+        }
+        if json_output {
+            print_json(&reports)?;
+        }
+        Ok(())
+    }
+}
+
+fn probe_repo_file(repo: &Repository, file: &SqPathBuf) -> Result<Value, LastLegendError> {
+    let index = repo.get_index_for(file)?;
+    let entry = index.get_entry(file)?;
+    let (header, _) = read_file_entry_header(&index, file)?;
+    let content = read_entry_content(&index, entry)?;
+
+    let mut report = Map::new();
+    report.insert(
+        "entry".into(),
+        to_value(&ManifestEntry::new(repo.repo_path(), &index, entry, file))?,
+    );
+    report.insert("dat_header".into(), dat_header_report(&header));
+    report.extend(content_report(Some(repo), file.as_str(), &content)?);
+    Ok(Value::Object(report))
+}
+
+fn probe_local_file(path: &Path) -> Result<Value, LastLegendError> {
+    if matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("index" | "index2")
+    ) {
+        return index_report(&Index2::load_from_path(path)?);
+    }
+    let content = std::fs::read(path)
+        .map_err(|e| LastLegendError::Io(format!("Couldn't read {}", path.display()), e))?;
+    let mut report = Map::new();
+    report.insert("size".into(), content.len().into());
+    report.extend(content_report(None, &path.to_string_lossy(), &content)?);
+    Ok(Value::Object(report))
+}
+
+pub(super) fn dat_header_report(header: &DatEntryHeader) -> Value {
+    let mut report = json!({
+        "content_type": format!("{:?}", header.content_type()),
+        "header_size": header.header_size(),
+        "uncompressed_size": header.uncompressed_size,
+        "block_size": header.block_size,
+    });
+    let blocks = match header.blocks() {
+        DatEntryHeaderBlocks::Empty => Value::Null,
+        DatEntryHeaderBlocks::Binary(blocks) => blocks
+            .iter()
+            .map(|b| {
+                json!({
+                    "offset": b.offset,
+                    "size": b.block_size,
+                    "decompressed_size": b.decompressed_size,
+                })
+            })
+            .collect(),
+        DatEntryHeaderBlocks::Model(model) => json!({
+            "version": model.version,
+            "lod_count": model.lod_count,
+            "material_count": model.material_count,
+            "block_count": model.block_sizes.len(),
+        }),
+        DatEntryHeaderBlocks::Texture(texture) => texture
+            .lod_blocks
+            .iter()
+            .map(|l| {
+                json!({
+                    "compressed_offset": l.compressed_offset,
+                    "compressed_size": l.compressed_size,
+                    "decompressed_size": l.decompressed_size,
+                    "block_count": l.block_count,
+                })
+            })
+            .collect(),
+    };
+    if !blocks.is_null() {
+        report["blocks"] = blocks;
+    }
+    report
+}
+
+/// Describe the headers of [content], based on the extension of [name]. Sheet pages are only
+/// counted if the [repo] is given.
+fn content_report(
+    repo: Option<&Repository>,
+    name: &str,
+    content: &[u8],
+) -> Result<Map<String, Value>, LastLegendError> {
+    let mut report = Map::new();
+    match Path::new(name).extension().and_then(|e| e.to_str()) {
+        Some("scd") => {
+            report.insert("scd_entries".into(), to_value(&read_scd_entries(content)?)?);
+        }
+        Some("exh") => {
+            report.insert(
+                "sheet".into(),
+                sheet_report(repo, name, &SheetInfo::parse(content)?),
+            );
+        }
+        Some("exd") => {
+            let page = PageHeader::parse(content)?;
+            let ids = page.row_offsets().iter().map(|r| r.index);
+            report.insert(
+                "page".into(),
+                json!({
+                    "rows": page.row_offsets().len(),
+                    "first_id": ids.clone().min(),
+                    "last_id": ids.max(),
+                }),
+            );
+        }
+        _ => {}
+    }
+    Ok(report)
+}
+
+fn sheet_report(repo: Option<&Repository>, file: &str, sheet_info: &SheetInfo) -> Value {
+    // Sheet names are the path under `exd/`, e.g. `quest/000/ClsGla001_00001`.
+    let sheet_name = file
+        .strip_prefix("exd/")
+        .unwrap_or(file)
+        .trim_end_matches(".exh");
+    let pages = sheet_info
+        .page_ranges
+        .iter()
+        .map(|range| {
+            let mut page = json!({
+                "first_id": range.start,
+                "last_id": range.end.saturating_sub(1),
+            });
+            if let Some(repo) = repo {
+                let rows = sheet_info
+                    .languages
+                    .iter()
+                    .map(|language| {
+                        let page_file = language.get_sheet_name(sheet_name, range.start);
+                        let rows = match count_page_rows(repo, &page_file) {
+                            Ok(rows) => Value::from(rows),
+                            Err(e) => {
+                                log::debug!("Couldn't read page {}: {}", page_file, e);
+                                Value::Null
+                            }
+                        };
+                        (language.code().to_string(), rows)
+                    })
+                    .collect::<Map<_, _>>();
+                page["rows"] = Value::Object(rows);
+            }
+            page
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "variant": format!("{:?}", sheet_info.variant),
+        "fixed_row_size": sheet_info.fixed_row_size,
+        "languages": sheet_info.languages.iter().map(|l| l.code()).collect::<Vec<_>>(),
+        "columns": sheet_info
+            .columns
+            .iter()
+            .map(|c| json!({
+                "type": format!("{:?}", c.data_type()),
+                "offset": c.offset(),
+            }))
+            .collect::<Vec<_>>(),
+        "pages": pages,
+    })
+}
+
+fn count_page_rows(repo: &Repository, page_file: &str) -> Result<usize, LastLegendError> {
+    let index = repo.get_index_for(page_file)?;
+    let content = read_entry_content(&index, index.get_entry(page_file)?)?;
+    Ok(PageHeader::parse(&content)?.row_offsets().len())
+}
+
+fn index_report(index: &Index2) -> Result<Value, LastLegendError> {
+    let dats = index
+        .dat_summaries()?
+        .into_iter()
+        .map(|dat| {
+            json!({
+                "data_file_id": dat.data_file_id,
+                "size": dat.dat_size,
+                "entries": dat.entry_count,
+                "max_offset": dat.max_offset,
+                "entries_out_of_bounds": dat.out_of_bounds.len(),
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(json!({
+        "format": format!("{:?}", index.format),
+        "platform": format!("{:?}", index.pack_header.platform_id),
+        "version": index.pack_header.version,
+        "content_type": format!("{:?}", index.pack_header.content_type),
+        "timestamp": format!("{:?}", index.pack_header.timestamp),
+        "index_data_offset": index.index_header.index_data_offset,
+        "index_data_size": index.index_header.index_data_size.0,
+        "entries": index.entries.len(),
+        "dats": dats,
+    }))
+}
+
+pub(super) fn to_value<T: serde::Serialize>(value: &T) -> Result<Value, LastLegendError> {
+    serde_json::to_value(value)
+        .map_err(|e| LastLegendError::Json("Couldn't build report".into(), e))
+}
+
+/// Print a report as indented `key: value` lines.
+pub(super) fn print_value(value: &Value, depth: usize) {
+    let indent = "  ".repeat(depth);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                if is_scalar_list(value) {
+                    println!("{}{}: {}", indent, key, scalar_text(value));
+                } else {
+                    println!("{}{}:", indent, key);
+                    print_value(value, depth + 1);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if is_scalar_list(item) {
+                    println!("{}[{}] {}", indent, i, scalar_text(item));
+                } else {
+                    println!("{}[{}]", indent, i);
+                    print_value(item, depth + 1);
+                }
+            }
+        }
+        scalar => println!("{}{}", indent, scalar_text(scalar)),
+    }
+}
+
+/// Whether [value] fits on one line: a scalar, or a list of scalars.
+fn is_scalar_list(value: &Value) -> bool {
+    match value {
+        Value::Object(_) => false,
+        Value::Array(items) => items
+            .iter()
+            .all(|i| !matches!(i, Value::Object(_) | Value::Array(_))),
+        _ => true,
+    }
+}
+
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "none".to_string(),
+        Value::Array(items) => items.iter().map(scalar_text).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use last_legend_dob::error::LastLegendError;
+use last_legend_dob::simple_task::format_index_hash_for_console;
+use last_legend_dob::sqpath::SqPathBuf;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Replace a file in the repository with a local file, for modding.
+///
+/// The new content is appended to the dat file, and the index files are updated to point at it.
+/// This changes the repository in place, so work on a copy, or back up the index files to undo
+/// it. Models and textures can't be replaced.
+#[derive(Args, Debug)]
+pub struct Replace {
+    /// The file in the repository to replace.
+    file: SqPathBuf,
+    /// The local file with the new content.
+    source: PathBuf,
+}
+
+impl LastLegendCommand for Replace {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let repo = global_args.open_repository();
+        let content = std::fs::read(&self.source).map_err(|e| {
+            LastLegendError::Io(format!("Couldn't read {}", self.source.display()), e)
+        })?;
+        let replaced = repo.replace_file(&self.file, &content)?;
+
+        if global_args.json_output() {
+            return print_json(&serde_json::json!({
+                "file": self.file.as_str(),
+                "hash": self.file.sq_index_hash_with(&global_args.path_hasher()),
+                "replaced": replaced,
+            }));
+        }
+        log::info!(
+            "Replaced {} ({}) with {} bytes, written to dat{} at 0x{:X}",
+            self.file,
+            format_index_hash_for_console(self.file.sq_index_hash_with(&global_args.path_hasher())),
+            content.len(),
+            replaced.data_file_id,
+            replaced.offset
+        );
+        for index_file in &replaced.index_files {
+            log::info!("Updated {}", index_file.display());
+        }
+        Ok(())
+    }
+}
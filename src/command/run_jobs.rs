@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser};
+use serde::Deserialize;
+
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+
+use crate::command::global_args::GlobalArgs;
+use crate::command::{LLDCommand, LastLegendCommand};
+
+/// Run several commands from a job file, sharing loaded indexes and cached content between them.
+///
+/// The job file is TOML, with a `[[job]]` table per command:
+///
+/// ```toml
+/// [[job]]
+/// name = "ost"
+/// output = "music"
+/// args = ["extract-music", "-t", "scd_to_flac", "--titles"]
+/// ```
+///
+/// `args` is the command and its arguments, as given after the repository on the command line.
+/// Each command runs in its `output` directory, relative to the job file and created if needed,
+/// so relative paths in `args` are relative to it too. Without `output`, commands run in the job
+/// file's directory. Global options, like `--content-cache-mib`, are given to `run-jobs` and apply
+/// to every job.
+#[derive(Args, Debug)]
+pub struct RunJobs {
+    /// The job file to run.
+    job_file: PathBuf,
+    /// Only run the jobs with these names. Can be given several times.
+    #[clap(long)]
+    only: Vec<String>,
+    /// Keep running the remaining jobs after one fails.
+    #[clap(short, long)]
+    keep_going: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct JobFile {
+    job: Vec<Job>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Job {
+    name: String,
+    output: Option<PathBuf>,
+    args: Vec<String>,
+}
+
+/// Parses the command of a job, the same way as the command line.
+#[derive(Parser, Debug)]
+#[clap(no_binary_name = true)]
+pub(crate) struct JobCommand {
+    #[clap(subcommand)]
+    pub command: LLDCommand,
+}
+
+impl LastLegendCommand for RunJobs {
+    fn run(self, mut global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let job_file = std::fs::read_to_string(&self.job_file).map_err(|e| {
+            LastLegendError::Io(format!("Couldn't read {}", self.job_file.display()), e)
+        })?;
+        let job_file: JobFile = toml::from_str(&job_file).map_err(|e| {
+            LastLegendError::Custom(format!(
+                "Invalid job file {}: {}",
+                self.job_file.display(),
+                e
+            ))
+        })?;
+        let base_dir = self
+            .job_file
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+
+        // Parse every job first, so mistakes are found before anything runs.
+        let mut jobs = Vec::new();
+        for job in job_file.job {
+            if !self.only.is_empty() && !self.only.contains(&job.name) {
+                continue;
+            }
+            let command = JobCommand::try_parse_from(&job.args)
+                .map_err(|e| {
+                    LastLegendError::Custom(format!("Invalid args for job '{}': {}", job.name, e))
+                })?
+                .command;
+            if matches!(command, LLDCommand::RunJobs(_) | LLDCommand::Watch(_)) {
+                return Err(LastLegendError::Custom(format!(
+                    "Job '{}' can't run more jobs or watch",
+                    job.name
+                )));
+            }
+            let directory = base_dir.join(job.output.as_deref().unwrap_or(Path::new("")));
+            jobs.push((job.name, directory, command));
+        }
+        for name in &self.only {
+            if !jobs.iter().any(|(job, _, _)| job == name) {
+                return Err(LastLegendError::Custom(format!("No job named '{}'", name)));
+            }
+        }
+
+        // Jobs change the working directory, so the repository path mustn't be relative.
+        if !global_args.is_remote() {
+            global_args.repository = std::path::absolute(&global_args.repository).map_err(|e| {
+                LastLegendError::Io("Couldn't resolve the repository path".into(), e)
+            })?;
+        }
+        global_args.shared_repository = Some(global_args.open_repository());
+        let original_dir = std::env::current_dir()
+            .map_err(|e| LastLegendError::Io("Couldn't get the working directory".into(), e))?;
+
+        let total = jobs.len();
+        let mut failed = 0;
+        for (name, directory, command) in jobs {
+            log::info!("Running job '{}' in {}", name, directory.display());
+            let result = std::fs::create_dir_all(&directory)
+                .and_then(|_| std::env::set_current_dir(&directory))
+                .map_err(|e| {
+                    LastLegendError::Io(format!("Couldn't enter {}", directory.display()), e)
+                })
+                .and_then(|_| command.run(global_args.clone()));
+            std::env::set_current_dir(&original_dir).map_err(|e| {
+                LastLegendError::Io("Couldn't return to the working directory".into(), e)
+            })?;
+            match result {
+                Ok(()) => {}
+                Err(e) if self.keep_going && e.category() != ErrorCategory::Cancelled => {
+                    log::error!("Job '{}' failed: {}", name, e);
+                    failed += 1;
+                }
+                Err(e) => return Err(e.add_context(format!("Job '{}' failed", name))),
+            }
+        }
+
+        if failed > 0 {
+            return Err(LastLegendError::PartialFailure { failed, total });
+        }
+        log::info!("All {} jobs finished", total);
+        Ok(())
+    }
+}
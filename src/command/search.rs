@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use clap::Args;
+use rayon::prelude::*;
+use serde_json::json;
+
+use last_legend_dob::data::repo::Repository;
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::manifest::{Manifest, ManifestEntry};
+use last_legend_dob::path_list::read_path_list;
+use last_legend_dob::simple_task::format_index_entry_for_console;
+use last_legend_dob::sqpath::SqPathBuf;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Search the repository for paths from a path list, such as the ResLogger path dumps.
+#[derive(Args, Debug)]
+pub struct Search {
+    /// The path list to search for, one path per line.
+    path_list: PathBuf,
+    /// Write a manifest of the found entries to this file, as JSON.
+    #[clap(short, long)]
+    manifest: Option<PathBuf>,
+}
+
+impl LastLegendCommand for Search {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let repo = global_args.open_repository();
+
+        let path_list = File::open(&self.path_list)
+            .map_err(|e| LastLegendError::Io("Couldn't open path list".into(), e))?;
+
+        let results = read_path_list(BufReader::new(path_list))
+            .par_bridge()
+            .map(|path| search_path(&repo, path?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let total = results.len();
+        let mut found = results.into_iter().flatten().collect::<Vec<_>>();
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+
+        log::info!("Found {} of {} paths", found.len(), total);
+
+        if global_args.json_output() {
+            print_json(&json!({
+                "searched": total,
+                "found": found,
+            }))?;
+        }
+
+        if let Some(manifest_path) = self.manifest {
+            let output = File::create(&manifest_path)
+                .map_err(|e| LastLegendError::Io("Couldn't create manifest".into(), e))?;
+            Manifest::new(found).write_json(BufWriter::new(output))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn search_path(
+    repo: &Repository,
+    path: SqPathBuf,
+) -> Result<Option<ManifestEntry>, LastLegendError> {
+    let index = match repo.get_index_for(&path) {
+        Ok(v) => v,
+        Err(e) if e.category() == ErrorCategory::NotFound => {
+            log::debug!("No index for {}: {}", path, e);
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    };
+    let Ok(entry) = index.get_entry(&path) else {
+        log::debug!("Not found: {}", path);
+        return Ok(None);
+    };
+    log::info!(
+        "Found {}",
+        format_index_entry_for_console(repo.repo_path(), &index, entry, &path)
+    );
+    Ok(Some(ManifestEntry::new(
+        repo.repo_path(),
+        &index,
+        entry,
+        &path,
+    )))
+}
@@ -0,0 +1,84 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use clap::Args;
+use serde_json::json;
+
+use last_legend_dob::data::repo::CategoryInfo;
+use last_legend_dob::error::LastLegendError;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Show entry counts, pack headers and dat file usage for index files.
+///
+/// The pack header's timestamp tells when the game built each index, which helps spot files left
+/// behind by a partial patch.
+#[derive(Args, Debug)]
+pub struct Stats {
+    /// The index files to summarize. Defaults to every index file in the repository.
+    files: Vec<PathBuf>,
+}
+
+impl LastLegendCommand for Stats {
+    fn run(mut self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let repo = global_args.open_repository();
+
+        if self.files.is_empty() {
+            self.files = repo.index_files()?;
+        }
+        self.files.sort();
+
+        if global_args.json_output() {
+            let mut report = Vec::new();
+            for file in &self.files {
+                let index = repo.load_index_file(Cow::Borrowed(file.as_path()))?;
+                let category = CategoryInfo::new(repo.repo_path(), &index);
+                report.push(json!({
+                    "file": file,
+                    "category": category.category,
+                    "expansion": category.expansion,
+                    "chunk": category.chunk,
+                    "entries": index.entries.len(),
+                    "header": category.header,
+                    "dats": index.dat_summaries()?,
+                    "reserved_bit_entries": index.reserved_bit_entries(),
+                }));
+            }
+            return print_json(&report);
+        }
+
+        for file in self.files.into_iter() {
+            let index = repo.load_index_file(Cow::Borrowed(file.as_path()))?;
+            let header = index.pack_header.info();
+            println!("{}: {} entries", file.display(), index.entries.len());
+            println!(
+                "  {} {}, version {}, {}",
+                header.platform,
+                header.content_type,
+                header.version,
+                header
+                    .timestamp
+                    .map_or_else(|| "no timestamp".to_string(), |t| format!("built {}", t))
+            );
+            for dat in index.dat_summaries()? {
+                let dat_size = dat
+                    .dat_size
+                    .map_or_else(|| "missing".to_string(), |s| format!("{} bytes", s));
+                println!(
+                    "  dat{}: {} entries, {}, last entry at 0x{:X}",
+                    dat.data_file_id, dat.entry_count, dat_size, dat.max_offset
+                );
+            }
+            let reserved = index.reserved_bit_entries();
+            if !reserved.is_empty() {
+                println!(
+                    "  {} entries have the reserved location bit set, their locations may be wrong",
+                    reserved.len()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use clap::Args;
+use rayon::prelude::*;
+use serde_json::json;
+
+use last_legend_dob::data::verify::{verify_index, VerifyLevel};
+use last_legend_dob::error::LastLegendError;
+
+use crate::command::extract_common::run_with_jobs;
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+use crate::progress::count_progress;
+
+/// Check that index files agree with their dat files.
+///
+/// Every entry is read, checking that its headers parse and its content decompresses to the size
+/// its header gives. Dat files are read an entry at a time, so this doesn't need much memory, but
+/// it does read the entire repository.
+#[derive(Args, Debug)]
+pub struct Verify {
+    /// The index files to verify, defaults to every index file in the repository.
+    files: Vec<PathBuf>,
+    /// Only check that entries start inside their dat files, which is much faster as the dat
+    /// files aren't read.
+    #[clap(long)]
+    offsets_only: bool,
+    /// How many index files to verify at once, defaults to the number of CPUs.
+    #[clap(short, long)]
+    jobs: Option<usize>,
+}
+
+impl LastLegendCommand for Verify {
+    fn run(mut self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let repo = global_args.open_repository();
+        let level = if self.offsets_only {
+            VerifyLevel::Offsets
+        } else {
+            VerifyLevel::Content
+        };
+
+        if self.files.is_empty() {
+            self.files = repo.index_files()?;
+        }
+        self.files.sort();
+
+        let indexes = self
+            .files
+            .iter()
+            .map(|file| repo.load_index_file(Cow::Borrowed(file.as_path())))
+            .collect::<Result<Vec<_>, _>>()?;
+        let total = indexes.iter().map(|i| i.entries.len()).sum::<usize>();
+
+        let progress = count_progress(total as u64, "entries");
+        let results = run_with_jobs(self.jobs, || {
+            indexes
+                .par_iter()
+                .map(|index| verify_index(index, level, || progress.inc(1)))
+                .collect::<Result<Vec<_>, _>>()
+        });
+        progress.finish_and_clear();
+        let results = results?;
+
+        let mut failed = 0;
+        let mut problems = Vec::new();
+        for (file, corrupt) in self.files.iter().zip(results) {
+            failed += corrupt.len();
+            for entry in corrupt {
+                log::error!(
+                    "{}: entry 0x{:08X} in dat{} at 0x{:X}: {}",
+                    file.display(),
+                    entry.hash,
+                    entry.data_file_id,
+                    entry.offset,
+                    entry.problem
+                );
+                if global_args.json_output() {
+                    problems.push(json!({ "file": file, "entry": entry }));
+                }
+            }
+        }
+
+        if global_args.json_output() {
+            print_json(&json!({
+                "total": total,
+                "failed": failed,
+                "problems": problems,
+            }))?;
+        }
+
+        if failed > 0 {
+            return Err(LastLegendError::PartialFailure { failed, total });
+        }
+
+        log::info!(
+            "All {} entries in {} index files are readable",
+            total,
+            self.files.len()
+        );
+        Ok(())
+    }
+}
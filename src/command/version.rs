@@ -0,0 +1,25 @@
+use clap::Args;
+
+use last_legend_dob::error::LastLegendError;
+
+use crate::command::global_args::{print_json, GlobalArgs};
+use crate::command::LastLegendCommand;
+
+/// Show the versions of the base game and each installed expansion, from the version files
+/// patches leave next to the repository.
+#[derive(Args, Debug)]
+pub struct Version {}
+
+impl LastLegendCommand for Version {
+    fn run(self, global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        let version = global_args.open_repository().game_version();
+        if global_args.json_output() {
+            return print_json(&version);
+        }
+        println!("ffxiv: {}", version.base.as_deref().unwrap_or("unknown"));
+        for (expansion, version) in &version.expansions {
+            println!("{}: {}", expansion, version);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::{Args, Parser};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::manifest::Journal;
+
+use crate::command::global_args::GlobalArgs;
+use crate::command::run_jobs::JobCommand;
+use crate::command::{LLDCommand, LastLegendCommand};
+
+/// How often to check for Ctrl-C while waiting for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Re-run an extraction whenever the repository's index or dat files change, e.g. after a game
+/// update.
+///
+/// The command is given after `--`, as it would be after the repository on the command line:
+///
+/// `lldob sqpack watch --state music.jsonl -- extract-music -m orchestrion --overwrite`
+///
+/// It runs once when watching starts, then again once the files have stopped changing. Extracted
+/// entries are recorded in the state file, and skipped while their hash and place in the
+/// repository stay the same, so each run only writes what changed. Give the command
+/// `--overwrite`, so changed entries replace their old outputs. Stop watching with Ctrl-C.
+#[derive(Args, Debug)]
+pub struct Watch {
+    /// Where to record extracted entries between runs.
+    #[clap(long)]
+    state: PathBuf,
+    /// Run the command in this directory, created if needed.
+    #[clap(long)]
+    output: Option<PathBuf>,
+    /// Seconds without changes to wait before running, so a whole update is picked up at once.
+    #[clap(long, default_value_t = 10)]
+    settle_secs: u64,
+    /// The extraction command to run, and its arguments.
+    #[clap(last = true, required = true)]
+    args: Vec<String>,
+}
+
+impl LastLegendCommand for Watch {
+    fn run(self, mut global_args: GlobalArgs) -> Result<(), LastLegendError> {
+        // Check the command before doing anything else.
+        self.parse_command()?;
+        if global_args.is_remote() {
+            return Err(LastLegendError::Custom(
+                "Only local repositories can be watched".into(),
+            ));
+        }
+
+        // The repository is re-opened for every run, so it sees the new indexes.
+        global_args.shared_repository = None;
+        global_args.repository = std::path::absolute(&global_args.repository)
+            .map_err(|e| LastLegendError::Io("Couldn't resolve the repository path".into(), e))?;
+        let state = std::path::absolute(&self.state)
+            .map_err(|e| LastLegendError::Io("Couldn't resolve the state path".into(), e))?;
+        if let Some(output) = &self.output {
+            std::fs::create_dir_all(output)
+                .and_then(|_| std::env::set_current_dir(output))
+                .map_err(|e| {
+                    LastLegendError::Io(format!("Couldn't enter {}", output.display()), e)
+                })?;
+        }
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // Only fails once watching has stopped.
+            let _ = sender.send(event);
+        })
+        .map_err(|e| LastLegendError::Custom(format!("Couldn't watch the repository: {}", e)))?;
+        watcher
+            .watch(&global_args.repository, RecursiveMode::Recursive)
+            .map_err(|e| {
+                LastLegendError::Custom(format!(
+                    "Couldn't watch {}: {}",
+                    global_args.repository.display(),
+                    e
+                ))
+            })?;
+
+        let settle_time = Duration::from_secs(self.settle_secs);
+        loop {
+            self.run_once(&global_args, &state)?;
+
+            log::info!("Watching {} for changes", global_args.repository.display());
+            if !wait_for_change(&receiver, None) {
+                break;
+            }
+            log::info!("Repository changed, waiting for it to settle");
+            while wait_for_change(&receiver, Some(settle_time)) {}
+            if crate::CANCELLATION.is_cancelled() {
+                break;
+            }
+        }
+        log::info!("Stopped watching");
+        Ok(())
+    }
+}
+
+impl Watch {
+    /// Parse the command again for each run, as running it consumes it.
+    fn parse_command(&self) -> Result<LLDCommand, LastLegendError> {
+        let command = JobCommand::try_parse_from(&self.args)
+            .map_err(|e| LastLegendError::Custom(format!("Invalid command to watch: {}", e)))?
+            .command;
+        match command {
+            LLDCommand::Extract(_)
+            | LLDCommand::ExtractAll(_)
+            | LLDCommand::ExtractAmbient(_)
+            | LLDCommand::ExtractMusic(_)
+            | LLDCommand::ExtractVfx(_)
+            | LLDCommand::ExtractVoice(_) => Ok(command),
+            _ => Err(LastLegendError::Custom(
+                "Only extraction commands can be watched".into(),
+            )),
+        }
+    }
+
+    /// Run the command, skipping entries in the [state] journal. Failures are logged, and the
+    /// failed entries are tried again on the next run, but cancellation stops watching.
+    fn run_once(&self, global_args: &GlobalArgs, state: &Path) -> Result<(), LastLegendError> {
+        let mut global_args = global_args.clone();
+        global_args.journal = Some(Arc::new(Journal::open(state)?));
+        match self.parse_command()?.run(global_args) {
+            Ok(()) => log::info!("Extraction finished"),
+            Err(e) if e.category() == ErrorCategory::Cancelled => return Err(e),
+            Err(e) => log::error!("Extraction failed: {}", e),
+        }
+        Ok(())
+    }
+}
+
+/// Wait for an index or dat file to change, for up to [timeout] if given. Returns whether one
+/// changed, which is never the case once cancelled.
+fn wait_for_change(receiver: &Receiver<notify::Result<Event>>, timeout: Option<Duration>) -> bool {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        if crate::CANCELLATION.is_cancelled() {
+            return false;
+        }
+        let wait = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) => left.min(POLL_INTERVAL),
+                None => return false,
+            },
+            None => POLL_INTERVAL,
+        };
+        match receiver.recv_timeout(wait) {
+            Ok(Ok(event)) if is_repository_change(&event) => {
+                log::debug!("Changed: {:?}", event.paths);
+                return true;
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => log::warn!("Error while watching: {}", e),
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+/// Whether [event] changed an index or dat file.
+fn is_repository_change(event: &Event) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+        && event.paths.iter().any(|path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| e == "index" || e == "index2" || e.starts_with("dat"))
+        })
+}
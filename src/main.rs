@@ -1,21 +1,92 @@
+use std::process::ExitCode;
+use std::sync::{Arc, LazyLock};
+
 use clap::Parser;
 use log::LevelFilter;
 
-use last_legend_dob::error::LastLegendError;
+use last_legend_dob::cancel::CancellationToken;
+use last_legend_dob::data::inflate::decompressor;
+use last_legend_dob::error::{ErrorCategory, LastLegendError};
+use last_legend_dob::ffmpeg::backend::{set_audio_backend, FakeAudioBackend};
+use last_legend_dob::ffmpeg::set_max_ffmpeg_processes;
+use last_legend_dob::metrics;
 
 use crate::command::{LastLegendCommand, LastLegendDob};
 
 mod command;
+mod progress;
+
+/// Shown at the end of `--help`, keep in sync with [exit_code_for].
+pub(crate) const EXIT_CODE_HELP: &str = "\
+Exit codes:
+  0   success
+  1   other error
+  2   path, entry, or sheet not found (also used for invalid arguments)
+  3   game data could not be parsed
+  4   FFMPEG failed
+  5   I/O error
+  6   data is in an unsupported format, e.g. an audio codec
+  10  some entries failed to extract
+  130 interrupted with Ctrl-C";
+
+/// Cancelled by the first Ctrl-C, so commands can stop cleanly. A second Ctrl-C exits right away.
+pub(crate) static CANCELLATION: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
 
-fn main() -> Result<(), LastLegendError> {
+fn main() -> ExitCode {
     let args = LastLegendDob::parse();
-    env_logger::Builder::new()
-        .filter_level(match args.global_args.verbose {
-            0 => LevelFilter::Info,
-            1 => LevelFilter::Debug,
-            _ => LevelFilter::Trace,
-        })
-        .init();
-
-    args.subcommand.run(args.global_args)
+    progress::init_logging(match args.global_args.verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    });
+
+    log::debug!("Inflating dat blocks with {}", decompressor().name());
+
+    if args.global_args.simulate {
+        set_audio_backend(Arc::new(FakeAudioBackend));
+    }
+    if let Some(max) = args.global_args.max_ffmpeg_processes {
+        set_max_ffmpeg_processes(max);
+    }
+
+    if args.subcommand.handles_ctrl_c() {
+        let handler = ctrlc::set_handler(|| {
+            if CANCELLATION.is_cancelled() {
+                std::process::exit(130);
+            }
+            log::warn!("Stopping, press Ctrl-C again to exit right away");
+            CANCELLATION.cancel();
+        });
+        if let Err(e) = handler {
+            log::debug!("Couldn't handle Ctrl-C: {}", e);
+        }
+    }
+
+    let mut global_args = args.global_args;
+    let result = global_args
+        .resolve_repository()
+        .and_then(|_| args.subcommand.run(global_args));
+    if let Some(summary) = metrics::global().summary() {
+        log::info!("{}", summary);
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_code_for(&e))
+        }
+    }
+}
+
+fn exit_code_for(error: &LastLegendError) -> u8 {
+    match error.category() {
+        ErrorCategory::Other => 1,
+        ErrorCategory::NotFound => 2,
+        ErrorCategory::Parse => 3,
+        ErrorCategory::Ffmpeg => 4,
+        ErrorCategory::Io => 5,
+        ErrorCategory::Unsupported => 6,
+        ErrorCategory::PartialFailure => 10,
+        ErrorCategory::Cancelled => 130,
+    }
 }
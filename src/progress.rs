@@ -0,0 +1,204 @@
+//! Progress reporting for extractions. On a terminal this draws progress bars, otherwise each
+//! finished file is logged with the overall progress.
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use indicatif_log_bridge::LogWrapper;
+use log::LevelFilter;
+use owo_colors::Style;
+
+use last_legend_dob::uwu_colors::{get_errstyle, ErrStyle};
+
+static MULTI_PROGRESS: LazyLock<MultiProgress> = LazyLock::new(|| {
+    if std::io::stderr().is_terminal() {
+        MultiProgress::new()
+    } else {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    }
+});
+
+/// Set up logging so that log lines are printed above any progress bars.
+pub(crate) fn init_logging(level: LevelFilter) {
+    // Logs must stay off stdout, `cat` writes file content there.
+    let logger = env_logger::Builder::new()
+        .filter_level(level)
+        .target(env_logger::Target::Stderr)
+        .build();
+    LogWrapper::new(MULTI_PROGRESS.clone(), logger)
+        .try_init()
+        .expect("Logging should only be initialized once");
+    log::set_max_level(level);
+}
+
+/// A bar counting [total] [things], for commands that don't write files. Hidden when stderr isn't
+/// a terminal.
+pub(crate) fn count_progress(total: u64, things: &str) -> ProgressBar {
+    let bar = MULTI_PROGRESS.add(ProgressBar::new(total));
+    bar.set_style(
+        ProgressStyle::with_template(&format!(
+            "{} {{pos}}/{{len}} {}, ETA {{eta}}",
+            get_errstyle(Style::new().cyan()).style("{wide_bar}"),
+            things
+        ))
+        .expect("Progress template should be valid"),
+    );
+    bar
+}
+
+/// Overall progress of a command, shared between threads.
+#[derive(Clone, Debug)]
+pub(crate) struct ExtractProgress {
+    state: Option<Arc<ProgressState>>,
+}
+
+#[derive(Debug)]
+struct ProgressState {
+    overall: ProgressBar,
+    bytes_written: AtomicU64,
+}
+
+impl ExtractProgress {
+    /// Report nothing, for callers that track progress themselves.
+    pub fn hidden() -> Self {
+        Self { state: None }
+    }
+
+    /// Report progress for [total] files, if known.
+    pub fn new(total: Option<u64>) -> Self {
+        let overall = MULTI_PROGRESS.add(match total {
+            Some(total) => ProgressBar::new(total),
+            None => ProgressBar::no_length(),
+        });
+        overall.set_style(
+            ProgressStyle::with_template(&format!(
+                "{} {{pos}}/{{len}} files, {{msg}} written, ETA {{eta}}",
+                get_errstyle(Style::new().cyan()).style("{wide_bar}")
+            ))
+            .expect("Progress template should be valid"),
+        );
+        overall.set_message(HumanBytes(0).to_string());
+        Self {
+            state: Some(Arc::new(ProgressState {
+                overall,
+                bytes_written: AtomicU64::new(0),
+            })),
+        }
+    }
+
+    /// Start tracking a single file. It counts as done once the returned value is dropped.
+    pub fn start_file(&self, name: &str) -> FileProgress {
+        let bar = self.state.as_ref().map(|_| {
+            let bar = MULTI_PROGRESS.add(ProgressBar::new_spinner());
+            bar.set_style(
+                ProgressStyle::with_template("{spinner} {msg} {bytes}")
+                    .expect("Progress template should be valid"),
+            );
+            bar.set_message(name.to_string());
+            bar.enable_steady_tick(Duration::from_millis(100));
+            bar
+        });
+        FileProgress {
+            progress: self.clone(),
+            bar,
+            bytes_written: 0,
+        }
+    }
+
+    /// Count a file as done without writing it, e.g. because it's unchanged.
+    pub fn skip_file(&self) {
+        if let Some(state) = &self.state {
+            state.overall.inc(1);
+        }
+    }
+
+    /// Count [count] more files to do, for files found as extraction goes.
+    pub fn add_files(&self, count: u64) {
+        if let Some(state) = &self.state {
+            state.overall.inc_length(count);
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(state) = &self.state {
+            state.overall.finish_and_clear();
+        }
+    }
+}
+
+pub(crate) struct FileProgress {
+    progress: ExtractProgress,
+    bar: Option<ProgressBar>,
+    bytes_written: u64,
+}
+
+impl FileProgress {
+    /// Wrap [writer] to count the bytes written to it.
+    pub fn wrap_write<W: Write>(&mut self, writer: W) -> ProgressWriter<'_, W> {
+        ProgressWriter { file: self, writer }
+    }
+
+    /// Mark the file as successfully written to [output_path].
+    pub fn finish(self, output_path: &Path) {
+        let Some(state) = &self.progress.state else {
+            log::info!("Wrote {}", output_path.display());
+            return;
+        };
+        let total_bytes = state.bytes_written.load(Ordering::Relaxed);
+        let done = state.overall.position() + 1;
+        let of_total = state
+            .overall
+            .length()
+            .map_or_else(|| done.to_string(), |total| format!("{}/{}", done, total));
+        log::info!(
+            "[{}] Wrote {} ({}), {} written so far",
+            of_total,
+            output_path.display().errstyle(Style::new().green()),
+            HumanBytes(self.bytes_written),
+            HumanBytes(total_bytes),
+        );
+    }
+}
+
+impl Drop for FileProgress {
+    fn drop(&mut self) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+            MULTI_PROGRESS.remove(&bar);
+        }
+        if let Some(state) = &self.progress.state {
+            state.overall.inc(1);
+        }
+    }
+}
+
+pub(crate) struct ProgressWriter<'a, W> {
+    file: &'a mut FileProgress,
+    writer: W,
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        let written_u64 = written as u64;
+        self.file.bytes_written += written_u64;
+        if let Some(bar) = &self.file.bar {
+            bar.inc(written_u64);
+        }
+        if let Some(state) = &self.file.progress.state {
+            let total = state
+                .bytes_written
+                .fetch_add(written_u64, Ordering::Relaxed)
+                + written_u64;
+            state.overall.set_message(HumanBytes(total).to_string());
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}